@@ -49,7 +49,7 @@ use sp_consensus::SelectChain;
 use sp_consensus_babe::BabeApi;
 use sc_rpc::SubscriptionTaskExecutor;
 use sp_transaction_pool::TransactionPool;
-use sc_client_api::AuxStore;
+use sc_client_api::{AuxStore, BlockchainEvents};
 
 /// Light client extra dependencies.
 pub struct LightDeps<C, F, P> {
@@ -115,7 +115,9 @@ pub fn create_full<C, P, SC, B>(
 	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + AuxStore +
 		HeaderMetadata<Block, Error=BlockChainError> + Sync + Send + 'static,
 	C::Api: substrate_frame_rpc_system::AccountNonceApi<Block, AccountId, Index>,
-	C::Api: pallet_contracts_rpc::ContractsRuntimeApi<Block, AccountId, Balance, BlockNumber>,
+	C: BlockchainEvents<Block>,
+	C::Api: pallet_contracts_rpc::ContractsRuntimeApi<Block, AccountId, Balance, BlockNumber, Hash>,
+	C::Api: pallet_contracts_rpc::ContractEventsRuntimeApi<Block, AccountId, Hash>,
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
 	C::Api: BabeApi<Block>,
 	C::Api: BlockBuilder<Block>,
@@ -125,7 +127,7 @@ pub fn create_full<C, P, SC, B>(
 	B::State: sc_client_api::backend::StateBackend<sp_runtime::traits::HashFor<Block>>,
 {
 	use substrate_frame_rpc_system::{FullSystem, SystemApi};
-	use pallet_contracts_rpc::{Contracts, ContractsApi};
+	use pallet_contracts_rpc::{Contracts, ContractsApi, ContractEvents, ContractsEventsApi};
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
 
 	let mut io = jsonrpc_core::IoHandler::default();
@@ -161,6 +163,11 @@ pub fn create_full<C, P, SC, B>(
 	io.extend_with(
 		ContractsApi::to_delegate(Contracts::new(client.clone()))
 	);
+	io.extend_with(
+		ContractsEventsApi::to_delegate(
+			ContractEvents::new(client.clone(), subscription_executor.clone())
+		)
+	);
 	io.extend_with(
 		TransactionPaymentApi::to_delegate(TransactionPayment::new(client.clone()))
 	);
@@ -200,7 +200,7 @@ impl frame_system::Config for Runtime {
 	type PalletInfo = PalletInfo;
 	type AccountData = pallet_balances::AccountData<Balance>;
 	type OnNewAccount = ();
-	type OnKilledAccount = ();
+	type OnKilledAccount = Contracts;
 	type SystemWeightInfo = frame_system::weights::SubstrateWeight<Runtime>;
 }
 
@@ -701,6 +701,12 @@ parameter_types! {
 	pub const MaxDepth: u32 = 32;
 	pub const StorageSizeOffset: u32 = 8;
 	pub const MaxValueSize: u32 = 16 * 1024;
+	pub const MaxBatchSize: u32 = 16;
+	// Allows for the instantiations of the `seal_instantiate` benchmark.
+	pub const MaxInstantiationsPerCall: u32 = 2_000;
+	pub const ContractEventsEnabled: bool = true;
+	pub const AuditReads: bool = false;
+	pub const AuditRetention: BlockNumber = 7 * DAYS;
 }
 
 impl pallet_contracts::Config for Runtime {
@@ -717,8 +723,21 @@ impl pallet_contracts::Config for Runtime {
 	type SurchargeReward = SurchargeReward;
 	type MaxDepth = MaxDepth;
 	type MaxValueSize = MaxValueSize;
+	type MaxBatchSize = MaxBatchSize;
+	type MaxInstantiationsPerCall = MaxInstantiationsPerCall;
+	type ContractEventsEnabled = ContractEventsEnabled;
 	type WeightPrice = pallet_transaction_payment::Module<Self>;
 	type WeightInfo = pallet_contracts::weights::SubstrateWeight<Self>;
+	type KeyPartitioner = ();
+	type WriteCostModel = ();
+	type ValueCipher = ();
+	type WriteMirror = ();
+	type DeferredBookkeeping = ();
+	type AuditReads = AuditReads;
+	type AuditRetention = AuditRetention;
+	type ChargeRentOnWrite = ();
+	type StorageDeltaEvents = ();
+	type StorageBatchEvents = ();
 }
 
 impl pallet_sudo::Config for Runtime {
@@ -1162,7 +1181,7 @@ impl_runtime_apis! {
 		}
 	}
 
-	impl pallet_contracts_rpc_runtime_api::ContractsApi<Block, AccountId, Balance, BlockNumber>
+	impl pallet_contracts_rpc_runtime_api::ContractsApi<Block, AccountId, Balance, BlockNumber, Hash>
 		for Runtime
 	{
 		fn call(
@@ -1175,6 +1194,24 @@ impl_runtime_apis! {
 			Contracts::bare_call(origin, dest, value, gas_limit, input_data)
 		}
 
+		fn call_profiled(
+			origin: AccountId,
+			dest: AccountId,
+			value: Balance,
+			gas_limit: u64,
+			input_data: Vec<u8>,
+		) -> pallet_contracts_primitives::ContractExecResult {
+			Contracts::bare_call_profiled(origin, dest, value, gas_limit, input_data)
+		}
+
+		fn call_batch(
+			origin: AccountId,
+			calls: Vec<(AccountId, Balance, Vec<u8>)>,
+			gas_limit: u64,
+		) -> pallet_contracts_primitives::ContractBatchExecResult {
+			Contracts::bare_call_batch(origin, calls, gas_limit)
+		}
+
 		fn get_storage(
 			address: AccountId,
 			key: [u8; 32],
@@ -1187,6 +1224,40 @@ impl_runtime_apis! {
 		) -> pallet_contracts_primitives::RentProjectionResult<BlockNumber> {
 			Contracts::rent_projection(address)
 		}
+
+		fn tombstone_info(
+			address: AccountId,
+		) -> Option<pallet_contracts_primitives::TombstoneInfo<Hash>> {
+			Contracts::tombstone_info(address)
+		}
+
+		fn list_tombstones(
+			start: Option<AccountId>,
+			count: u32,
+		) -> Vec<
+			pallet_contracts_primitives::TombstoneListing<AccountId, Hash, Balance, BlockNumber>
+		> {
+			Contracts::list_tombstones(start, count)
+		}
+
+		fn contract_snapshot(
+			address: AccountId,
+			start_key: Option<Vec<u8>>,
+			limit: u32,
+		) -> pallet_contracts_primitives::ContractSnapshotResult<Hash, Balance, BlockNumber> {
+			Contracts::contract_snapshot(address, start_key, limit)
+		}
+	}
+
+	impl pallet_contracts_rpc_runtime_api::ContractEventsApi<Block, AccountId, Hash>
+		for Runtime
+	{
+		fn contract_events(
+			address: AccountId,
+			topics: Vec<Hash>,
+		) -> Vec<pallet_contracts_primitives::ContractEvent<Hash>> {
+			Contracts::contract_events(&address, &topics)
+		}
 	}
 
 	impl pallet_transaction_payment_rpc_runtime_api::TransactionPaymentApi<
@@ -21,7 +21,7 @@
 use bitflags::bitflags;
 use codec::{Decode, Encode};
 use sp_runtime::{DispatchError, RuntimeDebug};
-use sp_std::prelude::*;
+use sp_std::{prelude::*, collections::btree_map::BTreeMap};
 
 /// Result type of a `bare_call` call.
 ///
@@ -30,8 +30,60 @@ use sp_std::prelude::*;
 pub struct ContractExecResult {
 	pub exec_result: ExecResult,
 	pub gas_consumed: u64,
+	/// The gas consumed per category of work.
+	///
+	/// Only recorded when profiling was requested for the call. `None` otherwise.
+	pub profile: Option<BTreeMap<GasCategory, u64>>,
+	/// The gas consumed by each call frame excluding the frames it called.
+	///
+	/// The frames are listed in the order they returned, i.e. every frame comes after the
+	/// frames it called. Only recorded together with the `profile`. `None` otherwise.
+	pub gas_breakdown: Option<Vec<FrameGas>>,
+	/// The version of the schedule the call was executed with.
+	///
+	/// The gas consumed by the same call is only comparable between results that share it.
+	pub schedule_version: u32,
+}
+
+/// The gas consumed by a single call or instantiation as recorded in a
+/// `ContractExecResult::gas_breakdown`.
+#[derive(Clone, Eq, PartialEq, Encode, Decode, RuntimeDebug)]
+pub struct FrameGas {
+	/// The SCALE encoded account id of the called or instantiated contract.
+	pub account: Vec<u8>,
+	/// The SCALE encoded code hash that was executed.
+	pub code_hash: Vec<u8>,
+	/// The gas consumed by the frame minus the gas consumed by the frames it called.
+	pub gas_consumed: u64,
+}
+
+/// The kinds of work that gas is charged for as recorded in a `ContractExecResult::profile`.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Encode, Decode, RuntimeDebug)]
+pub enum GasCategory {
+	/// Execution of wasm instructions.
+	Instructions,
+	/// Reading and writing contract storage.
+	Storage,
+	/// Calling and instantiating other contracts and transferring balance.
+	Calls,
+	/// Hashing done on behalf of the contract.
+	Hashing,
+	/// Everything else.
+	Other,
 }
 
+/// Result type of a `bare_call_batch` call.
+///
+/// The outputs of all calls of the batch along with the gas consumed by all of them.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug)]
+pub struct ContractBatchExecResult {
+	pub exec_result: BatchExecResult,
+	pub gas_consumed: u64,
+}
+
+/// The outputs of the calls of a batch or the error of the call that failed.
+pub type BatchExecResult = Result<Vec<ExecReturnValue>, ExecError>;
+
 /// Result type of a `get_storage` call.
 pub type GetStorageResult = Result<Option<Vec<u8>>, ContractAccessError>;
 
@@ -48,6 +100,89 @@ pub enum ContractAccessError {
 	IsTombstone,
 }
 
+/// Result type of a `contract_snapshot` call.
+pub type ContractSnapshotResult<Hash, Balance, BlockNumber> =
+	Result<SnapshotChunk<Hash, Balance, BlockNumber>, ContractAccessError>;
+
+/// Information about a contract that is part of the first chunk of a snapshot.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug)]
+pub struct SnapshotHeader<Hash, Balance, BlockNumber> {
+	/// The code hash of the contract.
+	pub code_hash: Hash,
+	/// The code as it was uploaded by `put_code`. `None` if it was removed.
+	pub pristine_code: Option<Vec<u8>>,
+	/// The free balance of the contract.
+	pub free_balance: Balance,
+	/// The rent allowance of the contract.
+	pub rent_allowance: Balance,
+	/// The size of all values stored by the contract.
+	pub storage_size: u64,
+	/// The number of key value pairs stored by the contract.
+	pub total_pair_count: u32,
+	/// The block at which rent was last collected from the contract.
+	pub deduct_block: BlockNumber,
+	/// The block at which the storage of the contract was last written to.
+	pub last_write: Option<BlockNumber>,
+	/// The number of successful top-level calls made to the contract.
+	pub call_count: u64,
+}
+
+/// A page of the storage of a contract as returned by `contract_snapshot`.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug)]
+pub struct SnapshotChunk<Hash, Balance, BlockNumber> {
+	/// Only set for the first chunk of a snapshot.
+	pub header: Option<SnapshotHeader<Hash, Balance, BlockNumber>>,
+	/// The pairs contained in this chunk. The keys are hashed as they are stored in the trie.
+	pub pairs: Vec<(Vec<u8>, Vec<u8>)>,
+	/// The key to pass as `start_key` in order to fetch the next chunk.
+	///
+	/// `None` if this is the last chunk.
+	pub next_key: Option<Vec<u8>>,
+}
+
+/// An event deposited by a contract through `seal_deposit_event`.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug)]
+pub struct ContractEvent<Hash> {
+	/// The topics the event was deposited with.
+	pub topics: Vec<Hash>,
+	/// The data as passed by the contract.
+	pub data: Vec<u8>,
+}
+
+/// The information recorded in the tombstone of an evicted contract.
+///
+/// It allows to restore the contract without keeping records of it off-chain.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug)]
+pub struct TombstoneInfo<Hash> {
+	/// The code hash of the evicted contract.
+	///
+	/// `None` for tombstones that were created before it was recorded.
+	pub code_hash: Option<Hash>,
+	/// The size of all values stored by the evicted contract.
+	///
+	/// `None` for tombstones that were created before it was recorded.
+	pub storage_size: Option<u64>,
+}
+
+/// A tombstone as returned by `list_tombstones`.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug)]
+pub struct TombstoneListing<AccountId, Hash, Balance, BlockNumber> {
+	/// The address of the tombstone.
+	pub address: AccountId,
+	/// The block in which the contract was evicted.
+	///
+	/// `None` for tombstones that were created before it was recorded.
+	pub evicted_at: Option<BlockNumber>,
+	/// The code hash of the evicted contract.
+	///
+	/// `None` for tombstones that were created before it was recorded.
+	pub code_hash: Option<Hash>,
+	/// The balance left at the address of the tombstone.
+	///
+	/// The eviction leaves at least the tombstone deposit behind.
+	pub deposit: Balance,
+}
+
 #[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug)]
 pub enum RentProjection<BlockNumber> {
 	/// Eviction is projected to happen at the specified block number.
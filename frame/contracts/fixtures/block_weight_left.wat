@@ -0,0 +1,43 @@
+;; Stores the weight left in the current block under the key [1, 0, 0, ...].
+(module
+	(import "seal0" "seal_block_weight_left" (func $seal_block_weight_left (param i32 i32)))
+	(import "seal0" "seal_set_storage" (func $seal_set_storage (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 32) storage key
+	(data (i32.const 0) "\01")
+
+	;; [32, 40) buffer for the weight left
+
+	;; [40, 44) size of the buffer
+	(data (i32.const 40) "\08")
+
+	(func $assert (param i32)
+		(block $ok
+			(br_if $ok
+				(get_local 0)
+			)
+			(unreachable)
+		)
+	)
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_block_weight_left (i32.const 32) (i32.const 40))
+
+		;; assert len == 8
+		(call $assert
+			(i32.eq
+				(i32.load (i32.const 40))
+				(i32.const 8)
+			)
+		)
+
+		(call $seal_set_storage
+			(i32.const 0)	;; Pointer to the storage key
+			(i32.const 32)	;; Pointer to the value
+			(i32.const 8)	;; Length of the value
+		)
+	)
+)
@@ -0,0 +1,50 @@
+;; Calls the first contract of the addresses passed as input and forwards the remaining
+;; addresses to it. Returns without calling anyone if there is no address left.
+;; Traps if the call fails.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_call" (func $seal_call (param i32 i32 i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 8) value to transfer
+	(data (i32.const 0) "\00\00\00\00\00\00\00\00")
+
+	;; [8, 12) size of the input buffer
+	(data (i32.const 8) "\00\10")
+
+	;; [12, 4108) input buffer
+	;; a sequence of 32 byte addresses
+
+	(func $assert (param i32)
+		(block $ok
+			(br_if $ok
+				(get_local 0)
+			)
+			(unreachable)
+		)
+	)
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 12) (i32.const 8))
+		(if (i32.eqz (i32.load (i32.const 8)))
+			(then (return))
+		)
+		(call $assert
+			(i32.eqz
+				(call $seal_call
+					(i32.const 12) ;; Pointer to "callee" address.
+					(i32.const 32) ;; Length of "callee" address.
+					(i64.const 0) ;; How much gas to devote for the execution. 0 = all.
+					(i32.const 0) ;; Pointer to the buffer with value to transfer
+					(i32.const 8) ;; Length of the buffer with value to transfer.
+					(i32.const 44) ;; Pointer to the remaining addresses
+					(i32.sub (i32.load (i32.const 8)) (i32.const 32)) ;; Length of them
+					(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy output
+					(i32.const 0) ;; Ptr to output buffer len
+				)
+			)
+		)
+	)
+)
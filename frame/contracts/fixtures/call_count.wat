@@ -0,0 +1,38 @@
+;; Returns the call count of the contract as observed by the current call.
+;; The first byte of the input selects how the call ends:
+;; 0 = return with success, 1 = revert, 2 = trap.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_call_count" (func $seal_call_count (param i32 i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 4) size of the input buffer
+	(data (i32.const 0) "\04")
+
+	;; [4, 8) input buffer
+
+	;; [8, 12) size of the call count buffer
+	(data (i32.const 8) "\08")
+
+	;; [12, 20) call count buffer
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 4) (i32.const 0))
+		(call $seal_call_count (i32.const 12) (i32.const 8))
+		(block $trap
+			(block $revert
+				(block $ok
+					(br_table $ok $revert $trap
+						(i32.load8_u (i32.const 4))
+					)
+				)
+				(call $seal_return (i32.const 0) (i32.const 12) (i32.const 8))
+			)
+			(call $seal_return (i32.const 1) (i32.const 12) (i32.const 8))
+		)
+		(unreachable)
+	)
+)
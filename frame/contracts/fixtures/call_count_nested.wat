@@ -0,0 +1,54 @@
+;; Calls the contract whose address is passed as input and returns the call count of this
+;; contract as observed after that call.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_call" (func $seal_call (param i32 i32 i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "seal0" "seal_call_count" (func $seal_call_count (param i32 i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 8) 0 balance
+	(data (i32.const 0) "\00\00\00\00\00\00\00\00")
+
+	;; [8, 12) size of the input data
+	(data (i32.const 8) "\20")
+
+	;; [12, 44) callee address
+
+	;; [44, 48) size of the call count buffer
+	(data (i32.const 44) "\08")
+
+	;; [48, 56) call count buffer
+
+	(func $assert (param i32)
+		(block $ok
+			(br_if $ok
+				(get_local 0)
+			)
+			(unreachable)
+		)
+	)
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 12) (i32.const 8))
+		(call $assert
+			(i32.eqz
+				(call $seal_call
+					(i32.const 12) ;; Pointer to "callee" address.
+					(i32.const 32) ;; Length of "callee" address.
+					(i64.const 0) ;; How much gas to devote for the execution. 0 = all.
+					(i32.const 0) ;; Pointer to the buffer with value to transfer
+					(i32.const 8) ;; Length of the buffer with value to transfer.
+					(i32.const 0) ;; Pointer to input data buffer address
+					(i32.const 0) ;; Length of input data buffer
+					(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy output
+					(i32.const 0) ;; Ptr to output buffer len
+				)
+			)
+		)
+		(call $seal_call_count (i32.const 48) (i32.const 44))
+		(call $seal_return (i32.const 0) (i32.const 48) (i32.const 8))
+	)
+)
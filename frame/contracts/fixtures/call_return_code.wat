@@ -1,6 +1,6 @@
 ;; This calls the supplied dest and transfers 100 balance during this call and copies
 ;; the return code of this call to the output buffer.
-;; It also forwards its input to the callee.
+;; It also forwards the rest of its input to the callee.
 (module
 	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
 	(import "seal0" "seal_call" (func $seal_call (param i32 i32 i64 i32 i32 i32 i32 i32 i32) (result i32)))
@@ -16,7 +16,7 @@
 	(data (i32.const 12) "\24")
 
 	;; [16, inf) here we store the input data
-	;; 32 byte dest + 4 byte forward
+	;; 32 byte dest + up to 4 byte forward
 
 	(func (export "deploy"))
 
@@ -31,7 +31,7 @@
 				(i32.const 0) ;; Pointer to the buffer with value to transfer
 				(i32.const 8) ;; Length of the buffer with value to transfer.
 				(i32.const 48) ;; Pointer to input data buffer address
-				(i32.const 4) ;; Length of input data buffer
+				(i32.sub (i32.load (i32.const 12)) (i32.const 32)) ;; Length of input data buffer
 				(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy output
 				(i32.const 0) ;; Ptr to output buffer len
 			)
@@ -0,0 +1,53 @@
+;; This calls the supplied dest through `[seal1] seal_call` and transfers 100 balance during
+;; this call.
+;; It returns the return code of this call followed by the buffer that was written to the
+;; output buffer of the call. This is the encoded `DispatchError` in case of a failure.
+;; It also forwards its input to the callee.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal1" "seal_call" (func $seal_call (param i32 i32 i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 8) 100 balance
+	(data (i32.const 0) "\64\00\00\00\00\00\00\00")
+
+	;; [8, 12) size of the input data
+	(data (i32.const 8) "\24")
+
+	;; [12, 48) here we store the input data
+	;; 32 byte dest + 4 byte forward
+
+	;; [48, 52) size of the output buffer
+	(data (i32.const 48) "\20")
+
+	;; [52, 56) here we store the return code of the call
+
+	;; [56, 88) output buffer of the call
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 12) (i32.const 8))
+		(i32.store
+			(i32.const 52)
+			(call $seal_call
+				(i32.const 12) ;; Pointer to "callee" address.
+				(i32.const 32) ;; Length of "callee" address.
+				(i64.const 0) ;; How much gas to devote for the execution. 0 = all.
+				(i32.const 0) ;; Pointer to the buffer with value to transfer
+				(i32.const 8) ;; Length of the buffer with value to transfer.
+				(i32.const 44) ;; Pointer to input data buffer address
+				(i32.const 4) ;; Length of input data buffer
+				(i32.const 56) ;; Pointer to the output buffer
+				(i32.const 48) ;; Ptr to output buffer len
+			)
+		)
+		;; exit with success and take the return code and the output buffer to the output buffer
+		(call $seal_return
+			(i32.const 0)
+			(i32.const 52)
+			(i32.add (i32.const 4) (i32.load (i32.const 48)))
+		)
+	)
+)
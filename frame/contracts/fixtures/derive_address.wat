@@ -0,0 +1,89 @@
+;; Derives the address of a contract with `seal_derive_address` and then instantiates it with
+;; the same code hash and salt. Traps if the instantiation fails or gives the new contract
+;; another address. Returns the derived address.
+;;
+;; The input is the endowment as u64 followed by the 32 byte code hash and the salt.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_derive_address" (func $seal_derive_address
+		(param i32 i32 i32 i32 i32 i32)
+	))
+	(import "seal0" "seal_instantiate" (func $seal_instantiate
+		(param i32 i32 i64 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+	))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 4) size of the input buffer
+	(data (i32.const 0) "\80")
+
+	;; [4, 132) input buffer
+
+	;; [132, 136) size of the derived address buffer
+	(data (i32.const 132) "\20")
+
+	;; [136, 168) derived address
+
+	;; [168, 172) size of the instantiated address buffer
+	(data (i32.const 168) "\20")
+
+	;; [172, 204) instantiated address
+
+	(func $assert (param i32)
+		(block $ok
+			(br_if $ok
+				(get_local 0)
+			)
+			(unreachable)
+		)
+	)
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(local $salt_len i32)
+		(call $seal_input (i32.const 4) (i32.const 0))
+		(set_local $salt_len
+			(i32.sub (i32.load (i32.const 0)) (i32.const 40))
+		)
+
+		(call $seal_derive_address
+			(i32.const 12) ;; Pointer to the code hash.
+			(i32.const 32) ;; Length of the code hash.
+			(i32.const 44) ;; Pointer to the salt.
+			(get_local $salt_len) ;; Length of the salt.
+			(i32.const 136) ;; Pointer to the output buffer.
+			(i32.const 132) ;; Pointer to the size of the output buffer.
+		)
+
+		(call $assert
+			(i32.eqz
+				(call $seal_instantiate
+					(i32.const 12) ;; Pointer to the code hash.
+					(i32.const 32) ;; Length of the code hash.
+					(i64.const 0) ;; How much gas to devote for the execution. 0 = all.
+					(i32.const 4) ;; Pointer to the buffer with value to transfer
+					(i32.const 8) ;; Length of the buffer with value to transfer.
+					(i32.const 0) ;; Pointer to input data buffer address
+					(i32.const 0) ;; Length of input data buffer
+					(i32.const 172) ;; Pointer to the address output buffer
+					(i32.const 168) ;; Pointer to the size of the address output buffer
+					(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy output
+					(i32.const 0) ;; Length is ignored in this case
+					(i32.const 44) ;; Pointer to the salt.
+					(get_local $salt_len) ;; Length of the salt.
+				)
+			)
+		)
+
+		;; Both addresses are 32 bytes long.
+		(call $assert (i32.eq (i32.load (i32.const 132)) (i32.const 32)))
+		(call $assert (i32.eq (i32.load (i32.const 168)) (i32.const 32)))
+		(call $assert (i64.eq (i64.load (i32.const 136)) (i64.load (i32.const 172))))
+		(call $assert (i64.eq (i64.load (i32.const 144)) (i64.load (i32.const 180))))
+		(call $assert (i64.eq (i64.load (i32.const 152)) (i64.load (i32.const 188))))
+		(call $assert (i64.eq (i64.load (i32.const 160)) (i64.load (i32.const 196))))
+
+		(call $seal_return (i32.const 0) (i32.const 136) (i32.const 32))
+	)
+)
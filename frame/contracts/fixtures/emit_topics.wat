@@ -0,0 +1,27 @@
+;; Deposits an event with the topics passed as input. The input is the SCALE encoded
+;; `Vec<H256>` of topics. The data of the event is always "\ca\fe".
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_deposit_event" (func $seal_deposit_event (param i32 i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 4) size of the input buffer
+	(data (i32.const 0) "\00\01")
+
+	;; [4, 6) data of the event
+	(data (i32.const 4) "\ca\fe")
+
+	;; [6, 262) input buffer
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 6) (i32.const 0))
+		(call $seal_deposit_event
+			(i32.const 6) ;; Pointer to the topics
+			(i32.load (i32.const 0)) ;; Length of the topics
+			(i32.const 4) ;; Pointer to the data
+			(i32.const 2) ;; Length of the data
+		)
+	)
+)
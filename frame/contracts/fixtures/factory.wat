@@ -0,0 +1,64 @@
+;; Instantiates the contract whose code hash is passed as input three times.
+;; The first two instantiations succeed. The third one is reverted by the child.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_instantiate" (func $seal_instantiate
+		(param i32 i32 i64 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+	))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 4) size of the input buffer
+	(data (i32.const 0) "\28")
+
+	;; [4, 44) input buffer
+	;; 32 byte code hash followed by the 8 byte endowment of each child
+
+	;; [44, 48) input for the child to return with success
+	(data (i32.const 44) "\00\00\00\00")
+
+	;; [48, 52) input for the child to revert
+	(data (i32.const 48) "\01\00\00\00")
+
+	;; [52, 55) one byte salt for each child
+	(data (i32.const 52) "\01\02\03")
+
+	(func $assert (param i32)
+		(block $ok
+			(br_if $ok
+				(get_local 0)
+			)
+			(unreachable)
+		)
+	)
+
+	(func $instantiate (param $input_ptr i32) (param $salt_ptr i32) (result i32)
+		(call $seal_instantiate
+			(i32.const 4) ;; Pointer to the code hash.
+			(i32.const 32) ;; Length of the code hash.
+			(i64.const 0) ;; How much gas to devote for the execution. 0 = all.
+			(i32.const 36) ;; Pointer to the buffer with value to transfer
+			(i32.const 8) ;; Length of the buffer with value to transfer.
+			(get_local $input_ptr) ;; Pointer to input data buffer address
+			(i32.const 4) ;; Length of input data buffer
+			(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy address
+			(i32.const 0) ;; Length is ignored in this case
+			(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy output
+			(i32.const 0) ;; Length is ignored in this case
+			(get_local $salt_ptr) ;; salt_ptr
+			(i32.const 1) ;; salt_len
+		)
+	)
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 4) (i32.const 0))
+
+		;; ReturnCode::Success
+		(call $assert (i32.eqz (call $instantiate (i32.const 44) (i32.const 52))))
+		(call $assert (i32.eqz (call $instantiate (i32.const 44) (i32.const 53))))
+
+		;; ReturnCode::CalleeReverted
+		(call $assert (i32.eq (call $instantiate (i32.const 48) (i32.const 54)) (i32.const 2)))
+	)
+)
@@ -0,0 +1,12 @@
+;; Imports the second version of `seal_input` from the module of its first version.
+;; Uploading this code must fail.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 4) (i32.const 0) (i32.const 0))
+	)
+)
@@ -0,0 +1,37 @@
+;; Reassembles the input in chunks of 4 KiB and returns it.
+(module
+	(import "seal1" "seal_input" (func $seal_input (param i32 i32 i32)))
+	(import "seal0" "seal_input_len" (func $seal_input_len (result i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 2 2))
+
+	;; [0, 4) size of the chunk buffer
+
+	;; [16, 128 KiB) the reassembled input
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(local $offset i32)
+		(local $len i32)
+		(set_local $len (call $seal_input_len))
+
+		(block $done
+			(loop $next
+				(br_if $done (i32.ge_u (get_local $offset) (get_local $len)))
+
+				;; Read at most 4 KiB right behind the previous chunk.
+				(i32.store (i32.const 0) (i32.const 4096))
+				(call $seal_input
+					(i32.add (i32.const 16) (get_local $offset))	;; Pointer to the output buffer
+					(i32.const 0)	;; Pointer to the size of the output buffer
+					(get_local $offset)	;; Offset into the input
+				)
+				(set_local $offset (i32.add (get_local $offset) (i32.load (i32.const 0))))
+				(br $next)
+			)
+		)
+
+		(call $seal_return (i32.const 0) (i32.const 16) (get_local $len))
+	)
+)
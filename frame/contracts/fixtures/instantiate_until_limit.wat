@@ -0,0 +1,59 @@
+;; Instantiates the contract whose code hash is passed as input until an instantiation fails.
+;; Returns the number of successful instantiations followed by the return code of the
+;; failed one, both as u32.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_instantiate" (func $seal_instantiate
+		(param i32 i32 i64 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+	))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 4) size of the input buffer
+	(data (i32.const 0) "\28")
+
+	;; [4, 44) input buffer
+	;; 32 byte code hash followed by the 8 byte endowment of each child
+
+	;; [44, 48) input for the child to return with success
+	(data (i32.const 44) "\00\00\00\00")
+
+	;; [48, 52) number of successful instantiations which is also used as salt
+
+	;; [52, 56) return code of the failed instantiation
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(local $return_code i32)
+		(call $seal_input (i32.const 4) (i32.const 0))
+
+		(block $done
+			(loop $next
+				(set_local $return_code
+					(call $seal_instantiate
+						(i32.const 4) ;; Pointer to the code hash.
+						(i32.const 32) ;; Length of the code hash.
+						(i64.const 0) ;; How much gas to devote for the execution. 0 = all.
+						(i32.const 36) ;; Pointer to the buffer with value to transfer
+						(i32.const 8) ;; Length of the buffer with value to transfer.
+						(i32.const 44) ;; Pointer to input data buffer address
+						(i32.const 4) ;; Length of input data buffer
+						(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy address
+						(i32.const 0) ;; Length is ignored in this case
+						(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy output
+						(i32.const 0) ;; Length is ignored in this case
+						(i32.const 48) ;; salt_ptr
+						(i32.const 4) ;; salt_len
+					)
+				)
+				(br_if $done (get_local $return_code))
+				(i32.store (i32.const 48) (i32.add (i32.load (i32.const 48)) (i32.const 1)))
+				(br $next)
+			)
+		)
+
+		(i32.store (i32.const 52) (get_local $return_code))
+		(call $seal_return (i32.const 0) (i32.const 48) (i32.const 8))
+	)
+)
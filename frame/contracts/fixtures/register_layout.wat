@@ -0,0 +1,21 @@
+;; Registers the input as the storage layout of the contract.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_register_layout" (func $seal_register_layout (param i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 4) length of the input buffer
+	(data (i32.const 0) "\00\01")
+
+	;; [4, 260) buffer where input is copied
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 4) (i32.const 0))
+		(call $seal_register_layout
+			(i32.const 4)				;; Pointer to the layout
+			(i32.load (i32.const 0))	;; Length of the layout
+		)
+	)
+)
@@ -0,0 +1,50 @@
+;; Reserves, unreserves or terminates depending on the first byte of the input:
+;; 0 reserves and 1 unreserves the amount that follows as u64 and returns the return code.
+;; 2 terminates the contract with the caller as beneficiary.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_caller" (func $seal_caller (param i32 i32)))
+	(import "seal0" "seal_reserve" (func $seal_reserve (param i32 i32) (result i32)))
+	(import "seal0" "seal_unreserve" (func $seal_unreserve (param i32 i32) (result i32)))
+	(import "seal0" "seal_terminate" (func $seal_terminate (param i32 i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 4) size of the input buffer
+	(data (i32.const 0) "\09")
+
+	;; [4, 13) input buffer
+	;; 1 byte operation followed by the 8 byte amount
+
+	;; [16, 20) return code
+
+	;; [20, 24) size of the caller buffer
+	(data (i32.const 20) "\20")
+
+	;; [24, 56) caller buffer
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(local $op i32)
+		(call $seal_input (i32.const 4) (i32.const 0))
+		(set_local $op (i32.load8_u (i32.const 4)))
+
+		(if (i32.eq (get_local $op) (i32.const 2))
+			(then
+				(call $seal_caller (i32.const 24) (i32.const 20))
+				(call $seal_terminate (i32.const 24) (i32.load (i32.const 20)))
+			)
+		)
+
+		(if (i32.eqz (get_local $op))
+			(then
+				(i32.store (i32.const 16) (call $seal_reserve (i32.const 5) (i32.const 8)))
+			)
+			(else
+				(i32.store (i32.const 16) (call $seal_unreserve (i32.const 5) (i32.const 8)))
+			)
+		)
+		(call $seal_return (i32.const 0) (i32.const 16) (i32.const 4))
+	)
+)
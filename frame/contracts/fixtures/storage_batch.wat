@@ -0,0 +1,48 @@
+;; Performs several storage writes within one call.
+;; The writes are reverted if the first byte of the input is non-zero.
+(module
+	(import "seal0" "seal_set_storage" (func $seal_set_storage (param i32 i32 i32)))
+	(import "seal0" "seal_clear_storage" (func $seal_clear_storage (param i32)))
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 32) first key
+	(data (i32.const 0) "\01")
+
+	;; [32, 64) second key
+	(data (i32.const 32) "\02")
+
+	;; [64, 96) third key, which is never set
+	(data (i32.const 64) "\03")
+
+	;; [96, 100) value to store
+	(data (i32.const 96) "\01\02\03\04")
+
+	;; [100, 104) size of the input buffer
+	(data (i32.const 100) "\04")
+
+	;; [104, 108) input buffer
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 104) (i32.const 100))
+
+		;; Insert two new pairs of 4 and 2 bytes.
+		(call $seal_set_storage (i32.const 0) (i32.const 96) (i32.const 4))
+		(call $seal_set_storage (i32.const 32) (i32.const 96) (i32.const 2))
+
+		;; Shrink the first pair to 1 byte.
+		(call $seal_set_storage (i32.const 0) (i32.const 96) (i32.const 1))
+
+		;; Clearing an absent key doesn't change the storage.
+		(call $seal_clear_storage (i32.const 64))
+
+		(if (i32.load8_u (i32.const 104))
+			(then
+				(call $seal_return (i32.const 1) (i32.const 0) (i32.const 0))
+			)
+		)
+	)
+)
@@ -0,0 +1,83 @@
+;; Writes two items of 4 and 2 bytes and then clears the first one.
+;;
+;; The input is a byte followed by an optional 32 byte address. If the address is present
+;; the contract at it is called twice: once with a non-zero byte and once with a zero byte.
+;; The writes are reverted if the byte is non-zero.
+(module
+	(import "seal0" "seal_set_storage" (func $seal_set_storage (param i32 i32 i32)))
+	(import "seal0" "seal_clear_storage" (func $seal_clear_storage (param i32)))
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_call" (func $seal_call (param i32 i32 i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 32) first key
+	(data (i32.const 0) "\01")
+
+	;; [32, 64) second key
+	(data (i32.const 32) "\02")
+
+	;; [64, 68) value to store
+	(data (i32.const 64) "\01\02\03\04")
+
+	;; [68, 76) value to transfer
+	(data (i32.const 68) "\00\00\00\00\00\00\00\00")
+
+	;; [76, 77) input that makes the callee revert
+	(data (i32.const 76) "\01")
+
+	;; [77, 78) input that makes the callee commit
+	(data (i32.const 77) "\00")
+
+	;; [80, 84) size of the input buffer
+	(data (i32.const 80) "\21")
+
+	;; [84, 117) input buffer
+
+	(func $assert (param i32)
+		(block $ok
+			(br_if $ok
+				(get_local 0)
+			)
+			(unreachable)
+		)
+	)
+
+	(func $call_callee (param $input i32) (result i32)
+		(call $seal_call
+			(i32.const 85) ;; Pointer to "callee" address.
+			(i32.const 32) ;; Length of "callee" address.
+			(i64.const 0) ;; How much gas to devote for the execution. 0 = all.
+			(i32.const 68) ;; Pointer to the buffer with value to transfer
+			(i32.const 8) ;; Length of the buffer with value to transfer.
+			(get_local $input) ;; Pointer to input data buffer address
+			(i32.const 1) ;; Length of input data buffer
+			(i32.const 0xffffffff) ;; u32 max sentinel value: do not copy output
+			(i32.const 0) ;; Ptr to output buffer len
+		)
+	)
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 84) (i32.const 80))
+
+		(call $seal_set_storage (i32.const 0) (i32.const 64) (i32.const 4))
+		(call $seal_set_storage (i32.const 32) (i32.const 64) (i32.const 2))
+		(call $seal_clear_storage (i32.const 0))
+
+		(if (i32.eq (i32.load (i32.const 80)) (i32.const 33))
+			(then
+				;; ReturnCode::CalleeReverted
+				(call $assert (i32.eq (call $call_callee (i32.const 76)) (i32.const 2)))
+				(call $assert (i32.eqz (call $call_callee (i32.const 77))))
+			)
+		)
+
+		(if (i32.load8_u (i32.const 84))
+			(then
+				(call $seal_return (i32.const 1) (i32.const 0) (i32.const 0))
+			)
+		)
+	)
+)
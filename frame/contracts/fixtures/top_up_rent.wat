@@ -0,0 +1,43 @@
+;; Tops up the rent allowance by the amount passed as input and returns the return code.
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal0" "seal_top_up_rent_allowance" (func $seal_top_up_rent_allowance (param i32 i32) (result i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 8) amount to top up the allowance by
+
+	;; [8, 12) size of the input buffer
+	(data (i32.const 8) "\08")
+
+	;; [12, 16) return code of the top up
+
+	(func $assert (param i32)
+		(block $ok
+			(br_if $ok
+				(get_local 0)
+			)
+			(unreachable)
+		)
+	)
+
+	(func (export "deploy"))
+
+	(func (export "call")
+		(call $seal_input (i32.const 0) (i32.const 8))
+
+		;; Input must be a u64.
+		(call $assert
+			(i32.eq
+				(i32.load (i32.const 8))
+				(i32.const 8)
+			)
+		)
+
+		(i32.store
+			(i32.const 12)
+			(call $seal_top_up_rent_allowance (i32.const 0) (i32.const 8))
+		)
+		(call $seal_return (i32.const 0) (i32.const 12) (i32.const 4))
+	)
+)
@@ -25,14 +25,19 @@
 
 use codec::Codec;
 use sp_std::vec::Vec;
-use pallet_contracts_primitives::{ContractExecResult, GetStorageResult, RentProjectionResult};
+use pallet_contracts_primitives::{
+	ContractExecResult, GetStorageResult, RentProjectionResult, ContractSnapshotResult,
+	TombstoneInfo, TombstoneListing, ContractBatchExecResult, ContractEvent,
+};
 
 sp_api::decl_runtime_apis! {
 	/// The API to interact with contracts without using executive.
-	pub trait ContractsApi<AccountId, Balance, BlockNumber> where
+	#[api_version(2)]
+	pub trait ContractsApi<AccountId, Balance, BlockNumber, Hash> where
 		AccountId: Codec,
 		Balance: Codec,
 		BlockNumber: Codec,
+		Hash: Codec,
 	{
 		/// Perform a call from a specified account to a given contract.
 		///
@@ -45,6 +50,26 @@ sp_api::decl_runtime_apis! {
 			input_data: Vec<u8>,
 		) -> ContractExecResult;
 
+		/// Perform a call like `call` and record the gas consumed per category of work.
+		///
+		/// Only meant for dry-runs.
+		fn call_profiled(
+			origin: AccountId,
+			dest: AccountId,
+			value: Balance,
+			gas_limit: u64,
+			input_data: Vec<u8>,
+		) -> ContractExecResult;
+
+		/// Perform several calls from a specified account as one atomic unit.
+		///
+		/// See the contracts' `call_batch` dispatchable function for more details.
+		fn call_batch(
+			origin: AccountId,
+			calls: Vec<(AccountId, Balance, Vec<u8>)>,
+			gas_limit: u64,
+		) -> ContractBatchExecResult;
+
 		/// Query a given storage key in a given contract.
 		///
 		/// Returns `Ok(Some(Vec<u8>))` if the storage value exists under the given key in the
@@ -63,5 +88,48 @@ sp_api::decl_runtime_apis! {
 		///
 		/// Returns `Err` if the contract is in a tombstone state or doesn't exist.
 		fn rent_projection(address: AccountId) -> RentProjectionResult<BlockNumber>;
+
+		/// Returns the code hash and storage size recorded in the tombstone at the given address.
+		///
+		/// Returns `None` if there is no tombstone at the given address.
+		fn tombstone_info(address: AccountId) -> Option<TombstoneInfo<Hash>>;
+
+		/// Returns up to `count` tombstones starting after the tombstone at `start`.
+		///
+		/// Passing `None` starts at the first tombstone. The address of the last returned
+		/// tombstone is passed as `start` in order to fetch the next page.
+		fn list_tombstones(
+			start: Option<AccountId>,
+			count: u32,
+		) -> Vec<TombstoneListing<AccountId, Hash, Balance, BlockNumber>>;
+
+		/// Returns a chunk of the code and storage of the given contract.
+		///
+		/// Up to `limit` pairs are returned, starting at the hashed `start_key` or at the first
+		/// key if `None` is passed. Only the first chunk (`start_key` is `None`) contains the
+		/// code and the info of the contract.
+		///
+		/// This is meant to be used by RPC only because its cost is not bounded by anything
+		/// but the size of the contract's code and storage.
+		///
+		/// Returns `Err` if the contract is in a tombstone state or doesn't exist.
+		fn contract_snapshot(
+			address: AccountId,
+			start_key: Option<Vec<u8>>,
+			limit: u32,
+		) -> ContractSnapshotResult<Hash, Balance, BlockNumber>;
+	}
+
+	/// The API to query the events deposited by contracts.
+	pub trait ContractEventsApi<AccountId, Hash> where
+		AccountId: Codec,
+		Hash: Codec,
+	{
+		/// Returns the events deposited by the given contract in the current block.
+		///
+		/// Only events that were deposited with all of the given `topics` are returned.
+		/// The block isn't initialized before the call because that would clear its events.
+		#[skip_initialize_block]
+		fn contract_events(address: AccountId, topics: Vec<Hash>) -> Vec<ContractEvent<Hash>>;
 	}
 }
@@ -0,0 +1,282 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A subscription to the events deposited by a contract.
+
+use std::{marker::PhantomData, sync::Arc};
+
+use codec::Codec;
+use futures::{StreamExt as _, TryStreamExt as _};
+use jsonrpc_core::{
+	Error, ErrorCode,
+	futures::{future::{Executor as Executor01, Future as Future01}, Sink},
+};
+use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{typed::Subscriber, SubscriptionId, manager::SubscriptionManager};
+use log::warn;
+use pallet_contracts_rpc_runtime_api::ContractEventsApi as ContractEventsRuntimeApi;
+use sc_client_api::BlockchainEvents;
+use serde::{Deserialize, Serialize};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::{Bytes, hashing::twox_128, storage::StorageKey};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, NumberFor, One, Saturating},
+};
+
+/// The maximum number of past blocks whose events are replayed to a new subscriber.
+///
+/// Every replayed block costs a runtime call. Subscribers that need to catch up further
+/// are expected to query the blocks themselves.
+const MAX_REPLAYED_BLOCKS: u32 = 64;
+
+/// An event deposited by a contract as it is pushed to subscribers.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcContractEvent<BlockHash, Hash> {
+	/// The block in which the event was deposited.
+	block: BlockHash,
+	/// The topics the event was deposited with.
+	topics: Vec<Hash>,
+	/// The data as passed by the contract.
+	data: Bytes,
+}
+
+/// Contracts RPC subscriptions.
+#[rpc]
+pub trait ContractsEventsApi<BlockHash, BlockNumber, AccountId, Hash> {
+	/// RPC Metadata
+	type Metadata;
+
+	/// Subscribe to the events deposited by the contract at `address`.
+	///
+	/// Only events that were deposited with all of the given `topics` are pushed. If
+	/// `from_block` is passed the events of all blocks from `from_block` up to the current
+	/// best block are pushed first. This allows clients to catch up on the blocks that they
+	/// missed while they were disconnected. At most the last 64 blocks can be replayed.
+	#[pubsub(
+		subscription = "contracts_events",
+		subscribe,
+		name = "contracts_subscribeEvents"
+	)]
+	fn subscribe_events(
+		&self,
+		metadata: Self::Metadata,
+		subscriber: Subscriber<RpcContractEvent<BlockHash, Hash>>,
+		address: AccountId,
+		topics: Vec<Hash>,
+		from_block: Option<BlockNumber>,
+	);
+
+	/// Unsubscribe from the events of a contract.
+	#[pubsub(
+		subscription = "contracts_events",
+		unsubscribe,
+		name = "contracts_unsubscribeEvents"
+	)]
+	fn unsubscribe_events(
+		&self,
+		metadata: Option<Self::Metadata>,
+		id: SubscriptionId,
+	) -> jsonrpc_core::Result<bool>;
+}
+
+/// An implementation of the contract event subscriptions.
+pub struct ContractEvents<C, B> {
+	client: Arc<C>,
+	manager: SubscriptionManager,
+	_marker: PhantomData<B>,
+}
+
+impl<C, B> ContractEvents<C, B> {
+	/// Create new `ContractEvents` with the given reference to the client and an executor
+	/// that drives the subscriptions.
+	pub fn new<E>(client: Arc<C>, executor: E) -> Self
+	where
+		E: Executor01<Box<dyn Future01<Item = (), Error = ()> + Send>> + Send + Sync + 'static,
+	{
+		ContractEvents {
+			client,
+			manager: SubscriptionManager::new(Arc::new(executor)),
+			_marker: Default::default(),
+		}
+	}
+}
+
+impl<C, Block, AccountId, Hash>
+	ContractsEventsApi<<Block as BlockT>::Hash, NumberFor<Block>, AccountId, Hash>
+	for ContractEvents<C, Block>
+where
+	Block: BlockT,
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + BlockchainEvents<Block>,
+	C: Send + Sync + 'static,
+	C::Api: ContractEventsRuntimeApi<Block, AccountId, Hash>,
+	AccountId: Codec + Clone + Send + 'static,
+	Hash: Codec + Clone + Send + 'static,
+{
+	type Metadata = sc_rpc_api::Metadata;
+
+	fn subscribe_events(
+		&self,
+		_metadata: Self::Metadata,
+		subscriber: Subscriber<RpcContractEvent<Block::Hash, Hash>>,
+		address: AccountId,
+		topics: Vec<Hash>,
+		from_block: Option<NumberFor<Block>>,
+	) {
+		// The events are stored under a single key which is changed by every block that
+		// deposits events.
+		let events_key = StorageKey([twox_128(b"System"), twox_128(b"Events")].concat());
+		let stream = match self.client.storage_changes_notification_stream(
+			Some(&[events_key]),
+			None,
+		) {
+			Ok(stream) => stream,
+			Err(err) => {
+				let _ = subscriber.reject(Error {
+					code: ErrorCode::InternalError,
+					message: format!("Failed to subscribe to the events: {:?}", err),
+					data: None,
+				});
+				return;
+			},
+		};
+
+		// The stream is created first so that no block is missed between replaying and
+		// subscribing. Blocks that were already replayed are skipped instead.
+		let best_number = self.client.info().best_number;
+		if let Some(from_block) = from_block {
+			if best_number.saturating_sub(from_block) >= MAX_REPLAYED_BLOCKS.into() {
+				let _ = subscriber.reject(Error {
+					code: ErrorCode::InvalidParams,
+					message: format!(
+						"Can't replay the events of more than {} blocks",
+						MAX_REPLAYED_BLOCKS,
+					),
+					data: None,
+				});
+				return;
+			}
+		}
+		let replayed_until = from_block.map(|_| best_number);
+
+		// The past blocks are only queried once the subscription is driven by the executor.
+		let numbers = std::iter::successors(from_block, |number| Some(*number + One::one()))
+			.take_while(move |number| *number <= best_number);
+		let replay_client = self.client.clone();
+		let (replay_address, replay_topics) = (address.clone(), topics.clone());
+		let replay = futures::stream::iter(numbers).map(move |number| {
+			match replay_client.hash(number) {
+				Ok(Some(block)) => {
+					events_at(&*replay_client, block, &replay_address, &replay_topics)
+				},
+				_ => Vec::new(),
+			}
+		});
+
+		let client = self.client.clone();
+		self.manager.add(subscriber, move |sink| {
+			let stream = stream
+				.map(move |(block, _)| {
+					let is_replayed = replayed_until.map_or(false, |until| {
+						matches!(client.number(block), Ok(Some(number)) if number <= until)
+					});
+					if is_replayed {
+						Vec::new()
+					} else {
+						events_at(&*client, block, &address, &topics)
+					}
+				});
+			let events = replay
+				.chain(stream)
+				.map(|events| {
+					futures::stream::iter(events.into_iter().map(|event| Ok::<_, ()>(Ok(event))))
+				})
+				.flatten()
+				.compat();
+
+			sink
+				.sink_map_err(|e| warn!("Error sending notifications: {:?}", e))
+				.send_all(events)
+				// we ignore the resulting Stream (if the first stream is over we are unsubscribed)
+				.map(|_| ())
+		});
+	}
+
+	fn unsubscribe_events(
+		&self,
+		_metadata: Option<Self::Metadata>,
+		id: SubscriptionId,
+	) -> jsonrpc_core::Result<bool> {
+		Ok(self.manager.cancel(id))
+	}
+}
+
+/// The events deposited by the contract at `address` with all of the given `topics`
+/// in `block`.
+///
+/// Failing to query the runtime is logged and treated as if there were no such events
+/// because there is no way to report it to the subscriber.
+fn events_at<C, Block, AccountId, Hash>(
+	client: &C,
+	block: Block::Hash,
+	address: &AccountId,
+	topics: &[Hash],
+) -> Vec<RpcContractEvent<Block::Hash, Hash>>
+where
+	Block: BlockT,
+	C: ProvideRuntimeApi<Block>,
+	C::Api: ContractEventsRuntimeApi<Block, AccountId, Hash>,
+	AccountId: Codec + Clone,
+	Hash: Codec + Clone,
+{
+	let at = BlockId::hash(block);
+	match client.runtime_api().contract_events(&at, address.clone(), topics.to_vec()) {
+		Ok(events) => events
+			.into_iter()
+			.map(|event| RpcContractEvent {
+				block,
+				topics: event.topics,
+				data: event.data.into(),
+			})
+			.collect(),
+		Err(err) => {
+			warn!("Failed to query the contract events of block {:?}: {:?}", block, err);
+			Vec::new()
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_core::H256;
+
+	#[test]
+	fn event_should_serialize_deserialize_properly() {
+		let expected = format!(
+			r#"{{"block":"{:?}","topics":["{:?}"],"data":"0xcafe"}}"#,
+			H256::repeat_byte(1),
+			H256::repeat_byte(2),
+		);
+		let event: RpcContractEvent<H256, H256> = serde_json::from_str(&expected).unwrap();
+		assert_eq!(event.topics, vec![H256::repeat_byte(2)]);
+		assert_eq!(serde_json::to_string(&event).unwrap(), expected);
+	}
+}
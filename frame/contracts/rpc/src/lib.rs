@@ -17,12 +17,12 @@
 
 //! Node-specific RPC methods for interaction with contracts.
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use codec::Codec;
 use jsonrpc_core::{Error, ErrorCode, Result};
 use jsonrpc_derive::rpc;
-use pallet_contracts_primitives::RentProjection;
+use pallet_contracts_primitives::{RentProjection, SnapshotChunk};
 use serde::{Deserialize, Serialize};
 use sp_api::ProvideRuntimeApi;
 use sp_blockchain::HeaderBackend;
@@ -34,9 +34,14 @@ use sp_runtime::{
 	DispatchError,
 };
 use std::convert::TryInto;
-use pallet_contracts_primitives::ContractExecResult;
+use pallet_contracts_primitives::{ContractExecResult, GasCategory};
 
-pub use pallet_contracts_rpc_runtime_api::ContractsApi as ContractsRuntimeApi;
+pub use pallet_contracts_rpc_runtime_api::{
+	ContractsApi as ContractsRuntimeApi, ContractEventsApi as ContractEventsRuntimeApi,
+};
+pub use events::{ContractEvents, ContractsEventsApi, RpcContractEvent};
+
+mod events;
 
 const RUNTIME_ERROR: i64 = 1;
 const CONTRACT_DOESNT_EXIST: i64 = 2;
@@ -52,6 +57,10 @@ const CONTRACT_IS_A_TOMBSTONE: i64 = 3;
 /// https://github.com/paritytech/substrate/pull/5446
 const GAS_PER_SECOND: u64 = 1_000_000_000_000;
 
+/// The number of storage pairs that are requested from the runtime per call when
+/// assembling a contract snapshot.
+const SNAPSHOT_PAGE_SIZE: u32 = 1_000;
+
 /// A private newtype for converting `ContractAccessError` into an RPC error.
 struct ContractAccessError(pallet_contracts_primitives::ContractAccessError);
 impl From<ContractAccessError> for Error {
@@ -82,6 +91,9 @@ pub struct CallRequest<AccountId, Balance> {
 	value: Balance,
 	gas_limit: number::NumberOrHex,
 	input_data: Bytes,
+	/// Record the gas consumed per category of work and return it as `profile`.
+	#[serde(default)]
+	profile: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -107,10 +119,53 @@ pub struct RpcContractExecResult {
 	debug_message: String,
 	/// Indicates whether the contract execution was successful or not.
 	result: std::result::Result<RpcContractExecSuccess, DispatchError>,
+	/// The gas consumed per category of work. Only present if it was requested.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	profile: Option<BTreeMap<String, u64>>,
+	/// The gas consumed by each call frame excluding the frames it called. Only present
+	/// together with the `profile`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	gas_breakdown: Option<Vec<RpcFrameGas>>,
+	/// The version of the schedule the call was executed with.
+	schedule_version: u32,
+}
+
+/// The gas consumed by a single call frame.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+struct RpcFrameGas {
+	/// The SCALE encoded account id of the contract.
+	account: Bytes,
+	/// The SCALE encoded code hash that was executed.
+	code_hash: Bytes,
+	/// The gas consumed by the frame minus the gas consumed by the frames it called.
+	gas_consumed: u64,
+}
+
+/// The name under which the gas consumed by `category` is reported.
+fn category_name(category: GasCategory) -> String {
+	match category {
+		GasCategory::Instructions => "instructions",
+		GasCategory::Storage => "storage",
+		GasCategory::Calls => "calls",
+		GasCategory::Hashing => "hashing",
+		GasCategory::Other => "other",
+	}.into()
 }
 
 impl From<ContractExecResult> for RpcContractExecResult {
 	fn from(r: ContractExecResult) -> Self {
+		let profile = r.profile.map(|profile| {
+			profile.into_iter().map(|(category, gas)| (category_name(category), gas)).collect()
+		});
+		let gas_breakdown = r.gas_breakdown.map(|frames| {
+			frames.into_iter().map(|frame| RpcFrameGas {
+				account: frame.account.into(),
+				code_hash: frame.code_hash.into(),
+				gas_consumed: frame.gas_consumed,
+			}).collect()
+		});
 		match r.exec_result {
 			Ok(val) => RpcContractExecResult {
 				gas_consumed: r.gas_consumed,
@@ -119,19 +174,52 @@ impl From<ContractExecResult> for RpcContractExecResult {
 					flags: val.flags.bits(),
 					data: val.data.into(),
 				}),
+				profile,
+				gas_breakdown,
+				schedule_version: r.schedule_version,
 			},
 			Err(err) => RpcContractExecResult {
 				gas_consumed: r.gas_consumed,
 				debug_message: String::new(),
 				result: Err(err.error),
+				profile,
+				gas_breakdown,
+				schedule_version: r.schedule_version,
 			},
 		}
 	}
 }
 
+/// A complete snapshot of the code and storage of a contract.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcContractSnapshot<Hash, Balance, BlockNumber> {
+	/// The code hash of the contract.
+	code_hash: Hash,
+	/// The code as it was uploaded by `put_code`. `None` if it was removed.
+	pristine_code: Option<Bytes>,
+	/// The free balance of the contract.
+	free_balance: Balance,
+	/// The rent allowance of the contract.
+	rent_allowance: Balance,
+	/// The size of all values stored by the contract.
+	storage_size: u64,
+	/// The number of key value pairs stored by the contract.
+	total_pair_count: u32,
+	/// The block at which rent was last collected from the contract.
+	deduct_block: BlockNumber,
+	/// The block at which the storage of the contract was last written to.
+	last_write: Option<BlockNumber>,
+	/// The number of successful top-level calls made to the contract.
+	call_count: u64,
+	/// All key value pairs of the contract. The keys are hashed as they are stored in the trie.
+	storage: Vec<(Bytes, Bytes)>,
+}
+
 /// Contracts RPC methods.
 #[rpc]
-pub trait ContractsApi<BlockHash, BlockNumber, AccountId, Balance> {
+pub trait ContractsApi<BlockHash, BlockNumber, AccountId, Balance, Hash> {
 	/// Executes a call to a contract.
 	///
 	/// This call is performed locally without submitting any transactions. Thus executing this
@@ -167,6 +255,18 @@ pub trait ContractsApi<BlockHash, BlockNumber, AccountId, Balance> {
 		address: AccountId,
 		at: Option<BlockHash>,
 	) -> Result<Option<BlockNumber>>;
+
+	/// Returns the code, info and the complete storage of a contract at the given block.
+	///
+	/// The storage is fetched in pages from the runtime. All pages are read from the same
+	/// block so that the snapshot is consistent. This is expensive for contracts with
+	/// a lot of storage.
+	#[rpc(name = "contracts_snapshot")]
+	fn snapshot(
+		&self,
+		address: AccountId,
+		at: Option<BlockHash>,
+	) -> Result<RpcContractSnapshot<Hash, Balance, BlockNumber>>;
 }
 
 /// An implementation of contract specific RPC methods.
@@ -184,12 +284,13 @@ impl<C, B> Contracts<C, B> {
 		}
 	}
 }
-impl<C, Block, AccountId, Balance>
+impl<C, Block, AccountId, Balance, Hash>
 	ContractsApi<
 		<Block as BlockT>::Hash,
 		<<Block as BlockT>::Header as HeaderT>::Number,
 		AccountId,
 		Balance,
+		Hash,
 	> for Contracts<C, Block>
 where
 	Block: BlockT,
@@ -199,9 +300,11 @@ where
 		AccountId,
 		Balance,
 		<<Block as BlockT>::Header as HeaderT>::Number,
+		Hash,
 	>,
-	AccountId: Codec,
+	AccountId: Codec + Clone,
 	Balance: Codec,
+	Hash: Codec,
 {
 	fn call(
 		&self,
@@ -219,6 +322,7 @@ where
 			value,
 			gas_limit,
 			input_data,
+			profile,
 		} = call_request;
 
 		// Make sure that gas_limit fits into 64 bits.
@@ -240,9 +344,11 @@ where
 			});
 		}
 
-		let exec_result = api
-			.call(&at, origin, dest, value, gas_limit, input_data.to_vec())
-			.map_err(runtime_error_into_rpc_err)?;
+		let exec_result = if profile {
+			api.call_profiled(&at, origin, dest, value, gas_limit, input_data.to_vec())
+		} else {
+			api.call(&at, origin, dest, value, gas_limit, input_data.to_vec())
+		}.map_err(runtime_error_into_rpc_err)?;
 
 		Ok(exec_result.into())
 	}
@@ -287,6 +393,51 @@ where
 			RentProjection::EvictionAt(block_num) => Some(block_num),
 		})
 	}
+
+	fn snapshot(
+		&self,
+		address: AccountId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<RpcContractSnapshot<Hash, Balance, <<Block as BlockT>::Header as HeaderT>::Number>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+
+		let fetch = |start_key: Option<Vec<u8>>| -> Result<SnapshotChunk<_, _, _>> {
+			let chunk = api
+				.contract_snapshot(&at, address.clone(), start_key, SNAPSHOT_PAGE_SIZE)
+				.map_err(runtime_error_into_rpc_err)?
+				.map_err(ContractAccessError)?;
+			Ok(chunk)
+		};
+
+		let SnapshotChunk { header, pairs, mut next_key } = fetch(None)?;
+		let header = header.ok_or_else(|| runtime_error_into_rpc_err(
+			"The first chunk of a snapshot must contain a header"
+		))?;
+		let mut storage: Vec<(Bytes, Bytes)> = pairs.into_iter()
+			.map(|(key, value)| (key.into(), value.into()))
+			.collect();
+		while let Some(start_key) = next_key {
+			let chunk = fetch(Some(start_key))?;
+			storage.extend(chunk.pairs.into_iter().map(|(key, value)| (key.into(), value.into())));
+			next_key = chunk.next_key;
+		}
+
+		Ok(RpcContractSnapshot {
+			code_hash: header.code_hash,
+			pristine_code: header.pristine_code.map(Into::into),
+			free_balance: header.free_balance,
+			rent_allowance: header.rent_allowance,
+			storage_size: header.storage_size,
+			total_pair_count: header.total_pair_count,
+			deduct_block: header.deduct_block,
+			last_write: header.last_write,
+			call_count: header.call_count,
+			storage,
+		})
+	}
 }
 
 /// Converts a runtime trap into an RPC error.
@@ -325,7 +476,9 @@ mod tests {
 			let actual = serde_json::to_string(&res).unwrap();
 			assert_eq!(actual, expected);
 		}
-		test(r#"{"gasConsumed":5000,"debugMessage":"helpOk","result":{"Ok":{"flags":5,"data":"0x1234"}}}"#);
-		test(r#"{"gasConsumed":3400,"debugMessage":"helpErr","result":{"Err":"BadOrigin"}}"#);
+		test(r#"{"gasConsumed":5000,"debugMessage":"helpOk","result":{"Ok":{"flags":5,"data":"0x1234"}},"scheduleVersion":1}"#);
+		test(r#"{"gasConsumed":3400,"debugMessage":"helpErr","result":{"Err":"BadOrigin"},"scheduleVersion":1}"#);
+		test(r#"{"gasConsumed":3400,"debugMessage":"","result":{"Err":"BadOrigin"},"profile":{"storage":3000},"scheduleVersion":2}"#);
+		test(r#"{"gasConsumed":3400,"debugMessage":"","result":{"Err":"BadOrigin"},"profile":{"storage":3000},"gasBreakdown":[{"account":"0x01","codeHash":"0x02","gasConsumed":3400}],"scheduleVersion":2}"#);
 	}
 }
@@ -98,6 +98,7 @@ impl ImportedMemory {
 }
 
 pub struct ImportedFunction {
+	pub module: &'static str,
 	pub name: &'static str,
 	pub params: Vec<ValueType>,
 	pub return_type: Option<ValueType>,
@@ -166,7 +167,7 @@ where
 				.build_sig();
 			let sig = contract.push_signature(sig);
 			contract = contract.import()
-				.module("seal0")
+				.module(func.module)
 				.field(func.name)
 				.with_external(parity_wasm::elements::External::Function(sig))
 				.build();
@@ -276,6 +277,7 @@ where
 		ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: getter_name,
 				params: vec![ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -305,6 +307,7 @@ where
 		ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name,
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -158,7 +158,7 @@ where
 		};
 
 		let mut contract = result.alive_info()?;
-		contract.storage_size = storage_size.saturated_into::<u32>();
+		contract.storage_size = storage_size.saturated_into::<u64>();
 		ContractInfoOf::<T>::insert(&result.account_id, ContractInfo::Alive(contract));
 
 		Ok(result)
@@ -289,6 +289,17 @@ benchmarks! {
 		let origin = RawOrigin::Signed(caller);
 	}: _(origin, module.code)
 
+	// Uploading code that is already stored only requires to hash it.
+	// `n`: Size of the code in kilobytes.
+	put_code_existing {
+		let n in 0 .. Contracts::<T>::current_schedule().limits.code_size / 1024;
+		let caller = whitelisted_caller();
+		T::Currency::make_free_balance_be(&caller, caller_funding::<T>());
+		let module = WasmModule::<T>::sized(n * 1024);
+		Contracts::<T>::put_code_raw(module.code.clone())?;
+		let origin = RawOrigin::Signed(caller);
+	}: put_code(origin, module.code)
+
 	// Instantiate uses a dummy contract constructor to measure the overhead of the instantiate.
 	// The size of the input data influences the runtime because it is hashed in order to determine
 	// the contract address.
@@ -311,7 +322,9 @@ benchmarks! {
 		// endowment was removed from the caller
 		assert_eq!(T::Currency::free_balance(&caller), caller_funding::<T>() - endowment);
 		// contract has the full endowment because no rent collection happended
-		assert_eq!(T::Currency::free_balance(&addr), endowment);
+		assert_eq!(T::Currency::total_balance(&addr), endowment);
+		// the tombstone deposit is reserved out of the endowment
+		assert_eq!(T::Currency::reserved_balance(&addr), T::TombstoneDeposit::get());
 		// instantiate should leave a alive contract
 		Contract::<T>::address_alive_info(&addr)?;
 	}
@@ -346,6 +359,22 @@ benchmarks! {
 		instance.alive_info()?;
 	}
 
+	// The same dummy contract is called `n` times in order to measure the overhead of
+	// every call of the batch.
+	call_batch {
+		let n in 0 .. T::MaxBatchSize::get();
+		let instance = Contract::<T>::with_caller(
+			whitelisted_caller(), WasmModule::dummy(), vec![], Endow::Max
+		)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+		let calls = (0..n)
+			.map(|_| (instance.addr.clone(), 0u32.into(), vec![]))
+			.collect::<Vec<_>>();
+	}: _(origin, calls, Weight::max_value())
+	verify {
+		instance.alive_info()?;
+	}
+
 	// We benchmark the costs for sucessfully evicting an empty contract.
 	// The actual costs are depending on how many storage items the evicted contract
 	// does have. However, those costs are not to be payed by the sender but
@@ -378,6 +407,68 @@ benchmarks! {
 		);
 	}
 
+	// Removes a contract together with its storage which is what happens when a contract
+	// terminates. The balance transfer is not included.
+	// `k`: Number of storage items of the contract.
+	destroy_contract {
+		let k in 0 .. 1024;
+		let instance = Contract::<T>::new(WasmModule::dummy(), vec![], Endow::Max)?;
+		instance.store(&create_storage::<T>(k, T::MaxValueSize::get())?)?;
+		let trie_id = instance.alive_info()?.trie_id;
+	}: {
+		Storage::<T>::destroy_contract(&instance.account_id, &trie_id);
+	}
+	verify {
+		assert!(ContractInfoOf::<T>::get(&instance.account_id).is_none());
+	}
+
+	pause_contract {
+		let instance = Contract::<T>::new(WasmModule::dummy(), vec![], Endow::Max)?;
+		let account_id = instance.account_id.clone();
+	}: _(RawOrigin::Root, account_id)
+	verify {
+		assert!(<PausedContracts<T>>::contains_key(&instance.account_id));
+	}
+
+	unpause_contract {
+		let instance = Contract::<T>::new(WasmModule::dummy(), vec![], Endow::Max)?;
+		let account_id = instance.account_id.clone();
+		<PausedContracts<T>>::insert(&account_id, ());
+	}: _(RawOrigin::Root, account_id)
+	verify {
+		assert!(!<PausedContracts<T>>::contains_key(&instance.account_id));
+	}
+
+	set_frozen {
+		let instance = Contract::<T>::new(WasmModule::dummy(), vec![], Endow::Max)?;
+		let account_id = instance.account_id.clone();
+	}: _(RawOrigin::Root, account_id, true)
+	verify {
+		assert!(Storage::<T>::is_frozen(&instance.account_id));
+	}
+
+	// `n`: Size of the layout in kilobytes.
+	set_layout {
+		let n in 0 .. T::MaxValueSize::get() / 1024;
+		let instance = Contract::<T>::new(WasmModule::dummy(), vec![], Endow::Max)?;
+		let account_id = instance.account_id.clone();
+	}: _(RawOrigin::Root, account_id, vec![42u8; (n * 1024) as usize])
+	verify {
+		assert!(Contracts::<T>::layout(&instance.account_id).is_some());
+	}
+
+	// The storage of the contract is not included. It is accounted for by `destroy_contract`.
+	force_terminate {
+		let instance = Contract::<T>::new(WasmModule::dummy(), vec![], Endow::Max)?;
+		let account_id = instance.account_id.clone();
+		let beneficiary = account::<T::AccountId>("beneficiary", 0, 0);
+		<PausedContracts<T>>::insert(&account_id, ());
+	}: _(RawOrigin::Root, account_id, beneficiary.clone(), 0)
+	verify {
+		assert!(ContractInfoOf::<T>::get(&instance.account_id).is_none());
+		assert_eq!(T::Currency::free_balance(&beneficiary), instance.endowment);
+	}
+
 	seal_caller {
 		let r in 0 .. API_BENCHMARK_BATCHES;
 		let instance = Contract::<T>::new(WasmModule::getter(
@@ -394,6 +485,58 @@ benchmarks! {
 		let origin = RawOrigin::Signed(instance.caller.clone());
 	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
 
+	// The salt is charged separately with the weight that `seal_instantiate` uses for it.
+	seal_derive_address {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let hash_bytes = T::Hashing::hash(&[]).encode();
+		let hash_len = hash_bytes.len();
+		let addr_len = sp_std::mem::size_of::<T::AccountId>();
+
+		// offsets where to place static data in contract memory
+		let addr_len_offset = 0;
+		let addr_offset = addr_len_offset + 4;
+		let hash_offset = addr_offset + addr_len;
+
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory { min_pages: 1, max_pages: 1 }),
+			imported_functions: vec![ImportedFunction {
+				module: "seal0",
+				name: "seal_derive_address",
+				params: vec![
+					ValueType::I32,
+					ValueType::I32,
+					ValueType::I32,
+					ValueType::I32,
+					ValueType::I32,
+					ValueType::I32,
+				],
+				return_type: None,
+			}],
+			data_segments: vec![
+				DataSegment {
+					offset: addr_len_offset as u32,
+					value: (addr_len as u32).to_le_bytes().into(),
+				},
+				DataSegment {
+					offset: hash_offset as u32,
+					value: hash_bytes,
+				},
+			],
+			call_body: Some(body::repeated(r * API_BENCHMARK_BATCH_SIZE, &[
+				Instruction::I32Const(hash_offset as i32), // code_hash_ptr
+				Instruction::I32Const(hash_len as i32), // code_hash_len
+				Instruction::I32Const(0), // salt_ptr
+				Instruction::I32Const(0), // salt_len
+				Instruction::I32Const(addr_offset as i32), // out_ptr
+				Instruction::I32Const(addr_len_offset as i32), // out_len_ptr
+				Instruction::Call(0),
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
 	seal_gas_left {
 		let r in 0 .. API_BENCHMARK_BATCHES;
 		let instance = Contract::<T>::new(WasmModule::getter(
@@ -450,6 +593,22 @@ benchmarks! {
 		let origin = RawOrigin::Signed(instance.caller.clone());
 	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
 
+	seal_block_weight_left {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let instance = Contract::<T>::new(WasmModule::getter(
+			"seal_block_weight_left", r * API_BENCHMARK_BATCH_SIZE
+		), vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
+	seal_call_count {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let instance = Contract::<T>::new(WasmModule::getter(
+			"seal_call_count", r * API_BENCHMARK_BATCH_SIZE
+		), vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
 	seal_now {
 		let r in 0 .. API_BENCHMARK_BATCHES;
 		let instance = Contract::<T>::new(WasmModule::getter(
@@ -464,6 +623,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_weight_to_fee",
 				params: vec![ValueType::I64, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -488,6 +648,7 @@ benchmarks! {
 		let r in 0 .. API_BENCHMARK_BATCHES;
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "gas",
 				params: vec![ValueType::I32],
 				return_type: None,
@@ -511,6 +672,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_input",
 				params: vec![ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -539,6 +701,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_input",
 				params: vec![ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -562,12 +725,94 @@ benchmarks! {
 		let origin = RawOrigin::Signed(instance.caller.clone());
 	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), data)
 
+	// Only the overhead of calling the function itself. Nothing is copied.
+	seal_input_chunk {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory::max::<T>()),
+			imported_functions: vec![ImportedFunction {
+				module: "seal1",
+				name: "seal_input",
+				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
+				return_type: None,
+			}],
+			data_segments: vec![
+				DataSegment {
+					offset: 0,
+					value: 0u32.to_le_bytes().to_vec(),
+				},
+			],
+			call_body: Some(body::repeated(r * API_BENCHMARK_BATCH_SIZE, &[
+				Instruction::I32Const(4), // ptr where to store output
+				Instruction::I32Const(0), // ptr to length
+				Instruction::I32Const(0), // offset
+				Instruction::Call(0),
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
+	seal_input_chunk_per_kb {
+		let n in 0 .. code::max_pages::<T>() * 64;
+		let pages = code::max_pages::<T>();
+		let buffer_size = pages * 64 * 1024 - 4;
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory::max::<T>()),
+			imported_functions: vec![ImportedFunction {
+				module: "seal1",
+				name: "seal_input",
+				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
+				return_type: None,
+			}],
+			data_segments: vec![
+				DataSegment {
+					offset: 0,
+					value: buffer_size.to_le_bytes().to_vec(),
+				},
+			],
+			call_body: Some(body::plain(vec![
+				Instruction::I32Const(4), // ptr where to store output
+				Instruction::I32Const(0), // ptr to length
+				Instruction::I32Const(0), // offset
+				Instruction::Call(0),
+				Instruction::End,
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		let data = vec![42u8; (n * 1024).min(buffer_size) as usize];
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), data)
+
+	seal_input_len {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory::max::<T>()),
+			imported_functions: vec![ImportedFunction {
+				module: "seal0",
+				name: "seal_input_len",
+				params: vec![],
+				return_type: Some(ValueType::I32),
+			}],
+			call_body: Some(body::repeated(r * API_BENCHMARK_BATCH_SIZE, &[
+				Instruction::Call(0),
+				Instruction::Drop,
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
 	// The same argument as for `seal_input` is true here.
 	seal_return {
 		let r in 0 .. 1;
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_return",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -589,6 +834,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_return",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -615,6 +861,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_terminate",
 				params: vec![ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -644,6 +891,43 @@ benchmarks! {
 		}
 	}
 
+	// Termination removes all storage items of the contract. We measure the additional
+	// weight of a contract that holds `k` batches of items over an empty one.
+	seal_terminate_per_key {
+		let k in 0 .. API_BENCHMARK_BATCHES;
+		let beneficiary = account::<T::AccountId>("beneficiary", 0, 0);
+		let beneficiary_bytes = beneficiary.encode();
+		let beneficiary_len = beneficiary_bytes.len();
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory::max::<T>()),
+			imported_functions: vec![ImportedFunction {
+				module: "seal0",
+				name: "seal_terminate",
+				params: vec![ValueType::I32, ValueType::I32],
+				return_type: None,
+			}],
+			data_segments: vec![
+				DataSegment {
+					offset: 0,
+					value: beneficiary_bytes,
+				},
+			],
+			call_body: Some(body::plain(vec![
+				Instruction::I32Const(0), // beneficiary_ptr
+				Instruction::I32Const(beneficiary_len as i32), // beneficiary_len
+				Instruction::Call(0),
+				Instruction::End,
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		instance.store(&create_storage::<T>(k * API_BENCHMARK_BATCH_SIZE, T::MaxValueSize::get())?)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+	verify {
+		assert_eq!(T::Currency::total_balance(&beneficiary), Endow::max::<T>());
+	}
+
 	seal_restore_to {
 		let r in 0 .. 1;
 
@@ -666,6 +950,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_restore_to",
 				params: vec![
 					ValueType::I32,
@@ -742,6 +1027,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_restore_to",
 				params: vec![
 					ValueType::I32,
@@ -812,6 +1098,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_random",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -842,6 +1129,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_deposit_event",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -873,6 +1161,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_deposit_event",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -903,6 +1192,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory { min_pages: 1, max_pages: 1 }),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_set_rent_allowance",
 				params: vec![ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -924,6 +1214,98 @@ benchmarks! {
 		let origin = RawOrigin::Signed(instance.caller.clone());
 	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
 
+	seal_top_up_rent_allowance {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let amount = BalanceOf::<T>::from(1u32).encode();
+		let amount_len = amount.len();
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory { min_pages: 1, max_pages: 1 }),
+			imported_functions: vec![ImportedFunction {
+				module: "seal0",
+				name: "seal_top_up_rent_allowance",
+				params: vec![ValueType::I32, ValueType::I32],
+				return_type: Some(ValueType::I32),
+			}],
+			data_segments: vec![
+				DataSegment {
+					offset: 0,
+					value: amount,
+				},
+			],
+			call_body: Some(body::repeated(r * API_BENCHMARK_BATCH_SIZE, &[
+				Instruction::I32Const(0), // value_ptr
+				Instruction::I32Const(amount_len as i32), // value_len
+				Instruction::Call(0),
+				Instruction::Drop,
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
+	seal_reserve {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let amount = BalanceOf::<T>::from(1u32).encode();
+		let amount_len = amount.len();
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory { min_pages: 1, max_pages: 1 }),
+			imported_functions: vec![ImportedFunction {
+				module: "seal0",
+				name: "seal_reserve",
+				params: vec![ValueType::I32, ValueType::I32],
+				return_type: Some(ValueType::I32),
+			}],
+			data_segments: vec![
+				DataSegment {
+					offset: 0,
+					value: amount,
+				},
+			],
+			call_body: Some(body::repeated(r * API_BENCHMARK_BATCH_SIZE, &[
+				Instruction::I32Const(0), // value_ptr
+				Instruction::I32Const(amount_len as i32), // value_len
+				Instruction::Call(0),
+				Instruction::Drop,
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
+	seal_unreserve {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let amount = BalanceOf::<T>::from(1u32).encode();
+		let amount_len = amount.len();
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory { min_pages: 1, max_pages: 1 }),
+			imported_functions: vec![ImportedFunction {
+				module: "seal0",
+				name: "seal_unreserve",
+				params: vec![ValueType::I32, ValueType::I32],
+				return_type: Some(ValueType::I32),
+			}],
+			data_segments: vec![
+				DataSegment {
+					offset: 0,
+					value: amount,
+				},
+			],
+			call_body: Some(body::repeated(r * API_BENCHMARK_BATCH_SIZE, &[
+				Instruction::I32Const(0), // value_ptr
+				Instruction::I32Const(amount_len as i32), // value_len
+				Instruction::Call(0),
+				Instruction::Drop,
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		T::Currency::reserve(&instance.account_id, (r * API_BENCHMARK_BATCH_SIZE).into())
+			.map_err(|_| "Failed to reserve the balance to unreserve")?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
 	// Only the overhead of calling the function itself with minimal arguments.
 	// The contract is a bit more complex because I needs to use different keys in order
 	// to generate unique storage accesses. However, it is still dominated by the storage
@@ -937,6 +1319,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_set_storage",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -966,6 +1349,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_set_storage",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: None,
@@ -1001,6 +1385,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_clear_storage",
 				params: vec![ValueType::I32],
 				return_type: None,
@@ -1043,6 +1428,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_get_storage",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: Some(ValueType::I32),
@@ -1083,6 +1469,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_get_storage",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: Some(ValueType::I32),
@@ -1119,6 +1506,49 @@ benchmarks! {
 		let origin = RawOrigin::Signed(instance.caller.clone());
 	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
 
+	// Every call overwrites the layout with an empty one.
+	seal_register_layout {
+		let r in 0 .. API_BENCHMARK_BATCHES;
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory::max::<T>()),
+			imported_functions: vec![ImportedFunction {
+				module: "seal0",
+				name: "seal_register_layout",
+				params: vec![ValueType::I32, ValueType::I32],
+				return_type: None,
+			}],
+			call_body: Some(body::repeated(r * API_BENCHMARK_BATCH_SIZE, &[
+				Instruction::I32Const(0), // layout_ptr
+				Instruction::I32Const(0), // layout_len
+				Instruction::Call(0),
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
+	seal_register_layout_per_kb {
+		let n in 0 .. T::MaxValueSize::get() / 1024;
+		let code = WasmModule::<T>::from(ModuleDefinition {
+			memory: Some(ImportedMemory::max::<T>()),
+			imported_functions: vec![ImportedFunction {
+				module: "seal0",
+				name: "seal_register_layout",
+				params: vec![ValueType::I32, ValueType::I32],
+				return_type: None,
+			}],
+			call_body: Some(body::repeated(API_BENCHMARK_BATCH_SIZE, &[
+				Instruction::I32Const(0), // layout_ptr
+				Instruction::I32Const((n * 1024) as i32), // layout_len
+				Instruction::Call(0),
+			])),
+			.. Default::default()
+		});
+		let instance = Contract::<T>::new(code, vec![], Endow::Max)?;
+		let origin = RawOrigin::Signed(instance.caller.clone());
+	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
+
 	// We transfer to unique accounts.
 	seal_transfer {
 		let r in 0 .. API_BENCHMARK_BATCHES;
@@ -1134,6 +1564,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_transfer",
 				params: vec![ValueType::I32, ValueType::I32, ValueType::I32, ValueType::I32],
 				return_type: Some(ValueType::I32),
@@ -1185,6 +1616,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_call",
 				params: vec![
 					ValueType::I32,
@@ -1235,6 +1667,7 @@ benchmarks! {
 		let callee_code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_return",
 				params: vec![
 					ValueType::I32,
@@ -1264,6 +1697,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_call",
 				params: vec![
 					ValueType::I32,
@@ -1312,8 +1746,13 @@ benchmarks! {
 	}: call(origin, instance.addr, 0u32.into(), Weight::max_value(), vec![])
 
 	// We assume that every instantiate sends at least the subsistence amount.
+	// The runtime needs to allow all of the instantiations in a single call. Otherwise
+	// failed instantiations would be measured.
 	seal_instantiate {
 		let r in 0 .. API_BENCHMARK_BATCHES;
+		if T::MaxInstantiationsPerCall::get() < API_BENCHMARK_BATCHES * API_BENCHMARK_BATCH_SIZE {
+			return Err("MaxInstantiationsPerCall is too low to benchmark seal_instantiate.");
+		}
 		let hashes = (0..r * API_BENCHMARK_BATCH_SIZE)
 			.map(|i| {
 				let code = WasmModule::<T>::from(ModuleDefinition {
@@ -1349,6 +1788,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_instantiate",
 				params: vec![
 					ValueType::I32,
@@ -1429,6 +1869,7 @@ benchmarks! {
 		let callee_code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_return",
 				params: vec![
 					ValueType::I32,
@@ -1471,6 +1912,7 @@ benchmarks! {
 		let code = WasmModule::<T>::from(ModuleDefinition {
 			memory: Some(ImportedMemory::max::<T>()),
 			imported_functions: vec![ImportedFunction {
+				module: "seal0",
 				name: "seal_instantiate",
 				params: vec![
 					ValueType::I32,
@@ -2394,14 +2836,68 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn weight_functions_match_benchmarks() {
+		use frame_benchmarking::{Benchmarking, BenchmarkResults};
+		use std::collections::BTreeSet;
+
+		// Extra benchmarks aren't used to compute any weight.
+		let benchmarks: BTreeSet<_> =
+			<Contracts<Test> as Benchmarking<BenchmarkResults>>::benchmarks(false)
+				.into_iter()
+				.map(|name| std::str::from_utf8(name).unwrap())
+				.collect();
+		let weights: BTreeSet<_> = crate::weights::WEIGHT_FUNCTIONS.iter().cloned().collect();
+		let without_weight: Vec<_> = benchmarks.difference(&weights).collect();
+		let without_benchmark: Vec<_> = weights.difference(&benchmarks).collect();
+		assert!(
+			without_weight.is_empty() && without_benchmark.is_empty(),
+			"missing weight functions: {:?}, missing benchmarks: {:?}",
+			without_weight,
+			without_benchmark,
+		);
+	}
+
+	/// The template renders the coefficients into `Weight` which is unsigned. A negative
+	/// coefficient therefore doesn't look negative but is larger than `i64::MAX`.
+	#[test]
+	fn weight_coefficients_are_not_negative() {
+		let source = include_str!("../weights.rs");
+		let start = source
+			.find("impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T>")
+			.unwrap();
+		let end = start + source[start..].find("\n}\n").unwrap();
+		let coefficients = source[start..end]
+			.split(" as Weight)")
+			.filter_map(|chunk| chunk.rsplit('(').next())
+			.filter_map(|literal| literal.replace('_', "").parse::<i128>().ok())
+			.collect::<Vec<_>>();
+		assert!(!coefficients.is_empty());
+		for coefficient in coefficients {
+			assert!(
+				coefficient >= 0 && coefficient <= i64::max_value() as i128,
+				"weight coefficient {} is negative", coefficient,
+			);
+		}
+	}
+
 	create_test!(update_schedule);
 	create_test!(put_code);
+	create_test!(put_code_existing);
 	create_test!(instantiate);
 	create_test!(call);
+	create_test!(call_batch);
 	create_test!(claim_surcharge);
+	create_test!(destroy_contract);
+	create_test!(pause_contract);
+	create_test!(unpause_contract);
+	create_test!(set_frozen);
+	create_test!(set_layout);
+	create_test!(force_terminate);
 
 	create_test!(seal_caller);
 	create_test!(seal_address);
+	create_test!(seal_derive_address);
 	create_test!(seal_gas_left);
 	create_test!(seal_balance);
 	create_test!(seal_value_transferred);
@@ -2409,24 +2905,35 @@ mod tests {
 	create_test!(seal_tombstone_deposit);
 	create_test!(seal_rent_allowance);
 	create_test!(seal_block_number);
+	create_test!(seal_block_weight_left);
+	create_test!(seal_call_count);
 	create_test!(seal_now);
 	create_test!(seal_weight_to_fee);
 	create_test!(seal_gas);
 	create_test!(seal_input);
 	create_test!(seal_input_per_kb);
+	create_test!(seal_input_chunk);
+	create_test!(seal_input_chunk_per_kb);
+	create_test!(seal_input_len);
 	create_test!(seal_return);
 	create_test!(seal_return_per_kb);
 	create_test!(seal_terminate);
+	create_test!(seal_terminate_per_key);
 	create_test!(seal_restore_to);
 	create_test!(seal_restore_to_per_delta);
 	create_test!(seal_random);
 	create_test!(seal_deposit_event);
 	create_test!(seal_deposit_event_per_topic_and_kb);
 	create_test!(seal_set_rent_allowance);
+	create_test!(seal_top_up_rent_allowance);
+	create_test!(seal_reserve);
+	create_test!(seal_unreserve);
 	create_test!(seal_set_storage);
 	create_test!(seal_set_storage_per_kb);
 	create_test!(seal_get_storage);
 	create_test!(seal_get_storage_per_kb);
+	create_test!(seal_register_layout);
+	create_test!(seal_register_layout_per_kb);
 	create_test!(seal_transfer);
 	create_test!(seal_call);
 	create_test!(seal_call_per_transfer_input_output_kb);
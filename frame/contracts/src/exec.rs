@@ -15,17 +15,22 @@
 // along with Substrate. If not, see <http://www.gnu.org/licenses/>.
 
 use crate::{
-	CodeHash, ConfigCache, Event, RawEvent, Config, Module as Contracts,
-	TrieId, BalanceOf, ContractInfo, gas::GasMeter, rent::Rent, storage::{self, Storage},
-	Error, ContractInfoOf
+	CodeHash, ConfigCache, Event, RawEvent, Config, Module as Contracts, KeyPartitioner,
+	TrieId, BalanceOf, ContractInfo, gas::{Gas, GasMeter, Token}, rent::Rent,
+	storage::{self, Storage}, Error, ContractInfoOf, PausedContracts, AliveContractInfo,
+	HostFnWeights, MirrorSink,
 };
+use codec::Encode;
 use sp_core::crypto::UncheckedFrom;
-use sp_std::prelude::*;
-use sp_runtime::traits::{Bounded, Zero, Convert, Saturating};
+use sp_std::{prelude::*, cell::Cell};
+#[cfg(debug_assertions)]
+use sp_io::hashing::blake2_256;
+use sp_runtime::traits::{Bounded, Zero, Convert, Saturating, SaturatedConversion};
 use frame_support::{
 	dispatch::DispatchError,
-	traits::{ExistenceRequirement, Currency, Time, Randomness},
+	traits::{ExistenceRequirement, Currency, Time, Randomness, Get},
 	weights::Weight,
+	storage::child::ChildInfo,
 	ensure, StorageMap,
 };
 use pallet_contracts_primitives::{ErrorOrigin, ExecError, ExecReturnValue, ExecResult, ReturnFlags};
@@ -34,6 +39,9 @@ pub type AccountIdOf<T> = <T as frame_system::Config>::AccountId;
 pub type MomentOf<T> = <<T as Config>::Time as Time>::Moment;
 pub type SeedOf<T> = <T as frame_system::Config>::Hash;
 pub type BlockNumberOf<T> = <T as frame_system::Config>::BlockNumber;
+/// The key of a contract storage entry.
+///
+/// Keys have a fixed length so that they can't be crafted to collide once hashed.
 pub type StorageKey = [u8; 32];
 
 /// A type that represents a topic of an event. At the moment a hash is used.
@@ -60,11 +68,18 @@ pub trait Ext {
 	///
 	/// Returns `None` if the `key` wasn't previously set by `set_storage` or
 	/// was deleted.
-	fn get_storage(&self, key: &StorageKey) -> Option<Vec<u8>>;
+	///
+	/// Returns `Err` if the call has an access list that doesn't declare `key`.
+	fn get_storage(&self, key: &StorageKey) -> Result<Option<Vec<u8>>, DispatchError>;
 
 	/// Sets the storage entry by the given key to the specified value. If `value` is `None` then
 	/// the storage entry is deleted.
-	fn set_storage(&mut self, key: StorageKey, value: Option<Vec<u8>>);
+	///
+	/// Returns `Err` if the call has an access list that doesn't declare `key`.
+	fn set_storage(&mut self, key: StorageKey, value: Option<Vec<u8>>) -> Result<(), DispatchError>;
+
+	/// Registers `layout` as the description of the storage layout of the current contract.
+	fn register_layout(&mut self, layout: Vec<u8>);
 
 	/// Instantiate a contract from the given code.
 	///
@@ -114,13 +129,21 @@ pub trait Ext {
 	///
 	/// This function will fail if the same contract is present
 	/// on the contract call stack.
+	///
+	/// Returns the number of `delta` keys that were actually stored by the current contract.
 	fn restore_to(
 		&mut self,
 		dest: AccountIdOf<Self::T>,
 		code_hash: CodeHash<Self::T>,
 		rent_allowance: BalanceOf<Self::T>,
 		delta: Vec<StorageKey>,
-	) -> Result<(), DispatchError>;
+	) -> Result<u32, DispatchError>;
+
+	/// Returns the address of the contract that the current contract would instantiate from
+	/// `code_hash` and `salt`.
+	///
+	/// The code doesn't need to exist.
+	fn derive_address(&self, code_hash: &CodeHash<Self::T>, salt: &[u8]) -> AccountIdOf<Self::T>;
 
 	/// Returns a reference to the account id of the caller.
 	fn caller(&self) -> &AccountIdOf<Self::T>;
@@ -150,18 +173,57 @@ pub trait Ext {
 
 	/// Deposit an event with the given topics.
 	///
-	/// There should not be any duplicates in `topics`.
+	/// There should not be any duplicates in `topics`. Does nothing if
+	/// `Config::ContractEventsEnabled` is `false`.
 	fn deposit_event(&mut self, topics: Vec<TopicOf<Self::T>>, data: Vec<u8>);
 
 	/// Set rent allowance of the contract
 	fn set_rent_allowance(&mut self, rent_allowance: BalanceOf<Self::T>);
 
+	/// Increase the rent allowance of the contract by `amount`.
+	///
+	/// Fails if the free balance of the contract doesn't cover `amount` on top of the
+	/// subsistence threshold.
+	fn top_up_rent_allowance(&mut self, amount: BalanceOf<Self::T>) -> Result<(), DispatchError>;
+
+	/// Move `amount` from the free to the reserved balance of the contract.
+	///
+	/// Fails if the free balance of the contract doesn't cover `amount` on top of the
+	/// subsistence threshold.
+	fn reserve(&mut self, amount: BalanceOf<Self::T>) -> Result<(), DispatchError>;
+
+	/// Move `amount` from the reserved to the free balance of the contract.
+	///
+	/// Fails if the reserved balance of the contract doesn't cover `amount`. The tombstone
+	/// deposit doesn't count towards the reserved balance here.
+	fn unreserve(&mut self, amount: BalanceOf<Self::T>) -> Result<(), DispatchError>;
+
 	/// Rent allowance of the contract
 	fn rent_allowance(&self) -> BalanceOf<Self::T>;
 
+	/// Returns the number of key value pairs currently stored by the contract.
+	///
+	/// This includes the writes of the current frame that are not yet booked.
+	fn total_pair_count(&self) -> u32;
+
+	/// Returns the number of successful top-level calls made to the contract.
+	///
+	/// This includes the current call if it is a top-level one.
+	fn call_count(&self) -> u64;
+
+	/// Returns whether the contract uses timelocks.
+	///
+	/// Every storage access of such a contract also accesses the lock of the key.
+	fn uses_timelocks(&self) -> bool;
+
 	/// Returns the current block number.
 	fn block_number(&self) -> BlockNumberOf<Self::T>;
 
+	/// Returns the weight that is left in the current block.
+	///
+	/// This is only advisory as the weight of the current extrinsic is not yet accounted.
+	fn block_weight_left(&self) -> Weight;
+
 	/// Returns the maximum allowed size of a storage item.
 	fn max_value_size(&self) -> u32;
 
@@ -176,10 +238,10 @@ pub trait Loader<T: Config> {
 
 	/// Load the initializer portion of the code specified by the `code_hash`. This
 	/// executable is called upon instantiation.
-	fn load_init(&self, code_hash: &CodeHash<T>) -> Result<Self::Executable, &'static str>;
+	fn load_init(&self, code_hash: &CodeHash<T>) -> Result<Self::Executable, DispatchError>;
 	/// Load the main portion of the code specified by the `code_hash`. This executable
 	/// is called for each call to a contract.
-	fn load_main(&self, code_hash: &CodeHash<T>) -> Result<Self::Executable, &'static str>;
+	fn load_main(&self, code_hash: &CodeHash<T>) -> Result<Self::Executable, DispatchError>;
 }
 
 /// A trait that represent a virtual machine.
@@ -202,16 +264,143 @@ pub trait Vm<T: Config> {
 	) -> ExecResult;
 }
 
+/// The totals of all storage writes a contract performed within one call.
+///
+/// They are deposited as a single `ContractStorageBatch` event when the call commits and
+/// `Config::StorageBatchEvents` is enabled.
+#[derive(Default)]
+struct StorageBatch {
+	size_delta: i64,
+	pair_delta: i64,
+	writes: u32,
+}
+
+impl StorageBatch {
+	fn record(&mut self, delta: storage::WriteDelta) {
+		self.size_delta = self.size_delta.saturating_add(delta.size_delta);
+		self.pair_delta = self.pair_delta.saturating_add(delta.pair_delta);
+		self.writes = self.writes.saturating_add(1);
+	}
+
+	fn deposit<T: Config>(&self, contract: &T::AccountId) {
+		if self.writes == 0 {
+			return;
+		}
+		deposit_event::<T>(
+			vec![],
+			RawEvent::ContractStorageBatch(
+				contract.clone(),
+				self.size_delta,
+				self.pair_delta,
+				self.writes,
+			),
+		);
+	}
+}
+
+/// The storage that a call and all of its committed nested calls added and freed.
+///
+/// It is deposited as a single `StorageChanged` event when a top-level call commits.
+#[derive(Default, Clone, Copy)]
+struct StorageUsage {
+	bytes_added: u64,
+	bytes_freed: u64,
+	items_added: u32,
+	items_freed: u32,
+}
+
+impl StorageUsage {
+	fn record(&mut self, delta: storage::WriteDelta) {
+		let split = |delta: i64| (delta.max(0) as u64, delta.min(0).saturating_neg() as u64);
+		let (bytes_added, bytes_freed) = split(delta.size_delta);
+		let (items_added, items_freed) = split(delta.pair_delta);
+		self.accumulate(&StorageUsage {
+			bytes_added,
+			bytes_freed,
+			items_added: items_added.saturated_into(),
+			items_freed: items_freed.saturated_into(),
+		});
+	}
+
+	fn accumulate(&mut self, other: &StorageUsage) {
+		self.bytes_added = self.bytes_added.saturating_add(other.bytes_added);
+		self.bytes_freed = self.bytes_freed.saturating_add(other.bytes_freed);
+		self.items_added = self.items_added.saturating_add(other.items_added);
+		self.items_freed = self.items_freed.saturating_add(other.items_freed);
+	}
+
+	fn is_empty(&self) -> bool {
+		self.bytes_added == 0 && self.bytes_freed == 0 &&
+			self.items_added == 0 && self.items_freed == 0
+	}
+
+	fn deposit<T: Config>(&self, contract: &T::AccountId) {
+		if self.is_empty() {
+			return;
+		}
+		deposit_event::<T>(
+			vec![],
+			RawEvent::StorageChanged(
+				contract.clone(),
+				self.bytes_added,
+				self.bytes_freed,
+				self.items_added,
+				self.items_freed,
+			),
+		);
+	}
+}
+
+/// The weight of depositing a `ContractStorageBatch` or `StorageChanged` event.
+///
+/// It is charged like an event deposited by a contract without topics whose data has the
+/// given length.
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+#[derive(Copy, Clone)]
+struct StorageEventToken(u32);
+
+impl<T: Config> Token<T> for StorageEventToken {
+	type Metadata = HostFnWeights<T>;
+
+	fn calculate_amount(&self, s: &HostFnWeights<T>) -> Gas {
+		s.deposit_event.saturating_add(s.deposit_event_per_byte.saturating_mul(self.0.into()))
+	}
+}
+
 pub struct ExecutionContext<'a, T: Config + 'a, V, L> {
 	pub caller: Option<&'a ExecutionContext<'a, T, V, L>>,
 	pub self_account: T::AccountId,
 	pub self_trie_id: Option<TrieId>,
+	/// The constructed `ChildInfo` of `self_trie_id`.
+	///
+	/// It is kept around so that storage accesses don't need to construct it every time.
+	self_child_info: Option<ChildInfo>,
 	pub depth: usize,
 	pub config: &'a ConfigCache<T>,
 	pub vm: &'a V,
 	pub loader: &'a L,
 	pub timestamp: MomentOf<T>,
 	pub block_number: T::BlockNumber,
+	storage_batch: StorageBatch,
+	storage_usage: StorageUsage,
+	access_list: Option<storage::AccessList<T::AccountId>>,
+	/// The bookkeeping changes of storage writes not yet applied to the `AliveContractInfo`.
+	///
+	/// Only used when `T::DeferredBookkeeping` is enabled.
+	pending_bookkeeping: Option<storage::WriteDelta>,
+	/// The `AliveContractInfo` of `self_account` as it was read when the call was set up.
+	///
+	/// Only set for calls. Only the fields that don't change while the contract executes
+	/// may be taken from it.
+	self_info: Option<AliveContractInfo<T>>,
+	/// Whether this is a top-level call that is counted once it succeeds.
+	counts_call: bool,
+	/// The number of contracts instantiated by the call so far.
+	///
+	/// Only maintained by the top level context. Use [`Self::instantiations`] to access it.
+	instantiations: Cell<u32>,
+	#[cfg(debug_assertions)]
+	key_collisions: storage::KeyCollisionDetector,
 }
 
 impl<'a, T, E, V, L> ExecutionContext<'a, T, V, L>
@@ -229,6 +418,7 @@ where
 		ExecutionContext {
 			caller: None,
 			self_trie_id: None,
+			self_child_info: None,
 			self_account: origin,
 			depth: 0,
 			config: &cfg,
@@ -236,14 +426,74 @@ where
 			loader: &loader,
 			timestamp: T::Time::now(),
 			block_number: <frame_system::Module<T>>::block_number(),
+			storage_batch: Default::default(),
+			storage_usage: Default::default(),
+			access_list: None,
+			pending_bookkeeping: None,
+			self_info: None,
+			counts_call: false,
+			instantiations: Cell::new(0),
+			#[cfg(debug_assertions)]
+			key_collisions: Default::default(),
 		}
 	}
 
+	/// Restrict the storage accesses of this call and all of its nested calls to the
+	/// keys declared in `access_list`.
+	///
+	/// Any access to an undeclared key fails with `UndeclaredKeyAccess`. By default no
+	/// access list is set and contracts can access any key.
+	pub fn set_access_list(&mut self, access_list: storage::AccessList<T::AccountId>) {
+		self.access_list = Some(access_list);
+	}
+
+	/// The access list set for the top level call, if any.
+	fn access_list(&self) -> Option<&storage::AccessList<T::AccountId>> {
+		self.access_list
+			.as_ref()
+			.or_else(|| self.caller.and_then(|caller| caller.access_list()))
+	}
+
+	/// The number of contracts instantiated by the top level call and its nested calls.
+	///
+	/// Instantiations of calls that were rolled back are not counted.
+	fn instantiations(&self) -> &Cell<u32> {
+		self.caller.map_or(&self.instantiations, |caller| caller.instantiations())
+	}
+
+	/// Apply the deferred bookkeeping changes and the call count of this context to its
+	/// contract with a single update of its `AliveContractInfo`.
+	///
+	/// The contract might not be alive anymore in case it terminated itself or was restored.
+	/// Its bookkeeping is gone then and there is nothing to update.
+	fn flush_bookkeeping(&mut self) {
+		let delta = self.pending_bookkeeping.take();
+		if delta.is_some() || self.counts_call {
+			let _ = Storage::<T>::apply_frame(&self.self_account, delta.as_ref(), self.counts_call);
+		}
+		// A context is also flushed before every nested call. Only the first flush counts the
+		// call which is from then on part of the contract info.
+		if self.counts_call {
+			self.counts_call = false;
+			if let Some(info) = self.self_info.as_mut() {
+				info.call_count = info.call_count.saturating_add(1);
+			}
+		}
+	}
+
+	/// The number of calls to `account` in the execution stack that are counted but not yet
+	/// applied to its contract.
+	fn pending_calls(&self, account: &T::AccountId) -> u64 {
+		let own = (self.counts_call && &self.self_account == account) as u64;
+		own + self.caller.map_or(0, |caller| caller.pending_calls(account))
+	}
+
 	fn nested<'b, 'c: 'b>(&'c self, dest: T::AccountId, trie_id: TrieId)
 		-> ExecutionContext<'b, T, V, L>
 	{
 		ExecutionContext {
 			caller: Some(self),
+			self_child_info: Some(crate::child_trie_info(&trie_id)),
 			self_trie_id: Some(trie_id),
 			self_account: dest,
 			depth: self.depth + 1,
@@ -252,6 +502,15 @@ where
 			loader: self.loader,
 			timestamp: self.timestamp.clone(),
 			block_number: self.block_number.clone(),
+			storage_batch: Default::default(),
+			storage_usage: Default::default(),
+			access_list: None,
+			pending_bookkeeping: None,
+			self_info: None,
+			counts_call: false,
+			instantiations: Cell::new(0),
+			#[cfg(debug_assertions)]
+			key_collisions: Default::default(),
 		}
 	}
 
@@ -267,10 +526,24 @@ where
 			Err(Error::<T>::MaxCallDepthReached)?
 		}
 
+		if <PausedContracts<T>>::contains_key(&dest) {
+			Err(Error::<T>::ContractPaused)?
+		}
+
+		// The origin of a top-level call transfers to a plain account by calling it without
+		// input data. Input data for a plain account is rejected because it can't be what
+		// the origin meant.
+		if self.depth == 0 && !<ContractInfoOf<T>>::contains_key(&dest) {
+			ensure!(input_data.is_empty(), Error::<T>::ContractNotFound);
+			let origin = self.self_account.clone();
+			transfer(TransferCause::Call, self.transactor_kind(), &origin, &dest, value, self)?;
+			return Ok(ExecReturnValue { flags: ReturnFlags::empty(), data: Vec::new() });
+		}
+
 		// Assumption: `collect` doesn't collide with overlay because
 		// `collect` will be done on first call and destination contract and balance
 		// cannot be changed before the first call
-		// We do not allow 'calling' plain accounts. For transfering value
+		// Contracts are not allowed to 'call' plain accounts. For transfering value
 		// `seal_transfer` must be used.
 		let contract = if let Some(ContractInfo::Alive(info)) = Rent::<T>::collect(&dest) {
 			info
@@ -280,8 +553,20 @@ where
 
 		let transactor_kind = self.transactor_kind();
 		let caller = self.self_account.clone();
+		let top_level = self.depth == 0;
+		let trie_id = contract.trie_id.clone();
+		let code_hash = contract.code_hash.clone();
+
+		// The code is loaded outside of the nested context so that marking it as rejected
+		// isn't rolled back together with the failed call.
+		let executable = self.loader.load_main(&code_hash)?;
+
+		let entered_at = gas_meter.enter_frame();
+		let output = self.with_nested_context(dest.clone(), move || Ok(trie_id), |nested| {
+			// The call is only counted when the context is flushed which doesn't happen if
+			// the call reverts or traps.
+			nested.counts_call = top_level;
 
-		self.with_nested_context(dest.clone(), contract.trie_id.clone(), |nested| {
 			if value > BalanceOf::<T>::zero() {
 				transfer(
 					TransferCause::Call,
@@ -293,16 +578,18 @@ where
 				)?
 			}
 
-			let executable = nested.loader.load_main(&contract.code_hash)
-				.map_err(|_| Error::<T>::CodeNotFound)?;
+			nested.self_info = Some(contract);
 			let output = nested.vm.execute(
 				&executable,
 				nested.new_call_context(caller, value),
 				input_data,
 				gas_meter,
 			).map_err(|e| ExecError { error: e.error, origin: ErrorOrigin::Callee })?;
+			nested.charge_storage_events(&output, gas_meter)?;
 			Ok(output)
-		})
+		});
+		gas_meter.exit_frame(entered_at, &dest, &code_hash);
+		output
 	}
 
 	pub fn instantiate(
@@ -317,24 +604,27 @@ where
 			Err(Error::<T>::MaxCallDepthReached)?
 		}
 
+		if self.instantiations().get() >= self.config.max_instantiations {
+			Err(Error::<T>::TooManyInstantiations)?
+		}
+
 		let transactor_kind = self.transactor_kind();
 		let caller = self.self_account.clone();
-		let dest = Contracts::<T>::contract_address(&caller, code_hash, salt);
+		let dest = self.derive_address(code_hash, salt);
 
 		// TrieId has not been generated yet and storage is empty since contract is new.
 		//
-		// Generate it now.
-		let dest_trie_id = Storage::<T>::generate_trie_id(&dest);
+		// It is generated within the nested context so that the bump of the `AccountCounter`
+		// is rolled back together with everything else if the instantiation fails.
+		let dest_trie_id = || Storage::<T>::generate_trie_id(&dest);
+
+		// Loaded outside of the nested context for the same reason as in `call`.
+		let executable = self.loader.load_init(&code_hash)?;
 
+		let entered_at = gas_meter.enter_frame();
 		let output = self.with_nested_context(dest.clone(), dest_trie_id, |nested| {
-			Storage::<T>::place_contract(
-				&dest,
-				nested
-					.self_trie_id
-					.clone()
-					.expect("the nested context always has to have self_trie_id"),
-				code_hash.clone()
-			)?;
+			let instantiations = nested.instantiations();
+			instantiations.set(instantiations.get() + 1);
 
 			// Send funds unconditionally here. If the `endowment` is below existential_deposit
 			// then error will be returned here.
@@ -347,8 +637,30 @@ where
 				nested,
 			)?;
 
-			let executable = nested.loader.load_init(&code_hash)
-				.map_err(|_| Error::<T>::CodeNotFound)?;
+			// A zero endowment doesn't create the account. The contract is only placed once
+			// its account exists so that there is never a `ContractInfoOf` entry without one.
+			ensure!(
+				<frame_system::Module<T>>::account_exists(&dest),
+				Error::<T>::NewContractNotFunded,
+			);
+
+			Storage::<T>::place_contract(
+				&dest,
+				nested
+					.self_trie_id
+					.clone()
+					.expect("the nested context always has to have self_trie_id"),
+				code_hash.clone()
+			)?;
+
+			// The reference keeps the account from being reaped as long as the contract
+			// is alive.
+			Storage::<T>::hold_account(&dest);
+
+			// Reserved before the constructor runs so that the contract can't spend the
+			// funds that pay for its tombstone.
+			Storage::<T>::reserve_tombstone_deposit(&dest)?;
+
 			let output = nested.vm
 				.execute(
 					&executable,
@@ -363,12 +675,15 @@ where
 			if T::Currency::total_balance(&dest) < nested.config.subsistence_threshold() {
 				Err(Error::<T>::NewContractNotFunded)?
 			}
+			nested.charge_storage_events(&output, gas_meter)?;
 
 			// Deposit an instantiation event.
 			deposit_event::<T>(vec![], RawEvent::Instantiated(caller.clone(), dest.clone()));
 
 			Ok(output)
-		})?;
+		});
+		gas_meter.exit_frame(entered_at, &dest, code_hash);
+		let output = output?;
 
 		Ok((dest, output))
 	}
@@ -390,19 +705,89 @@ where
 	}
 
 	/// Execute the given closure within a nested execution context.
-	fn with_nested_context<F>(&mut self, dest: T::AccountId, trie_id: TrieId, func: F)
+	///
+	/// The trie id of the nested context is obtained from `trie_id` within the storage
+	/// transaction of the nested context. Nothing that is done in order to set up the nested
+	/// context is therefore visible to `self` unless the closure succeeds.
+	fn with_nested_context<F, I>(&mut self, dest: T::AccountId, trie_id: I, func: F)
 		-> ExecResult
-		where F: FnOnce(&mut ExecutionContext<T, V, L>) -> ExecResult
+		where
+			F: FnOnce(&mut ExecutionContext<T, V, L>) -> ExecResult,
+			I: FnOnce() -> Result<TrieId, DispatchError>,
 	{
 		use frame_support::storage::TransactionOutcome::*;
-		let mut nested = self.nested(dest, trie_id);
-		frame_support::storage::with_transaction(|| {
+		let instantiations = self.instantiations().get();
+		let top_level = self.depth == 0;
+		let mut committed_usage = None;
+		let output = frame_support::storage::with_transaction(|| {
+			let trie_id = match trie_id() {
+				Ok(trie_id) => trie_id,
+				Err(err) => return Rollback(Err(err.into())),
+			};
+			let mut nested = self.nested(dest, trie_id);
 			let output = func(&mut nested);
 			match output {
-				Ok(ref rv) if !rv.flags.contains(ReturnFlags::REVERT) => Commit(output),
-				_ => Rollback(output),
+				Ok(ref rv) if !rv.flags.contains(ReturnFlags::REVERT) => {
+					// Depositing within the transaction makes sure that the event is discarded
+					// together with the writes whenever an enclosing call is rolled back.
+					nested.flush_bookkeeping();
+					if T::StorageBatchEvents::get() {
+						nested.storage_batch.deposit::<T>(&nested.self_account);
+					}
+					if top_level {
+						if T::StorageDeltaEvents::get() {
+							nested.storage_usage.deposit::<T>(&nested.self_account);
+						}
+					} else {
+						committed_usage = Some(nested.storage_usage);
+					}
+					Commit(output)
+				}
+				_ => {
+					// The contracts instantiated by the rolled back call don't exist anymore.
+					nested.instantiations().set(instantiations);
+					Rollback(output)
+				}
+			}
+		});
+		// The storage usage of a nested call is folded into its caller unless it reverted.
+		if let Some(usage) = committed_usage {
+			self.storage_usage.accumulate(&usage);
+		}
+		output
+	}
+
+	/// Charge for the `ContractStorageBatch` and `StorageChanged` events that are deposited
+	/// once this call commits.
+	///
+	/// Nothing is charged for an event that is disabled or if the call reverted. The
+	/// `ContractStorageBatch` event is only deposited if the call wrote to storage and the
+	/// `StorageChanged` event only for a top-level call that changed the storage.
+	fn charge_storage_events(
+		&self,
+		output: &ExecReturnValue,
+		gas_meter: &mut GasMeter<T>,
+	) -> Result<(), DispatchError> {
+		if output.flags.contains(ReturnFlags::REVERT) {
+			return Ok(());
+		}
+		let weights = &self.config.schedule.host_fn_weights;
+		let mut charge = |counters_len: usize| -> Result<(), DispatchError> {
+			let len = self.self_account.encoded_size().saturating_add(counters_len);
+			if gas_meter.charge(weights, StorageEventToken(len.saturated_into())).is_out_of_gas() {
+				Err(Error::<T>::OutOfGas)?
 			}
-		})
+			Ok(())
+		};
+		if T::StorageBatchEvents::get() && self.storage_batch.writes > 0 {
+			// The two deltas and the number of writes follow the account of the contract.
+			charge(2 * 8 + 4)?;
+		}
+		if self.depth == 1 && T::StorageDeltaEvents::get() && !self.storage_usage.is_empty() {
+			// The four counters follow the account of the contract.
+			charge(2 * 8 + 2 * 4)?;
+		}
+		Ok(())
 	}
 
 	/// Returns whether a contract, identified by address, is currently live in the execution
@@ -412,6 +797,15 @@ where
 			self.caller.map_or(false, |caller| caller.is_live(account))
 	}
 
+	/// The address of the contract that the current account instantiates from `code_hash`
+	/// and `salt`.
+	///
+	/// Both `instantiate` and `Ext::derive_address` go through this so that a derived address
+	/// is always the one a later instantiation uses.
+	fn derive_address(&self, code_hash: &CodeHash<T>, salt: &[u8]) -> T::AccountId {
+		Contracts::<T>::contract_address(&self.self_account, code_hash, salt)
+	}
+
 	fn transactor_kind(&self) -> TransactorKind {
 		if self.depth == 0 {
 			debug_assert!(self.self_trie_id.is_none());
@@ -500,31 +894,89 @@ where
 {
 	type T = T;
 
-	fn get_storage(&self, key: &StorageKey) -> Option<Vec<u8>> {
-		let trie_id = self.ctx.self_trie_id.as_ref().expect(
-			"`ctx.self_trie_id` points to an alive contract within the `CallContext`;\
+	fn get_storage(&self, key: &StorageKey) -> Result<Option<Vec<u8>>, DispatchError> {
+		let child_info = self.ctx.self_child_info.as_ref().expect(
+			"`ctx.self_child_info` points to an alive contract within the `CallContext`;\
 				it cannot be `None`;\
 				expect can't fail;\
 				qed",
 		);
-		Storage::<T>::read(trie_id, key)
+		Storage::<T>::read_declared(
+			self.ctx.access_list(),
+			&self.ctx.self_account,
+			child_info,
+			self.uses_timelocks(),
+			key,
+		)
+		.map_err(|_| Error::<T>::UndeclaredKeyAccess.into())
 	}
 
-	fn set_storage(&mut self, key: StorageKey, value: Option<Vec<u8>>) {
-		let trie_id = self.ctx.self_trie_id.as_ref().expect(
-			"`ctx.self_trie_id` points to an alive contract within the `CallContext`;\
+	fn set_storage(&mut self, key: StorageKey, value: Option<Vec<u8>>) -> Result<(), DispatchError> {
+		if let Some(access_list) = self.ctx.access_list() {
+			access_list
+				.check(&self.ctx.self_account, &key)
+				.map_err(|_| Error::<T>::UndeclaredKeyAccess)?;
+		}
+		if !T::KeyPartitioner::may_write(&self.ctx.self_account, &self.caller, &key) {
+			Err(Error::<T>::KeyAccessDenied)?
+		}
+		#[cfg(debug_assertions)]
+		self.ctx.key_collisions.observe(&key, blake2_256(&key));
+		let child_info = self.ctx.self_child_info.as_ref().expect(
+			"`ctx.self_child_info` points to an alive contract within the `CallContext`;\
 				it cannot be `None`;\
 				expect can't fail;\
 				qed",
 		);
-		if let Err(storage::ContractAbsentError) =
-			Storage::<T>::write(&self.ctx.self_account, trie_id, &key, value)
-		{
-			panic!(
+		if T::DeferredBookkeeping::get() {
+			// `write_deferred` doesn't look at the contract info which is why we need to.
+			let frozen = match self.ctx.self_info {
+				Some(ref info) => info.frozen,
+				None => Storage::<T>::is_frozen(&self.ctx.self_account),
+			};
+			if frozen {
+				Err(Error::<T>::ContractFrozen)?
+			}
+			if self.uses_timelocks() {
+				Storage::<T>::clear_timelock(child_info, &key);
+			}
+			let mirrored = value.clone();
+			let delta = Storage::<T>::write_deferred(child_info, &key, value);
+			T::WriteMirror::mirror(
+				&self.ctx.self_account,
+				&storage::meta::hashed_key(&key),
+				mirrored.as_deref(),
+			);
+			self.ctx.pending_bookkeeping.get_or_insert_with(Default::default).accumulate(&delta);
+			self.ctx.storage_batch.record(delta);
+			self.ctx.storage_usage.record(delta);
+			return Ok(());
+		}
+		match Storage::<T>::write_child(&self.ctx.self_account, child_info, &key, value) {
+			Ok(delta) => {
+				self.ctx.storage_batch.record(delta);
+				self.ctx.storage_usage.record(delta);
+			}
+			Err(storage::WriteError::ContractFrozen) => Err(Error::<T>::ContractFrozen)?,
+			Err(storage::WriteError::RentNotCovered) => Err(Error::<T>::RentNotCovered)?,
+			Err(storage::WriteError::ValueTooLarge) => Err(Error::<T>::ValueTooLarge)?,
+			Err(storage::WriteError::ContractAbsent) => panic!(
 				"the contract must be in the alive state within the `CallContext`;\
 				the contract cannot be absent in storage;
 				write cannot return `None`;
 				qed"
+			),
+		}
+		Ok(())
+	}
+
+	fn register_layout(&mut self, layout: Vec<u8>) {
+		if let Err(storage::ContractAbsentError) =
+			Storage::<T>::register_layout(&self.ctx.self_account, layout)
+		{
+			panic!(
+				"`self_account` points to an alive contract within the `CallContext`;
+					register_layout cannot return `Err`; qed"
 			);
 		}
 	}
@@ -537,6 +989,8 @@ where
 		input_data: Vec<u8>,
 		salt: &[u8],
 	) -> Result<(AccountIdOf<T>, ExecReturnValue), ExecError> {
+		// Nested calls must observe the bookkeeping of the writes done so far.
+		self.ctx.flush_bookkeeping();
 		self.ctx.instantiate(endowment, gas_meter, code_hash, input_data, salt)
 	}
 
@@ -560,12 +1014,22 @@ where
 		beneficiary: &AccountIdOf<Self::T>,
 	) -> Result<(), DispatchError> {
 		let self_id = self.ctx.self_account.clone();
-		let value = T::Currency::free_balance(&self_id);
 		if let Some(caller_ctx) = self.ctx.caller {
 			if caller_ctx.is_live(&self_id) {
 				return Err(Error::<T>::ReentranceDenied.into());
 			}
 		}
+		// The reserved balance goes to the beneficiary together with the free balance.
+		let _ = T::Currency::unreserve(&self_id, T::Currency::reserved_balance(&self_id));
+		let value = T::Currency::free_balance(&self_id);
+		let self_trie_id = self.ctx.self_trie_id.as_ref().expect(
+			"this function is only invoked by in the context of a contract;\
+				a contract has a trie id;\
+				this can't be None; qed",
+		);
+		// Destroying the contract first releases the reference on its account which
+		// would prevent the transfer from reaping it. A failed transfer rolls back the call.
+		Storage::<T>::destroy_contract(&self_id, self_trie_id);
 		transfer(
 			TransferCause::Terminate,
 			TransactorKind::Contract,
@@ -574,12 +1038,6 @@ where
 			value,
 			self.ctx,
 		)?;
-		let self_trie_id = self.ctx.self_trie_id.as_ref().expect(
-			"this function is only invoked by in the context of a contract;\
-				a contract has a trie id;\
-				this can't be None; qed",
-		);
-		Storage::<T>::destroy_contract(&self_id, self_trie_id);
 		Ok(())
 	}
 
@@ -590,6 +1048,8 @@ where
 		gas_meter: &mut GasMeter<T>,
 		input_data: Vec<u8>,
 	) -> ExecResult {
+		// Nested calls must observe the bookkeeping of the writes done so far.
+		self.ctx.flush_bookkeeping();
 		self.ctx.call(to.clone(), value, gas_meter, input_data)
 	}
 
@@ -599,13 +1059,14 @@ where
 		code_hash: CodeHash<Self::T>,
 		rent_allowance: BalanceOf<Self::T>,
 		delta: Vec<StorageKey>,
-	) -> Result<(), DispatchError> {
+	) -> Result<u32, DispatchError> {
 		if let Some(caller_ctx) = self.ctx.caller {
 			if caller_ctx.is_live(&self.ctx.self_account) {
 				return Err(Error::<T>::ReentranceDenied.into());
 			}
 		}
 
+		self.ctx.flush_bookkeeping();
 		let result = Rent::<T>::restore_to(
 			self.ctx.self_account.clone(),
 			dest.clone(),
@@ -627,6 +1088,10 @@ where
 		result
 	}
 
+	fn derive_address(&self, code_hash: &CodeHash<T>, salt: &[u8]) -> T::AccountId {
+		self.ctx.derive_address(code_hash, salt)
+	}
+
 	fn address(&self) -> &T::AccountId {
 		&self.ctx.self_account
 	}
@@ -660,6 +1125,9 @@ where
 	}
 
 	fn deposit_event(&mut self, topics: Vec<T::Hash>, data: Vec<u8>) {
+		if !T::ContractEventsEnabled::get() {
+			return;
+		}
 		deposit_event::<Self::T>(
 			topics,
 			RawEvent::ContractExecution(self.ctx.self_account.clone(), data)
@@ -677,13 +1145,80 @@ where
 		}
 	}
 
+	fn top_up_rent_allowance(&mut self, amount: BalanceOf<T>) -> Result<(), DispatchError> {
+		// The rent depends on the storage size the contract occupies right now.
+		self.ctx.flush_bookkeeping();
+		match Storage::<T>::top_up_rent(&self.ctx.self_account, amount) {
+			Ok(_) => Ok(()),
+			Err(storage::TopUpRentError::BelowSubsistenceThreshold) =>
+				Err(Error::<T>::BelowSubsistenceThreshold.into()),
+			Err(storage::TopUpRentError::ContractAbsent) => panic!(
+				"`self_account` points to an alive contract within the `CallContext`;
+					top_up_rent cannot return `ContractAbsent`; qed"
+			),
+		}
+	}
+
+	fn reserve(&mut self, amount: BalanceOf<T>) -> Result<(), DispatchError> {
+		let self_id = &self.ctx.self_account;
+		let free_balance = T::Currency::free_balance(self_id);
+		let required = Storage::<T>::required_free_balance(self_id);
+		ensure!(
+			free_balance.saturating_sub(amount) >= required && free_balance >= amount,
+			Error::<T>::BelowSubsistenceThreshold,
+		);
+		T::Currency::reserve(self_id, amount).map_err(|_| Error::<T>::TransferFailed.into())
+	}
+
+	fn unreserve(&mut self, amount: BalanceOf<T>) -> Result<(), DispatchError> {
+		let self_id = &self.ctx.self_account;
+		// The tombstone deposit can't be unreserved by the contract.
+		ensure!(
+			Storage::<T>::free_reserve(self_id) >= amount,
+			Error::<T>::InsufficientReserve,
+		);
+		// Unreserves all of `amount` because the reserve was checked above.
+		let _ = T::Currency::unreserve(self_id, amount);
+		Ok(())
+	}
+
 	fn rent_allowance(&self) -> BalanceOf<T> {
 		Storage::<T>::rent_allowance(&self.ctx.self_account)
 			.unwrap_or_else(|_| <BalanceOf<T>>::max_value()) // Must never be triggered actually
 	}
 
+	fn total_pair_count(&self) -> u32 {
+		let booked = <ContractInfoOf<T>>::get(&self.ctx.self_account)
+			.and_then(|c| c.get_alive())
+			.map(|c| i64::from(c.total_pair_count))
+			.unwrap_or(0);
+		let pending = self.ctx.pending_bookkeeping.as_ref().map(|d| d.pair_delta).unwrap_or(0);
+		booked.saturating_add(pending).max(0).saturated_into()
+	}
+
+	fn call_count(&self) -> u64 {
+		let booked = match self.ctx.self_info {
+			Some(ref info) => info.call_count,
+			None => Storage::<T>::call_count(&self.ctx.self_account),
+		};
+		booked.saturating_add(self.ctx.pending_calls(&self.ctx.self_account))
+	}
+
+	fn uses_timelocks(&self) -> bool {
+		match self.ctx.self_info {
+			Some(ref info) => info.timelocked,
+			None => Storage::<T>::uses_timelocks(&self.ctx.self_account),
+		}
+	}
+
 	fn block_number(&self) -> T::BlockNumber { self.block_number }
 
+	fn block_weight_left(&self) -> Weight {
+		<T as frame_system::Config>::BlockWeights::get()
+			.max_block
+			.saturating_sub(<frame_system::Module<T>>::block_weight().total())
+	}
+
 	fn max_value_size(&self) -> u32 {
 		self.ctx.config.max_value_size
 	}
@@ -718,11 +1253,14 @@ mod tests {
 		gas::GasMeter, tests::{ExtBuilder, Test, MetaEvent},
 		exec::ExecReturnValue, CodeHash, ConfigCache,
 		gas::Gas,
-		storage::Storage,
+		storage::{self, Storage},
 		tests::{ALICE, BOB, CHARLIE},
 		Error,
 	};
-	use crate::tests::test_utils::{place_contract, set_balance, get_balance};
+	use crate::tests::{
+		KeyPartitioning,
+		test_utils::{place_contract, set_balance, get_balance, get_storage},
+	};
 	use sp_runtime::DispatchError;
 	use assert_matches::assert_matches;
 	use std::{cell::RefCell, collections::HashMap, marker::PhantomData, rc::Rc};
@@ -790,17 +1328,17 @@ mod tests {
 	impl<'a> Loader<Test> for MockLoader<'a> {
 		type Executable = MockExecutable<'a>;
 
-		fn load_init(&self, code_hash: &CodeHash<Test>) -> Result<Self::Executable, &'static str> {
+		fn load_init(&self, code_hash: &CodeHash<Test>) -> Result<Self::Executable, DispatchError> {
 			self.map
 				.get(code_hash)
 				.cloned()
-				.ok_or_else(|| "code not found")
+				.ok_or_else(|| Error::<Test>::CodeNotFound.into())
 		}
-		fn load_main(&self, code_hash: &CodeHash<Test>) -> Result<Self::Executable, &'static str> {
+		fn load_main(&self, code_hash: &CodeHash<Test>) -> Result<Self::Executable, DispatchError> {
 			self.map
 				.get(code_hash)
 				.cloned()
-				.ok_or_else(|| "code not found")
+				.ok_or_else(|| Error::<Test>::CodeNotFound.into())
 		}
 	}
 
@@ -1337,6 +1875,58 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn instantiations_are_limited_per_call() {
+		let vm = MockVm::new();
+
+		let mut loader = MockLoader::empty();
+		let dummy_ch = loader.insert(|_| exec_success());
+		let endowment = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let reverting_ch = loader.insert({
+			let dummy_ch = dummy_ch.clone();
+			move |ctx| {
+				assert_matches!(
+					ctx.ext.instantiate(&dummy_ch, endowment, ctx.gas_meter, vec![], &[1]),
+					Ok(_)
+				);
+				Ok(ExecReturnValue { flags: ReturnFlags::REVERT, data: Vec::new() })
+			}
+		});
+		let instantiator_ch = loader.insert({
+			let dummy_ch = dummy_ch.clone();
+			move |ctx| {
+				// The instantiation of the reverted call doesn't count towards the limit.
+				assert_matches!(ctx.ext.call(&CHARLIE, 0, ctx.gas_meter, vec![]), Ok(_));
+				for salt in 2..4 {
+					assert_matches!(
+						ctx.ext.instantiate(&dummy_ch, endowment, ctx.gas_meter, vec![], &[salt]),
+						Ok(_)
+					);
+				}
+				assert_eq!(
+					ctx.ext.instantiate(&dummy_ch, endowment, ctx.gas_meter, vec![], &[4]),
+					Err(Error::<Test>::TooManyInstantiations.into())
+				);
+				exec_success()
+			}
+		});
+
+		ExtBuilder::default().existential_deposit(15).build().execute_with(|| {
+			let mut cfg = ConfigCache::preload();
+			cfg.max_instantiations = 2;
+			let mut ctx = ExecutionContext::top_level(ALICE, &cfg, &vm, &loader);
+			set_balance(&BOB, 1000);
+			set_balance(&CHARLIE, 1000);
+			place_contract(&BOB, instantiator_ch);
+			place_contract(&CHARLIE, reverting_ch);
+
+			assert_matches!(
+				ctx.call(BOB, 0, &mut GasMeter::<Test>::new(GAS_LIMIT), vec![]),
+				Ok(_)
+			);
+		});
+	}
+
 	#[test]
 	fn instantiation_traps() {
 		let vm = MockVm::new();
@@ -1352,7 +1942,7 @@ mod tests {
 				assert_matches!(
 					ctx.ext.instantiate(
 						&dummy_ch,
-						15u64,
+						ConfigCache::<Test>::subsistence_threshold_uncached(),
 						ctx.gas_meter,
 						vec![],
 						&[],
@@ -1385,6 +1975,105 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn failed_instantiation_leaves_no_trace() {
+		use crate::{ContractInfoOf, Module as Contracts};
+
+		let vm = MockVm::new();
+		let mut loader = MockLoader::empty();
+		let dummy_ch = loader.insert(|_| exec_success());
+		let transferring_ch = loader.insert(|ctx| {
+			ctx.ext.transfer(&ALICE, 1_000)?;
+			exec_success()
+		});
+		let reverting_ch = loader.insert(|_| {
+			Ok(ExecReturnValue { flags: ReturnFlags::REVERT, data: Vec::new() })
+		});
+		let endowment = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let instantiator_ch = loader.insert(move |ctx| {
+			let root = sp_io::storage::root();
+
+			// The endowment can't be transferred before the contract is placed.
+			assert_eq!(
+				ctx.ext.instantiate(&dummy_ch, 10_000, ctx.gas_meter, vec![], &[]),
+				Err(Error::<Test>::BelowSubsistenceThreshold.into()),
+			);
+			assert_eq!(sp_io::storage::root(), root);
+
+			// The constructor of the placed contract fails to transfer.
+			assert_matches!(
+				ctx.ext.instantiate(&transferring_ch, endowment, ctx.gas_meter, vec![], &[]),
+				Err(ExecError { origin: ErrorOrigin::Callee, .. })
+			);
+			assert_eq!(sp_io::storage::root(), root);
+
+			// The constructor of the placed contract reverts.
+			assert_matches!(
+				ctx.ext.instantiate(&reverting_ch, endowment, ctx.gas_meter, vec![], &[]),
+				Ok((_, ExecReturnValue { flags: ReturnFlags::REVERT, .. }))
+			);
+			assert_eq!(sp_io::storage::root(), root);
+
+			exec_success()
+		});
+
+		ExtBuilder::default().existential_deposit(15).build().execute_with(|| {
+			let cfg = ConfigCache::preload();
+			set_balance(&ALICE, 1000);
+			set_balance(&BOB, 100);
+			place_contract(&BOB, instantiator_ch);
+
+			let mut ctx = ExecutionContext::top_level(ALICE, &cfg, &vm, &loader);
+			assert_matches!(ctx.call(BOB, 0, &mut GasMeter::<Test>::new(GAS_LIMIT), vec![]), Ok(_));
+
+			// Neither of the contracts nor their accounts exist.
+			for code_hash in &[dummy_ch, transferring_ch, reverting_ch] {
+				let addr = Contracts::<Test>::contract_address(&BOB, code_hash, &[]);
+				assert!(ContractInfoOf::<Test>::get(&addr).is_none());
+				assert!(!<frame_system::Module<Test>>::account_exists(&addr));
+			}
+
+			// The same holds for an instantiation by a plain account.
+			let root = sp_io::storage::root();
+			let mut ctx = ExecutionContext::top_level(ALICE, &cfg, &vm, &loader);
+			assert_eq!(
+				ctx.instantiate(
+					10_000,
+					&mut GasMeter::<Test>::new(GAS_LIMIT),
+					&dummy_ch,
+					vec![],
+					&[],
+				),
+				Err(Error::<Test>::TransferFailed.into()),
+			);
+			assert_eq!(sp_io::storage::root(), root);
+		});
+	}
+
+	#[test]
+	fn zero_endowment_is_rejected_before_the_contract_is_placed() {
+		let vm = MockVm::new();
+		let mut loader = MockLoader::empty();
+		let constructor_ch = loader.insert(|_| panic!("the constructor must not run"));
+
+		ExtBuilder::default().existential_deposit(15).build().execute_with(|| {
+			let cfg = ConfigCache::preload();
+			set_balance(&ALICE, 1000);
+
+			let mut ctx = ExecutionContext::top_level(ALICE, &cfg, &vm, &loader);
+			assert_eq!(
+				ctx.instantiate(
+					0,
+					&mut GasMeter::<Test>::new(GAS_LIMIT),
+					&constructor_ch,
+					vec![],
+					&[],
+				),
+				Err(Error::<Test>::NewContractNotFunded.into()),
+			);
+		});
+	}
+
 	#[test]
 	fn termination_from_instantiate_fails() {
 		let vm = MockVm::new();
@@ -1448,4 +2137,100 @@ mod tests {
 			assert_matches!(result, Ok(_));
 		});
 	}
+
+	#[test]
+	fn access_list_is_enforced() {
+		let vm = MockVm::new();
+		let mut loader = MockLoader::empty();
+		let declared_ch = loader.insert(|ctx| {
+			assert_eq!(ctx.ext.set_storage([1; 32], Some(vec![42])), Ok(()));
+			assert_eq!(ctx.ext.get_storage(&[1; 32]), Ok(Some(vec![42])));
+			exec_success()
+		});
+		let undeclared_ch = loader.insert(|ctx| {
+			assert_eq!(
+				ctx.ext.get_storage(&[2; 32]),
+				Err(Error::<Test>::UndeclaredKeyAccess.into()),
+			);
+			assert_eq!(
+				ctx.ext.set_storage([2; 32], Some(vec![42])),
+				Err(Error::<Test>::UndeclaredKeyAccess.into()),
+			);
+			assert_eq!(
+				ctx.ext.set_storage([2; 32], None),
+				Err(Error::<Test>::UndeclaredKeyAccess.into()),
+			);
+			exec_success()
+		});
+
+		ExtBuilder::default().build().execute_with(|| {
+			let cfg = ConfigCache::preload();
+			place_contract(&BOB, declared_ch);
+			place_contract(&CHARLIE, undeclared_ch);
+
+			let mut access_list = storage::AccessList::default();
+			access_list.declare(BOB, vec![[1; 32]]);
+			access_list.declare(CHARLIE, vec![[1; 32]]);
+
+			let mut ctx = ExecutionContext::top_level(ALICE, &cfg, &vm, &loader);
+			ctx.set_access_list(access_list);
+			assert_matches!(ctx.call(BOB, 0, &mut GasMeter::<Test>::new(GAS_LIMIT), vec![]), Ok(_));
+			assert_matches!(
+				ctx.call(CHARLIE, 0, &mut GasMeter::<Test>::new(GAS_LIMIT), vec![]),
+				Ok(_)
+			);
+		});
+	}
+
+	#[test]
+	fn access_list_is_off_by_default() {
+		let vm = MockVm::new();
+		let mut loader = MockLoader::empty();
+		let code_ch = loader.insert(|ctx| {
+			assert_eq!(ctx.ext.set_storage([2; 32], Some(vec![42])), Ok(()));
+			assert_eq!(ctx.ext.get_storage(&[2; 32]), Ok(Some(vec![42])));
+			exec_success()
+		});
+
+		ExtBuilder::default().build().execute_with(|| {
+			let cfg = ConfigCache::preload();
+			place_contract(&BOB, code_ch);
+
+			let mut ctx = ExecutionContext::top_level(ALICE, &cfg, &vm, &loader);
+			assert_matches!(ctx.call(BOB, 0, &mut GasMeter::<Test>::new(GAS_LIMIT), vec![]), Ok(_));
+		});
+	}
+
+	#[test]
+	fn key_partitioner_rejects_cross_tenant_writes() {
+		let vm = MockVm::new();
+		let mut loader = MockLoader::empty();
+		let results = RefCell::new(Vec::new());
+		let code_ch = loader.insert(|ctx| {
+			results.borrow_mut().push((
+				ctx.ext.set_storage([1; 32], Some(vec![1])),
+				ctx.ext.set_storage([3; 32], Some(vec![3])),
+			));
+			exec_success()
+		});
+
+		ExtBuilder::default().build().execute_with(|| {
+			let cfg = ConfigCache::preload();
+			place_contract(&BOB, code_ch);
+			KeyPartitioning::set(true);
+
+			for caller in &[ALICE, CHARLIE] {
+				let mut ctx = ExecutionContext::top_level(caller.clone(), &cfg, &vm, &loader);
+				assert_matches!(
+					ctx.call(BOB, 0, &mut GasMeter::<Test>::new(GAS_LIMIT), vec![]),
+					Ok(_)
+				);
+			}
+
+			let denied: Result<(), DispatchError> = Err(Error::<Test>::KeyAccessDenied.into());
+			assert_eq!(*results.borrow(), vec![(Ok(()), denied.clone()), (denied, Ok(()))]);
+			assert_eq!(get_storage(&BOB, &[1; 32]), Some(vec![1]));
+			assert_eq!(get_storage(&BOB, &[3; 32]), Some(vec![3]));
+		});
+	}
 }
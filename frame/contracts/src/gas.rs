@@ -15,12 +15,13 @@
 // along with Substrate. If not, see <http://www.gnu.org/licenses/>.
 
 use crate::Config;
-use sp_std::marker::PhantomData;
+use sp_std::{marker::PhantomData, collections::btree_map::BTreeMap};
 use sp_runtime::traits::Zero;
 use frame_support::dispatch::{
 	DispatchResultWithPostInfo, PostDispatchInfo, DispatchErrorWithPostInfo,
 };
-use pallet_contracts_primitives::ExecError;
+use pallet_contracts_primitives::{ExecError, FrameGas, GasCategory};
+use codec::Encode;
 
 #[cfg(test)]
 use std::{any::Any, fmt::Debug};
@@ -75,6 +76,25 @@ pub trait Token<T: Config>: Copy + Clone + TestAuxiliaries {
 	/// while calculating the amount. In this case it is ok to use saturating operations
 	/// since on overflow they will return `max_value` which should consume all gas.
 	fn calculate_amount(&self, metadata: &Self::Metadata) -> Gas;
+
+	/// The kind of work this token is charged for.
+	///
+	/// This is only consulted when the gas meter records a profile.
+	fn category(&self) -> GasCategory {
+		GasCategory::Other
+	}
+}
+
+/// The gas consumed per `GasCategory`.
+pub type GasProfile = BTreeMap<GasCategory, Gas>;
+
+/// The gas consumed per call frame.
+#[derive(Default)]
+struct GasBreakdown {
+	/// The frames that already returned.
+	frames: Vec<FrameGas>,
+	/// The gas consumed by the nested frames of every frame that didn't return yet.
+	nested: Vec<Gas>,
 }
 
 /// A wrapper around a type-erased trait object of what used to be a `Token`.
@@ -89,6 +109,10 @@ pub struct GasMeter<T: Config> {
 	/// Amount of gas left from initial gas limit. Can reach zero.
 	gas_left: Gas,
 	_phantom: PhantomData<T>,
+	/// Only recorded if profiling was enabled with `enable_profiling`.
+	profile: Option<GasProfile>,
+	/// Only recorded if profiling was enabled with `enable_profiling`.
+	breakdown: Option<GasBreakdown>,
 	#[cfg(test)]
 	tokens: Vec<ErasedToken>,
 }
@@ -98,11 +122,67 @@ impl<T: Config> GasMeter<T> {
 			gas_limit,
 			gas_left: gas_limit,
 			_phantom: PhantomData,
+			profile: None,
+			breakdown: None,
 			#[cfg(test)]
 			tokens: Vec::new(),
 		}
 	}
 
+	/// Record the gas charged by this meter per `GasCategory` and per call frame from now on.
+	///
+	/// Profiling is meant for dry-runs. Meters created by `new` don't record anything.
+	pub fn enable_profiling(&mut self) {
+		self.profile.get_or_insert_with(Default::default);
+		self.breakdown.get_or_insert_with(Default::default);
+	}
+
+	/// Returns the recorded profile or `None` if profiling isn't enabled.
+	pub fn profile(&self) -> Option<&GasProfile> {
+		self.profile.as_ref()
+	}
+
+	/// Returns the gas consumed per call frame or `None` if profiling isn't enabled.
+	///
+	/// Only frames that returned are included.
+	pub fn breakdown(&self) -> Option<&Vec<FrameGas>> {
+		self.breakdown.as_ref().map(|breakdown| &breakdown.frames)
+	}
+
+	/// Start attributing the gas charged from now on to a new call frame.
+	///
+	/// Returns the gas spent so far which needs to be passed to [`Self::exit_frame`] once
+	/// the frame returns. Frames must be exited in the reverse order they were entered.
+	pub fn enter_frame(&mut self) -> Gas {
+		if let Some(breakdown) = self.breakdown.as_mut() {
+			breakdown.nested.push(0);
+		}
+		self.gas_spent()
+	}
+
+	/// Record the gas consumed by the last entered frame since `entered_at`.
+	///
+	/// The gas consumed by its nested frames isn't attributed to it.
+	pub fn exit_frame<A: Encode, H: Encode>(
+		&mut self,
+		entered_at: Gas,
+		account: &A,
+		code_hash: &H,
+	) {
+		let consumed = self.gas_spent().saturating_sub(entered_at);
+		if let Some(breakdown) = self.breakdown.as_mut() {
+			let nested = breakdown.nested.pop().unwrap_or(0);
+			if let Some(parent) = breakdown.nested.last_mut() {
+				*parent = parent.saturating_add(consumed);
+			}
+			breakdown.frames.push(FrameGas {
+				account: account.encode(),
+				code_hash: code_hash.encode(),
+				gas_consumed: consumed.saturating_sub(nested),
+			});
+		}
+	}
+
 	/// Account for used gas.
 	///
 	/// Amount is calculated by the given `token`.
@@ -129,6 +209,10 @@ impl<T: Config> GasMeter<T> {
 		}
 
 		let amount = token.calculate_amount(metadata);
+		if let Some(profile) = self.profile.as_mut() {
+			let consumed = profile.entry(token.category()).or_default();
+			*consumed = consumed.saturating_add(amount.min(self.gas_left));
+		}
 		let new_value = match self.gas_left.checked_sub(amount) {
 			None => None,
 			Some(val) => Some(val),
@@ -146,9 +230,9 @@ impl<T: Config> GasMeter<T> {
 	// Account for not fully used gas.
 	//
 	// This can be used after dispatching a runtime call to refund gas that was not
-	// used by the dispatchable.
+	// used by the dispatchable. The gas left never exceeds the limit.
 	pub fn refund(&mut self, gas: Gas) {
-		self.gas_left = self.gas_left.saturating_add(gas).max(self.gas_limit);
+		self.gas_left = self.gas_left.saturating_add(gas).min(self.gas_limit);
 	}
 
 	/// Allocate some amount of gas and perform some work with
@@ -170,10 +254,14 @@ impl<T: Config> GasMeter<T> {
 		} else {
 			self.gas_left = self.gas_left - amount;
 			let mut nested = GasMeter::new(amount);
+			nested.profile = self.profile.take();
+			nested.breakdown = self.breakdown.take();
 
 			let r = f(Some(&mut nested));
 
 			self.gas_left = self.gas_left + nested.gas_left;
+			self.profile = nested.profile.take();
+			self.breakdown = nested.breakdown.take();
 
 			r
 		}
@@ -294,6 +382,16 @@ mod tests {
 		assert_eq!(gas_meter.gas_left(), 49_970);
 	}
 
+	#[test]
+	fn refund_does_not_exceed_limit() {
+		let mut gas_meter = GasMeter::<Test>::new(50000);
+		assert!(!gas_meter.charge(&(), SimpleToken(100)).is_out_of_gas());
+		gas_meter.refund(40);
+		assert_eq!(gas_meter.gas_left(), 49_940);
+		gas_meter.refund(1_000);
+		assert_eq!(gas_meter.gas_left(), 50000);
+	}
+
 	#[test]
 	fn tracing() {
 		let mut gas_meter = GasMeter::<Test>::new(50000);
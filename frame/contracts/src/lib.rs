@@ -80,24 +80,49 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(feature = "runtime-benchmarks", recursion_limit="256")]
 
+// The helpers behind the `wat` feature need `std` and must never end up in a runtime blob.
+// Feature unification can enable `wat` for a `no_std` build without anyone asking for it.
+#[cfg(all(feature = "wat", not(feature = "std")))]
+compile_error!("the `wat` feature of pallet-contracts requires its `std` feature");
+
+/// Log an error under the `runtime::contracts` target.
+///
+/// Compiles to nothing without the `logging` feature. The arguments are still evaluated
+/// by reference so that they don't become unused.
+macro_rules! log_error {
+	($fmt:literal $(, $arg:expr)* $(,)?) => {{
+		#[cfg(feature = "logging")]
+		frame_support::debug::error!(target: "runtime::contracts", $fmt $(, $arg)*);
+		#[cfg(not(feature = "logging"))]
+		{ $( let _ = &$arg; )* }
+	}};
+}
+
 #[macro_use]
 mod gas;
 mod storage;
 mod exec;
 mod wasm;
 mod rent;
+mod migration;
 mod benchmarking;
 mod schedule;
 pub mod weights;
+#[cfg(feature = "std")]
+pub mod test_utils;
 
 #[cfg(test)]
 mod tests;
 
 pub use crate::{
 	gas::{Gas, GasMeter},
+	storage::meta,
 	wasm::ReturnCode as RuntimeReturnCode,
 	weights::WeightInfo,
-	schedule::{Schedule, HostFnWeights, InstructionWeights, Limits},
+	schedule::{
+		Schedule, HostFnWeights, InstructionWeights, Limits, ScheduleBuilder, ScheduleError,
+		MIN_STACK_HEIGHT, MAX_MEMORY_PAGES,
+	},
 };
 use crate::{
 	exec::ExecutionContext,
@@ -106,29 +131,60 @@ use crate::{
 	storage::Storage,
 };
 use sp_core::crypto::UncheckedFrom;
-use sp_std::{prelude::*, marker::PhantomData, fmt::Debug};
+use sp_std::{prelude::*, marker::PhantomData, fmt::Debug, convert::TryInto};
 use codec::{Codec, Encode, Decode};
 use sp_runtime::{
 	traits::{
 		Hash, StaticLookup, Zero, MaybeSerializeDeserialize, Member, Convert, Saturating,
 	},
-	RuntimeDebug,
+	DispatchError, RuntimeDebug,
 };
 use frame_support::{
-	decl_module, decl_event, decl_storage, decl_error, ensure,
+	decl_module, decl_event, decl_storage, decl_error, ensure, transactional,
 	storage::child::ChildInfo,
 	dispatch::{DispatchResult, DispatchResultWithPostInfo},
-	traits::{OnUnbalanced, Currency, Get, Time, Randomness},
+	traits::{
+		OnUnbalanced, OnKilledAccount, Currency, ReservableCurrency, ExistenceRequirement, Get,
+		Time, Randomness,
+	},
 };
 use frame_system::{ensure_signed, ensure_root};
 use pallet_contracts_primitives::{
 	RentProjectionResult, GetStorageResult, ContractAccessError, ContractExecResult, ExecResult,
+	ContractSnapshotResult, SnapshotChunk, SnapshotHeader, TombstoneInfo, TombstoneListing,
+	ContractBatchExecResult, BatchExecResult, ExecError, ErrorOrigin, ReturnFlags,
+	ContractEvent,
 };
 use frame_support::weights::Weight;
 
 pub type CodeHash<T> = <T as frame_system::Config>::Hash;
 pub type TrieId = Vec<u8>;
 
+// A value placed in storage that represents the current version of the Contracts storage.
+// This value is used by the `on_runtime_upgrade` logic to determine whether we run
+// storage migration logic.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+enum Releases {
+	/// `storage_size` of alive contracts is stored as a `u64`.
+	V1,
+	/// Tombstones store the code hash and storage size of the evicted contract.
+	V2,
+	/// Alive contracts store whether they are frozen.
+	V3,
+	/// `GlobalRentAllowance` is initialized.
+	V4,
+	/// The accounts of alive contracts hold a reference.
+	V5,
+	/// Tombstones store the block in which the contract was evicted.
+	V6,
+	/// Alive contracts count the top-level calls made to them.
+	V7,
+	/// `TombstoneIndex` is initialized.
+	V8,
+	/// Alive contracts store whether they use timelocks.
+	V9,
+}
+
 /// Information for managing an account and its sub trie abstraction.
 /// This is the required info to cache for an account
 #[derive(Encode, Decode, RuntimeDebug)]
@@ -201,7 +257,7 @@ pub struct RawAliveContractInfo<CodeHash, Balance, BlockNumber> {
 	/// The total number of bytes used by this contract.
 	///
 	/// It is a sum of each key-value pair stored by this contract.
-	pub storage_size: u32,
+	pub storage_size: u64,
 	/// The number of key-value pairs that have values of zero length.
 	/// The condition `empty_pair_count ≤ total_pair_count` always holds.
 	pub empty_pair_count: u32,
@@ -215,6 +271,16 @@ pub struct RawAliveContractInfo<CodeHash, Balance, BlockNumber> {
 	pub deduct_block: BlockNumber,
 	/// Last block child storage has been written.
 	pub last_write: Option<BlockNumber>,
+	/// A frozen contract can't write to its storage. Reading it still works.
+	pub frozen: bool,
+	/// The number of successful top-level calls made to this contract.
+	///
+	/// Calls that revert or trap aren't counted.
+	pub call_count: u64,
+	/// Whether a key of this contract was ever locked by `Storage::write_timelock`.
+	///
+	/// Reads only look up the locks of contracts that use them.
+	pub timelocked: bool,
 }
 
 impl<CodeHash, Balance, BlockNumber> RawAliveContractInfo<CodeHash, Balance, BlockNumber> {
@@ -225,28 +291,67 @@ impl<CodeHash, Balance, BlockNumber> RawAliveContractInfo<CodeHash, Balance, Blo
 }
 
 /// Associated child trie unique id is built from the hash part of the trie id.
+///
+/// This is an implementation detail. Use [`meta::child_trie_info`] from outside.
 pub(crate) fn child_trie_info(trie_id: &[u8]) -> ChildInfo {
-	ChildInfo::new_default(trie_id)
+	storage::meta::child_trie_info(trie_id)
 }
 
-pub type TombstoneContractInfo<T> =
-	RawTombstoneContractInfo<<T as frame_system::Config>::Hash, <T as frame_system::Config>::Hashing>;
+pub type TombstoneContractInfo<T> = RawTombstoneContractInfo<
+	<T as frame_system::Config>::Hash,
+	<T as frame_system::Config>::Hashing,
+	<T as frame_system::Config>::BlockNumber,
+>;
 
 #[derive(Encode, Decode, PartialEq, Eq, RuntimeDebug)]
-pub struct RawTombstoneContractInfo<H, Hasher>(H, PhantomData<Hasher>);
+pub struct RawTombstoneContractInfo<H, Hasher, BlockNumber> {
+	/// The hash over the storage root and the code hash of the evicted contract.
+	///
+	/// A restoration must reproduce this hash.
+	hash: H,
+	/// The code hash of the evicted contract.
+	///
+	/// `None` for tombstones that were created before it was recorded.
+	pub code_hash: Option<H>,
+	/// The size of all values stored by the evicted contract.
+	///
+	/// `None` for tombstones that were created before it was recorded.
+	pub storage_size: Option<u64>,
+	/// The block in which the contract was evicted.
+	///
+	/// `None` for tombstones that were created before it was recorded.
+	pub evicted_at: Option<BlockNumber>,
+	_phantom: PhantomData<Hasher>,
+}
 
-impl<H, Hasher> RawTombstoneContractInfo<H, Hasher>
+impl<H, Hasher, BlockNumber> RawTombstoneContractInfo<H, Hasher, BlockNumber>
 where
 	H: Member + MaybeSerializeDeserialize+ Debug
 		+ AsRef<[u8]> + AsMut<[u8]> + Copy + Default
 		+ sp_std::hash::Hash + Codec,
 	Hasher: Hash<Output=H>,
 {
-	fn new(storage_root: &[u8], code_hash: H) -> Self {
+	fn new(storage_root: &[u8], code_hash: H, storage_size: u64, evicted_at: BlockNumber) -> Self {
+		RawTombstoneContractInfo {
+			hash: Self::hash(storage_root, code_hash),
+			code_hash: Some(code_hash),
+			storage_size: Some(storage_size),
+			evicted_at: Some(evicted_at),
+			_phantom: PhantomData,
+		}
+	}
+
+	/// Whether a contract with the given storage root and code hash can be restored
+	/// to this tombstone.
+	fn matches(&self, storage_root: &[u8], code_hash: H) -> bool {
+		self.hash == Self::hash(storage_root, code_hash)
+	}
+
+	fn hash(storage_root: &[u8], code_hash: H) -> H {
 		let mut buf = Vec::new();
 		storage_root.using_encoded(|encoded| buf.extend_from_slice(encoded));
 		buf.extend_from_slice(code_hash.as_ref());
-		RawTombstoneContractInfo(<Hasher as Hash>::hash(&buf[..]), PhantomData)
+		<Hasher as Hash>::hash(&buf[..])
 	}
 }
 
@@ -261,12 +366,104 @@ pub type BalanceOf<T> =
 pub type NegativeImbalanceOf<T> =
 	<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::NegativeImbalance;
 
+/// Decides which storage keys a contract may write to on behalf of its caller.
+///
+/// Contracts that serve multiple tenants can use this in order to keep each tenant
+/// within its own key range.
+pub trait KeyPartitioner<AccountId> {
+	/// Returns whether `contract` may write to `key` while being called by `caller`.
+	fn may_write(contract: &AccountId, caller: &AccountId, key: &exec::StorageKey) -> bool;
+}
+
+impl<AccountId> KeyPartitioner<AccountId> for () {
+	fn may_write(_: &AccountId, _: &AccountId, _: &exec::StorageKey) -> bool {
+		true
+	}
+}
+
+/// The kind of change a write makes to the storage of a contract.
+#[derive(Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum WriteKind {
+	/// A value is stored under a key that had no value.
+	Insert,
+	/// The value stored under a key is replaced.
+	Overwrite,
+	/// The value stored under a key is removed.
+	Delete,
+}
+
+/// Computes the weight of a write to the storage of a contract from the change it makes.
+///
+/// Use `()` in order to charge the flat weights of `seal_set_storage` and `seal_clear_storage`
+/// for every write.
+pub trait WriteCostModel<T: Config> {
+	/// Returns the weight of a write of `kind` which replaces a value of `old_len` bytes
+	/// by one of `new_len` bytes. Absent values have a length of zero.
+	fn write_cost(weights: &HostFnWeights<T>, kind: WriteKind, old_len: u32, new_len: u32) -> Weight;
+}
+
+impl<T: Config> WriteCostModel<T> for () {
+	fn write_cost(weights: &HostFnWeights<T>, kind: WriteKind, _: u32, new_len: u32) -> Weight {
+		match kind {
+			WriteKind::Insert | WriteKind::Overwrite => weights.set_storage
+				.saturating_add(weights.set_storage_per_byte.saturating_mul(new_len.into())),
+			WriteKind::Delete => weights.clear_storage,
+		}
+	}
+}
+
+/// Receives the writes that `Storage::write` and executing contracts make to the storage of
+/// contracts.
+///
+/// In contrast to events this is called synchronously and may store data of its own. This
+/// allows a pallet to maintain a searchable index of contract storage. Use `()` in order to
+/// not mirror any writes.
+pub trait MirrorSink<AccountId> {
+	/// Called after `value` was stored under `hashed_key` of `contract` and its bookkeeping
+	/// was updated. `None` means that the value was removed.
+	///
+	/// With `Config::DeferredBookkeeping` the writes of a contract call are mirrored as they
+	/// happen while the bookkeeping is only updated when the call finishes.
+	///
+	/// The value is passed as written by the contract, i.e. before `Config::ValueCipher`
+	/// was applied.
+	fn mirror(contract: &AccountId, hashed_key: &[u8; 32], value: Option<&[u8]>);
+}
+
+impl<AccountId> MirrorSink<AccountId> for () {
+	fn mirror(_: &AccountId, _: &[u8; 32], _: Option<&[u8]>) {}
+}
+
+/// Transforms the values of contract storage before they are put into the trie.
+///
+/// Use `()` in order to store values as they are.
+pub trait Cipher {
+	/// Transforms a value written by a contract into the bytes that are stored.
+	fn encrypt(plaintext: Vec<u8>) -> Vec<u8>;
+
+	/// Reverses [`Self::encrypt`].
+	fn decrypt(ciphertext: Vec<u8>) -> Vec<u8>;
+}
+
+impl Cipher for () {
+	fn encrypt(plaintext: Vec<u8>) -> Vec<u8> {
+		plaintext
+	}
+
+	fn decrypt(ciphertext: Vec<u8>) -> Vec<u8> {
+		ciphertext
+	}
+}
+
 pub trait Config: frame_system::Config {
 	type Time: Time;
 	type Randomness: Randomness<Self::Hash>;
 
 	/// The currency in which fees are paid and contract balances are held.
-	type Currency: Currency<Self::AccountId>;
+	///
+	/// The reserved balance of a contract can be turned into rent allowance by
+	/// `Storage::refill_rent_allowance_from_reserve`.
+	type Currency: ReservableCurrency<Self::AccountId>;
 
 	/// The overarching event type.
 	type Event: From<Event<Self>> + Into<<Self as frame_system::Config>::Event>;
@@ -281,6 +478,9 @@ pub trait Config: frame_system::Config {
 	type SignedClaimHandicap: Get<Self::BlockNumber>;
 
 	/// The minimum amount required to generate a tombstone.
+	///
+	/// It is reserved on the account of a contract when it is instantiated and pays for its
+	/// tombstone when the contract is evicted.
 	type TombstoneDeposit: Get<BalanceOf<Self>>;
 
 	/// A size offset for an contract. A just created account with untouched storage will have that
@@ -312,6 +512,19 @@ pub trait Config: frame_system::Config {
 	/// The maximum size of a storage value and event payload in bytes.
 	type MaxValueSize: Get<u32>;
 
+	/// The maximum number of calls that can be dispatched by a single `call_batch`.
+	type MaxBatchSize: Get<u32>;
+
+	/// The maximum number of contracts that can be instantiated by a single call including
+	/// all of its nested calls.
+	type MaxInstantiationsPerCall: Get<u32>;
+
+	/// Whether contracts can deposit events.
+	///
+	/// When `false` `seal_deposit_event` is still charged and succeeds but doesn't deposit
+	/// the event. Events deposited by the pallet itself are not affected.
+	type ContractEventsEnabled: Get<bool>;
+
 	/// Used to answer contracts's queries regarding the current weight price. This is **not**
 	/// used to calculate the actual fee and is only for informational purposes.
 	type WeightPrice: Convert<Weight, BalanceOf<Self>>;
@@ -319,6 +532,76 @@ pub trait Config: frame_system::Config {
 	/// Describes the weights of the dispatchables of this module and is also used to
 	/// construct a default cost schedule.
 	type WeightInfo: WeightInfo;
+
+	/// Consulted on every storage write of a contract. Writes that it denies fail
+	/// with `KeyAccessDenied`.
+	///
+	/// Use `()` in order to allow every write.
+	type KeyPartitioner: KeyPartitioner<Self::AccountId>;
+
+	/// Computes the weight of the writes done through `Storage::write_weighted`.
+	///
+	/// Use `()` in order to keep the flat weights of the `Schedule`.
+	type WriteCostModel: WriteCostModel<Self>;
+
+	/// Applied to every value of contract storage when it is written and reversed when
+	/// it is read.
+	///
+	/// The storage size of a contract is measured in encrypted bytes as those are what
+	/// occupies the trie. Use `()` in order to store values unencrypted.
+	type ValueCipher: Cipher;
+
+	/// Informed about every successful write through `Storage::write` and about every write
+	/// of an executing contract.
+	///
+	/// Writes through `Storage::write_deferred` outside of a call are not mirrored as they
+	/// aren't associated with a contract. Use `()` in order to not mirror any writes.
+	type WriteMirror: MirrorSink<Self::AccountId>;
+
+	/// Whether the bookkeeping of contract storage writes is deferred to the end of a call.
+	///
+	/// When `true` the size and pair count changes of all writes of a call are accumulated
+	/// and the `AliveContractInfo` is updated once when the call finishes. Otherwise it is
+	/// updated on every write. Both modes lead to the same final state.
+	///
+	/// Use `()` in order to update the bookkeeping on every write.
+	type DeferredBookkeeping: Get<bool>;
+
+	/// Whether the storage reads of executing contracts are recorded in `ReadAudit`.
+	///
+	/// This turns every read into a write and should therefore only be enabled by chains
+	/// that are required to keep an access log.
+	type AuditReads: Get<bool>;
+
+	/// The number of blocks for which the entries of `ReadAudit` are kept.
+	///
+	/// The entries of a block are removed at the beginning of the block that is
+	/// `AuditRetention` blocks later. Must be at least 1. Lowering it removes the blocks that
+	/// expire at once over the following blocks, see `storage::AUDIT_PRUNE_LIMIT`.
+	type AuditRetention: Get<Self::BlockNumber>;
+
+	/// Whether writes through `Storage::write` bring the rent of the contract up to date.
+	///
+	/// When `true` the rent due since `deduct_block` is charged before the size change of a
+	/// write is applied, which keeps `rent_allowance` current while the storage grows. A
+	/// write whose contract can't pay its rent is rejected instead of evicting the contract.
+	/// Deferred writes are only charged when rent is collected the next time. This is why
+	/// it can't be enabled together with `DeferredBookkeeping`.
+	///
+	/// Use `()` in order to only charge rent when it is collected.
+	type ChargeRentOnWrite: Get<bool>;
+
+	/// Whether a `StorageChanged` event is deposited at the end of every top-level call.
+	///
+	/// The event reports how much storage the call and all of its nested calls added and
+	/// freed. Its weight is charged to the call. Use `()` in order to not deposit it.
+	type StorageDeltaEvents: Get<bool>;
+
+	/// Whether a `ContractStorageBatch` event is deposited for every call that wrote to the
+	/// storage of its contract.
+	///
+	/// Its weight is charged to the call. Use `()` in order to not deposit it.
+	type StorageBatchEvents: Get<bool>;
 }
 
 decl_error! {
@@ -351,6 +634,10 @@ decl_error! {
 		BelowSubsistenceThreshold,
 		/// The newly created contract is below the subsistence threshold after executing
 		/// its contructor. No contracts are allowed to exist below that threshold.
+		///
+		/// The tombstone deposit is reserved out of the endowment before the constructor
+		/// runs. The minimum endowment is therefore the subsistence threshold, i.e. the
+		/// existential deposit plus `TombstoneDeposit`.
 		NewContractNotFunded,
 		/// Performing the requested transfer failed for a reason originating in the
 		/// chosen currency implementation of the runtime. Most probably the balance is
@@ -378,6 +665,43 @@ decl_error! {
 		/// on the call stack. Those actions are contract self destruction and restoration
 		/// of a tombstone.
 		ReentranceDenied,
+		/// The contract that was called was paused by root and can't be called until
+		/// it is unpaused.
+		ContractPaused,
+		/// No empty child trie could be found for a new contract. This indicates that the
+		/// `AccountCounter` is inconsistent with the stored tries.
+		TrieIdCollision,
+		/// The contract accessed a storage key that is not part of the access list of the call.
+		UndeclaredKeyAccess,
+		/// The `KeyPartitioner` doesn't allow the contract to write to the key on behalf
+		/// of its caller.
+		KeyAccessDenied,
+		/// The offset passed to `[seal1] seal_input` lies beyond the end of the input.
+		InputOffsetOutOfBounds,
+		/// More calls than `MaxBatchSize` were passed to `call_batch`.
+		BatchTooLarge,
+		/// One of the calls of a `call_batch` reverted. All calls of the batch were rolled back.
+		BatchCallReverted,
+		/// The contract is frozen and can't write to its storage.
+		ContractFrozen,
+		/// The call already instantiated `MaxInstantiationsPerCall` contracts.
+		TooManyInstantiations,
+		/// The destination contract was evicted in the current block. It can be restored
+		/// from the next block on.
+		TombstoneTooRecent,
+		/// The rent due before a storage write can't be paid from the rent allowance or
+		/// the balance of the contract.
+		RentNotCovered,
+		/// The reserved balance of the contract doesn't cover the amount to unreserve.
+		InsufficientReserve,
+		/// The code failed to be re-instrumented with the current schedule and can't be
+		/// executed until the schedule is changed.
+		CodeRejected,
+		/// Input data was passed to `call` for an account that is no contract.
+		ContractNotFound,
+		/// The `pair_count` passed to `force_terminate` is less than the number of storage
+		/// items of the contract.
+		InvalidPairCountWitness,
 	}
 }
 
@@ -431,8 +755,55 @@ decl_module! {
 		/// The maximum size of a storage value in bytes. A reasonable default is 16 KiB.
 		const MaxValueSize: u32 = T::MaxValueSize::get();
 
+		/// The maximum number of calls that can be dispatched by a single `call_batch`.
+		const MaxBatchSize: u32 = T::MaxBatchSize::get();
+
+		/// The maximum number of contracts that can be instantiated by a single call.
+		const MaxInstantiationsPerCall: u32 = T::MaxInstantiationsPerCall::get();
+
+		/// Whether events deposited by contracts through `seal_deposit_event` are recorded.
+		const ContractEventsEnabled: bool = T::ContractEventsEnabled::get();
+
 		fn deposit_event() = default;
 
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			Storage::<T>::prune_read_audit(n)
+		}
+
+		fn on_runtime_upgrade() -> Weight {
+			migration::migrate::<T>()
+		}
+
+		fn integrity_test() {
+			assert!(
+				T::MaxDepth::get() > 0,
+				"`MaxDepth` must be at least 1, otherwise no contract can ever be called",
+			);
+			assert!(
+				T::MaxBatchSize::get() > 0,
+				"`MaxBatchSize` must be at least 1, otherwise `call_batch` can never succeed",
+			);
+			assert!(
+				!T::AuditRetention::get().is_zero(),
+				"`AuditRetention` must be at least 1, otherwise reads are forgotten in the same block",
+			);
+			assert!(
+				!(T::DeferredBookkeeping::get() && T::ChargeRentOnWrite::get()),
+				"`ChargeRentOnWrite` can't be combined with `DeferredBookkeeping` because deferred \
+				writes don't update the contract info that rent is charged on",
+			);
+
+			// Storage values and event payloads are copied in and out of the linear memory of a
+			// contract. A value that doesn't fit into it could never be read back.
+			let memory_size = u64::from(Limits::default().memory_pages)
+				.saturating_mul(64 * 1024);
+			assert!(
+				u64::from(T::MaxValueSize::get()) < memory_size,
+				"`MaxValueSize` must be smaller than the memory a contract can allocate \
+				according to the default `Limits::memory_pages`",
+			);
+		}
+
 		/// Updates the schedule for metering contracts.
 		///
 		/// The schedule must have a greater version than the stored schedule.
@@ -451,28 +822,38 @@ decl_module! {
 
 		/// Stores the given binary Wasm code into the chain's storage and returns its `codehash`.
 		/// You can instantiate contracts only with stored code.
+		///
+		/// Uploading code that is already stored succeeds without storing it again. It is
+		/// charged much less and doesn't emit `CodeStored`.
 		#[weight = T::WeightInfo::put_code(code.len() as u32 / 1024)]
 		pub fn put_code(
 			origin,
 			code: Vec<u8>
-		) -> DispatchResult {
-			ensure_signed(origin)?;
+		) -> DispatchResultWithPostInfo {
+			let uploader = ensure_signed(origin)?;
 			let schedule = <Module<T>>::current_schedule();
 			ensure!(code.len() as u32 <= schedule.limits.code_size, Error::<T>::CodeTooLarge);
-			let result = wasm::save_code::<T>(code, &schedule);
-			if let Ok(code_hash) = result {
-				Self::deposit_event(RawEvent::CodeStored(code_hash));
+			let code_len = code.len() as u32;
+			if <PristineCode<T>>::contains_key(T::Hashing::hash(&code)) {
+				return Ok(Some(T::WeightInfo::put_code_existing(code_len / 1024)).into());
 			}
-			result.map(|_| ()).map_err(Into::into)
+			let code_hash = wasm::save_code::<T>(code, &schedule)?;
+			Self::deposit_event(RawEvent::CodeStored(code_hash, uploader));
+			Ok(().into())
 		}
 
 		/// Makes a call to an account, optionally transferring some balance.
 		///
 		/// * If the account is a smart-contract account, the associated code will be
 		/// executed and any value will be transferred.
-		/// * If the account is a regular account, any value will be transferred.
-		/// * If no account exists and the call value is not less than `existential_deposit`,
-		/// a regular account will be created and any value will be transferred.
+		/// * If the account is a regular account and `data` is empty, any value will be
+		/// transferred.
+		/// * If no account exists, `data` is empty and the call value is not less than
+		/// `existential_deposit`, a regular account will be created and any value will be
+		/// transferred.
+		/// * If the account is no contract and `data` isn't empty, the call fails with
+		/// `ContractNotFound` and nothing is transferred.
+		/// * If the account is a tombstone, the call fails with `NotCallable`.
 		#[weight = T::WeightInfo::call().saturating_add(*gas_limit)]
 		pub fn call(
 			origin,
@@ -491,6 +872,30 @@ decl_module! {
 			gas_meter.into_dispatch_result(result)
 		}
 
+		/// Makes several calls one after another as a single atomic unit.
+		///
+		/// Each item of `calls` is a `(dest, value, data)` tuple that is handled like the
+		/// arguments of `call`. All calls share the same `gas_limit`. If any of them fails
+		/// or reverts all changes of the batch are rolled back.
+		///
+		/// At most `MaxBatchSize` calls can be passed.
+		#[weight = T::WeightInfo::call_batch(calls.len() as u32).saturating_add(*gas_limit)]
+		pub fn call_batch(
+			origin,
+			calls: Vec<(<T::Lookup as StaticLookup>::Source, BalanceOf<T>, Vec<u8>)>,
+			#[compact] gas_limit: Gas
+		) -> DispatchResultWithPostInfo {
+			let origin = ensure_signed(origin)?;
+			ensure!(calls.len() <= T::MaxBatchSize::get() as usize, Error::<T>::BatchTooLarge);
+			let calls = calls.into_iter()
+				.map(|(dest, value, data)| Ok((T::Lookup::lookup(dest)?, value, data)))
+				.collect::<Result<Vec<_>, DispatchError>>()?;
+			let mut gas_meter = GasMeter::new(gas_limit);
+
+			let result = Self::execute_batch(origin, &mut gas_meter, calls);
+			gas_meter.into_dispatch_result(result)
+		}
+
 		/// Instantiates a new contract from the `code_hash` generated by `put_code`,
 		/// optionally transferring some balance.
 		///
@@ -560,6 +965,101 @@ decl_module! {
 				T::Currency::deposit_into_existing(&rewarded, T::SurchargeReward::get())?;
 			}
 		}
+
+		/// Pause the contract at `dest`.
+		///
+		/// All calls into a paused contract fail with `ContractPaused`. This applies to calls
+		/// that are dispatched as an extrinsic as well as calls from other contracts.
+		/// It doesn't affect rent collection or the eviction of the contract.
+		///
+		/// The dispatch origin for this call must be _Root_.
+		#[weight = T::WeightInfo::pause_contract()]
+		pub fn pause_contract(origin, dest: T::AccountId) -> DispatchResult {
+			ensure_root(origin)?;
+			<PausedContracts<T>>::insert(&dest, ());
+			Self::deposit_event(RawEvent::ContractPaused(dest));
+			Ok(())
+		}
+
+		/// Freeze or unfreeze the contract at `dest`.
+		///
+		/// A frozen contract can still be called and read its storage but any attempt to
+		/// write to it fails with `ContractFrozen`.
+		///
+		/// The dispatch origin for this call must be _Root_.
+		#[weight = T::WeightInfo::set_frozen()]
+		pub fn set_frozen(origin, dest: T::AccountId, frozen: bool) -> DispatchResult {
+			ensure_root(origin)?;
+			Storage::<T>::set_frozen(&dest, frozen)
+				.map_err(|_| Error::<T>::NotCallable)?;
+			Self::deposit_event(RawEvent::FrozenStateChanged(dest, frozen));
+			Ok(())
+		}
+
+		/// Register `layout` as the description of the storage layout of the contract at `dest`.
+		///
+		/// Contracts usually do this themselves with `seal_register_layout`. This allows
+		/// to supply a layout for contracts that don't.
+		///
+		/// The dispatch origin for this call must be _Root_.
+		#[weight = T::WeightInfo::set_layout(layout.len() as u32 / 1024)]
+		pub fn set_layout(origin, dest: T::AccountId, layout: Vec<u8>) -> DispatchResult {
+			ensure_root(origin)?;
+			ensure!(layout.len() as u32 <= T::MaxValueSize::get(), Error::<T>::ValueTooLarge);
+			Storage::<T>::register_layout(&dest, layout)
+				.map_err(|_| Error::<T>::NotCallable)?;
+			Ok(())
+		}
+
+		/// Remove the contract at `contract` and transfer its whole balance to `beneficiary`.
+		///
+		/// This does the same as a contract calling `seal_terminate` and also works on paused
+		/// and frozen contracts. No tombstone is left behind. Tombstones can't be removed
+		/// with this call.
+		///
+		/// `pair_count` must be at least the number of storage items of the contract. The
+		/// weight of removing them is charged based on it.
+		///
+		/// The dispatch origin for this call must be _Root_.
+		#[weight = T::WeightInfo::force_terminate()
+			.saturating_add(Storage::<T>::termination_weight(*pair_count))]
+		#[transactional]
+		pub fn force_terminate(
+			origin,
+			contract: T::AccountId,
+			beneficiary: T::AccountId,
+			#[compact] pair_count: u32,
+		) -> DispatchResult {
+			ensure_root(origin)?;
+			let info = <ContractInfoOf<T>>::get(&contract)
+				.and_then(|c| c.get_alive())
+				.ok_or(Error::<T>::NotCallable)?;
+			ensure!(info.total_pair_count <= pair_count, Error::<T>::InvalidPairCountWitness);
+			// The reference on the account is released before the transfer can reap it. Like
+			// with `seal_terminate` the balance reserved by the contract is released as well
+			// and goes to the beneficiary together with the free balance.
+			Storage::<T>::destroy_contract(&contract, &info.trie_id);
+			T::Currency::transfer(
+				&contract,
+				&beneficiary,
+				T::Currency::free_balance(&contract),
+				ExistenceRequirement::AllowDeath,
+			).map_err(|_| Error::<T>::TransferFailed)?;
+			<PausedContracts<T>>::remove(&contract);
+			Self::deposit_event(RawEvent::Terminated(contract, beneficiary));
+			Ok(())
+		}
+
+		/// Unpause the contract at `dest` which was paused by `pause_contract`.
+		///
+		/// The dispatch origin for this call must be _Root_.
+		#[weight = T::WeightInfo::unpause_contract()]
+		pub fn unpause_contract(origin, dest: T::AccountId) -> DispatchResult {
+			ensure_root(origin)?;
+			<PausedContracts<T>>::remove(&dest);
+			Self::deposit_event(RawEvent::ContractUnpaused(dest));
+			Ok(())
+		}
 	}
 }
 
@@ -581,12 +1081,39 @@ where
 		gas_limit: Gas,
 		input_data: Vec<u8>,
 	) -> ContractExecResult {
+		Self::bare_call_inner(origin, dest, value, gas_limit, input_data, false)
+	}
+
+	/// Perform a call like `Self::bare_call` and record where the gas was spent.
+	///
+	/// The returned `ContractExecResult::profile` contains the gas consumed per
+	/// `GasCategory` and `ContractExecResult::gas_breakdown` the gas consumed by each of
+	/// the called contracts. This is meant for dry-runs that help developers with optimizing
+	/// their contracts.
+	pub fn bare_call_profiled(
+		origin: T::AccountId,
+		dest: T::AccountId,
+		value: BalanceOf<T>,
+		gas_limit: Gas,
+		input_data: Vec<u8>,
+	) -> ContractExecResult {
+		Self::bare_call_inner(origin, dest, value, gas_limit, input_data, true)
+	}
+
+	/// Perform a batch of calls from a specified account.
+	///
+	/// This function is similar to `Self::call_batch`, but doesn't perform any address lookups
+	/// and better suitable for calling directly from Rust. The outputs of all calls are returned
+	/// in order.
+	pub fn bare_call_batch(
+		origin: T::AccountId,
+		calls: Vec<(T::AccountId, BalanceOf<T>, Vec<u8>)>,
+		gas_limit: Gas,
+	) -> ContractBatchExecResult {
 		let mut gas_meter = GasMeter::new(gas_limit);
-		let exec_result = Self::execute_wasm(origin, &mut gas_meter, |ctx, gas_meter| {
-			ctx.call(dest, value, gas_meter, input_data)
-		});
+		let exec_result = Self::execute_batch(origin, &mut gas_meter, calls);
 		let gas_consumed = gas_meter.gas_spent();
-		ContractExecResult {
+		ContractBatchExecResult {
 			exec_result,
 			gas_consumed,
 		}
@@ -607,6 +1134,165 @@ where
 		Rent::<T>::compute_projection(&address)
 	}
 
+	/// Query the information recorded in the tombstone at `address`.
+	///
+	/// Returns `None` if there is no tombstone at `address`.
+	pub fn tombstone_info(address: T::AccountId) -> Option<TombstoneInfo<T::Hash>> {
+		ContractInfoOf::<T>::get(&address)
+			.and_then(|info| info.get_tombstone())
+			.map(|tombstone| TombstoneInfo {
+				code_hash: tombstone.code_hash,
+				storage_size: tombstone.storage_size,
+			})
+	}
+
+	/// List up to `count` tombstones starting after the tombstone at `start`.
+	///
+	/// The listing starts at the first tombstone if `None` is passed. The address of the
+	/// last returned tombstone must be passed as `start` in order to fetch the next page. It
+	/// doesn't need to be a tombstone anymore by then. The order of the tombstones is stable
+	/// but unrelated to their addresses.
+	///
+	/// # Note
+	///
+	/// This is meant to be used by RPC only. Its cost is not metered.
+	pub fn list_tombstones(
+		start: Option<T::AccountId>,
+		count: u32,
+	) -> Vec<TombstoneListing<T::AccountId, T::Hash, BalanceOf<T>, T::BlockNumber>> {
+		let prefix = <TombstoneIndex<T>>::final_prefix();
+		let mut cursor = match start {
+			Some(start) => <TombstoneIndex<T>>::hashed_key_for(&start),
+			None => prefix.to_vec(),
+		};
+		let mut tombstones = Vec::new();
+		while (tombstones.len() as u32) < count {
+			cursor = match sp_io::storage::next_key(&cursor) {
+				Some(key) if key.starts_with(&prefix) => key,
+				_ => break,
+			};
+			// The encoded address follows the 8 byte hash prepended by `twox_64_concat`.
+			let address = match T::AccountId::decode(&mut &cursor[prefix.len() + 8..]) {
+				Ok(address) => address,
+				Err(_) => continue,
+			};
+			let tombstone = match <ContractInfoOf<T>>::get(&address) {
+				Some(ContractInfo::Tombstone(tombstone)) => tombstone,
+				_ => continue,
+			};
+			tombstones.push(TombstoneListing {
+				deposit: T::Currency::total_balance(&address),
+				address,
+				evicted_at: tombstone.evicted_at,
+				code_hash: tombstone.code_hash,
+			});
+		}
+		tombstones
+	}
+
+	/// Check that `TombstoneIndex` contains exactly the tombstones of `ContractInfoOf`.
+	///
+	/// This iterates over all contracts and is meant for tests and off-chain checks of the
+	/// state only.
+	pub fn check_tombstone_index() -> Result<(), &'static str> {
+		for (address, info) in <ContractInfoOf<T>>::iter() {
+			let is_tombstone = matches!(info, ContractInfo::Tombstone(_));
+			if is_tombstone != <TombstoneIndex<T>>::contains_key(&address) {
+				return Err("TombstoneIndex and ContractInfoOf disagree about a contract");
+			}
+		}
+		for (address, ()) in <TombstoneIndex<T>>::iter() {
+			if !<ContractInfoOf<T>>::contains_key(&address) {
+				return Err("TombstoneIndex contains an address without a contract");
+			}
+		}
+		Ok(())
+	}
+
+	/// Check that no alive contract has paid its rent for a block after the current one.
+	///
+	/// This iterates over all contracts and is meant for tests and off-chain checks of the
+	/// state only.
+	pub fn check_deduct_blocks() -> Result<(), &'static str> {
+		let current_block = <frame_system::Module<T>>::block_number();
+		let paid_ahead = <ContractInfoOf<T>>::iter()
+			.filter_map(|(_, info)| info.get_alive())
+			.any(|info| info.deduct_block > current_block);
+		if paid_ahead {
+			return Err("An alive contract has a deduct_block after the current block");
+		}
+		Ok(())
+	}
+
+	/// The events deposited by the contract at `address` in the current block.
+	///
+	/// Only events that were deposited with all of the given `topics` are returned.
+	/// Passing no topics returns all events of the contract.
+	pub fn contract_events(
+		address: &T::AccountId,
+		topics: &[T::Hash],
+	) -> Vec<ContractEvent<T::Hash>>
+	where
+		<T as frame_system::Config>::Event: TryInto<Event<T>>,
+	{
+		<frame_system::Module<T>>::events()
+			.into_iter()
+			.filter(|record| topics.iter().all(|topic| record.topics.contains(topic)))
+			.filter_map(|record| {
+				let topics = record.topics;
+				match record.event.try_into().ok()? {
+					RawEvent::ContractExecution(emitter, data) if &emitter == address =>
+						Some(ContractEvent { topics, data }),
+					_ => None,
+				}
+			})
+			.collect()
+	}
+
+	/// Export a chunk of the code and storage of the contract at `address`.
+	///
+	/// Up to `limit` kv pairs are returned starting at the hashed key `start_key` or at the
+	/// first key if `None` is passed. Only in the latter case the chunk contains the
+	/// `SnapshotHeader` with the code and info of the contract. The `next_key` of the
+	/// returned chunk must be passed as `start_key` in order to fetch the next chunk.
+	///
+	/// # Note
+	///
+	/// This is meant to be used by RPC only. Its cost depends on the size of the code and
+	/// the values stored by the contract and it is not metered in any way.
+	pub fn contract_snapshot(
+		address: T::AccountId,
+		start_key: Option<Vec<u8>>,
+		limit: u32,
+	) -> ContractSnapshotResult<CodeHash<T>, BalanceOf<T>, T::BlockNumber> {
+		let contract_info = ContractInfoOf::<T>::get(&address)
+			.ok_or(ContractAccessError::DoesntExist)?
+			.get_alive()
+			.ok_or(ContractAccessError::IsTombstone)?;
+
+		let header = if start_key.is_none() {
+			Some(SnapshotHeader {
+				code_hash: contract_info.code_hash,
+				pristine_code: <PristineCode<T>>::get(&contract_info.code_hash),
+				free_balance: T::Currency::free_balance(&address),
+				rent_allowance: contract_info.rent_allowance,
+				storage_size: contract_info.storage_size,
+				total_pair_count: contract_info.total_pair_count,
+				deduct_block: contract_info.deduct_block,
+				last_write: contract_info.last_write,
+				call_count: contract_info.call_count,
+			})
+		} else {
+			None
+		};
+		let (pairs, next_key) = Storage::<T>::read_page(&contract_info.trie_id, start_key, limit);
+		Ok(SnapshotChunk {
+			header,
+			pairs,
+			next_key,
+		})
+	}
+
 	/// Put code for benchmarks which does not check or instrument the code.
 	#[cfg(feature = "runtime-benchmarks")]
 	pub fn put_code_raw(code: Vec<u8>) -> DispatchResult {
@@ -642,17 +1328,92 @@ impl<T: Config> Module<T>
 where
 	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
 {
-	fn execute_wasm(
+	fn bare_call_inner(
+		origin: T::AccountId,
+		dest: T::AccountId,
+		value: BalanceOf<T>,
+		gas_limit: Gas,
+		input_data: Vec<u8>,
+		profile: bool,
+	) -> ContractExecResult {
+		let mut gas_meter = GasMeter::new(gas_limit);
+		if profile {
+			gas_meter.enable_profiling();
+		}
+		let (exec_result, schedule_version) =
+			Self::execute_wasm(origin, &mut gas_meter, |ctx, gas_meter| {
+				(ctx.call(dest, value, gas_meter, input_data), ctx.config.schedule.version)
+			});
+		let gas_consumed = gas_meter.gas_spent();
+		ContractExecResult {
+			exec_result,
+			gas_consumed,
+			profile: gas_meter.profile().cloned(),
+			gas_breakdown: gas_meter.breakdown().cloned(),
+			schedule_version,
+		}
+	}
+
+	fn execute_wasm<R>(
 		origin: T::AccountId,
 		gas_meter: &mut GasMeter<T>,
-		func: impl FnOnce(&mut ExecutionContext<T, WasmVm<T>, WasmLoader<T>>, &mut GasMeter<T>) -> ExecResult,
-	) -> ExecResult {
+		func: impl FnOnce(&mut ExecutionContext<T, WasmVm<T>, WasmLoader<T>>, &mut GasMeter<T>) -> R,
+	) -> R {
 		let cfg = ConfigCache::preload();
 		let vm = WasmVm::new(&cfg.schedule);
 		let loader = WasmLoader::new(&cfg.schedule);
 		let mut ctx = ExecutionContext::top_level(origin, &cfg, &vm, &loader);
 		func(&mut ctx, gas_meter)
 	}
+
+	/// Execute `calls` one after another from the same top level context.
+	///
+	/// All changes are rolled back if any of the calls fails or reverts.
+	fn execute_batch(
+		origin: T::AccountId,
+		gas_meter: &mut GasMeter<T>,
+		calls: Vec<(T::AccountId, BalanceOf<T>, Vec<u8>)>,
+	) -> BatchExecResult {
+		use frame_support::storage::{with_transaction, TransactionOutcome::*};
+		with_transaction(|| {
+			let result = Self::execute_wasm(origin, gas_meter, |ctx, gas_meter| {
+				calls.into_iter()
+					.map(|(dest, value, data)| {
+						let output = ctx.call(dest, value, gas_meter, data)?;
+						if output.flags.contains(ReturnFlags::REVERT) {
+							return Err(ExecError {
+								error: Error::<T>::BatchCallReverted.into(),
+								origin: ErrorOrigin::Callee,
+							});
+						}
+						Ok(output)
+					})
+					.collect::<BatchExecResult>()
+			});
+			match result {
+				Ok(_) => Commit(result),
+				Err(_) => Rollback(result),
+			}
+		})
+	}
+}
+
+/// Removes contracts whose accounts were reaped.
+///
+/// The accounts of alive contracts hold a reference which prevents transfers from reaping
+/// them. They can still be reaped by other means, e.g. by slashing. A contract whose account
+/// is gone can't afford a tombstone and is therefore removed completely.
+impl<T: Config> OnKilledAccount<T::AccountId> for Module<T>
+where
+	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
+{
+	fn on_killed_account(who: &T::AccountId) {
+		if let Some(ContractInfo::Alive(info)) = <ContractInfoOf<T>>::get(who) {
+			Storage::<T>::destroy_contract(who, &info.trie_id);
+			<PausedContracts<T>>::remove(who);
+			Self::deposit_event(RawEvent::Evicted(who.clone(), false));
+		}
+	}
 }
 
 decl_event! {
@@ -685,9 +1446,9 @@ decl_event! {
 		/// - `rent_allowance: `Balance`: Rent allowance of the restored contract
 		Restored(AccountId, AccountId, Hash, Balance),
 
-		/// Code with the specified hash has been stored.
-		/// \[code_hash\]
-		CodeStored(Hash),
+		/// Code with the specified hash has been stored by the uploader.
+		/// \[code_hash, uploader\]
+		CodeStored(Hash, AccountId),
 
 		/// Triggered when the current \[schedule\] is updated.
 		ScheduleUpdated(u32),
@@ -695,6 +1456,57 @@ decl_event! {
 		/// An event deposited upon execution of a contract from the account.
 		/// \[account, data\]
 		ContractExecution(AccountId, Vec<u8>),
+
+		/// All calls into the contract are denied until it is unpaused.
+		/// \[contract\]
+		ContractPaused(AccountId),
+
+		/// The contract can be called again.
+		/// \[contract\]
+		ContractUnpaused(AccountId),
+
+		/// Summary of all storage writes a contract performed during a call.
+		/// \[contract, total_size_delta, total_pair_delta, writes\]
+		///
+		/// Deposited once when the call returns successfully and
+		/// `Config::StorageBatchEvents` is enabled. Nothing is deposited for calls that are
+		/// reverted or that didn't write to storage.
+		ContractStorageBatch(AccountId, i64, i64, u32),
+
+		/// The contract was frozen or unfrozen by `set_frozen`.
+		/// \[contract, frozen\]
+		FrozenStateChanged(AccountId, bool),
+
+		/// The contract was removed by `force_terminate`.
+		/// \[contract, beneficiary\]
+		///
+		/// # Params
+		///
+		/// - `contract`: The contract that was removed.
+		/// - `beneficiary`: The account that received the whole balance of the contract.
+		Terminated(AccountId, AccountId),
+
+		/// Code with the specified hash failed to be re-instrumented with the current
+		/// schedule. It can't be executed until the schedule version is increased.
+		/// \[code_hash\]
+		///
+		/// Deposited only once for every schedule version that rejects the code.
+		CodeRejected(Hash),
+
+		/// The storage usage of a top-level call and all of its nested calls.
+		/// \[contract, bytes_added, bytes_freed, items_added, items_freed\]
+		///
+		/// # Params
+		///
+		/// - `contract`: The contract that was called or instantiated by the origin.
+		/// - `bytes_added`: The bytes by which the writes of the call grew the storage.
+		/// - `bytes_freed`: The bytes by which the writes of the call shrank the storage.
+		/// - `items_added`: The number of keys that the call wrote to for the first time.
+		/// - `items_freed`: The number of keys that the call removed.
+		///
+		/// Only deposited when `Config::StorageDeltaEvents` is enabled and the call changed
+		/// the storage. Writes of reverted calls are not included.
+		StorageChanged(AccountId, u64, u64, u32, u32),
 	}
 }
 
@@ -715,6 +1527,126 @@ decl_storage! {
 		///
 		/// TWOX-NOTE: SAFE since `AccountId` is a secure hash.
 		pub ContractInfoOf: map hasher(twox_64_concat) T::AccountId => Option<ContractInfo<T>>;
+		/// Contracts that were paused by root and therefore can't be called.
+		///
+		/// TWOX-NOTE: SAFE since `AccountId` is a secure hash.
+		pub PausedContracts: map hasher(twox_64_concat) T::AccountId => Option<()>;
+		/// The addresses of all tombstones in `ContractInfoOf`.
+		///
+		/// Allows to list the tombstones without iterating over all contracts.
+		///
+		/// TWOX-NOTE: SAFE since `AccountId` is a secure hash.
+		pub TombstoneIndex: map hasher(twox_64_concat) T::AccountId => Option<()>;
+		/// The tombstone deposit that was reserved on the account of a contract when it was
+		/// instantiated.
+		///
+		/// It pays for the tombstone when the contract is evicted and is released when the
+		/// contract is terminated or used for a restoration. Contracts that were instantiated
+		/// before deposits were reserved have no entry.
+		///
+		/// TWOX-NOTE: SAFE since `AccountId` is a secure hash.
+		pub TombstoneDeposits: map hasher(twox_64_concat) T::AccountId => BalanceOf<T>;
+		/// A description of the storage layout of a contract for use by tooling.
+		///
+		/// It is registered by the contract itself or by root and opaque to the runtime.
+		///
+		/// TWOX-NOTE: SAFE since `AccountId` is a secure hash.
+		pub ContractLayout get(fn layout): map hasher(twox_64_concat) T::AccountId => Option<Vec<u8>>;
+		/// The most recent idempotency keys of writes done through `Storage::write_idempotent`.
+		///
+		/// Bounded by `storage::IDEMPOTENCY_HISTORY_LEN` and ordered from oldest to newest.
+		///
+		/// TWOX-NOTE: SAFE since `AccountId` is a secure hash.
+		pub IdempotencyKeys: map hasher(twox_64_concat) T::AccountId => Vec<[u8; 32]>;
+		/// Contracts whose writes through `Storage::write_attributed` are attributed to
+		/// their writer.
+		///
+		/// TWOX-NOTE: SAFE since `AccountId` is a secure hash.
+		pub AttributedContracts: map hasher(twox_64_concat) T::AccountId => Option<()>;
+		/// The account that last wrote to a key of a contract together with the block of
+		/// the write, indexed by trie id and hashed key.
+		///
+		/// TWOX-NOTE: SAFE since the trie id is derived from a secure hash. The hashed key
+		/// is already a blake2 hash.
+		pub WriteAttribution:
+			double_map hasher(twox_64_concat) TrieId, hasher(identity) [u8; 32]
+			=> Option<(T::AccountId, T::BlockNumber)>;
+		/// Values stored through `Storage::write_dedup` by their blake2 hash together with
+		/// the number of keys pointing to them.
+		pub SharedBlobs get(fn shared_blob): map hasher(identity) [u8; 32] => Option<(u32, Vec<u8>)>;
+		/// The number of bytes stored in `SharedBlobs`.
+		pub SharedBlobBytes get(fn shared_blob_bytes): u64;
+		/// The content hash that a key of a contract written by `Storage::write_dedup` points
+		/// to, indexed by trie id and hashed key.
+		///
+		/// TWOX-NOTE: SAFE since the trie id is derived from a secure hash. The hashed key
+		/// is already a blake2 hash.
+		pub DedupPointers:
+			double_map hasher(twox_64_concat) TrieId, hasher(identity) [u8; 32] => Option<[u8; 32]>;
+		/// The block from which on a key of a contract written by `Storage::write_timelock`
+		/// becomes readable, indexed by trie id and hashed key.
+		///
+		/// TWOX-NOTE: SAFE since the trie id is derived from a secure hash. The hashed key
+		/// is already a blake2 hash.
+		pub Timelocks:
+			double_map hasher(twox_64_concat) TrieId, hasher(identity) [u8; 32]
+			=> Option<T::BlockNumber>;
+		/// The integer keys that were written by `Storage::write_ordered`, indexed by trie id
+		/// and the big endian encoding of the key.
+		///
+		/// The second key isn't hashed so that the keys of a trie are stored in numeric order.
+		///
+		/// TWOX-NOTE: SAFE since the trie id is derived from a secure hash. The second key is
+		/// chosen by the contract but only ever iterated for its own trie id.
+		pub OrderedIndex:
+			double_map hasher(twox_64_concat) TrieId, hasher(identity) [u8; 16] => Option<()>;
+		/// The contract that owns a child trie, indexed by its trie id.
+		///
+		/// Recorded for every contract placed by `Storage::place_contract` so that
+		/// `Storage::write_raw` can refuse to write to the trie of a contract.
+		///
+		/// TWOX-NOTE: SAFE since the trie id is derived from a secure hash.
+		pub TrieOwner: map hasher(twox_64_concat) TrieId => Option<T::AccountId>;
+		/// The sum of the rent allowances of all alive contracts.
+		///
+		/// See `Storage::total_rent_allowance` for which allowances are included.
+		pub GlobalRentAllowance: BalanceOf<T>;
+		/// The storage reads of executing contracts by block when `Config::AuditReads`
+		/// is enabled.
+		///
+		/// Every entry consists of the trie id, the hashed key and the reading contract.
+		/// Entries are kept for `Config::AuditRetention` blocks.
+		///
+		/// TWOX-NOTE: SAFE since the block number isn't controlled by users.
+		pub ReadAudit get(fn read_audit):
+			map hasher(twox_64_concat) T::BlockNumber => Vec<(TrieId, [u8; 32], T::AccountId)>;
+		/// The first and the last block that have entries in `ReadAudit`.
+		///
+		/// This allows to prune every expired block even if `Config::AuditReads` was disabled
+		/// or `Config::AuditRetention` was lowered since the entries were recorded.
+		pub ReadAuditBlocks get(fn read_audit_blocks): Option<(T::BlockNumber, T::BlockNumber)>;
+		/// The bookkeeping of the storage that is shared by all contracts with the same
+		/// code hash.
+		///
+		/// See `Storage::write_shared`.
+		pub SharedStorage: map hasher(identity) CodeHash<T> => storage::SharedStorageInfo;
+		/// The contract that pays for a pair of the storage shared by the contracts of a code
+		/// hash, indexed by code hash and hashed key.
+		///
+		/// See `Storage::write_shared`.
+		pub SharedPairOwners:
+			double_map hasher(identity) CodeHash<T>, hasher(identity) [u8; 32]
+			=> Option<T::AccountId>;
+		/// The pairs of shared storage that a contract pays for, indexed by contract and hashed
+		/// key. The value is the code hash whose shared storage holds the pair.
+		///
+		/// TWOX-NOTE: SAFE since `AccountId` is a secure hash. The hashed key is already a
+		/// blake2 hash.
+		pub SharedPairsOf:
+			double_map hasher(twox_64_concat) T::AccountId, hasher(identity) [u8; 32]
+			=> Option<CodeHash<T>>;
+		/// Storage version of the pallet. `None` for chains that predate versioning.
+		StorageVersion build(|_| Some(Releases::V9)): Option<Releases>;
 	}
 }
 
@@ -728,6 +1660,7 @@ pub struct ConfigCache<T: Config> {
 	pub tombstone_deposit: BalanceOf<T>,
 	pub max_depth: u32,
 	pub max_value_size: u32,
+	pub max_instantiations: u32,
 }
 
 impl<T: Config> ConfigCache<T>
@@ -741,6 +1674,7 @@ where
 			tombstone_deposit: T::TombstoneDeposit::get(),
 			max_depth: T::MaxDepth::get(),
 			max_value_size: T::MaxValueSize::get(),
+			max_instantiations: T::MaxInstantiationsPerCall::get(),
 		}
 	}
 
@@ -0,0 +1,336 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate. If not, see <http://www.gnu.org/licenses/>.
+
+//! Migrations of the contracts storage to its current layout.
+
+use crate::{
+	AliveContractInfo, BalanceOf, CodeHash, Config, ContractInfo, ContractInfoOf,
+	GlobalRentAllowance, Releases, StorageVersion, TombstoneContractInfo, TombstoneIndex, TrieId,
+	storage::Storage,
+};
+use codec::Decode;
+use sp_core::crypto::UncheckedFrom;
+use sp_std::{cell::Cell, marker::PhantomData};
+use frame_support::{
+	storage::{IterableStorageMap, StorageValue},
+	traits::Get,
+	weights::Weight,
+};
+
+/// An `AliveContractInfo` as it was stored before `storage_size` was widened to a `u64`.
+#[derive(Decode)]
+struct AliveContractInfoV0<T: Config> {
+	trie_id: TrieId,
+	storage_size: u32,
+	empty_pair_count: u32,
+	total_pair_count: u32,
+	code_hash: CodeHash<T>,
+	rent_allowance: BalanceOf<T>,
+	deduct_block: T::BlockNumber,
+	last_write: Option<T::BlockNumber>,
+}
+
+/// A `TombstoneContractInfo` as it was stored before the code hash and storage size
+/// were recorded.
+#[derive(Decode)]
+struct TombstoneContractInfoV1<T: Config>(T::Hash);
+
+impl<T: Config> From<TombstoneContractInfoV1<T>> for TombstoneContractInfo<T> {
+	fn from(old: TombstoneContractInfoV1<T>) -> Self {
+		TombstoneContractInfo::<T> {
+			hash: old.0,
+			code_hash: None,
+			storage_size: None,
+			evicted_at: None,
+			_phantom: PhantomData,
+		}
+	}
+}
+
+/// A `TombstoneContractInfo` as it was stored before the eviction block was recorded.
+#[derive(Decode)]
+struct TombstoneContractInfoV2<T: Config> {
+	hash: T::Hash,
+	code_hash: Option<T::Hash>,
+	storage_size: Option<u64>,
+}
+
+impl<T: Config> From<TombstoneContractInfoV2<T>> for TombstoneContractInfo<T> {
+	fn from(old: TombstoneContractInfoV2<T>) -> Self {
+		TombstoneContractInfo::<T> {
+			hash: old.hash,
+			code_hash: old.code_hash,
+			storage_size: old.storage_size,
+			evicted_at: None,
+			_phantom: PhantomData,
+		}
+	}
+}
+
+/// An `AliveContractInfo` as it was stored before the `frozen` flag was added.
+#[derive(Decode)]
+struct AliveContractInfoV2<T: Config> {
+	trie_id: TrieId,
+	storage_size: u64,
+	empty_pair_count: u32,
+	total_pair_count: u32,
+	code_hash: CodeHash<T>,
+	rent_allowance: BalanceOf<T>,
+	deduct_block: T::BlockNumber,
+	last_write: Option<T::BlockNumber>,
+}
+
+impl<T: Config> From<AliveContractInfoV2<T>> for AliveContractInfo<T> {
+	fn from(old: AliveContractInfoV2<T>) -> Self {
+		AliveContractInfo::<T> {
+			trie_id: old.trie_id,
+			storage_size: old.storage_size,
+			empty_pair_count: old.empty_pair_count,
+			total_pair_count: old.total_pair_count,
+			code_hash: old.code_hash,
+			rent_allowance: old.rent_allowance,
+			deduct_block: old.deduct_block,
+			last_write: old.last_write,
+			frozen: false,
+			call_count: 0,
+			timelocked: false,
+		}
+	}
+}
+
+/// An `AliveContractInfo` as it was stored before the calls to it were counted.
+#[derive(Decode)]
+struct AliveContractInfoV3<T: Config> {
+	trie_id: TrieId,
+	storage_size: u64,
+	empty_pair_count: u32,
+	total_pair_count: u32,
+	code_hash: CodeHash<T>,
+	rent_allowance: BalanceOf<T>,
+	deduct_block: T::BlockNumber,
+	last_write: Option<T::BlockNumber>,
+	frozen: bool,
+}
+
+impl<T: Config> From<AliveContractInfoV3<T>> for AliveContractInfo<T> {
+	fn from(old: AliveContractInfoV3<T>) -> Self {
+		AliveContractInfo::<T> {
+			trie_id: old.trie_id,
+			storage_size: old.storage_size,
+			empty_pair_count: old.empty_pair_count,
+			total_pair_count: old.total_pair_count,
+			code_hash: old.code_hash,
+			rent_allowance: old.rent_allowance,
+			deduct_block: old.deduct_block,
+			last_write: old.last_write,
+			frozen: old.frozen,
+			call_count: 0,
+			timelocked: false,
+		}
+	}
+}
+
+/// An `AliveContractInfo` as it was stored before the use of timelocks was recorded.
+#[derive(Decode)]
+struct AliveContractInfoV7<T: Config> {
+	trie_id: TrieId,
+	storage_size: u64,
+	empty_pair_count: u32,
+	total_pair_count: u32,
+	code_hash: CodeHash<T>,
+	rent_allowance: BalanceOf<T>,
+	deduct_block: T::BlockNumber,
+	last_write: Option<T::BlockNumber>,
+	frozen: bool,
+	call_count: u64,
+}
+
+impl<T: Config> From<AliveContractInfoV7<T>> for AliveContractInfo<T> {
+	fn from(old: AliveContractInfoV7<T>) -> Self {
+		AliveContractInfo::<T> {
+			trie_id: old.trie_id,
+			storage_size: old.storage_size,
+			empty_pair_count: old.empty_pair_count,
+			total_pair_count: old.total_pair_count,
+			code_hash: old.code_hash,
+			rent_allowance: old.rent_allowance,
+			deduct_block: old.deduct_block,
+			last_write: old.last_write,
+			frozen: old.frozen,
+			call_count: old.call_count,
+			timelocked: false,
+		}
+	}
+}
+
+/// A `ContractInfo` as it was stored before `storage_size` was widened to a `u64`.
+#[derive(Decode)]
+enum ContractInfoV0<T: Config> {
+	Alive(AliveContractInfoV0<T>),
+	Tombstone(TombstoneContractInfoV1<T>),
+}
+
+/// A `ContractInfo` as it was stored before tombstones recorded the code hash and
+/// storage size.
+#[derive(Decode)]
+enum ContractInfoV1<T: Config> {
+	Alive(AliveContractInfoV2<T>),
+	Tombstone(TombstoneContractInfoV1<T>),
+}
+
+/// A `ContractInfo` as it was stored before the `frozen` flag was added.
+#[derive(Decode)]
+enum ContractInfoV2<T: Config> {
+	Alive(AliveContractInfoV2<T>),
+	Tombstone(TombstoneContractInfoV2<T>),
+}
+
+/// A `ContractInfo` as it was stored before tombstones recorded the eviction block.
+#[derive(Decode)]
+enum ContractInfoV5<T: Config> {
+	Alive(AliveContractInfoV3<T>),
+	Tombstone(TombstoneContractInfoV2<T>),
+}
+
+/// A `ContractInfo` as it was stored before the calls to alive contracts were counted.
+#[derive(Decode)]
+enum ContractInfoV6<T: Config> {
+	Alive(AliveContractInfoV3<T>),
+	Tombstone(TombstoneContractInfo<T>),
+}
+
+/// A `ContractInfo` as it was stored before alive contracts recorded the use of timelocks.
+#[derive(Decode)]
+enum ContractInfoV8<T: Config> {
+	Alive(AliveContractInfoV7<T>),
+	Tombstone(TombstoneContractInfo<T>),
+}
+
+/// Migrate the storage to the current `Releases` and return the consumed weight.
+///
+/// Does nothing if the storage is already up to date.
+pub fn migrate<T: Config>() -> Weight
+where
+	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
+{
+	let translated = Cell::new(0u64);
+	let count = || translated.set(translated.get() + 1);
+	let version = StorageVersion::get();
+	match version {
+		None => <ContractInfoOf<T>>::translate::<ContractInfoV0<T>, _>(|_, old| {
+			count();
+			Some(match old {
+				ContractInfoV0::Alive(old) => ContractInfo::Alive(AliveContractInfo::<T> {
+					trie_id: old.trie_id,
+					storage_size: old.storage_size.into(),
+					empty_pair_count: old.empty_pair_count,
+					total_pair_count: old.total_pair_count,
+					code_hash: old.code_hash,
+					rent_allowance: old.rent_allowance,
+					deduct_block: old.deduct_block,
+					last_write: old.last_write,
+					frozen: false,
+					call_count: 0,
+					timelocked: false,
+				}),
+				ContractInfoV0::Tombstone(old) => ContractInfo::Tombstone(old.into()),
+			})
+		}),
+		Some(Releases::V1) => <ContractInfoOf<T>>::translate::<ContractInfoV1<T>, _>(|_, old| {
+			count();
+			Some(match old {
+				ContractInfoV1::Alive(old) => ContractInfo::Alive(old.into()),
+				ContractInfoV1::Tombstone(old) => ContractInfo::Tombstone(old.into()),
+			})
+		}),
+		Some(Releases::V2) => <ContractInfoOf<T>>::translate::<ContractInfoV2<T>, _>(|_, old| {
+			count();
+			Some(match old {
+				ContractInfoV2::Alive(old) => ContractInfo::Alive(old.into()),
+				ContractInfoV2::Tombstone(old) => ContractInfo::Tombstone(old.into()),
+			})
+		}),
+		Some(Releases::V3) | Some(Releases::V4) | Some(Releases::V5) =>
+			<ContractInfoOf<T>>::translate::<ContractInfoV5<T>, _>(|_, old| {
+				count();
+				Some(match old {
+					ContractInfoV5::Alive(old) => ContractInfo::Alive(old.into()),
+					ContractInfoV5::Tombstone(old) => ContractInfo::Tombstone(old.into()),
+				})
+			}),
+		Some(Releases::V6) => <ContractInfoOf<T>>::translate::<ContractInfoV6<T>, _>(|_, old| {
+			count();
+			Some(match old {
+				ContractInfoV6::Alive(old) => ContractInfo::Alive(old.into()),
+				ContractInfoV6::Tombstone(info) => ContractInfo::Tombstone(info),
+			})
+		}),
+		Some(Releases::V7) | Some(Releases::V8) =>
+			<ContractInfoOf<T>>::translate::<ContractInfoV8<T>, _>(|_, old| {
+				count();
+				Some(match old {
+					ContractInfoV8::Alive(old) => ContractInfo::Alive(old.into()),
+					ContractInfoV8::Tombstone(info) => ContractInfo::Tombstone(info),
+				})
+			}),
+		Some(Releases::V9) => return T::DbWeight::get().reads(1),
+	}
+
+	// Accumulate the allowances of all alive contracts from scratch unless that was
+	// already done. Each contract accounts for one read and one write of the aggregate.
+	let init_allowance = !matches!(
+		version,
+		Some(Releases::V4) | Some(Releases::V5) | Some(Releases::V6) | Some(Releases::V7) |
+			Some(Releases::V8)
+	);
+	if init_allowance {
+		<GlobalRentAllowance<T>>::kill();
+	}
+	// Every alive contract additionally reads and writes its account to take a reference.
+	let hold_accounts = !matches!(
+		version,
+		Some(Releases::V5) | Some(Releases::V6) | Some(Releases::V7) | Some(Releases::V8)
+	);
+	let mut accessed = 0u64;
+	let mut scanned = 0u64;
+	// The `TombstoneIndex` is built by every migration that starts before `V8`. Nothing
+	// else needs the contracts to be scanned.
+	let build_index = version != Some(Releases::V8);
+	for (account, info) in <ContractInfoOf<T>>::iter().take_while(|_| build_index) {
+		scanned += 1;
+		match info {
+			ContractInfo::Alive(info) => {
+				accessed += hold_accounts as u64 + init_allowance as u64;
+				if init_allowance {
+					Storage::<T>::note_rent_allowance_change(None, Some(info.rent_allowance));
+				}
+				if hold_accounts {
+					Storage::<T>::hold_account(&account);
+				}
+			}
+			ContractInfo::Tombstone(_) => {
+				accessed += 1;
+				<TombstoneIndex<T>>::insert(&account, ());
+			}
+		}
+	}
+	StorageVersion::put(Releases::V9);
+
+	T::DbWeight::get().reads_writes(
+		translated.get() + scanned + accessed + 1,
+		translated.get() + accessed + 2,
+	)
+}
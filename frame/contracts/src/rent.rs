@@ -17,15 +17,16 @@
 //! A module responsible for computing the right amount of weight and charging it.
 
 use crate::{
-	AliveContractInfo, BalanceOf, ContractInfo, ContractInfoOf, Module, RawEvent,
-	TombstoneContractInfo, Config, CodeHash, ConfigCache, Error,
+	AliveContractInfo, BalanceOf, ContractInfo, ContractInfoOf, ContractLayout, Module, RawEvent,
+	IdempotencyKeys, AttributedContracts, Timelocks, TrieOwner,
+	TombstoneContractInfo, TombstoneIndex, Config, CodeHash, ConfigCache, Error,
+	storage::{meta::hashed_key, Storage, StorageMeter},
 };
 use sp_std::prelude::*;
-use sp_io::hashing::blake2_256;
 use sp_core::crypto::UncheckedFrom;
 use frame_support::storage::child;
 use frame_support::traits::{Currency, ExistenceRequirement, Get, OnUnbalanced, WithdrawReasons};
-use frame_support::StorageMap;
+use frame_support::{StorageMap, StorageDoubleMap};
 use pallet_contracts_primitives::{ContractAccessError, RentProjection, RentProjectionResult};
 use sp_runtime::{
 	DispatchError,
@@ -97,7 +98,7 @@ where
 	///
 	/// This function accounts for the storage rent deposit. I.e. if the contract possesses enough funds
 	/// then the fee can drop to zero.
-	fn compute_fee_per_block(
+	pub fn compute_fee_per_block(
 		free_balance: &BalanceOf<T>,
 		contract: &AliveContractInfo<T>
 	) -> BalanceOf<T> {
@@ -108,10 +109,11 @@ where
 		// For now, we treat every empty KV pair as if it was one byte long.
 		let empty_pairs_equivalent = contract.empty_pair_count;
 
-		let effective_storage_size = <BalanceOf<T>>::from(
-			contract.storage_size + T::StorageSizeOffset::get() + empty_pairs_equivalent,
-		)
-		.saturating_sub(free_storage);
+		let effective_storage_size = contract.storage_size
+			.saturating_add(T::StorageSizeOffset::get().into())
+			.saturating_add(empty_pairs_equivalent.into())
+			.saturated_into::<BalanceOf<T>>()
+			.saturating_sub(free_storage);
 
 		effective_storage_size
 			.checked_mul(&T::RentByteFee::get())
@@ -125,6 +127,7 @@ where
 	///
 	/// In case the toal_balance is below the subsistence threshold, this function returns `None`.
 	fn rent_budget(
+		account: &T::AccountId,
 		total_balance: &BalanceOf<T>,
 		free_balance: &BalanceOf<T>,
 		contract: &AliveContractInfo<T>,
@@ -137,8 +140,10 @@ where
 		}
 
 		// However, reserved balance cannot be charged so we need to use the free balance
-		// to calculate the actual budget (which can be 0).
-		let rent_allowed_to_charge = free_balance.saturating_sub(subsistence_threshold);
+		// to calculate the actual budget (which can be 0). The part of the threshold that is
+		// covered by the reserved tombstone deposit doesn't need to be kept free.
+		let rent_allowed_to_charge = free_balance
+			.saturating_sub(Storage::<T>::required_free_balance(account));
 		Some(<BalanceOf<T>>::min(
 			contract.rent_allowance,
 			rent_allowed_to_charge,
@@ -170,15 +175,28 @@ where
 		let free_balance = T::Currency::free_balance(account);
 
 		// An amount of funds to charge per block for storage taken up by the contract.
-		let fee_per_block = Self::compute_fee_per_block(&free_balance, contract);
+		let fee_per_block = Self::compute_fee_per_block(
+			&Storage::<T>::rent_deposit_balance(account),
+			contract,
+		);
 		if fee_per_block.is_zero() {
 			// The rent deposit offset reduced the fee to 0. This means that the contract
-			// gets the rent for free.
-			return Verdict::Exempt;
+			// gets the rent for free. The blocks still need to be marked as paid. Otherwise
+			// they are charged retroactively as soon as the storage outgrows the deposit,
+			// possibly by a later call in this very block.
+			return Verdict::Charge {
+				amount: OutstandingAmount::new(Zero::zero()),
+			};
 		}
 
-		let rent_budget = match Self::rent_budget(&total_balance, &free_balance, contract) {
+		let budget = Self::rent_budget(account, &total_balance, &free_balance, contract);
+		let rent_budget = match budget {
 			Some(rent_budget) => rent_budget,
+			None if Storage::<T>::has_tombstone_deposit(account) => {
+				// The balance is gone but the reserved tombstone deposit still pays for
+				// the tombstone.
+				return Verdict::Evict { amount: None };
+			}
 			None => {
 				// The contract's total balance is already below subsistence threshold. That
 				// indicates that the contract cannot afford to leave a tombstone.
@@ -238,6 +256,14 @@ where
 			Verdict::Exempt => return Some(ContractInfo::Alive(alive_contract_info)),
 			Verdict::Kill => {
 				<ContractInfoOf<T>>::remove(account);
+				Storage::<T>::note_rent_allowance_change(
+					Some(alive_contract_info.rent_allowance),
+					None,
+				);
+				Storage::<T>::release_account(account);
+				<ContractLayout<T>>::remove(account);
+				Storage::<T>::release_tombstone_deposit(account);
+				Storage::<T>::remove_side_tables(account, &alive_contract_info.trie_id);
 				child::kill_storage(
 					&alive_contract_info.child_trie_info(),
 					None,
@@ -258,21 +284,40 @@ where
 				let tombstone = <TombstoneContractInfo<T>>::new(
 					&child_storage_root[..],
 					alive_contract_info.code_hash,
+					alive_contract_info.storage_size,
+					current_block_number,
 				);
 				let tombstone_info = ContractInfo::Tombstone(tombstone);
 				<ContractInfoOf<T>>::insert(account, &tombstone_info);
+				<TombstoneIndex<T>>::insert(account, ());
+				Storage::<T>::note_rent_allowance_change(
+					Some(alive_contract_info.rent_allowance),
+					None,
+				);
+				Storage::<T>::release_account(account);
+				// The tombstone deposit is still recorded and stays reserved.
+				Storage::<T>::remove_side_tables(account, &alive_contract_info.trie_id);
 
 				child::kill_storage(
 					&alive_contract_info.child_trie_info(),
 					None,
 				);
 
+				// Consumed last because this can reap the account which must not find an
+				// alive contract anymore.
+				Storage::<T>::consume_tombstone_deposit(account);
+
 				<Module<T>>::deposit_event(RawEvent::Evicted(account.clone(), true));
 				Some(tombstone_info)
 			}
 			Verdict::Charge { amount } => {
+				let rent_allowance = alive_contract_info.rent_allowance - amount.peek();
+				Storage::<T>::note_rent_allowance_change(
+					Some(alive_contract_info.rent_allowance),
+					Some(rent_allowance),
+				);
 				let contract_info = ContractInfo::Alive(AliveContractInfo::<T> {
-					rent_allowance: alive_contract_info.rent_allowance - amount.peek(),
+					rent_allowance,
 					deduct_block: current_block_number,
 					..alive_contract_info
 				});
@@ -305,6 +350,34 @@ where
 		Self::enact_verdict(account, alive_contract_info, current_block_number, verdict)
 	}
 
+	/// Bring the rent accounting of a contract up to date before one of its storage writes.
+	///
+	/// Charges the rent due since `deduct_block` like [`Self::collect`] and updates
+	/// `rent_allowance` and `deduct_block` of `contract` which the caller has to store.
+	/// The contract is never evicted by this: If it can't pay, `false` is returned and
+	/// nothing is changed.
+	pub fn charge_before_write(
+		account: &T::AccountId,
+		contract: &mut AliveContractInfo<T>,
+	) -> bool {
+		let current_block_number = <frame_system::Module<T>>::block_number();
+		match Self::consider_case(account, current_block_number, Zero::zero(), contract) {
+			Verdict::Exempt => true,
+			Verdict::Kill | Verdict::Evict { .. } => false,
+			Verdict::Charge { amount } => {
+				let rent_allowance = contract.rent_allowance - amount.peek();
+				Storage::<T>::note_rent_allowance_change(
+					Some(contract.rent_allowance),
+					Some(rent_allowance),
+				);
+				contract.rent_allowance = rent_allowance;
+				contract.deduct_block = current_block_number;
+				amount.withdraw(account);
+				true
+			}
+		}
+	}
+
 	/// Process a report that a contract under the given address should be evicted.
 	///
 	/// Enact the eviction right away if the contract should be evicted and return true.
@@ -380,35 +453,45 @@ where
 		};
 
 		// Compute how much would the fee per block be with the *updated* balance.
+		Ok(Self::project_eviction(account, &alive_contract_info))
+	}
+
+	/// Project the eviction of the given alive contract without charging any rent.
+	///
+	/// The projection is computed from the current balance of `account` and the dues
+	/// that accumulated since `contract.deduct_block`.
+	pub fn project_eviction(
+		account: &T::AccountId,
+		contract: &AliveContractInfo<T>,
+	) -> RentProjection<T::BlockNumber> {
 		let total_balance = T::Currency::total_balance(account);
 		let free_balance = T::Currency::free_balance(account);
-		let fee_per_block = Self::compute_fee_per_block(&free_balance, &alive_contract_info);
+		let fee_per_block = Self::compute_fee_per_block(
+			&Storage::<T>::rent_deposit_balance(account),
+			contract,
+		);
 		if fee_per_block.is_zero() {
-			return Ok(RentProjection::NoEviction);
+			return RentProjection::NoEviction;
 		}
 
-		// Then compute how much the contract will sustain under these circumstances.
-		let rent_budget = Self::rent_budget(&total_balance, &free_balance, &alive_contract_info).expect(
-			"the contract exists and in the alive state;
-			the updated balance must be greater than subsistence deposit;
-			this function doesn't return `None`;
-			qed
-			",
-		);
+		// Then compute how much the contract will sustain under these circumstances. A contract
+		// below the subsistence threshold can't sustain any block.
+		let rent_budget = Self::rent_budget(account, &total_balance, &free_balance, contract)
+			.unwrap_or_else(Zero::zero);
 		let blocks_left = match rent_budget.checked_div(&fee_per_block) {
 			Some(blocks_left) => blocks_left,
 			None => {
 				// `fee_per_block` is not zero here, so `checked_div` can return `None` if
 				// there is an overflow. This cannot happen with integers though. Return
 				// `NoEviction` here just in case.
-				return Ok(RentProjection::NoEviction);
+				return RentProjection::NoEviction;
 			}
 		};
 
 		let blocks_left = blocks_left.saturated_into::<u32>().into();
-		Ok(RentProjection::EvictionAt(
-			current_block_number + blocks_left,
-		))
+		RentProjection::EvictionAt(
+			contract.deduct_block + blocks_left,
+		)
 	}
 
 	/// Restores the destination account using the origin as prototype.
@@ -417,18 +500,21 @@ where
 	/// - origin exists and is alive,
 	/// - the origin's storage is not written in the current block
 	/// - the restored account has tombstone
+	/// - the tombstone wasn't created in the current block
 	/// - the tombstone matches the hash of the origin storage root, and code hash.
 	///
 	/// Upon succesful restoration, `origin` will be destroyed, all its funds are transferred to
 	/// the restored account. The restored account will inherit the last write block and its last
 	/// deduct block will be set to the current block.
+	///
+	/// Returns the number of `delta` keys that were actually stored by `origin`.
 	pub fn restore_to(
 		origin: T::AccountId,
 		dest: T::AccountId,
 		code_hash: CodeHash<T>,
 		rent_allowance: BalanceOf<T>,
 		delta: Vec<crate::exec::StorageKey>,
-	) -> Result<(), DispatchError> {
+	) -> Result<u32, DispatchError> {
 		let mut origin_contract = <ContractInfoOf<T>>::get(&origin)
 			.and_then(|c| c.get_alive())
 			.ok_or(Error::<T>::InvalidSourceContract)?;
@@ -441,10 +527,20 @@ where
 			return Err(Error::<T>::InvalidContractOrigin.into());
 		}
 
+		// The restoration removes the origin contract and moves its storage.
+		if origin_contract.frozen {
+			return Err(Error::<T>::ContractFrozen.into());
+		}
+
 		let dest_tombstone = <ContractInfoOf<T>>::get(&dest)
 			.and_then(|c| c.get_tombstone())
 			.ok_or(Error::<T>::InvalidDestinationContract)?;
 
+		// Restoring in the block of the eviction would race with the eviction itself.
+		if dest_tombstone.evicted_at == Some(current_block) {
+			return Err(Error::<T>::TombstoneTooRecent.into());
+		}
+
 		let last_write = if !delta.is_empty() {
 			Some(current_block)
 		} else {
@@ -453,32 +549,51 @@ where
 
 		let key_values_taken = delta.iter()
 			.filter_map(|key| {
-				child::get_raw(&child_trie_info, &blake2_256(key)).map(|value| {
-					child::kill(&child_trie_info, &blake2_256(key));
+				child::get_raw(&child_trie_info, &hashed_key(key)).map(|value| {
+					child::kill(&child_trie_info, &hashed_key(key));
 					(key, value)
 				})
 			})
 			.collect::<Vec<_>>();
 
-		let tombstone = <TombstoneContractInfo<T>>::new(
+		let tombstone_matches = dest_tombstone.matches(
 			// This operation is cheap enough because last_write (delta not included)
 			// is not this block as it has been checked earlier.
 			&child::root(&child_trie_info)[..],
 			code_hash,
 		);
 
-		if tombstone != dest_tombstone {
+		if !tombstone_matches {
 			for (key, value) in key_values_taken {
-				child::put_raw(&child_trie_info, &blake2_256(key), &value);
+				child::put_raw(&child_trie_info, &hashed_key(key), &value);
 			}
 			return Err(Error::<T>::InvalidTombstone.into());
 		}
 
-		origin_contract.storage_size -= key_values_taken.iter()
-			.map(|(_, value)| value.len() as u32)
-			.sum::<u32>();
+		let mut meter = StorageMeter::default();
+		for (key, value) in &key_values_taken {
+			meter.record_write(Some(value.len() as u32), None);
+			Storage::<T>::release_pointer(&origin_contract.trie_id, &hashed_key(key));
+			<Timelocks<T>>::remove(&origin_contract.trie_id, hashed_key(key));
+		}
+		meter.apply_to(&mut origin_contract);
+		// The shared storage that `origin` pays for belongs to its code and doesn't move.
+		let shared = Storage::<T>::remove_shared_pairs(&origin);
+		StorageMeter::from(shared).apply_to(&mut origin_contract);
 
+		// The restored storage matches the tombstone. A layout that was registered for
+		// `dest` before it was evicted therefore stays valid.
 		<ContractInfoOf<T>>::remove(&origin);
+		<ContractLayout<T>>::remove(&origin);
+		<TombstoneIndex<T>>::remove(&dest);
+		<IdempotencyKeys<T>>::remove(&origin);
+		<TrieOwner<T>>::insert(&origin_contract.trie_id, &dest);
+		// The attribution is indexed by the trie id which moves to `dest`.
+		if <AttributedContracts<T>>::take(&origin).is_some() {
+			<AttributedContracts<T>>::insert(&dest, ());
+		}
+		Storage::<T>::note_rent_allowance_change(Some(origin_contract.rent_allowance), None);
+		Storage::<T>::note_rent_allowance_change(None, Some(rent_allowance));
 		<ContractInfoOf<T>>::insert(&dest, ContractInfo::Alive(AliveContractInfo::<T> {
 			trie_id: origin_contract.trie_id,
 			storage_size: origin_contract.storage_size,
@@ -488,12 +603,26 @@ where
 			rent_allowance,
 			deduct_block: current_block,
 			last_write,
+			frozen: false,
+			call_count: origin_contract.call_count,
+			timelocked: origin_contract.timelocked,
 		}));
 
+		// The tombstone deposit of `origin` and whatever the contract reserved itself move to
+		// `dest` together with the free balance.
+		let reserve = Storage::<T>::free_reserve(&origin);
+		if !reserve.is_zero() {
+			let _ = T::Currency::unreserve(&origin, reserve);
+		}
+		Storage::<T>::release_tombstone_deposit(&origin);
 		let origin_free_balance = T::Currency::free_balance(&origin);
+		Storage::<T>::release_account(&origin);
 		T::Currency::make_free_balance_be(&origin, <BalanceOf<T>>::zero());
 		T::Currency::deposit_creating(&dest, origin_free_balance);
+		Storage::<T>::hold_account(&dest);
+		// `origin` was above the subsistence threshold which covers the deposit.
+		let _ = Storage::<T>::reserve_tombstone_deposit(&dest);
 
-		Ok(())
+		Ok(key_values_taken.len() as u32)
 	}
 }
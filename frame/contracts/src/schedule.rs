@@ -38,9 +38,20 @@ pub const API_BENCHMARK_BATCH_SIZE: u32 = 100;
 /// as for `API_BENCHMARK_BATCH_SIZE`.
 pub const INSTR_BENCHMARK_BATCH_SIZE: u32 = 1_000;
 
+/// The smallest `Limits::stack_height` that `ScheduleBuilder` accepts.
+///
+/// Contracts compiled by common toolchains need a few hundred stack elements. A smaller
+/// limit makes most of them trap right away.
+pub const MIN_STACK_HEIGHT: u32 = 128;
+
+/// The largest `Limits::memory_pages` that `ScheduleBuilder` accepts.
+///
+/// This is the number of 64KiB pages that a 32 bit linear memory can address.
+pub const MAX_MEMORY_PAGES: u32 = 65_536;
+
 /// Definition of the cost schedule and other parameterizations for wasm vm.
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
-#[cfg_attr(feature = "std", serde(bound(serialize = "", deserialize = "")))]
+#[cfg_attr(feature = "std", serde(bound(serialize = "", deserialize = ""), deny_unknown_fields))]
 #[derive(Clone, Encode, Decode, PartialEq, Eq, ScheduleDebug)]
 pub struct Schedule<T: Config> {
 	/// Version of the schedule.
@@ -62,6 +73,7 @@ pub struct Schedule<T: Config> {
 
 /// Describes the upper limits on various metrics.
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(deny_unknown_fields))]
 #[derive(Clone, Encode, Decode, PartialEq, Eq, RuntimeDebug)]
 pub struct Limits {
 	/// The maximum number of topics supported by an event.
@@ -130,6 +142,7 @@ pub struct Limits {
 ///    that use them as supporting instructions. Supporting means mainly pushing arguments
 ///    and dropping return values in order to maintain a valid module.
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(deny_unknown_fields))]
 #[derive(Clone, Encode, Decode, PartialEq, Eq, WeightDebug)]
 pub struct InstructionWeights<T: Config> {
 	pub i64const: u32,
@@ -184,11 +197,13 @@ pub struct InstructionWeights<T: Config> {
 	pub i64rotl: u32,
 	pub i64rotr: u32,
 	/// The type parameter is used in the default implementation.
+	#[cfg_attr(feature = "std", serde(skip))]
 	pub _phantom: PhantomData<T>,
 }
 
 /// Describes the weight for each imported function that a contract is allowed to call.
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(deny_unknown_fields))]
 #[derive(Clone, Encode, Decode, PartialEq, Eq, WeightDebug)]
 pub struct HostFnWeights<T: Config> {
 	/// Weight of calling `seal_caller`.
@@ -197,6 +212,12 @@ pub struct HostFnWeights<T: Config> {
 	/// Weight of calling `seal_address`.
 	pub address: Weight,
 
+	/// Weight of calling `seal_derive_address`.
+	///
+	/// The salt is charged with `instantiate_per_salt_byte` because it is hashed in the same
+	/// way as by `seal_instantiate`.
+	pub derive_address: Weight,
+
 	/// Weight of calling `seal_gas_left`.
 	pub gas_left: Weight,
 
@@ -218,6 +239,12 @@ pub struct HostFnWeights<T: Config> {
 	/// Weight of calling `seal_block_number`.
 	pub block_number: Weight,
 
+	/// Weight of calling `seal_block_weight_left`.
+	pub block_weight_left: Weight,
+
+	/// Weight of calling `seal_call_count`.
+	pub call_count: Weight,
+
 	/// Weight of calling `seal_now`.
 	pub now: Weight,
 
@@ -233,6 +260,15 @@ pub struct HostFnWeights<T: Config> {
 	/// Weight per input byte copied to contract memory by `seal_input`.
 	pub input_per_byte: Weight,
 
+	/// Weight of calling `[seal1] seal_input`.
+	pub input_chunk: Weight,
+
+	/// Weight per input byte copied to contract memory by `[seal1] seal_input`.
+	pub input_chunk_per_byte: Weight,
+
+	/// Weight of calling `seal_input_len`.
+	pub input_len: Weight,
+
 	/// Weight of calling `seal_return`.
 	pub r#return: Weight,
 
@@ -242,6 +278,9 @@ pub struct HostFnWeights<T: Config> {
 	/// Weight of calling `seal_terminate`.
 	pub terminate: Weight,
 
+	/// Weight per key stored by the contract that is removed by `seal_terminate`.
+	pub terminate_per_key: Weight,
+
 	/// Weight of calling `seal_restore_to`.
 	pub restore_to: Weight,
 
@@ -263,6 +302,15 @@ pub struct HostFnWeights<T: Config> {
 	/// Weight of calling `seal_set_rent_allowance`.
 	pub set_rent_allowance: Weight,
 
+	/// Weight of calling `seal_top_up_rent_allowance`.
+	pub top_up_rent_allowance: Weight,
+
+	/// Weight of calling `seal_reserve`.
+	pub reserve: Weight,
+
+	/// Weight of calling `seal_unreserve`.
+	pub unreserve: Weight,
+
 	/// Weight of calling `seal_set_storage`.
 	pub set_storage: Weight,
 
@@ -278,6 +326,12 @@ pub struct HostFnWeights<T: Config> {
 	/// Weight per byte of an item received via `seal_get_storage`.
 	pub get_storage_per_byte: Weight,
 
+	/// Weight of calling `seal_register_layout`.
+	pub register_layout: Weight,
+
+	/// Weight per byte of a layout registered with `seal_register_layout`.
+	pub register_layout_per_byte: Weight,
+
 	/// Weight of calling `seal_transfer`.
 	pub transfer: Weight,
 
@@ -330,6 +384,7 @@ pub struct HostFnWeights<T: Config> {
 	pub hash_blake2_128_per_byte: Weight,
 
 	/// The type parameter is used in the default implementation.
+	#[cfg_attr(feature = "std", serde(skip))]
 	pub _phantom: PhantomData<T>
 }
 
@@ -505,6 +560,7 @@ impl<T: Config> Default for HostFnWeights<T> {
 		Self {
 			caller: cost_batched!(seal_caller),
 			address: cost_batched!(seal_address),
+			derive_address: cost_batched!(seal_derive_address),
 			gas_left: cost_batched!(seal_gas_left),
 			balance: cost_batched!(seal_balance),
 			value_transferred: cost_batched!(seal_value_transferred),
@@ -512,14 +568,20 @@ impl<T: Config> Default for HostFnWeights<T> {
 			tombstone_deposit: cost_batched!(seal_tombstone_deposit),
 			rent_allowance: cost_batched!(seal_rent_allowance),
 			block_number: cost_batched!(seal_block_number),
+			block_weight_left: cost_batched!(seal_block_weight_left),
+			call_count: cost_batched!(seal_call_count),
 			now: cost_batched!(seal_now),
 			weight_to_fee: cost_batched!(seal_weight_to_fee),
 			gas: cost_batched!(seal_gas),
 			input: cost!(seal_input),
 			input_per_byte: cost_byte!(seal_input_per_kb),
+			input_chunk: cost_batched!(seal_input_chunk),
+			input_chunk_per_byte: cost_byte!(seal_input_chunk_per_kb),
+			input_len: cost_batched!(seal_input_len),
 			r#return: cost!(seal_return),
 			return_per_byte: cost_byte!(seal_return_per_kb),
 			terminate: cost!(seal_terminate),
+			terminate_per_key: cost_batched!(seal_terminate_per_key),
 			restore_to: cost!(seal_restore_to),
 			restore_to_per_delta: cost_batched!(seal_restore_to_per_delta),
 			random: cost_batched!(seal_random),
@@ -527,11 +589,16 @@ impl<T: Config> Default for HostFnWeights<T> {
 			deposit_event_per_topic: cost_batched_args!(seal_deposit_event_per_topic_and_kb, 1, 0),
 			deposit_event_per_byte: cost_byte_batched_args!(seal_deposit_event_per_topic_and_kb, 0, 1),
 			set_rent_allowance: cost_batched!(seal_set_rent_allowance),
+			top_up_rent_allowance: cost_batched!(seal_top_up_rent_allowance),
+			reserve: cost_batched!(seal_reserve),
+			unreserve: cost_batched!(seal_unreserve),
 			set_storage: cost_batched!(seal_set_storage),
 			set_storage_per_byte: cost_byte_batched!(seal_set_storage_per_kb),
 			clear_storage: cost_batched!(seal_clear_storage),
 			get_storage: cost_batched!(seal_get_storage),
 			get_storage_per_byte: cost_byte_batched!(seal_get_storage_per_kb),
+			register_layout: cost_batched!(seal_register_layout),
+			register_layout_per_byte: cost_byte_batched!(seal_register_layout_per_kb),
 			transfer: cost_batched!(seal_transfer),
 			call: cost_batched!(seal_call),
 			call_transfer_surcharge: cost_batched_args!(seal_call_per_transfer_input_output_kb, 1, 0, 0),
@@ -655,6 +722,88 @@ impl<'a, T: Config> rules::Rules for ScheduleRules<'a, T> {
 	}
 }
 
+/// A reason why `ScheduleBuilder::build` rejected a schedule.
+#[derive(Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum ScheduleError {
+	/// One of the `InstructionWeights` is zero which would make that instruction free.
+	ZeroInstructionWeight,
+	/// `Limits::stack_height` is below `MIN_STACK_HEIGHT`.
+	StackHeightTooLow,
+	/// `Limits::memory_pages` is above `MAX_MEMORY_PAGES`.
+	TooManyMemoryPages,
+}
+
+/// Builds a [`Schedule`] and checks it for obvious misconfigurations.
+///
+/// Meant for chains that don't use the default schedule. Start from the default or from an
+/// existing schedule, replace the parts that should differ and call `build`.
+pub struct ScheduleBuilder<T: Config> {
+	schedule: Schedule<T>,
+}
+
+impl<T: Config> Default for ScheduleBuilder<T> {
+	fn default() -> Self {
+		Self::from_schedule(Default::default())
+	}
+}
+
+impl<T: Config> ScheduleBuilder<T> {
+	/// Start from `schedule` instead of the default schedule.
+	pub fn from_schedule(schedule: Schedule<T>) -> Self {
+		Self { schedule }
+	}
+
+	/// Set the version of the schedule.
+	pub fn version(mut self, version: u32) -> Self {
+		self.schedule.version = version;
+		self
+	}
+
+	/// Allow or forbid the use of `seal_println`.
+	pub fn enable_println(mut self, enable: bool) -> Self {
+		self.schedule.enable_println = enable;
+		self
+	}
+
+	/// Use the given `limits`.
+	pub fn limits(mut self, limits: Limits) -> Self {
+		self.schedule.limits = limits;
+		self
+	}
+
+	/// Use the given `instruction_weights`.
+	pub fn instruction_weights(mut self, weights: InstructionWeights<T>) -> Self {
+		self.schedule.instruction_weights = weights;
+		self
+	}
+
+	/// Use the given `host_fn_weights`.
+	pub fn host_fn_weights(mut self, weights: HostFnWeights<T>) -> Self {
+		self.schedule.host_fn_weights = weights;
+		self
+	}
+
+	/// Return the schedule if it passes validation.
+	pub fn build(self) -> Result<Schedule<T>, ScheduleError> {
+		let schedule = self.schedule;
+		// All fields of `InstructionWeights` are `u32` and the phantom data isn't encoded.
+		let zero_weight = schedule.instruction_weights
+			.encode()
+			.chunks(4)
+			.any(|weight| weight.iter().all(|byte| *byte == 0));
+		if zero_weight {
+			return Err(ScheduleError::ZeroInstructionWeight);
+		}
+		if schedule.limits.stack_height < MIN_STACK_HEIGHT {
+			return Err(ScheduleError::StackHeightTooLow);
+		}
+		if schedule.limits.memory_pages > MAX_MEMORY_PAGES {
+			return Err(ScheduleError::TooManyMemoryPages);
+		}
+		Ok(schedule)
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use crate::tests::Test;
@@ -665,4 +814,71 @@ mod test {
 		let schedule = Schedule::<Test>::default();
 		println!("{:#?}", schedule);
 	}
+
+	#[test]
+	fn schedule_round_trips_through_json() {
+		let schedule = ScheduleBuilder::<Test>::default()
+			.version(3)
+			.enable_println(true)
+			.build()
+			.unwrap();
+		let json = serde_json::to_string(&schedule).unwrap();
+		assert_eq!(serde_json::from_str::<Schedule<Test>>(&json).unwrap(), schedule);
+	}
+
+	#[test]
+	fn unknown_schedule_fields_are_rejected() {
+		let schedule = serde_json::to_value(Schedule::<Test>::default()).unwrap();
+		let paths: &[&[&str]] = &[&[], &["limits"], &["instruction_weights"], &["host_fn_weights"]];
+		for path in paths {
+			let mut typo = schedule.clone();
+			let target = path.iter().fold(&mut typo, |value, key| &mut value[*key]);
+			target.as_object_mut().unwrap().insert("i64cnst".into(), 1.into());
+			assert!(serde_json::from_value::<Schedule<Test>>(typo).is_err(), "{:?}", path);
+		}
+	}
+
+	#[test]
+	fn genesis_schedule_is_read_from_chain_spec() {
+		use sp_runtime::BuildStorage;
+
+		let schedule = ScheduleBuilder::<Test>::default().version(7).build().unwrap();
+		let json = serde_json::json!({ "currentSchedule": schedule }).to_string();
+		let config: crate::GenesisConfig<Test> = serde_json::from_str(&json).unwrap();
+		sp_io::TestExternalities::new(config.build_storage().unwrap()).execute_with(|| {
+			assert_eq!(crate::Module::<Test>::current_schedule(), schedule);
+		});
+	}
+
+	#[test]
+	fn default_schedule_is_valid() {
+		assert!(ScheduleBuilder::<Test>::default().build().is_ok());
+	}
+
+	#[test]
+	fn zero_instruction_weights_are_rejected() {
+		let weights = InstructionWeights::<Test> { i64rotr: 0, .. Default::default() };
+		assert_eq!(
+			ScheduleBuilder::<Test>::default().instruction_weights(weights).build(),
+			Err(ScheduleError::ZeroInstructionWeight),
+		);
+	}
+
+	#[test]
+	fn low_stack_height_is_rejected() {
+		let limits = Limits { stack_height: MIN_STACK_HEIGHT - 1, .. Default::default() };
+		assert_eq!(
+			ScheduleBuilder::<Test>::default().limits(limits).build(),
+			Err(ScheduleError::StackHeightTooLow),
+		);
+	}
+
+	#[test]
+	fn excessive_memory_pages_are_rejected() {
+		let limits = Limits { memory_pages: MAX_MEMORY_PAGES + 1, .. Default::default() };
+		assert_eq!(
+			ScheduleBuilder::<Test>::default().limits(limits).build(),
+			Err(ScheduleError::TooManyMemoryPages),
+		);
+	}
 }
@@ -15,124 +15,1750 @@
 // along with Substrate. If not, see <http://www.gnu.org/licenses/>.
 
 //! This module contains routines for accessing and altering a contract related state.
+//!
+//! Apart from [`meta`] everything in here is an implementation detail of the pallet.
 
 use crate::{
 	exec::{AccountIdOf, StorageKey},
 	AliveContractInfo, BalanceOf, CodeHash, ContractInfo, ContractInfoOf, Config, TrieId,
-	AccountCounter,
+	AccountCounter, ConfigCache, RawAliveContractInfo, Error, rent::Rent, weights::WeightInfo,
+	ContractLayout, GlobalRentAllowance, IdempotencyKeys, AttributedContracts,
+	WriteAttribution, SharedBlobs, SharedBlobBytes, DedupPointers, ReadAudit, ReadAuditBlocks,
+	Timelocks, TrieOwner, OrderedIndex, TombstoneDeposits, SharedStorage, SharedPairOwners,
+	SharedPairsOf, WriteCostModel, WriteKind, Cipher, MirrorSink,
 };
+use codec::{Encode, Decode};
 use sp_std::prelude::*;
+use sp_std::collections::{btree_map::{BTreeMap, Entry}, btree_set::BTreeSet};
 use sp_std::marker::PhantomData;
-use sp_io::hashing::blake2_256;
-use sp_runtime::traits::Bounded;
+use sp_std::convert::TryFrom;
+use sp_runtime::{
+	DispatchError, RuntimeDebug,
+	traits::{Bounded, CheckedSub, One, Saturating, SaturatedConversion, Zero},
+};
 use sp_core::crypto::UncheckedFrom;
-use frame_support::{storage::child, StorageMap};
+use frame_support::{
+	storage::child::{self, ChildInfo},
+	traits::{Currency, ReservableCurrency, Get, OnUnbalanced},
+	weights::Weight,
+	StorageMap, StorageValue, StorageDoubleMap, IterableStorageMap, IterableStorageDoubleMap,
+};
+use pallet_contracts_primitives::RentProjection;
+
+/// The layout of contract storage within the state.
+///
+/// This module is meant for crates which need to locate contract storage within a state
+/// snapshot without going through the pallet, e.g. light clients verifying storage proofs.
+/// It is considered stable: Any change to these functions would make existing contract
+/// storage unreachable and is therefore not allowed.
+pub mod meta {
+	use super::StorageKey;
+	use frame_support::storage::child::ChildInfo;
+	use sp_core::storage::well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX;
+
+	/// The length of a storage key as used by contracts.
+	pub const KEY_LEN: usize = 32;
+
+	/// The length of a key within the child trie of a contract.
+	pub const HASHED_KEY_LEN: usize = 32;
+
+	/// The prefix of the key under which the root of a contract's child trie is stored
+	/// in the main trie. It is followed by the trie id of the contract.
+	pub const CHILD_STORAGE_KEY_PREFIX: &[u8] = DEFAULT_CHILD_STORAGE_KEY_PREFIX;
+
+	/// The child trie holding the storage of the contract with the given `trie_id`.
+	pub fn child_trie_info(trie_id: &[u8]) -> ChildInfo {
+		ChildInfo::new_default(trie_id)
+	}
+
+	/// The key within the child trie under which the value of `key` is stored.
+	pub fn hashed_key(key: &StorageKey) -> [u8; HASHED_KEY_LEN] {
+		sp_io::hashing::blake2_256(key)
+	}
+
+	/// The child trie and the key within it under which the value of `key` of the
+	/// contract with the given `trie_id` is stored.
+	pub fn contract_child_storage_key(
+		trie_id: &[u8],
+		key: &StorageKey,
+	) -> (ChildInfo, [u8; HASHED_KEY_LEN]) {
+		(child_trie_info(trie_id), hashed_key(key))
+	}
+}
+
+/// An error that means that the account requested either doesn't exist or represents a tombstone
+/// account.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct ContractAbsentError;
+
+/// An error that means that a storage key was accessed which wasn't declared in the
+/// [`AccessList`] of the call.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct UndeclaredKeyAccess;
+
+/// Detects distinct storage keys that are hashed to the same location in the child trie.
+///
+/// Keys are always exactly 32 bytes long which makes a collision of their blake2 hashes
+/// practically impossible. This is merely a safety harness used in debug builds.
+#[derive(Default)]
+pub struct KeyCollisionDetector(BTreeMap<[u8; 32], StorageKey>);
+
+impl KeyCollisionDetector {
+	/// Record that `key` is stored under `hashed_key`.
+	///
+	/// Returns `true` and logs an error if a different key was recorded under the same hash.
+	pub fn observe(&mut self, key: &StorageKey, hashed_key: [u8; 32]) -> bool {
+		match self.0.entry(hashed_key) {
+			Entry::Vacant(entry) => {
+				entry.insert(*key);
+				false
+			}
+			Entry::Occupied(entry) if entry.get() == key => false,
+			Entry::Occupied(entry) => {
+				log_error!(
+					"storage keys {:?} and {:?} are hashed to the same location {:?}",
+					entry.get(),
+					key,
+					hashed_key,
+				);
+				true
+			}
+		}
+	}
+}
+
+/// The storage keys a call is allowed to access, grouped by contract.
+///
+/// A contract without an entry isn't allowed to access any key.
+pub struct AccessList<AccountId>(BTreeMap<AccountId, BTreeSet<StorageKey>>);
+
+impl<AccountId> Default for AccessList<AccountId> {
+	fn default() -> Self {
+		AccessList(BTreeMap::new())
+	}
+}
+
+impl<AccountId: Ord> AccessList<AccountId> {
+	/// Allow `account` to access `keys` in addition to the keys it already declared.
+	pub fn declare(&mut self, account: AccountId, keys: impl IntoIterator<Item = StorageKey>) {
+		self.0.entry(account).or_insert_with(BTreeSet::new).extend(keys);
+	}
+
+	/// Returns `Err` if `account` didn't declare `key`.
+	pub fn check(&self, account: &AccountId, key: &StorageKey) -> Result<(), UndeclaredKeyAccess> {
+		if self.0.get(account).map_or(false, |keys| keys.contains(key)) {
+			Ok(())
+		} else {
+			Err(UndeclaredKeyAccess)
+		}
+	}
+}
+
+/// How many trie ids `generate_trie_id` generates before giving up on finding an empty one.
+pub const TRIE_ID_GENERATION_ATTEMPTS: u32 = 4;
+
+/// The storage key under which [`Storage::write_ordered`] stores the value of `int_key`.
+///
+/// These are the keys whose first 16 bytes are zero followed by the big endian encoding of
+/// `int_key`.
+pub fn ordered_key(int_key: u128) -> StorageKey {
+	let mut key = [0; 32];
+	key[16..].copy_from_slice(&int_key.to_be_bytes());
+	key
+}
+
+/// A storage key derived from a sequence of typed components.
+///
+/// The key is the `blake2_256` hash of the concatenated SCALE encodings of all components
+/// in the order they were pushed. Off-chain code can reproduce it as
+/// `blake2_256(&(address, token_id, field).encode())` which encodes tuples the same way.
+/// The value is then stored within the child trie under [`meta::hashed_key`] of that key,
+/// exactly like any other storage key.
+///
+/// Keys with the same sequence of component types only collide if all components are equal.
+/// Keys made up of different types can collide, e.g. a `u64` followed by a `u32` and a `u32`
+/// followed by a `u64`. Start every kind of key with a distinct tag to rule that out.
+#[derive(Default, Clone)]
+pub struct CompoundKey(Vec<u8>);
+
+impl CompoundKey {
+	/// Start a key without any components.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Append `component` to the key.
+	pub fn push<E: Encode>(mut self, component: &E) -> Self {
+		component.encode_to(&mut self.0);
+		self
+	}
+
+	/// The storage key made up of all components pushed so far.
+	pub fn storage_key(&self) -> StorageKey {
+		sp_io::hashing::blake2_256(&self.0)
+	}
+}
+
+/// How many keys `trie_depth_estimate` reads at most.
+pub const TRIE_DEPTH_SAMPLES: u32 = 256;
+
+/// An error that can happen when writing to the storage of a contract.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum WriteError {
+	/// The contract doesn't exist or is a tombstone.
+	ContractAbsent,
+	/// The contract is frozen and can't write to its storage.
+	ContractFrozen,
+	/// The contract can't pay the rent that is charged before the write.
+	///
+	/// Only returned when `T::ChargeRentOnWrite` is enabled.
+	RentNotCovered,
+	/// The value is larger than `Config::MaxValueSize`.
+	///
+	/// Only returned by [`Storage::write_dedup`]. All other writes leave this check to the
+	/// caller.
+	ValueTooLarge,
+}
+
+impl From<ContractAbsentError> for WriteError {
+	fn from(_: ContractAbsentError) -> Self {
+		WriteError::ContractAbsent
+	}
+}
+
+/// An error that means that [`Storage::write_raw`] was asked to write to the trie of a
+/// contract.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct OwnedTrie;
+
+/// Implements `From<WriteError>` for errors that wrap a failed write in a `Write` variant.
+macro_rules! impl_from_write_error {
+	($($error:ident),*) => {
+		$(
+			impl From<WriteError> for $error {
+				fn from(err: WriteError) -> Self {
+					$error::Write(err)
+				}
+			}
+		)*
+	};
+}
+
+impl_from_write_error!(
+	PatchError,
+	MonotonicWriteError,
+	WriteTypedError,
+	VersionedWriteError,
+	SequenceError
+);
+
+/// An error that can happen when patching a stored value.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum PatchError {
+	/// The underlying write failed.
+	Write(WriteError),
+	/// The end of the patched region doesn't fit into the address space.
+	Overflow,
+}
+
+/// An error that can happen when writing a monotonic counter.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum MonotonicWriteError {
+	/// The underlying write failed.
+	Write(WriteError),
+	/// The new value isn't greater than the stored one.
+	NonMonotonic,
+}
+
+/// An error that can happen when writing a versioned value.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum VersionedWriteError {
+	/// The underlying write failed.
+	Write(WriteError),
+	/// The version of the new value is lower than the version of the stored one.
+	VersionDowngrade,
+}
+
+/// An error that can happen when allocating the next id of a sequence.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum SequenceError {
+	/// The underlying write failed.
+	Write(WriteError),
+	/// All ids of the sequence were handed out already.
+	Exhausted,
+}
+
+/// An error that can happen when accessing the storage shared by the contracts of a code hash.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum SharedStorageError {
+	/// The contract doesn't exist or is a tombstone.
+	ContractAbsent,
+	/// The contract is frozen and can't write to its storage.
+	ContractFrozen,
+	/// The contract doesn't run the code whose shared storage was accessed.
+	CodeHashMismatch,
+	/// The value is larger than `Config::MaxValueSize`.
+	ValueTooLarge,
+}
+
+/// The bookkeeping of the storage that is shared by all contracts with the same code hash.
+///
+/// See [`Storage::write_shared`].
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct SharedStorageInfo {
+	/// The number of bytes stored in the shared storage.
+	pub storage_size: u64,
+	/// The number of kv pairs stored in the shared storage.
+	pub pair_count: u32,
+}
+
+/// An error that can happen when writing a typed value.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum WriteTypedError {
+	/// The underlying write failed.
+	Write(WriteError),
+	/// The stored value isn't exactly one encoded value of the requested type.
+	UndecodableOldValue,
+}
+
+/// An error that can happen when topping up the rent allowance of a contract.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum TopUpRentError {
+	/// The contract doesn't exist or is a tombstone.
+	ContractAbsent,
+	/// The free balance of the contract doesn't cover the amount on top of the
+	/// subsistence threshold.
+	BelowSubsistenceThreshold,
+}
+
+/// An error that can happen when refilling the rent allowance of a contract from its
+/// reserved balance.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum RefillRentError {
+	/// The contract doesn't exist or is a tombstone.
+	ContractAbsent,
+	/// The reserved balance of the contract is less than the requested amount.
+	InsufficientReserve,
+}
+
+/// The number of idempotency keys that are remembered per contract.
+///
+/// Once the history is full the oldest key is forgotten for every new one. A write
+/// replayed after that many newer idempotent writes is applied again.
+pub const IDEMPOTENCY_HISTORY_LEN: usize = 32;
+
+/// The number of blocks whose entries in `ReadAudit` are removed at most per block.
+///
+/// More than one block expires at once when `Config::AuditRetention` is lowered. Pruning
+/// catches up with those over the following blocks.
+pub const AUDIT_PRUNE_LIMIT: u32 = 16;
+
+/// The outcome of [`Storage::write_idempotent`].
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum IdempotentWrite {
+	/// The write was performed and changed the bookkeeping as described.
+	Applied(WriteDelta),
+	/// A write with the same idempotency key was already performed. Nothing was written.
+	Duplicate,
+}
+
+/// How one or more storage writes changed the bookkeeping of a contract.
+#[derive(Default, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct WriteDelta {
+	/// The change of the number of bytes stored by the contract.
+	pub size_delta: i64,
+	/// The change of the number of kv pairs stored by the contract.
+	pub pair_delta: i64,
+	/// The change of the number of kv pairs with an empty value stored by the contract.
+	pub empty_pair_delta: i64,
+}
+
+impl WriteDelta {
+	/// Add the changes of another write to this one.
+	pub fn accumulate(&mut self, other: &WriteDelta) {
+		self.size_delta = self.size_delta.saturating_add(other.size_delta);
+		self.pair_delta = self.pair_delta.saturating_add(other.pair_delta);
+		self.empty_pair_delta = self.empty_pair_delta.saturating_add(other.empty_pair_delta);
+	}
+}
+
+/// Accounts for how storage changes affect the bookkeeping of a contract.
+///
+/// Every change of the size and pair counters of a contract goes through this type so that
+/// single writes, batches and the restoration can't disagree about the counter math.
+#[derive(Default, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct StorageMeter {
+	delta: WriteDelta,
+}
+
+impl From<WriteDelta> for StorageMeter {
+	fn from(delta: WriteDelta) -> Self {
+		Self { delta }
+	}
+}
+
+impl StorageMeter {
+	/// Record that a value of `old_len` bytes was replaced by one of `new_len` bytes.
+	///
+	/// `None` means that there was no value before or that the value was removed.
+	pub fn record_write(&mut self, old_len: Option<u32>, new_len: Option<u32>) {
+		let len = |len: Option<u32>| len.map_or(0, i64::from);
+		let is_empty = |len: Option<u32>| (len == Some(0)) as i64;
+		self.delta.accumulate(&WriteDelta {
+			size_delta: len(new_len) - len(old_len),
+			pair_delta: new_len.is_some() as i64 - old_len.is_some() as i64,
+			empty_pair_delta: is_empty(new_len) - is_empty(old_len),
+		});
+	}
+
+	/// The changes recorded so far.
+	pub fn delta(&self) -> WriteDelta {
+		self.delta
+	}
+
+	/// Apply the recorded changes to the bookkeeping of the given contract.
+	pub fn apply_to<CodeHash, Balance, BlockNumber>(
+		&self,
+		info: &mut RawAliveContractInfo<CodeHash, Balance, BlockNumber>,
+	) {
+		let delta = &self.delta;
+		apply_pair_count_delta(&mut info.total_pair_count, delta.pair_delta, "total_pair_count");
+		apply_pair_count_delta(
+			&mut info.empty_pair_count,
+			delta.empty_pair_delta,
+			"empty_pair_count",
+		);
+		apply_size_delta(&mut info.storage_size, delta.size_delta);
+	}
+
+	/// Apply the recorded changes to the bookkeeping of a shared storage.
+	pub fn apply_to_shared(&self, info: &mut SharedStorageInfo) {
+		apply_pair_count_delta(&mut info.pair_count, self.delta.pair_delta, "pair_count");
+		apply_size_delta(&mut info.storage_size, self.delta.size_delta);
+	}
+}
+
+/// Change a storage size by `delta` without under- or overflowing it.
+fn apply_size_delta(storage_size: &mut u64, delta: i64) {
+	let size_change = delta.wrapping_abs() as u64;
+	*storage_size = if delta < 0 {
+		storage_size.saturating_sub(size_change)
+	} else {
+		storage_size.saturating_add(size_change)
+	};
+}
+
+pub type DetailedContractInfo<T> =
+	RawDetailedContractInfo<CodeHash<T>, BalanceOf<T>, <T as frame_system::Config>::BlockNumber>;
+
+/// The stored information of an alive contract together with values derived from it.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct RawDetailedContractInfo<CodeHash, Balance, BlockNumber> {
+	/// The information as stored in `ContractInfoOf`.
+	pub info: RawAliveContractInfo<CodeHash, Balance, BlockNumber>,
+	/// The block at which the contract is projected to be evicted.
+	pub projected_eviction: RentProjection<BlockNumber>,
+	/// Number of blocks that passed since the storage of the contract was last written to.
+	///
+	/// `None` if the storage was never written to.
+	pub blocks_since_last_write: Option<BlockNumber>,
+	/// The number of bytes actually stored by the contract.
+	pub physical_size: u64,
+	/// The number of bytes the contract is charged rent for.
+	///
+	/// This includes the `StorageSizeOffset` and every empty pair counted as one byte.
+	pub logical_size: u64,
+}
+
+/// Identifies a savepoint created by [`Savepoints::savepoint`].
+#[derive(Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct SavepointId(u32);
+
+/// The savepoint passed to [`Savepoints::rollback_to`] was already rolled back.
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct UnknownSavepoint;
+
+/// Savepoints within the storage changes of a call.
+///
+/// Every savepoint opens a storage transaction. Rolling back to a savepoint discards all
+/// storage changes made after it was created while the changes made before are kept. This
+/// includes the bookkeeping of the contracts, which is therefore always consistent with the
+/// storage that remains.
+///
+/// The changes that weren't rolled back are kept once the savepoints are committed or
+/// dropped. This makes sure that every opened storage transaction is also closed.
+#[derive(Default)]
+pub struct Savepoints {
+	open: u32,
+}
+
+impl Savepoints {
+	/// Create a savepoint at the current state of the storage.
+	pub fn savepoint(&mut self) -> SavepointId {
+		sp_io::storage::start_transaction();
+		self.open += 1;
+		SavepointId(self.open)
+	}
+
+	/// Discard all storage changes made since the savepoint `id` was created.
+	///
+	/// The savepoint itself and all savepoints created after it are released.
+	/// Returns `Err` if the savepoint was already released by an earlier rollback.
+	pub fn rollback_to(&mut self, id: SavepointId) -> Result<(), UnknownSavepoint> {
+		if id.0 == 0 || id.0 > self.open {
+			return Err(UnknownSavepoint);
+		}
+		while self.open >= id.0 {
+			sp_io::storage::rollback_transaction();
+			self.open -= 1;
+		}
+		Ok(())
+	}
+
+	/// Keep all storage changes that weren't rolled back.
+	pub fn commit(mut self) {
+		self.commit_open();
+	}
+
+	fn commit_open(&mut self) {
+		while self.open > 0 {
+			sp_io::storage::commit_transaction();
+			self.open -= 1;
+		}
+	}
+}
+
+impl Drop for Savepoints {
+	fn drop(&mut self) {
+		self.commit_open();
+	}
+}
+
+pub struct Storage<T>(PhantomData<T>);
+
+/// Change a pair counter of a contract by `delta` without underflowing it.
+///
+/// The counters can only underflow if they are inconsistent with the pairs actually stored by
+/// the contract. This is a bug which we report rather than turning it into a panic
+/// or an absurd rent bill.
+fn apply_pair_count_delta(counter: &mut u32, delta: i64, name: &str) {
+	let change = u32::try_from(delta.wrapping_abs() as u64).unwrap_or(u32::max_value());
+	if delta >= 0 {
+		*counter = counter.saturating_add(change);
+		return;
+	}
+	debug_assert!(*counter >= change, "{} of a contract underflowed", name);
+	if *counter < change {
+		log_error!("{} of a contract underflowed; the counter is corrupt", name);
+	}
+	*counter = counter.saturating_sub(change);
+}
+
+impl<T> Storage<T>
+where
+	T: Config,
+	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>
+{
+	/// Reads a storage kv pair of a contract.
+	///
+	/// The read is performed from the `trie_id` only. The `address` is not necessary. If the contract
+	/// doesn't store under the given `key` `None` is returned.
+	pub fn read(trie_id: &TrieId, key: &StorageKey) -> Option<Vec<u8>> {
+		Self::read_child(&crate::child_trie_info(&trie_id), key)
+	}
+
+	/// Reads a storage kv pair of a contract from an already constructed `child_trie_info`.
+	///
+	/// Executing contracts keep the `ChildInfo` of their trie around and use this in order
+	/// to not construct it again for every read.
+	///
+	/// A key that is locked by [`Self::write_timelock`] reads as absent until it unlocks.
+	/// The contract info isn't known here which is why the lock of `key` is always looked up.
+	pub fn read_child(child_trie_info: &ChildInfo, key: &StorageKey) -> Option<Vec<u8>> {
+		Self::read_locked(child_trie_info, true, key)
+	}
+
+	/// Reads a storage kv pair like [`Self::read_child`] but only looks up the lock of `key`
+	/// if the contract is `timelocked` as recorded in its contract info.
+	fn read_locked(
+		child_trie_info: &ChildInfo,
+		timelocked: bool,
+		key: &StorageKey,
+	) -> Option<Vec<u8>> {
+		let hashed_key = meta::hashed_key(key);
+		child::get_raw(child_trie_info, &hashed_key)
+			.filter(|_| !(timelocked && Self::is_locked(child_trie_info, &hashed_key)))
+			.map(|value| Self::resolve(child_trie_info, &hashed_key, value))
+	}
+
+	/// Reads one key from each of the given tries.
+	///
+	/// The entries can belong to different contracts. The value read for an entry is
+	/// returned at the same position as the entry. Every read behaves like [`Self::read`].
+	pub fn read_across(entries: &[(TrieId, StorageKey)]) -> Vec<Option<Vec<u8>>> {
+		entries.iter().map(|(trie_id, key)| Self::read(trie_id, key)).collect()
+	}
+
+	/// Whether the given key of a contract is locked by [`Self::write_timelock`] at the
+	/// current block.
+	fn is_locked(child_trie_info: &ChildInfo, hashed_key: &[u8]) -> bool {
+		<[u8; 32]>::try_from(hashed_key).ok()
+			.and_then(|hashed_key| {
+				<Timelocks<T>>::get(child_trie_info.storage_key().to_vec(), hashed_key)
+			})
+			.map_or(false, |unlock_at| <frame_system::Module<T>>::block_number() < unlock_at)
+	}
+
+	/// Remove the lock that [`Self::write_timelock`] put on the given key of a contract.
+	///
+	/// Only needs to be called for contracts that are `timelocked` according to their
+	/// contract info.
+	pub fn clear_timelock(child_trie_info: &ChildInfo, key: &StorageKey) {
+		<Timelocks<T>>::remove(child_trie_info.storage_key().to_vec(), meta::hashed_key(key));
+	}
+
+	/// Whether the contract with the given `account` uses timelocks.
+	///
+	/// Returns `false` if the contract doesn't exist or is a tombstone.
+	pub fn uses_timelocks(account: &AccountIdOf<T>) -> bool {
+		<ContractInfoOf<T>>::get(account)
+			.and_then(|info| info.get_alive())
+			.map_or(false, |info| info.timelocked)
+	}
+
+	/// Turn a value as stored in the child trie into the value that was written.
+	///
+	/// Values written by [`Self::write_dedup`] are replaced by the blob they point to. They
+	/// are only recognized by their entry in [`DedupPointers`] so that every other value is
+	/// returned exactly as it was written. A pointer is always exactly as long as a content
+	/// hash which is why only values of that length need to look up the entry.
+	fn resolve(child_trie_info: &ChildInfo, hashed_key: &[u8], value: Vec<u8>) -> Vec<u8> {
+		if value.len() == 32 {
+			let pointer = <[u8; 32]>::try_from(hashed_key).ok().and_then(|hashed_key| {
+				<DedupPointers<T>>::get(child_trie_info.storage_key().to_vec(), hashed_key)
+			});
+			if let Some(content_hash) = pointer {
+				return match <SharedBlobs<T>>::get(content_hash) {
+					Some((_, blob)) => T::ValueCipher::decrypt(blob),
+					None => {
+						log_error!("the blob {:?} of a pointer is missing", content_hash);
+						Vec::new()
+					}
+				};
+			}
+		}
+		T::ValueCipher::decrypt(value)
+	}
+
+	/// Reads the chunk with index `chunk_index` of a value when it is split into chunks of
+	/// `chunk_size` bytes. Only the last chunk can be shorter.
+	///
+	/// This allows to copy a value into the sandbox memory piece by piece. The value is still
+	/// read as a whole from the trie for every chunk. Returns `None` if the contract doesn't
+	/// store under the given `key`, if the chunk starts past the end of the value or if
+	/// `chunk_size` is zero.
+	pub fn read_chunk(
+		trie_id: &TrieId,
+		key: &StorageKey,
+		chunk_index: u32,
+		chunk_size: u32,
+	) -> Option<Vec<u8>> {
+		if chunk_size == 0 {
+			return None;
+		}
+		let value = Self::read(trie_id, key)?;
+		let start = (chunk_index as usize).checked_mul(chunk_size as usize)?;
+		if start >= value.len() {
+			return None;
+		}
+		let end = value.len().min(start.saturating_add(chunk_size as usize));
+		Some(value[start..end].to_vec())
+	}
+
+	/// Reads a storage kv pair of a contract and SCALE decodes its value.
+	///
+	/// Returns `Ok(None)` if the contract doesn't store under the given `key` and `Err` if
+	/// the stored value isn't exactly one encoded `D`.
+	pub fn read_decoded<D: Decode>(
+		trie_id: &TrieId,
+		key: &StorageKey,
+	) -> Result<Option<D>, codec::Error> {
+		let value = match Self::read(trie_id, key) {
+			Some(value) => value,
+			None => return Ok(None),
+		};
+		let mut input = &value[..];
+		let decoded = D::decode(&mut input)?;
+		if !input.is_empty() {
+			return Err("value has trailing bytes after decoding".into());
+		}
+		Ok(Some(decoded))
+	}
+
+	/// Reads a storage kv pair of a contract and falls back to the storage of another one.
+	///
+	/// The pair is read from `trie_id` and only if the contract doesn't store under the
+	/// given `key` it is read from `fallback_trie_id`. This allows instances of a template
+	/// contract to inherit its storage. Writes need to go to `trie_id` via [`Self::write`]
+	/// which shadows the value of the template from then on.
+	pub fn read_with_fallback(
+		trie_id: &TrieId,
+		fallback_trie_id: &TrieId,
+		key: &StorageKey,
+	) -> Option<Vec<u8>> {
+		Self::read(trie_id, key).or_else(|| Self::read(fallback_trie_id, key))
+	}
+
+	/// Reads a storage kv pair of a contract if the access to it is declared.
+	///
+	/// Behaves like [`Self::read_child`] if no `access_list` is passed. Otherwise `Err` is
+	/// returned if `key` isn't declared for `account` in the `access_list`. The lock of `key`
+	/// is only looked up if the contract is `timelocked`.
+	///
+	/// Declared reads are recorded in `ReadAudit` when `Config::AuditReads` is enabled.
+	pub fn read_declared(
+		access_list: Option<&AccessList<AccountIdOf<T>>>,
+		account: &AccountIdOf<T>,
+		child_trie_info: &ChildInfo,
+		timelocked: bool,
+		key: &StorageKey,
+	) -> Result<Option<Vec<u8>>, UndeclaredKeyAccess> {
+		if let Some(access_list) = access_list {
+			access_list.check(account, key)?;
+		}
+		if T::AuditReads::get() {
+			let now = <frame_system::Module<T>>::block_number();
+			<ReadAudit<T>>::append(
+				now,
+				(child_trie_info.storage_key().to_vec(), meta::hashed_key(key), account.clone()),
+			);
+			<ReadAuditBlocks<T>>::mutate(|blocks| match blocks {
+				Some((_, newest)) => *newest = now,
+				None => *blocks = Some((now, now)),
+			});
+		}
+		Ok(Self::read_locked(child_trie_info, timelocked, key))
+	}
+
+	/// Remove the entries of `ReadAudit` that expired at the block `now`.
+	///
+	/// This doesn't depend on `Config::AuditReads` so that the entries that were recorded
+	/// before it was disabled are removed as well. At most [`AUDIT_PRUNE_LIMIT`] blocks are
+	/// removed at once.
+	pub fn prune_read_audit(now: T::BlockNumber) -> Weight {
+		let db_weight = T::DbWeight::get();
+		let (mut oldest, newest) = match <ReadAuditBlocks<T>>::get() {
+			Some(blocks) => blocks,
+			None => return db_weight.reads(1),
+		};
+		let expired = match now.checked_sub(&T::AuditRetention::get()) {
+			Some(expired) => expired.min(newest),
+			None => return db_weight.reads(1),
+		};
+		let mut pruned = 0;
+		while oldest <= expired && pruned < AUDIT_PRUNE_LIMIT {
+			<ReadAudit<T>>::remove(oldest);
+			oldest = oldest.saturating_add(One::one());
+			pruned += 1;
+		}
+		if pruned == 0 {
+			return db_weight.reads(1);
+		}
+		if oldest > newest {
+			<ReadAuditBlocks<T>>::kill();
+		} else {
+			<ReadAuditBlocks<T>>::put((oldest, newest));
+		}
+		db_weight.reads_writes(1, Weight::from(pruned + 1))
+	}
+
+	/// Reads up to `limit` kv pairs of a contract in the order of their hashed keys.
+	///
+	/// The iteration starts at the hashed key `start_key` (inclusive) or at the first key if
+	/// `None` is passed. The returned pairs contain the hashed keys. The second element of the
+	/// returned tuple is the hashed key to continue the iteration with or `None` if the end
+	/// of the trie was reached.
+	pub fn read_page(
+		trie_id: &TrieId,
+		start_key: Option<Vec<u8>>,
+		limit: u32,
+	) -> (Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<u8>>) {
+		let child_trie_info = crate::child_trie_info(&trie_id);
+		let next_key = |key: &[u8]| {
+			sp_io::default_child_storage::next_key(child_trie_info.storage_key(), key)
+		};
+		let mut pairs = Vec::new();
+		let mut cursor = start_key.or_else(|| next_key(&[]));
+		while let Some(key) = cursor {
+			if pairs.len() as u32 >= limit {
+				return (pairs, Some(key));
+			}
+			cursor = next_key(&key);
+			if Self::is_locked(&child_trie_info, &key) {
+				continue;
+			}
+			if let Some(value) = child::get_raw(&child_trie_info, &key) {
+				let value = Self::resolve(&child_trie_info, &key, value);
+				pairs.push((key, value));
+			}
+		}
+		(pairs, None)
+	}
+
+	/// Iterates over the hashed keys of a contract in ascending lexicographic order.
+	///
+	/// The order is part of the contract of this function and the same on every node: keys
+	/// are visited through `next_key` whose order every backend has to follow. It can be
+	/// relied upon to compute digests or diffs of the storage of a contract. Keys that are
+	/// locked by [`Self::write_timelock`] are skipped like they are by [`Self::read_page`].
+	pub fn iter_keys(trie_id: &TrieId) -> impl Iterator<Item = Vec<u8>> {
+		let child_trie_info = crate::child_trie_info(&trie_id);
+		let storage_key = child_trie_info.storage_key().to_vec();
+		let first = sp_io::default_child_storage::next_key(&storage_key, &[]);
+		sp_std::iter::successors(first, move |key| {
+			sp_io::default_child_storage::next_key(&storage_key, key)
+		})
+		.filter(move |key| !Self::is_locked(&child_trie_info, key))
+	}
+
+	/// Estimates the maximum depth of the child trie of a contract in nodes.
+	///
+	/// The depth of a key is the number of branch nodes on its path plus its leaf. It is
+	/// derived from the nibbles that the key shares with its neighbours. Only a sample of
+	/// up to [`TRIE_DEPTH_SAMPLES`] keys spread over the 16 subtries of the root is read.
+	/// Deeper paths that aren't sampled are missed, which is why this is only an estimate.
+	///
+	/// This reads hundreds of keys and is meant for off-chain diagnostics only. Returns `0`
+	/// for an empty trie.
+	pub fn trie_depth_estimate(trie_id: &TrieId) -> u32 {
+		let child_trie_info = crate::child_trie_info(&trie_id);
+		let next_key = |key: &[u8]| {
+			sp_io::default_child_storage::next_key(child_trie_info.storage_key(), key)
+		};
+		let samples_per_subtrie = (TRIE_DEPTH_SAMPLES / 16) as usize;
+		let subtries = (0u8..16)
+			.map(|nibble| {
+				let mut keys = Vec::new();
+				let mut cursor = next_key(&[nibble << 4]);
+				while let Some(key) = cursor {
+					let in_subtrie = key.first().map(|b| b >> 4) == Some(nibble);
+					if keys.len() >= samples_per_subtrie || !in_subtrie {
+						break;
+					}
+					cursor = next_key(&key);
+					keys.push(key);
+				}
+				keys
+			})
+			.filter(|keys| !keys.is_empty())
+			.collect::<Vec<_>>();
+
+		// The root is a branch as soon as the keys don't share their first nibble.
+		let root_branch = if subtries.len() > 1 { 1 } else { 0 };
+		subtries.iter()
+			.map(|keys| root_branch + Self::sampled_depth(keys))
+			.max()
+			.unwrap_or(0)
+	}
+
+	/// The maximum depth of the given sorted keys when only those are considered.
+	///
+	/// The branch nodes on the path of a key are at the distinct lengths of the prefixes it
+	/// shares with the other keys. For sorted keys these are the running minima of the shared
+	/// prefixes of neighbouring keys.
+	fn sampled_depth(keys: &[Vec<u8>]) -> u32 {
+		let shared: Vec<u32> = keys.windows(2)
+			.map(|pair| Self::shared_nibbles(&pair[0], &pair[1]))
+			.collect();
+		(0..keys.len())
+			.map(|i| {
+				let mut branches = BTreeSet::new();
+				let mut min = u32::max_value();
+				for len in &shared[i..] {
+					min = min.min(*len);
+					branches.insert(min);
+				}
+				let mut min = u32::max_value();
+				for len in shared[..i].iter().rev() {
+					min = min.min(*len);
+					branches.insert(min);
+				}
+				branches.len() as u32 + 1
+			})
+			.max()
+			.unwrap_or(0)
+	}
+
+	/// The number of leading nibbles that `a` and `b` have in common.
+	fn shared_nibbles(a: &[u8], b: &[u8]) -> u32 {
+		let mut nibbles = 0;
+		for (a, b) in a.iter().zip(b) {
+			if a != b {
+				if a >> 4 == b >> 4 {
+					nibbles += 1;
+				}
+				break;
+			}
+			nibbles += 2;
+		}
+		nibbles
+	}
+
+	/// Counts the values stored by all alive contracts by their size.
+	///
+	/// `buckets` are the ascending upper bounds of the size classes in bytes. A value is
+	/// counted in the first bucket whose bound is at least its size. The returned vector has
+	/// one more entry than `buckets` which counts the values that are larger than all bounds.
+	/// Sizes are taken as stored in the child tries, including keys that are currently
+	/// locked and the pointers stored in place of deduplicated values.
+	///
+	/// This scans the storage of every contract and is meant for off-chain analysis only.
+	pub fn value_size_histogram(buckets: &[u32]) -> Vec<u64> {
+		let mut histogram = vec![0u64; buckets.len() + 1];
+		let tries = <ContractInfoOf<T>>::iter()
+			.filter_map(|(_, info)| info.get_alive())
+			.map(|info| crate::child_trie_info(&info.trie_id));
+		for child_trie_info in tries {
+			let storage_key = child_trie_info.storage_key();
+			let mut cursor = sp_io::default_child_storage::next_key(storage_key, &[]);
+			while let Some(key) = cursor {
+				// Reading into an empty buffer returns the length without copying the value.
+				let len = sp_io::default_child_storage::read(storage_key, &key, &mut [], 0)
+					.unwrap_or(0);
+				let bucket = buckets.iter()
+					.position(|bound| len <= *bound)
+					.unwrap_or(buckets.len());
+				histogram[bucket] += 1;
+				cursor = sp_io::default_child_storage::next_key(storage_key, &key);
+			}
+		}
+		histogram
+	}
+
+	/// Update a storage entry into a contract's kv storage.
+	///
+	/// If the `opt_new_value` is `None` then the kv pair is removed.
+	///
+	/// This function also updates the bookkeeping info such as: number of total non-empty pairs a
+	/// contract owns, the last block the storage was written to, etc. That's why, in contrast to
+	/// `read`, this function also requires the `account` ID.
+	///
+	/// Returns how the write changed the size and the number of pairs of the contract storage.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn write(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<WriteDelta, WriteError> {
+		Self::write_child(account, &crate::child_trie_info(&trie_id), key, opt_new_value)
+	}
+
+	/// Update a storage entry like [`Self::write`] and return the previous value together with
+	/// the root of the contract's child trie after the write.
+	///
+	/// Calculating the root is much more expensive than the write itself. Only use this if the
+	/// root is needed right away, e.g. to anchor a storage proof.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn write_return_root(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<(Option<Vec<u8>>, Vec<u8>), WriteError> {
+		let child_trie_info = crate::child_trie_info(&trie_id);
+		let prev_value = Self::read_child(&child_trie_info, key);
+		Self::write_child(account, &child_trie_info, key, opt_new_value)?;
+		Ok((prev_value, child::root(&child_trie_info)))
+	}
+
+	/// Update a storage entry like [`Self::write`] with the SCALE encoded `new_value` and
+	/// return the previous value decoded as `D`.
+	///
+	/// Returns `Ok(None)` if there was no previous value. The previous value is decoded
+	/// like [`Self::read_decoded`] before writing, so nothing is written if it can't be
+	/// decoded.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn write_typed<D: Decode, E: Encode>(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		new_value: Option<E>,
+	) -> Result<Option<D>, WriteTypedError> {
+		let prev_value = Self::read_decoded::<D>(trie_id, key)
+			.map_err(|_| WriteTypedError::UndecodableOldValue)?;
+		Self::write(account, trie_id, key, new_value.map(|value| value.encode()))?;
+		Ok(prev_value)
+	}
+
+	/// Remove the value under `key` like [`Self::write`] but only if it still equals
+	/// `expected`.
+	///
+	/// Returns whether the value was removed. Nothing is written if the value differs or
+	/// the key is absent which keeps a concurrent update of the key from being clobbered.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn delete_if_equals(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		expected: Vec<u8>,
+	) -> Result<bool, WriteError> {
+		if <ContractInfoOf<T>>::get(account).and_then(|info| info.get_alive()).is_none() {
+			return Err(WriteError::ContractAbsent);
+		}
+		if Self::read(trie_id, key) != Some(expected) {
+			return Ok(false);
+		}
+		Self::write(account, trie_id, key, None)?;
+		Ok(true)
+	}
+
+	/// Update a storage entry like [`Self::write`] and return the weight of the write as
+	/// computed by `T::WriteCostModel`.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn write_weighted(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<(WriteDelta, Weight), WriteError> {
+		let child_trie_info = crate::child_trie_info(&trie_id);
+		// The value as stored is what the bookkeeping accounts for. It differs from the value
+		// that is read for encrypted and deduplicated values and includes locked keys.
+		let old_len = child::get_raw(&child_trie_info, &meta::hashed_key(key))
+			.map(|v| v.len() as u32);
+		let new_len = opt_new_value.as_ref().map(|v| v.len() as u32);
+		let kind = match (old_len, new_len) {
+			(None, Some(_)) => WriteKind::Insert,
+			(Some(_), Some(_)) => WriteKind::Overwrite,
+			(_, None) => WriteKind::Delete,
+		};
+		let delta = Self::write_child(account, &child_trie_info, key, opt_new_value)?;
+		let weight = T::WriteCostModel::write_cost(
+			&<crate::Module<T>>::current_schedule().host_fn_weights,
+			kind,
+			old_len.unwrap_or(0),
+			new_len.unwrap_or(0),
+		);
+		Ok((delta, weight))
+	}
+
+	/// Update a storage entry like [`Self::write`] unless a write with the same
+	/// `idempotency_key` was already performed for the contract.
+	///
+	/// This allows a message that is delivered more than once to be applied only once. Only
+	/// the last [`IDEMPOTENCY_HISTORY_LEN`] keys of each contract are remembered.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn write_idempotent(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+		idempotency_key: [u8; 32],
+	) -> Result<IdempotentWrite, WriteError> {
+		// A duplicate is only reported for a contract that would have accepted the write.
+		match <ContractInfoOf<T>>::get(account).and_then(|info| info.get_alive()) {
+			Some(info) if info.frozen => return Err(WriteError::ContractFrozen),
+			Some(_) => (),
+			None => return Err(WriteError::ContractAbsent),
+		}
+		let mut history = <IdempotencyKeys<T>>::get(account);
+		if history.contains(&idempotency_key) {
+			return Ok(IdempotentWrite::Duplicate);
+		}
+		let delta = Self::write(account, trie_id, key, opt_new_value)?;
+		if history.len() >= IDEMPOTENCY_HISTORY_LEN {
+			history.remove(0);
+		}
+		history.push(idempotency_key);
+		<IdempotencyKeys<T>>::insert(account, history);
+		Ok(IdempotentWrite::Applied(delta))
+	}
+
+	/// Update a storage entry like [`Self::write`] and record `writer` as the last writer
+	/// of the key if the contract opted in via [`Self::set_write_attribution`].
+	///
+	/// Removals are attributed as well. Use [`Self::last_writer`] to read the attribution.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn write_attributed(
+		writer: AccountIdOf<T>,
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<WriteDelta, WriteError> {
+		let delta = Self::write(account, trie_id, key, opt_new_value)?;
+		if <AttributedContracts<T>>::contains_key(account) {
+			<WriteAttribution<T>>::insert(
+				trie_id,
+				meta::hashed_key(key),
+				(writer, <frame_system::Module<T>>::block_number()),
+			);
+		}
+		Ok(delta)
+	}
+
+	/// Returns the account that last wrote to `key` through [`Self::write_attributed`]
+	/// together with the block of the write.
+	///
+	/// Returns `None` if the key was never written to while the contract had the
+	/// attribution enabled.
+	pub fn last_writer(
+		trie_id: &TrieId,
+		key: &StorageKey,
+	) -> Option<(AccountIdOf<T>, T::BlockNumber)> {
+		<WriteAttribution<T>>::get(trie_id, meta::hashed_key(key))
+	}
+
+	/// Enable or disable the attribution of writes for the contract given by the account id.
+	///
+	/// The attribution requires an additional storage item per written key which is why it
+	/// is disabled by default. Disabling it removes all recorded attribution.
+	///
+	/// Returns `Err` if the contract doesn't exist or is a tombstone.
+	pub fn set_write_attribution(
+		account: &AccountIdOf<T>,
+		enabled: bool,
+	) -> Result<(), ContractAbsentError> {
+		let info = <ContractInfoOf<T>>::get(account)
+			.and_then(|i| i.get_alive())
+			.ok_or(ContractAbsentError)?;
+		if enabled {
+			<AttributedContracts<T>>::insert(account, ());
+		} else {
+			<AttributedContracts<T>>::remove(account);
+			<WriteAttribution<T>>::remove_prefix(&info.trie_id);
+		}
+		Ok(())
+	}
+
+	/// Update a storage entry like [`Self::write`] but keep it hidden from reads until
+	/// the block `unlock_at`.
+	///
+	/// Reads return `None` for the key as if it was absent before `unlock_at` and the value
+	/// from `unlock_at` on. A later write to the key through any other function removes the
+	/// lock. The value is accounted for in the bookkeeping of the contract right away.
+	///
+	/// The contract is marked as `timelocked` from then on which makes its reads look up
+	/// the locks of their keys.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn write_timelock(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		value: Vec<u8>,
+		unlock_at: T::BlockNumber,
+	) -> Result<WriteDelta, WriteError> {
+		let delta = Self::write(account, trie_id, key, Some(value))?;
+		<ContractInfoOf<T>>::mutate(account, |info| {
+			if let Some(ContractInfo::Alive(info)) = info {
+				info.timelocked = true;
+			}
+		});
+		<Timelocks<T>>::insert(trie_id, meta::hashed_key(key), unlock_at);
+		Ok(delta)
+	}
+
+	/// Update a storage entry of a contract whose `child_trie_info` is already constructed.
+	///
+	/// See [`Self::write`] for the details.
+	pub fn write_child(
+		account: &AccountIdOf<T>,
+		child_trie_info: &ChildInfo,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<WriteDelta, WriteError> {
+		let mirrored = opt_new_value.clone();
+		Self::write_checked(account, child_trie_info, key, mirrored.as_deref(), || {
+			Self::write_deferred(child_trie_info, key, opt_new_value)
+		})
+	}
+
+	/// Perform a write with `put` once the contract is known to accept it and update the
+	/// bookkeeping of the contract with the returned changes.
+	///
+	/// Every write to the storage of a contract that isn't deferred goes through here so that
+	/// all of them are checked and have the same side effects. `mirrored` is the value as it
+	/// was written which is passed on to `T::WriteMirror`.
+	fn write_checked(
+		account: &AccountIdOf<T>,
+		child_trie_info: &ChildInfo,
+		key: &StorageKey,
+		mirrored: Option<&[u8]>,
+		put: impl FnOnce() -> WriteDelta,
+	) -> Result<WriteDelta, WriteError> {
+		let mut new_info = match <ContractInfoOf<T>>::get(account) {
+			Some(ContractInfo::Alive(alive)) => alive,
+			None | Some(ContractInfo::Tombstone(_)) => return Err(WriteError::ContractAbsent),
+		};
+		if new_info.frozen {
+			return Err(WriteError::ContractFrozen);
+		}
+		if T::ChargeRentOnWrite::get() && !Rent::<T>::charge_before_write(account, &mut new_info) {
+			return Err(WriteError::RentNotCovered);
+		}
+		if new_info.timelocked {
+			Self::clear_timelock(child_trie_info, key);
+		}
+		let delta = put();
+		StorageMeter::from(delta).apply_to(&mut new_info);
+		new_info.last_write = Some(<frame_system::Module<T>>::block_number());
+		<ContractInfoOf<T>>::insert(&account, ContractInfo::Alive(new_info));
+		T::WriteMirror::mirror(account, &meta::hashed_key(key), mirrored);
+		Ok(delta)
+	}
+
+	/// Update a storage entry of a contract without updating its bookkeeping.
+	///
+	/// The returned changes need to be applied to the contract with
+	/// [`Self::apply_bookkeeping`]. This allows to apply the changes of many writes with only
+	/// one update of the `AliveContractInfo`. It is the callers responsibility to make sure that
+	/// the contract is alive and not frozen.
+	pub fn write_deferred(
+		child_trie_info: &ChildInfo,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> WriteDelta {
+		let opt_new_value = opt_new_value.map(T::ValueCipher::encrypt);
+		Self::put_deferred(child_trie_info, meta::hashed_key(key), opt_new_value)
+	}
+
+	/// Write to the child trie `trie_id` without any bookkeeping.
+	///
+	/// This is for tries of the pallet itself, e.g. a scratch trie that is used during a
+	/// migration, which don't belong to a contract and therefore aren't billed. Neither
+	/// `ContractInfoOf` nor any other per contract state is read or updated. The value is
+	/// stored the same way as by [`Self::write`] so that [`Self::read`] can retrieve it.
+	///
+	/// Never use this on the trie of a contract: its storage size and pair counts would no
+	/// longer match its storage and rent would be charged for the wrong amount. Tries of
+	/// contracts are recorded in [`TrieOwner`] and rejected with [`OwnedTrie`].
+	pub fn write_raw(
+		trie_id: &TrieId,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<(), OwnedTrie> {
+		if <TrieOwner<T>>::contains_key(trie_id) {
+			return Err(OwnedTrie);
+		}
+		let child_trie_info = crate::child_trie_info(trie_id);
+		let hashed_key = meta::hashed_key(key);
+		match opt_new_value.map(T::ValueCipher::encrypt) {
+			Some(new_value) => child::put_raw(&child_trie_info, &hashed_key, &new_value[..]),
+			None => child::kill(&child_trie_info, &hashed_key),
+		}
+		Ok(())
+	}
+
+	/// The trie that holds the storage shared by all contracts with the given code hash.
+	///
+	/// It is derived from the code hash alone and therefore the same for every instance of
+	/// the code, no matter when it was instantiated.
+	pub fn shared_trie_id(code_hash: &CodeHash<T>) -> TrieId {
+		use sp_runtime::traits::Hash;
+
+		let buf: Vec<_> = b"contracts:shared".iter().chain(code_hash.as_ref()).cloned().collect();
+		T::Hashing::hash(&buf).as_ref().into()
+	}
+
+	/// Reads a kv pair of the storage that is shared by all contracts with the given
+	/// `code_hash`.
+	///
+	/// Only a contract that runs this code can read the shared storage. `Err` is returned if
+	/// the contract specified by the id `account` doesn't exist or runs another code.
+	pub fn read_shared(
+		account: &AccountIdOf<T>,
+		code_hash: &CodeHash<T>,
+		key: &StorageKey,
+	) -> Result<Option<Vec<u8>>, SharedStorageError> {
+		Self::shared_access(account, code_hash)?;
+		Ok(Self::read(&Self::shared_trie_id(code_hash), key))
+	}
+
+	/// Update a kv pair of the storage that is shared by all contracts with the given
+	/// `code_hash`.
+	///
+	/// The value is stored like by [`Self::write`]. A pair is paid for by the contract that
+	/// wrote it last: It is accounted in the `AliveContractInfo` of that contract and no longer
+	/// in the one of the contract that wrote it before. The pairs a contract pays for are
+	/// removed together with the contract. [`SharedStorage`] sums up all pairs of a code hash
+	/// and its entry is removed with the last one.
+	///
+	/// Only a contract that runs this code can write to the shared storage. `Err` is returned
+	/// if the contract specified by the id `account` doesn't exist, is frozen or runs another
+	/// code or if the value is larger than `Config::MaxValueSize`.
+	pub fn write_shared(
+		account: &AccountIdOf<T>,
+		code_hash: &CodeHash<T>,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<WriteDelta, SharedStorageError> {
+		if Self::shared_access(account, code_hash)?.frozen {
+			return Err(SharedStorageError::ContractFrozen);
+		}
+		let max_value_size = T::MaxValueSize::get() as usize;
+		if opt_new_value.as_ref().map_or(false, |value| value.len() > max_value_size) {
+			return Err(SharedStorageError::ValueTooLarge);
+		}
+		let child_trie_info = crate::child_trie_info(&Self::shared_trie_id(code_hash));
+		let hashed_key = meta::hashed_key(key);
+
+		// The contract that wrote the pair before stops paying for it.
+		let mut delta = Self::put_deferred(&child_trie_info, hashed_key, None);
+		if let Some(owner) = <SharedPairOwners<T>>::take(code_hash, hashed_key) {
+			<SharedPairsOf<T>>::remove(&owner, hashed_key);
+			Self::charge_shared(&owner, &delta);
+		}
+		if opt_new_value.is_some() {
+			let added = Self::write_deferred(&child_trie_info, key, opt_new_value);
+			<SharedPairOwners<T>>::insert(code_hash, hashed_key, account);
+			<SharedPairsOf<T>>::insert(account, hashed_key, code_hash);
+			Self::charge_shared(account, &added);
+			delta.accumulate(&added);
+		}
+		Self::apply_to_shared_storage(code_hash, &delta);
+		Ok(delta)
+	}
+
+	/// Remove the pairs of shared storage that the contract `account` pays for.
+	///
+	/// See [`Self::write_shared`]. Returns how the removal changes the bookkeeping of the
+	/// contract. It isn't applied to the contract which is about to be removed anyway.
+	pub(crate) fn remove_shared_pairs(account: &AccountIdOf<T>) -> WriteDelta {
+		let mut delta = WriteDelta::default();
+		for (hashed_key, code_hash) in <SharedPairsOf<T>>::drain_prefix(account) {
+			<SharedPairOwners<T>>::remove(&code_hash, hashed_key);
+			let child_trie_info = crate::child_trie_info(&Self::shared_trie_id(&code_hash));
+			let removed = Self::put_deferred(&child_trie_info, hashed_key, None);
+			Self::apply_to_shared_storage(&code_hash, &removed);
+			delta.accumulate(&removed);
+		}
+		delta
+	}
+
+	/// Account a change of the shared storage to the contract that pays for it.
+	fn charge_shared(account: &AccountIdOf<T>, delta: &WriteDelta) {
+		<ContractInfoOf<T>>::mutate(account, |info| {
+			if let Some(ContractInfo::Alive(info)) = info {
+				StorageMeter::from(*delta).apply_to(info);
+			}
+		});
+	}
+
+	/// Apply a change of the shared storage of `code_hash` to its [`SharedStorage`] entry.
+	fn apply_to_shared_storage(code_hash: &CodeHash<T>, delta: &WriteDelta) {
+		<SharedStorage<T>>::mutate_exists(code_hash, |info| {
+			let mut shared = info.take().unwrap_or_default();
+			StorageMeter::from(*delta).apply_to_shared(&mut shared);
+			*info = Some(shared).filter(|shared| shared.pair_count > 0);
+		});
+	}
+
+	/// The info of the contract `account` if it is allowed to access the shared storage of
+	/// `code_hash`.
+	fn shared_access(
+		account: &AccountIdOf<T>,
+		code_hash: &CodeHash<T>,
+	) -> Result<AliveContractInfo<T>, SharedStorageError> {
+		let info = <ContractInfoOf<T>>::get(account)
+			.and_then(|info| info.get_alive())
+			.ok_or(SharedStorageError::ContractAbsent)?;
+		if info.code_hash != *code_hash {
+			return Err(SharedStorageError::CodeHashMismatch);
+		}
+		Ok(info)
+	}
+
+	/// Reads the value stored under the [`CompoundKey`] `key` like [`Self::read`].
+	pub fn read_compound(trie_id: &TrieId, key: &CompoundKey) -> Option<Vec<u8>> {
+		Self::read(trie_id, &key.storage_key())
+	}
+
+	/// Update the storage entry of a contract under the [`CompoundKey`] `key` like
+	/// [`Self::write`].
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn write_compound(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &CompoundKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<WriteDelta, WriteError> {
+		Self::write(account, trie_id, &key.storage_key(), opt_new_value)
+	}
+
+	/// Update the storage entry of a contract under the integer key `int_key` like
+	/// [`Self::write`] and keep track of the key so that it can be found by [`Self::range`].
+	///
+	/// The value is stored under [`ordered_key`] which allows the contract to access it
+	/// like any other value.
+	pub fn write_ordered(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		int_key: u128,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<WriteDelta, WriteError> {
+		let exists = opt_new_value.is_some();
+		let delta = Self::write(account, trie_id, &ordered_key(int_key), opt_new_value)?;
+		if exists {
+			<OrderedIndex<T>>::insert(trie_id, int_key.to_be_bytes(), ());
+		} else {
+			<OrderedIndex<T>>::remove(trie_id, int_key.to_be_bytes());
+		}
+		Ok(delta)
+	}
+
+	/// Returns the values that were stored by [`Self::write_ordered`] under the integer keys
+	/// from `lo` to `hi` (both inclusive), ordered by their key.
+	///
+	/// Only the keys within the range are visited. Keys whose value was removed by other
+	/// means than [`Self::write_ordered`] are skipped.
+	pub fn range(trie_id: &TrieId, lo: u128, hi: u128) -> Vec<(u128, Vec<u8>)> {
+		let start = <OrderedIndex<T>>::hashed_key_for(trie_id, lo.to_be_bytes());
+		let prefix_len = start.len() - 16;
+		let mut next = if frame_support::storage::unhashed::exists(&start) {
+			Some(start.clone())
+		} else {
+			sp_io::storage::next_key(&start)
+		};
+		let mut entries = Vec::new();
+		while let Some(key) = next {
+			if key.len() != start.len() || key[..prefix_len] != start[..prefix_len] {
+				break;
+			}
+			let mut int_key = [0; 16];
+			int_key.copy_from_slice(&key[prefix_len..]);
+			let int_key = u128::from_be_bytes(int_key);
+			if int_key > hi {
+				break;
+			}
+			if let Some(value) = Self::read(trie_id, &ordered_key(int_key)) {
+				entries.push((int_key, value));
+			}
+			next = sp_io::storage::next_key(&key);
+		}
+		entries
+	}
+
+	/// Put a value into the child trie as is and return the bookkeeping changes.
+	///
+	/// A pointer of [`Self::write_dedup`] which is overwritten releases its blob.
+	fn put_deferred(
+		child_trie_info: &ChildInfo,
+		hashed_key: [u8; 32],
+		opt_new_value: Option<Vec<u8>>,
+	) -> WriteDelta {
+		// In order to correctly update the book keeping we need to fetch the previous
+		// value of the key-value pair.
+		//
+		// It might be a bit more clean if we had an API that supported getting the size
+		// of the value without going through the loading of it. But at the moment of
+		// writing, there is no such API.
+		//
+		// That's not a show stopper in any case, since the performance cost is
+		// dominated by the trie traversal anyway.
+		let opt_prev_value = child::get_raw(child_trie_info, &hashed_key);
+		if opt_prev_value.as_ref().map_or(false, |value| value.len() == 32) {
+			Self::release_pointer(&child_trie_info.storage_key().to_vec(), &hashed_key);
+		}
+
+		let len = |value: &Option<Vec<u8>>| value.as_ref().map(|v| v.len() as u32);
+		let mut meter = StorageMeter::default();
+		meter.record_write(len(&opt_prev_value), len(&opt_new_value));
+
+		// Finally, perform the change on the storage.
+		match opt_new_value {
+			Some(new_value) => child::put_raw(child_trie_info, &hashed_key, &new_value[..]),
+			None => child::kill(child_trie_info, &hashed_key),
+		}
+
+		meter.delta()
+	}
+
+	/// Update a storage entry like [`Self::write`] but store `value` only once for all
+	/// contracts that write the same value this way.
+	///
+	/// The value is stored in [`SharedBlobs`] under its blake2 hash together with the number of
+	/// keys pointing to it. The contract only stores the 32 byte content hash under `key`,
+	/// which is also all it is charged for. The key is recorded in [`DedupPointers`] and reads
+	/// resolve the hash transparently. The blob is removed once no key points to it anymore.
+	///
+	/// If the contract specified by the id `account` doesn't exist or is frozen or if `value` is
+	/// larger than `Config::MaxValueSize` `Err` is returned.
+	pub fn write_dedup(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		value: Vec<u8>,
+	) -> Result<WriteDelta, WriteError> {
+		if value.len() > T::MaxValueSize::get() as usize {
+			return Err(WriteError::ValueTooLarge);
+		}
+		let hashed_key = meta::hashed_key(key);
+		let content_hash = sp_io::hashing::blake2_256(&value);
+		let child_trie_info = crate::child_trie_info(&trie_id);
+		let mirrored = value.clone();
+		Self::write_checked(account, &child_trie_info, key, Some(&mirrored[..]), || {
+			// Retaining the blob before the previous value is released makes sure that writing
+			// the same value again doesn't remove the blob in between.
+			Self::retain_blob(content_hash, value);
+			let pointer = Some(content_hash.to_vec());
+			let delta = Self::put_deferred(&child_trie_info, hashed_key, pointer);
+			<DedupPointers<T>>::insert(trie_id, hashed_key, content_hash);
+			delta
+		})
+	}
+
+	/// Add a reference to the blob with the given `content_hash`, storing `value` as the
+	/// blob if it doesn't exist yet.
+	fn retain_blob(content_hash: [u8; 32], value: Vec<u8>) {
+		<SharedBlobs<T>>::mutate(content_hash, |blob| match blob {
+			Some((refcount, _)) => *refcount = refcount.saturating_add(1),
+			None => {
+				let value = T::ValueCipher::encrypt(value);
+				<SharedBlobBytes>::mutate(|bytes| *bytes = bytes.saturating_add(value.len() as u64));
+				*blob = Some((1, value));
+			}
+		});
+	}
+
+	/// Remove a reference from the blob with the given `content_hash`.
+	fn release_blob(content_hash: [u8; 32]) {
+		<SharedBlobs<T>>::mutate_exists(content_hash, |blob| {
+			if let Some((refcount, value)) = blob {
+				*refcount = refcount.saturating_sub(1);
+				if *refcount == 0 {
+					let len = value.len() as u64;
+					<SharedBlobBytes>::mutate(|bytes| *bytes = bytes.saturating_sub(len));
+					*blob = None;
+				}
+			}
+		});
+	}
+
+	/// Release the blob that the given key of a contract points to, if any.
+	///
+	/// This needs to be called by everything that removes a value from the child trie without
+	/// going through [`Self::write`].
+	pub(crate) fn release_pointer(trie_id: &TrieId, hashed_key: &[u8; 32]) {
+		if let Some(content_hash) = <DedupPointers<T>>::take(trie_id, hashed_key) {
+			Self::release_blob(content_hash);
+		}
+	}
 
-/// An error that means that the account requested either doesn't exist or represents a tombstone
-/// account.
-#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
-pub struct ContractAbsentError;
+	/// Release the blobs that any key of a contract points to.
+	pub(crate) fn release_pointers(trie_id: &TrieId) {
+		for (_, content_hash) in <DedupPointers<T>>::drain_prefix(trie_id) {
+			Self::release_blob(content_hash);
+		}
+	}
 
-pub struct Storage<T>(PhantomData<T>);
+	/// Apply the accumulated bookkeeping changes of [`Self::write_deferred`] to a contract.
+	///
+	/// Returns `Err` if the contract specified by the id `account` doesn't exist.
+	pub fn apply_bookkeeping(
+		account: &AccountIdOf<T>,
+		delta: &WriteDelta,
+	) -> Result<(), ContractAbsentError> {
+		Self::apply_frame(account, Some(delta), false)
+	}
 
-impl<T> Storage<T>
-where
-	T: Config,
-	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>
-{
-	/// Reads a storage kv pair of a contract.
+	/// Apply what a successful call did to a contract with one update of its
+	/// `AliveContractInfo`.
 	///
-	/// The read is performed from the `trie_id` only. The `address` is not necessary. If the contract
-	/// doesn't store under the given `key` `None` is returned.
-	pub fn read(trie_id: &TrieId, key: &StorageKey) -> Option<Vec<u8>> {
-		child::get_raw(&crate::child_trie_info(&trie_id), &blake2_256(key))
+	/// `delta` are the bookkeeping changes of [`Self::write_deferred`], if any. `count_call`
+	/// increments the call count of the contract.
+	///
+	/// Returns `Err` if the contract specified by the id `account` doesn't exist.
+	pub fn apply_frame(
+		account: &AccountIdOf<T>,
+		delta: Option<&WriteDelta>,
+		count_call: bool,
+	) -> Result<(), ContractAbsentError> {
+		<ContractInfoOf<T>>::try_mutate(account, |info| {
+			let info = info.as_mut().and_then(|i| i.as_alive_mut()).ok_or(ContractAbsentError)?;
+			if let Some(delta) = delta {
+				StorageMeter::from(*delta).apply_to(info);
+				info.last_write = Some(<frame_system::Module<T>>::block_number());
+			}
+			if count_call {
+				info.call_count = info.call_count.saturating_add(1);
+			}
+			Ok(())
+		})
 	}
 
-	/// Update a storage entry into a contract's kv storage.
+	/// Increment a counter stored under the given `key` by `by` and return the new value.
 	///
-	/// If the `opt_new_value` is `None` then the kv pair is removed.
+	/// The counter is stored as a little-endian `u64`. An absent or empty value is treated as
+	/// zero. Values shorter than 8 bytes are zero-extended and only the first 8 bytes of longer
+	/// values are considered. The addition saturates at `u64::MAX`.
 	///
-	/// This function also updates the bookkeeping info such as: number of total non-empty pairs a
-	/// contract owns, the last block the storage was written to, etc. That's why, in contrast to
-	/// `read`, this function also requires the `account` ID.
+	/// The result is stored through [`Self::write`] and therefore performs the same bookkeeping.
 	///
-	/// If the contract specified by the id `account` doesn't exist `Err` is returned.`
-	pub fn write(
+	/// If the contract specified by the id `account` doesn't exist or is frozen `Err` is returned.
+	pub fn increment_u64(
 		account: &AccountIdOf<T>,
 		trie_id: &TrieId,
 		key: &StorageKey,
-		opt_new_value: Option<Vec<u8>>,
-	) -> Result<(), ContractAbsentError> {
-		let mut new_info = match <ContractInfoOf<T>>::get(account) {
-			Some(ContractInfo::Alive(alive)) => alive,
-			None | Some(ContractInfo::Tombstone(_)) => return Err(ContractAbsentError),
-		};
+		by: u64,
+	) -> Result<u64, WriteError> {
+		let new_value = Self::read_u64(trie_id, key).saturating_add(by);
+		Self::write(account, trie_id, key, Some(new_value.to_le_bytes().to_vec()))?;
+		Ok(new_value)
+	}
 
-		let hashed_key = blake2_256(key);
-		let child_trie_info = &crate::child_trie_info(&trie_id);
+	/// Allocate the next id of the sequence stored under the given `seq_key` and return it.
+	///
+	/// The sequence is stored like a counter of [`Self::increment_u64`]. An absent sequence
+	/// hands out `1` first. Reading, incrementing and storing the id is done with one update
+	/// of the contract info through [`Self::write`]. In contrast to [`Self::increment_u64`] the
+	/// sequence doesn't saturate: no id is ever handed out twice.
+	///
+	/// Returns `Err` if the contract specified by the id `account` doesn't exist, is frozen or
+	/// if the sequence is exhausted.
+	pub fn next_sequence(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		seq_key: &StorageKey,
+	) -> Result<u64, SequenceError> {
+		let id = Self::read_u64(trie_id, seq_key)
+			.checked_add(1)
+			.ok_or(SequenceError::Exhausted)?;
+		Self::write(account, trie_id, seq_key, Some(id.to_le_bytes().to_vec()))?;
+		Ok(id)
+	}
 
-		// In order to correctly update the book keeping we need to fetch the previous
-		// value of the key-value pair.
-		//
-		// It might be a bit more clean if we had an API that supported getting the size
-		// of the value without going through the loading of it. But at the moment of
-		// writing, there is no such API.
-		//
-		// That's not a show stopper in any case, since the performance cost is
-		// dominated by the trie traversal anyway.
-		let opt_prev_value = child::get_raw(&child_trie_info, &hashed_key);
-
-		// Update the total number of KV pairs and the number of empty pairs.
-		match (&opt_prev_value, &opt_new_value) {
-			(Some(prev_value), None) => {
-				new_info.total_pair_count -= 1;
-				if prev_value.is_empty() {
-					new_info.empty_pair_count -= 1;
-				}
-			},
-			(None, Some(new_value)) => {
-				new_info.total_pair_count += 1;
-				if new_value.is_empty() {
-					new_info.empty_pair_count += 1;
-				}
-			},
-			(Some(prev_value), Some(new_value)) => {
-				if prev_value.is_empty() {
-					new_info.empty_pair_count -= 1;
-				}
-				if new_value.is_empty() {
-					new_info.empty_pair_count += 1;
-				}
-			}
-			(None, None) => {}
+	/// Store `value` under the given `key` only if it is greater than the stored value.
+	///
+	/// This is meant for nonces and similar values which must never decrease. The stored
+	/// value is interpreted like in [`Self::increment_u64`]. Nothing is written if `value`
+	/// isn't greater than the stored value.
+	///
+	/// Returns `Err` if the contract specified by the id `account` doesn't exist, is frozen or
+	/// if `value` isn't greater than the stored value.
+	pub fn write_monotonic_u64(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		value: u64,
+	) -> Result<(), MonotonicWriteError> {
+		if value <= Self::read_u64(trie_id, key) {
+			return Err(MonotonicWriteError::NonMonotonic);
 		}
+		Self::write(account, trie_id, key, Some(value.to_le_bytes().to_vec()))?;
+		Ok(())
+	}
 
-		// Update the total storage size.
-		let prev_value_len = opt_prev_value
-			.as_ref()
-			.map(|old_value| old_value.len() as u32)
-			.unwrap_or(0);
-		let new_value_len = opt_new_value
-			.as_ref()
-			.map(|new_value| new_value.len() as u32)
-			.unwrap_or(0);
-		new_info.storage_size = new_info
-			.storage_size
-			.saturating_add(new_value_len)
-			.saturating_sub(prev_value_len);
-
-		new_info.last_write = Some(<frame_system::Module<T>>::block_number());
-		<ContractInfoOf<T>>::insert(&account, ContractInfo::Alive(new_info));
+	/// Store `value` under the given `key` unless it would downgrade the schema version.
+	///
+	/// The first byte of a versioned value is its version. An empty or absent value counts
+	/// as version `0`. Writing a value with the same or a higher version than the stored one
+	/// is allowed while a lower version is rejected and nothing is written. Removing the
+	/// value is always allowed.
+	///
+	/// Returns how the write changed the bookkeeping of the contract.
+	pub fn write_versioned(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		opt_new_value: Option<Vec<u8>>,
+	) -> Result<WriteDelta, VersionedWriteError> {
+		let version = |value: &[u8]| value.first().copied().unwrap_or(0);
+		if let Some(new_value) = &opt_new_value {
+			let stored = Self::read(trie_id, key).map_or(0, |value| version(&value));
+			if version(new_value) < stored {
+				return Err(VersionedWriteError::VersionDowngrade);
+			}
+		}
+		Ok(Self::write(account, trie_id, key, opt_new_value)?)
+	}
 
-		// Finally, perform the change on the storage.
-		match opt_new_value {
-			Some(new_value) => child::put_raw(&child_trie_info, &hashed_key, &new_value[..]),
-			None => child::kill(&child_trie_info, &hashed_key),
+	/// Read the value under the given `key` as a little-endian `u64`.
+	///
+	/// See [`Self::increment_u64`] for how values of other lengths are interpreted.
+	fn read_u64(trie_id: &TrieId, key: &StorageKey) -> u64 {
+		let mut buf = [0u8; 8];
+		if let Some(prev_value) = Self::read(trie_id, key) {
+			let len = prev_value.len().min(buf.len());
+			buf[..len].copy_from_slice(&prev_value[..len]);
 		}
+		u64::from_le_bytes(buf)
+	}
 
+	/// Overwrite a part of the value stored under the given `key` with `patch_bytes`.
+	///
+	/// The bytes are written starting at `offset`. If the value is shorter than
+	/// `offset + patch_bytes.len()` it is extended with zeros. An absent value is treated
+	/// as an empty one. The bookkeeping is updated through [`Self::write`].
+	///
+	/// Returns `Err` if the contract specified by the id `account` doesn't exist, is frozen or
+	/// if `offset + patch_bytes.len()` overflows.
+	pub fn patch(
+		account: &AccountIdOf<T>,
+		trie_id: &TrieId,
+		key: &StorageKey,
+		offset: u32,
+		patch_bytes: &[u8],
+	) -> Result<(), PatchError> {
+		let end = u32::try_from(patch_bytes.len())
+			.ok()
+			.and_then(|len| offset.checked_add(len))
+			.ok_or(PatchError::Overflow)? as usize;
+		let offset = offset as usize;
+		let mut value = Self::read(trie_id, key).unwrap_or_default();
+		if value.len() < end {
+			value.resize(end, 0);
+		}
+		value[offset..end].copy_from_slice(patch_bytes);
+		Self::write(account, trie_id, key, Some(value))?;
 		Ok(())
 	}
 
+	/// Returns whether the contract given by the account id is frozen.
+	///
+	/// A contract that doesn't exist or is a tombstone is not frozen.
+	pub fn is_frozen(account: &AccountIdOf<T>) -> bool {
+		<ContractInfoOf<T>>::get(account)
+			.and_then(|i| i.as_alive().map(|i| i.frozen))
+			.unwrap_or(false)
+	}
+
+	/// Freeze or unfreeze the contract given by the account id.
+	///
+	/// While a contract is frozen all writes to its storage fail. Reads are unaffected.
+	///
+	/// Returns `Err` if the contract doesn't exist or is a tombstone.
+	pub fn set_frozen(account: &AccountIdOf<T>, frozen: bool) -> Result<(), ContractAbsentError> {
+		<ContractInfoOf<T>>::mutate(account, |maybe_contract_info| match maybe_contract_info {
+			Some(ContractInfo::Alive(ref mut alive_info)) => {
+				alive_info.frozen = frozen;
+				Ok(())
+			}
+			_ => Err(ContractAbsentError),
+		})
+	}
+
+	/// Returns the number of top-level calls counted for the contract given by the account id.
+	///
+	/// A contract that doesn't exist or is a tombstone has no calls counted.
+	pub fn call_count(account: &AccountIdOf<T>) -> u64 {
+		<ContractInfoOf<T>>::get(account)
+			.and_then(|i| i.as_alive().map(|i| i.call_count))
+			.unwrap_or(0)
+	}
+
 	/// Returns the rent allowance set for the contract give by the account id.
 	pub fn rent_allowance(
 		account: &AccountIdOf<T>,
@@ -145,6 +1771,10 @@ where
 
 	/// Set the rent allowance for the contract given by the account id.
 	///
+	/// The allowance is not checked against the balance of the contract. Any value is accepted
+	/// and the rent payment saturates at whatever the contract is able to pay. Use
+	/// `top_up_rent` in order to increase the allowance only by what is backed by free balance.
+	///
 	/// Returns `Err` if the contract doesn't exist or is a tombstone.
 	pub fn set_rent_allowance(
 		account: &AccountIdOf<T>,
@@ -152,6 +1782,7 @@ where
 	) -> Result<(), ContractAbsentError> {
 		<ContractInfoOf<T>>::mutate(account, |maybe_contract_info| match maybe_contract_info {
 			Some(ContractInfo::Alive(ref mut alive_info)) => {
+				Self::note_rent_allowance_change(Some(alive_info.rent_allowance), Some(rent_allowance));
 				alive_info.rent_allowance = rent_allowance;
 				Ok(())
 			}
@@ -159,6 +1790,181 @@ where
 		})
 	}
 
+	/// Increase the rent allowance of the contract given by the account id by `amount`.
+	///
+	/// The free balance of the contract must cover `amount` on top of the subsistence
+	/// threshold. The allowance saturates at the maximum balance value. Returns the new
+	/// rent allowance.
+	///
+	/// Returns `Err` if the contract doesn't exist, is a tombstone or its balance is too low.
+	pub fn top_up_rent(
+		account: &AccountIdOf<T>,
+		amount: BalanceOf<T>,
+	) -> Result<BalanceOf<T>, TopUpRentError> {
+		let required = Self::required_free_balance(account).saturating_add(amount);
+		<ContractInfoOf<T>>::mutate(account, |maybe_contract_info| match maybe_contract_info {
+			Some(ContractInfo::Alive(ref mut alive_info)) => {
+				if T::Currency::free_balance(account) < required {
+					return Err(TopUpRentError::BelowSubsistenceThreshold);
+				}
+				let new_allowance = alive_info.rent_allowance.saturating_add(amount);
+				Self::note_rent_allowance_change(Some(alive_info.rent_allowance), Some(new_allowance));
+				alive_info.rent_allowance = new_allowance;
+				Ok(alive_info.rent_allowance)
+			}
+			_ => Err(TopUpRentError::ContractAbsent),
+		})
+	}
+
+	/// Move `amount` from the reserved balance of the contract given by the account id to its
+	/// free balance and increase its rent allowance by the same amount.
+	///
+	/// This lets a contract fund its rent from funds that were reserved for it earlier. The
+	/// allowance saturates at the maximum balance value. Nothing is changed if the reserved
+	/// balance doesn't cover `amount`.
+	///
+	/// Returns `Err` if the contract doesn't exist, is a tombstone or its reserve is too low.
+	pub fn refill_rent_allowance_from_reserve(
+		account: &AccountIdOf<T>,
+		amount: BalanceOf<T>,
+	) -> Result<(), RefillRentError> {
+		<ContractInfoOf<T>>::mutate(account, |maybe_contract_info| match maybe_contract_info {
+			Some(ContractInfo::Alive(ref mut alive_info)) => {
+				if Self::free_reserve(account) < amount {
+					return Err(RefillRentError::InsufficientReserve);
+				}
+				// Unreserves all of `amount` because the reserve was checked above.
+				let _ = T::Currency::unreserve(account, amount);
+				let new_allowance = alive_info.rent_allowance.saturating_add(amount);
+				Self::note_rent_allowance_change(Some(alive_info.rent_allowance), Some(new_allowance));
+				alive_info.rent_allowance = new_allowance;
+				Ok(())
+			}
+			_ => Err(RefillRentError::ContractAbsent),
+		})
+	}
+
+	/// Returns the sum of the rent allowances of all alive contracts.
+	///
+	/// Unlimited allowances are not included. An allowance is considered unlimited when it
+	/// exceeds half of the maximum balance value: Allowances start out at the maximum and are
+	/// only decreased by rent payments which can't realistically add up to that amount.
+	/// The sum saturates at the maximum balance value.
+	pub fn total_rent_allowance() -> BalanceOf<T> {
+		<GlobalRentAllowance<T>>::get()
+	}
+
+	/// Updates the sum returned by `total_rent_allowance` after the allowance of a contract
+	/// changed from `old` to `new`. `None` means that there is no alive contract.
+	pub(crate) fn note_rent_allowance_change(old: Option<BalanceOf<T>>, new: Option<BalanceOf<T>>) {
+		let counted = |allowance: Option<BalanceOf<T>>| match allowance {
+			Some(allowance) if allowance <= <BalanceOf<T>>::max_value() / 2u32.into() => allowance,
+			_ => Zero::zero(),
+		};
+		let (old, new) = (counted(old), counted(new));
+		if old != new {
+			<GlobalRentAllowance<T>>::mutate(|total| {
+				*total = total.saturating_sub(old).saturating_add(new);
+			});
+		}
+	}
+
+	/// Returns the information of the contract given by the account id together with values
+	/// derived from it at the current block.
+	///
+	/// In contrast to `Rent::compute_projection` no rent is collected.
+	///
+	/// Returns `Err` if the contract doesn't exist or is a tombstone.
+	pub fn contract_info_detailed(
+		account: &AccountIdOf<T>,
+	) -> Result<DetailedContractInfo<T>, ContractAbsentError> {
+		let info = <ContractInfoOf<T>>::get(account)
+			.and_then(|i| i.get_alive())
+			.ok_or(ContractAbsentError)?;
+		let current_block = <frame_system::Module<T>>::block_number();
+		Ok(DetailedContractInfo {
+			projected_eviction: Rent::<T>::project_eviction(account, &info),
+			blocks_since_last_write: info.last_write
+				.map(|last_write| current_block.saturating_sub(last_write)),
+			physical_size: info.storage_size,
+			logical_size: info.storage_size
+				.saturating_add(T::StorageSizeOffset::get().into())
+				.saturating_add(info.empty_pair_count.into()),
+			info,
+		})
+	}
+
+	/// Returns the number of blocks the rent allowance of the contract given by the account id
+	/// covers at its current storage size and balance.
+	///
+	/// Returns `None` if the contract doesn't pay any rent or its allowance is unlimited. In
+	/// contrast to `Rent::compute_projection` this is the relative runway of the allowance and
+	/// not the absolute block at which the contract is evicted. The balance of the contract
+	/// running out earlier is not considered.
+	///
+	/// Returns `Err` if the contract doesn't exist or is a tombstone.
+	pub fn allowance_runway(
+		account: &AccountIdOf<T>,
+	) -> Result<Option<T::BlockNumber>, ContractAbsentError> {
+		let info = <ContractInfoOf<T>>::get(account)
+			.and_then(|i| i.get_alive())
+			.ok_or(ContractAbsentError)?;
+		if info.rent_allowance == <BalanceOf<T>>::max_value() {
+			return Ok(None);
+		}
+		let fee_per_block = Rent::<T>::compute_fee_per_block(
+			&Self::rent_deposit_balance(account),
+			&info,
+		);
+		if fee_per_block.is_zero() {
+			return Ok(None);
+		}
+		let blocks: u128 = (info.rent_allowance / fee_per_block).saturated_into();
+		Ok(Some(blocks.saturated_into()))
+	}
+
+	/// Returns the weight of removing the storage of the contract given by the account id.
+	///
+	/// This is what terminating the contract costs on top of the fixed costs of the
+	/// termination itself. It scales with the number of storage items of the contract.
+	///
+	/// Returns `Err` if the contract doesn't exist or is a tombstone.
+	pub fn estimate_termination_weight(
+		account: &AccountIdOf<T>,
+	) -> Result<Weight, ContractAbsentError> {
+		let info = <ContractInfoOf<T>>::get(account)
+			.and_then(|i| i.get_alive())
+			.ok_or(ContractAbsentError)?;
+		Ok(Self::termination_weight(info.total_pair_count))
+	}
+
+	/// Returns the weight of removing the storage of a contract with `pair_count` storage
+	/// items.
+	///
+	/// See [`Self::estimate_termination_weight`].
+	pub fn termination_weight(pair_count: u32) -> Weight {
+		let weight_per_key = T::WeightInfo::destroy_contract(1)
+			.saturating_sub(T::WeightInfo::destroy_contract(0));
+		weight_per_key.saturating_mul(pair_count.into())
+	}
+
+	/// Stores `layout` as the description of the storage layout of the contract given by
+	/// the account id. Replaces any previously registered layout.
+	///
+	/// Returns `Err` if the contract doesn't exist or is a tombstone.
+	pub fn register_layout(
+		account: &AccountIdOf<T>,
+		layout: Vec<u8>,
+	) -> Result<(), ContractAbsentError> {
+		match <ContractInfoOf<T>>::get(account) {
+			Some(ContractInfo::Alive(_)) => {
+				<ContractLayout<T>>::insert(account, layout);
+				Ok(())
+			},
+			_ => Err(ContractAbsentError),
+		}
+	}
+
 	/// Creates a new contract descriptor in the storage with the given code hash at the given address.
 	///
 	/// Returns `Err` if there is already a contract (or a tombstone) exists at the given address.
@@ -166,25 +1972,54 @@ where
 		account: &AccountIdOf<T>,
 		trie_id: TrieId,
 		ch: CodeHash<T>,
+	) -> Result<(), &'static str> {
+		Self::place_contract_with_storage(account, trie_id, ch, Vec::new())
+	}
+
+	/// Creates a new contract like [`Self::place_contract`] whose storage already contains
+	/// the `initial` kv pairs.
+	///
+	/// The pairs are stored like by [`Self::write_deferred`] and the contract info is written
+	/// only once instead of once per pair. If a key appears more than once, its last value is
+	/// stored. The values aren't checked against `MaxValueSize`.
+	///
+	/// Returns `Err` if there is already a contract (or a tombstone) exists at the given address.
+	pub fn place_contract_with_storage(
+		account: &AccountIdOf<T>,
+		trie_id: TrieId,
+		ch: CodeHash<T>,
+		initial: Vec<(StorageKey, Vec<u8>)>,
 	) -> Result<(), &'static str> {
 		<ContractInfoOf<T>>::mutate(account, |maybe_contract_info| {
 			if maybe_contract_info.is_some() {
 				return Err("Alive contract or tombstone already exists");
 			}
 
-			*maybe_contract_info = Some(
-				AliveContractInfo::<T> {
-					code_hash: ch,
-					storage_size: 0,
-					trie_id,
-					deduct_block: <frame_system::Module<T>>::block_number(),
-					rent_allowance: <BalanceOf<T>>::max_value(),
-					empty_pair_count: 0,
-					total_pair_count: 0,
-					last_write: None,
-				}
-				.into(),
-			);
+			let initial: BTreeMap<_, _> = initial.into_iter().collect();
+			let child_trie_info = crate::child_trie_info(&trie_id);
+			let mut delta = WriteDelta::default();
+			for (key, value) in &initial {
+				delta.accumulate(&Self::write_deferred(&child_trie_info, key, Some(value.clone())));
+			}
+
+			<TrieOwner<T>>::insert(&trie_id, account);
+
+			let current_block = <frame_system::Module<T>>::block_number();
+			let mut info = AliveContractInfo::<T> {
+				code_hash: ch,
+				storage_size: 0,
+				trie_id,
+				deduct_block: current_block,
+				rent_allowance: <BalanceOf<T>>::max_value(),
+				empty_pair_count: 0,
+				total_pair_count: 0,
+				last_write: if initial.is_empty() { None } else { Some(current_block) },
+				frozen: false,
+				call_count: 0,
+				timelocked: false,
+			};
+			StorageMeter::from(delta).apply_to(&mut info);
+			*maybe_contract_info = Some(info.into());
 
 			Ok(())
 		})
@@ -192,29 +2027,154 @@ where
 
 	/// Removes the contract and all the storage associated with it.
 	///
-	/// This function doesn't affect the account.
+	/// This function doesn't affect the total balance of the account. It only releases the
+	/// reference that kept it from being reaped, the tombstone deposit and whatever else the
+	/// contract reserved.
 	pub fn destroy_contract(address: &AccountIdOf<T>, trie_id: &TrieId) {
-		<ContractInfoOf<T>>::remove(address);
+		if let Some(ContractInfo::Alive(info)) = <ContractInfoOf<T>>::take(address) {
+			Self::note_rent_allowance_change(Some(info.rent_allowance), None);
+			Self::release_account(address);
+		}
+		Self::release_tombstone_deposit(address);
+		<ContractLayout<T>>::remove(address);
+		Self::remove_side_tables(address, trie_id);
 		child::kill_storage(&crate::child_trie_info(&trie_id), None);
 	}
 
+	/// Removes what is stored about a contract besides its contract info and its child trie.
+	///
+	/// This is done whenever a contract ceases to be alive: when it is destroyed as well as
+	/// when it is evicted or removed for not paying rent. The balance reserved by the
+	/// contract, except for its tombstone deposit, is moved back to its free balance.
+	pub(crate) fn remove_side_tables(account: &AccountIdOf<T>, trie_id: &TrieId) {
+		Self::remove_shared_pairs(account);
+		<IdempotencyKeys<T>>::remove(account);
+		<AttributedContracts<T>>::remove(account);
+		<WriteAttribution<T>>::remove_prefix(trie_id);
+		<Timelocks<T>>::remove_prefix(trie_id);
+		<OrderedIndex<T>>::remove_prefix(trie_id);
+		<TrieOwner<T>>::remove(trie_id);
+		Self::release_pointers(trie_id);
+		let reserve = Self::free_reserve(account);
+		if !reserve.is_zero() {
+			let _ = T::Currency::unreserve(account, reserve);
+		}
+	}
+
+	/// Take a reference on the account of an alive contract.
+	///
+	/// The reference keeps the account from being reaped by transfers while the contract is
+	/// alive. Does nothing if the account doesn't exist because taking a reference would
+	/// create it without an endowment.
+	pub(crate) fn hold_account(account: &AccountIdOf<T>) {
+		if <frame_system::Module<T>>::account_exists(account) {
+			<frame_system::Module<T>>::inc_ref(account);
+		}
+	}
+
+	/// Release the reference taken by [`Self::hold_account`].
+	///
+	/// Does nothing if the account was already reaped.
+	pub(crate) fn release_account(account: &AccountIdOf<T>) {
+		if <frame_system::Module<T>>::account_exists(account) {
+			<frame_system::Module<T>>::dec_ref(account);
+		}
+	}
+
+	/// Reserve the tombstone deposit on the account of a newly placed contract.
+	///
+	/// The deposit stays reserved while the contract is alive so that it can always pay for
+	/// its tombstone. Returns `Err` if the free balance doesn't cover the deposit.
+	pub(crate) fn reserve_tombstone_deposit(account: &AccountIdOf<T>) -> Result<(), DispatchError> {
+		let deposit = T::TombstoneDeposit::get();
+		if deposit.is_zero() {
+			return Ok(());
+		}
+		T::Currency::reserve(account, deposit).map_err(|_| Error::<T>::NewContractNotFunded)?;
+		<TombstoneDeposits<T>>::insert(account, deposit);
+		Ok(())
+	}
+
+	/// Move the tombstone deposit of a contract back to its free balance.
+	///
+	/// Only what is still reserved is released. Nothing is done for a reaped account.
+	pub(crate) fn release_tombstone_deposit(account: &AccountIdOf<T>) {
+		let deposit = <TombstoneDeposits<T>>::take(account)
+			.min(T::Currency::reserved_balance(account));
+		if !deposit.is_zero() {
+			let _ = T::Currency::unreserve(account, deposit);
+		}
+	}
+
+	/// Pay for the tombstone of an evicted contract with its tombstone deposit.
+	pub(crate) fn consume_tombstone_deposit(account: &AccountIdOf<T>) {
+		let deposit = <TombstoneDeposits<T>>::take(account);
+		if !deposit.is_zero() {
+			let (imbalance, _) = T::Currency::slash_reserved(account, deposit);
+			T::RentPayment::on_unbalanced(imbalance);
+		}
+	}
+
+	/// Whether the contract still has its whole tombstone deposit reserved.
+	pub(crate) fn has_tombstone_deposit(account: &AccountIdOf<T>) -> bool {
+		let deposit = <TombstoneDeposits<T>>::get(account);
+		!deposit.is_zero() && T::Currency::reserved_balance(account) >= deposit
+	}
+
+	/// The reserved balance of a contract that isn't its tombstone deposit.
+	pub fn free_reserve(account: &AccountIdOf<T>) -> BalanceOf<T> {
+		T::Currency::reserved_balance(account).saturating_sub(<TombstoneDeposits<T>>::get(account))
+	}
+
+	/// The balance of a contract that counts towards its rent deposit.
+	///
+	/// This is the free balance together with the tombstone deposit which is still owned by
+	/// the contract and therefore doesn't make its rent more expensive.
+	pub fn rent_deposit_balance(account: &AccountIdOf<T>) -> BalanceOf<T> {
+		T::Currency::free_balance(account).saturating_add(<TombstoneDeposits<T>>::get(account))
+	}
+
+	/// The free balance that a contract needs to keep in order to stay above the
+	/// subsistence threshold.
+	///
+	/// The tombstone deposit of the contract already covers that part of the threshold.
+	pub fn required_free_balance(account: &AccountIdOf<T>) -> BalanceOf<T> {
+		ConfigCache::<T>::subsistence_threshold_uncached()
+			.saturating_sub(<TombstoneDeposits<T>>::get(account))
+	}
+
 	/// This generator uses inner counter for account id and applies the hash over `AccountId +
 	/// accountid_counter`.
-	pub fn generate_trie_id(account_id: &AccountIdOf<T>) -> TrieId {
+	///
+	/// The counter alone guarantees uniqueness. Nevertheless, the child trie of a generated id
+	/// is probed for emptiness and a new id is generated in the unlikely case it already
+	/// contains data. Returns `Err` if no empty trie could be found within
+	/// `TRIE_ID_GENERATION_ATTEMPTS` attempts.
+	pub fn generate_trie_id(account_id: &AccountIdOf<T>) -> Result<TrieId, DispatchError> {
 		use frame_support::StorageValue;
 		use sp_runtime::traits::Hash;
-		// Note that skipping a value due to error is not an issue here.
-		// We only need uniqueness, not sequence.
-		let new_seed = AccountCounter::mutate(|v| {
-			*v = v.wrapping_add(1);
-			*v
-		});
 
-		let buf: Vec<_> = account_id.as_ref().iter()
-			.chain(&new_seed.to_le_bytes())
-			.cloned()
-			.collect();
-		T::Hashing::hash(&buf).as_ref().into()
+		for _ in 0..TRIE_ID_GENERATION_ATTEMPTS {
+			// Note that skipping a value due to error is not an issue here.
+			// We only need uniqueness, not sequence.
+			let new_seed = AccountCounter::mutate(|v| {
+				*v = v.wrapping_add(1);
+				*v
+			});
+
+			let buf: Vec<_> = account_id.as_ref().iter()
+				.chain(&new_seed.to_le_bytes())
+				.cloned()
+				.collect();
+			let trie_id: TrieId = T::Hashing::hash(&buf).as_ref().into();
+
+			let child_trie_info = crate::child_trie_info(&trie_id);
+			if sp_io::default_child_storage::next_key(child_trie_info.storage_key(), &[]).is_none() {
+				return Ok(trie_id);
+			}
+		}
+
+		Err(Error::<T>::TrieIdCollision.into())
 	}
 
 	/// Returns the code hash of the contract specified by `account` ID.
@@ -225,4 +2185,114 @@ where
 			.and_then(|i| i.as_alive().map(|i| i.code_hash))
 			.ok_or(ContractAbsentError)
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use super::{StorageMeter, WriteDelta};
+	use crate::RawAliveContractInfo;
+	use sp_std::collections::btree_map::BTreeMap;
+
+	type Info = RawAliveContractInfo<(), u64, u64>;
+
+	fn info(storage_size: u64, empty_pair_count: u32, total_pair_count: u32) -> Info {
+		RawAliveContractInfo {
+			trie_id: Default::default(),
+			storage_size,
+			empty_pair_count,
+			total_pair_count,
+			code_hash: (),
+			rent_allowance: 0,
+			deduct_block: 0,
+			last_write: None,
+			frozen: false,
+			call_count: 0,
+			timelocked: false,
+		}
+	}
+
+	fn counters(info: &Info) -> (u64, u32, u32) {
+		(info.storage_size, info.empty_pair_count, info.total_pair_count)
+	}
+
+	#[test]
+	fn meter_handles_all_transitions() {
+		// (old_len, new_len, size_delta, pair_delta, empty_pair_delta)
+		let cases = [
+			(None, None, 0, 0, 0),
+			(None, Some(0), 0, 1, 1),
+			(None, Some(5), 5, 1, 0),
+			(Some(0), None, 0, -1, -1),
+			(Some(0), Some(0), 0, 0, 0),
+			(Some(0), Some(5), 5, 0, -1),
+			(Some(5), None, -5, -1, 0),
+			(Some(5), Some(0), -5, 0, 1),
+			(Some(5), Some(3), -2, 0, 0),
+			(Some(3), Some(5), 2, 0, 0),
+		];
+		for &(old_len, new_len, size_delta, pair_delta, empty_pair_delta) in cases.iter() {
+			let mut meter = StorageMeter::default();
+			meter.record_write(old_len, new_len);
+			assert_eq!(
+				meter.delta(),
+				WriteDelta { size_delta, pair_delta, empty_pair_delta },
+				"transition {:?} -> {:?}", old_len, new_len
+			);
+		}
+	}
+
+	#[test]
+	fn meter_applies_to_contract() {
+		let mut contract = info(10, 1, 3);
+		let mut meter = StorageMeter::default();
+		meter.record_write(None, Some(4));
+		meter.record_write(Some(0), None);
+		meter.record_write(Some(6), Some(0));
+		meter.apply_to(&mut contract);
+		assert_eq!(counters(&contract), (8, 1, 3));
+	}
+
+	#[test]
+	fn meter_matches_recomputation() {
+		// A xorshift generator keeps the sequence reproducible without extra dependencies.
+		let mut state = 0x2545_f491_4f6c_dd1du64;
+		let mut next = move |bound: u64| {
+			state ^= state << 13;
+			state ^= state >> 7;
+			state ^= state << 17;
+			state % bound
+		};
+
+		let mut stored = BTreeMap::<u8, u32>::new();
+		let mut contract = info(0, 0, 0);
+		let mut batch = StorageMeter::default();
+		let mut batched = info(0, 0, 0);
+
+		for _ in 0..1_000 {
+			let key = next(16) as u8;
+			let new_len = match next(4) {
+				0 => None,
+				1 => Some(0),
+				_ => Some(next(64) as u32),
+			};
+			let old_len = match new_len {
+				Some(len) => stored.insert(key, len),
+				None => stored.remove(&key),
+			};
+
+			let mut meter = StorageMeter::default();
+			meter.record_write(old_len, new_len);
+			meter.apply_to(&mut contract);
+			batch.record_write(old_len, new_len);
+
+			let expected = (
+				stored.values().map(|len| u64::from(*len)).sum::<u64>(),
+				stored.values().filter(|len| **len == 0).count() as u32,
+				stored.len() as u32,
+			);
+			assert_eq!(counters(&contract), expected);
+		}
+
+		batch.apply_to(&mut batched);
+		assert_eq!(counters(&batched), counters(&contract));
+	}
+}
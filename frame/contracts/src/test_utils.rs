@@ -0,0 +1,235 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate. If not, see <http://www.gnu.org/licenses/>.
+
+//! Helpers for testing contracts against a mock runtime.
+//!
+//! These are meant for crates that test their contracts together with this pallet. They
+//! work with any runtime that includes the pallet. The existential deposit is a constant
+//! of the runtime and therefore can't be set from here.
+//!
+//! `compile_wat` is only available with the `wat` feature.
+//!
+//! # Example
+//!
+//! The example assumes a mock runtime `Test` that includes this pallet as `Contracts`.
+//! See the tests of this pallet for a complete one.
+//!
+//! ```ignore
+//! use pallet_contracts::{test_utils::{self, ExtBuilder}, RawEvent};
+//!
+//! const ALICE: AccountId32 = AccountId32::new([1u8; 32]);
+//!
+//! #[test]
+//! fn flipper_flips() {
+//! 	let (code, _) = test_utils::compile_wat::<Test>(r#"
+//! 		(module
+//! 			(import "seal0" "seal_deposit_event" (func $seal_deposit_event (param i32 i32 i32 i32)))
+//! 			(import "env" "memory" (memory 1 1))
+//! 			(data (i32.const 0) "\01")
+//! 			(func (export "deploy"))
+//! 			(func (export "call")
+//! 				(call $seal_deposit_event (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 1))
+//! 			)
+//! 		)
+//! 	"#);
+//!
+//! 	ExtBuilder::<Test>::default()
+//! 		.balance(ALICE, 1_000_000)
+//! 		.build()
+//! 		.execute_with(|| {
+//! 			let (addr, result) = test_utils::instantiate_and_call::<Test>(
+//! 				ALICE, code, 100_000, vec![], vec![],
+//! 			).unwrap();
+//! 			assert!(result.exec_result.unwrap().is_success());
+//! 			assert_eq!(test_utils::contract_emitted::<Test>(&addr), vec![vec![1]]);
+//! 		});
+//! }
+//! ```
+
+use crate::{
+	BalanceOf, CodeHash, Config, ContractInfoOf, Event, GenesisConfig, Gas, Module, RawEvent,
+	Schedule, exec::{AccountIdOf, StorageKey}, storage::Storage,
+};
+use pallet_contracts_primitives::ContractExecResult;
+use sp_core::crypto::UncheckedFrom;
+use sp_std::{convert::TryInto, fmt::Debug};
+use frame_support::{dispatch::DispatchErrorWithPostInfo, traits::Currency, StorageMap};
+use frame_system::RawOrigin;
+
+/// The gas limit used by the convenience wrappers of this module.
+pub const GAS_LIMIT: Gas = 10_000_000_000;
+
+/// Builds the externalities of a test with this pallet in its genesis state.
+///
+/// The block number is set to `1` so that events are recorded.
+pub struct ExtBuilder<T: Config> {
+	balances: Vec<(AccountIdOf<T>, BalanceOf<T>)>,
+	schedule: Schedule<T>,
+}
+
+impl<T: Config> Default for ExtBuilder<T> {
+	fn default() -> Self {
+		Self {
+			balances: Vec::new(),
+			schedule: Default::default(),
+		}
+	}
+}
+
+impl<T: Config> ExtBuilder<T> {
+	/// Endow `who` with a free balance of `amount`.
+	pub fn balance(mut self, who: AccountIdOf<T>, amount: BalanceOf<T>) -> Self {
+		self.balances.push((who, amount));
+		self
+	}
+
+	/// Use `schedule` instead of the default schedule.
+	pub fn schedule(mut self, schedule: Schedule<T>) -> Self {
+		self.schedule = schedule;
+		self
+	}
+
+	pub fn build(self) -> sp_io::TestExternalities {
+		let mut t = frame_system::GenesisConfig::default().build_storage::<T>().unwrap();
+		GenesisConfig::<T> {
+			current_schedule: self.schedule,
+		}.assimilate_storage(&mut t).unwrap();
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| {
+			// Endowing at block zero keeps the resulting events out of the records.
+			for (who, amount) in self.balances {
+				T::Currency::make_free_balance_be(&who, amount);
+			}
+			<frame_system::Module<T>>::set_block_number(1u32.into());
+		});
+		ext
+	}
+}
+
+/// Compile the given wat `source` and return the wasm binary together with its code hash.
+///
+/// Panics if `source` isn't valid wat.
+#[cfg(any(test, feature = "wat"))]
+pub fn compile_wat<T: frame_system::Config>(source: &str) -> (Vec<u8>, T::Hash) {
+	use sp_runtime::traits::Hash;
+	let wasm_binary = wat::parse_str(source).expect("invalid wat passed to compile_wat");
+	let code_hash = T::Hashing::hash(&wasm_binary);
+	(wasm_binary, code_hash)
+}
+
+/// Upload `code` and instantiate it with `data` passed to its constructor.
+///
+/// Returns the address of the new contract.
+pub fn instantiate<T: Config>(
+	origin: AccountIdOf<T>,
+	code: Vec<u8>,
+	endowment: BalanceOf<T>,
+	data: Vec<u8>,
+) -> Result<AccountIdOf<T>, DispatchErrorWithPostInfo>
+where
+	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
+{
+	use sp_runtime::traits::Hash;
+	let code_hash = T::Hashing::hash(&code);
+	<Module<T>>::put_code(RawOrigin::Signed(origin.clone()).into(), code)?;
+	<Module<T>>::instantiate(
+		RawOrigin::Signed(origin.clone()).into(),
+		endowment,
+		GAS_LIMIT,
+		code_hash,
+		data,
+		Vec::new(),
+	)?;
+	Ok(<Module<T>>::contract_address(&origin, &code_hash, &[]))
+}
+
+/// Like [`instantiate`] but also calls the new contract with `call_data` through `bare_call`.
+pub fn instantiate_and_call<T: Config>(
+	origin: AccountIdOf<T>,
+	code: Vec<u8>,
+	endowment: BalanceOf<T>,
+	constructor_data: Vec<u8>,
+	call_data: Vec<u8>,
+) -> Result<(AccountIdOf<T>, ContractExecResult), DispatchErrorWithPostInfo>
+where
+	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
+{
+	let addr = instantiate::<T>(origin.clone(), code, endowment, constructor_data)?;
+	let result = <Module<T>>::bare_call(origin, addr.clone(), 0u32.into(), GAS_LIMIT, call_data);
+	Ok((addr, result))
+}
+
+/// Place a contract with the given `code_hash` at `address` without running its constructor.
+pub fn place_contract<T: Config>(address: &AccountIdOf<T>, code_hash: CodeHash<T>)
+where
+	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
+{
+	let trie_id = Storage::<T>::generate_trie_id(address).unwrap();
+	Storage::<T>::place_contract(address, trie_id, code_hash).unwrap()
+}
+
+/// Write `value` under `key` to the storage of the alive contract at `address`.
+pub fn set_storage<T: Config>(address: &AccountIdOf<T>, key: &StorageKey, value: Option<Vec<u8>>)
+where
+	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
+{
+	let contract_info = <ContractInfoOf<T>>::get(address).unwrap().get_alive().unwrap();
+	Storage::<T>::write(address, &contract_info.trie_id, key, value).unwrap();
+}
+
+/// Read the value under `key` from the storage of the alive contract at `address`.
+pub fn get_storage<T: Config>(address: &AccountIdOf<T>, key: &StorageKey) -> Option<Vec<u8>>
+where
+	T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
+{
+	let contract_info = <ContractInfoOf<T>>::get(address).unwrap().get_alive().unwrap();
+	Storage::<T>::read(&contract_info.trie_id, key)
+}
+
+/// The events deposited by this pallet in the order they were deposited.
+pub fn contract_events<T: Config>() -> Vec<Event<T>>
+where
+	<T as frame_system::Config>::Event: TryInto<Event<T>>,
+{
+	<frame_system::Module<T>>::events()
+		.into_iter()
+		.filter_map(|record| record.event.try_into().ok())
+		.collect()
+}
+
+/// The data of the events deposited by the contract at `address` through
+/// `seal_deposit_event`.
+pub fn contract_emitted<T: Config>(address: &AccountIdOf<T>) -> Vec<Vec<u8>>
+where
+	<T as frame_system::Config>::Event: TryInto<Event<T>>,
+{
+	contract_events::<T>()
+		.into_iter()
+		.filter_map(|event| match event {
+			RawEvent::ContractExecution(emitter, data) if &emitter == address => Some(data),
+			_ => None,
+		})
+		.collect()
+}
+
+/// Assert that this pallet deposited exactly the `expected` events.
+pub fn assert_contract_events<T: Config>(expected: Vec<Event<T>>)
+where
+	<T as frame_system::Config>::Event: TryInto<Event<T>>,
+	Event<T>: PartialEq + Debug,
+{
+	assert_eq!(contract_events::<T>(), expected);
+}
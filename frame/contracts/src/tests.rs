@@ -15,10 +15,11 @@
 // along with Substrate. If not, see <http://www.gnu.org/licenses/>.
 
 use crate::{
-	BalanceOf, ContractInfo, ContractInfoOf, GenesisConfig, Module,
+	BalanceOf, ContractInfo, ContractInfoOf, Module,
 	RawAliveContractInfo, RawEvent, Config, Schedule, gas::Gas,
-	Error, ConfigCache, RuntimeReturnCode, storage::Storage,
-	exec::AccountIdOf,
+	Error, ConfigCache, RuntimeReturnCode, KeyPartitioner, storage::{self, Storage, WriteDelta},
+	WriteCostModel, WriteKind, HostFnWeights, Cipher, MirrorSink, Rent,
+	exec::{AccountIdOf, StorageKey},
 };
 use assert_matches::assert_matches;
 use codec::Encode;
@@ -28,13 +29,14 @@ use sp_runtime::{
 	AccountId32,
 };
 use frame_support::{
-	assert_ok, assert_err_ignore_postinfo, impl_outer_dispatch, impl_outer_event,
+	assert_ok, assert_err, assert_err_ignore_postinfo, impl_outer_dispatch, impl_outer_event,
 	impl_outer_origin, parameter_types, StorageMap,
 	traits::{Currency, ReservableCurrency},
-	weights::{Weight, PostDispatchInfo},
+	weights::{Weight, PostDispatchInfo, DispatchClass},
 	dispatch::DispatchErrorWithPostInfo,
 };
 use frame_system::{self as system, EventRecord, Phase};
+use std::cell::RefCell;
 
 mod contracts {
 	// Re-export contents of the root. This basically
@@ -66,24 +68,17 @@ impl_outer_dispatch! {
 #[macro_use]
 pub mod test_utils {
 	use super::{Test, Balances};
-	use crate::{
-		ContractInfoOf, CodeHash,
-		storage::Storage,
-		exec::{StorageKey, AccountIdOf},
-	};
-	use frame_support::{StorageMap, traits::Currency};
+	use crate::{CodeHash, exec::{StorageKey, AccountIdOf}};
+	use frame_support::traits::Currency;
 
 	pub fn set_storage(addr: &AccountIdOf<Test>, key: &StorageKey, value: Option<Vec<u8>>) {
-		let contract_info = <ContractInfoOf::<Test>>::get(&addr).unwrap().get_alive().unwrap();
-		Storage::<Test>::write(addr, &contract_info.trie_id, key, value).unwrap();
+		crate::test_utils::set_storage::<Test>(addr, key, value)
 	}
 	pub fn get_storage(addr: &AccountIdOf<Test>, key: &StorageKey) -> Option<Vec<u8>> {
-		let contract_info = <ContractInfoOf::<Test>>::get(&addr).unwrap().get_alive().unwrap();
-		Storage::<Test>::read(&contract_info.trie_id, key)
+		crate::test_utils::get_storage::<Test>(addr, key)
 	}
 	pub fn place_contract(address: &AccountIdOf<Test>, code_hash: CodeHash<Test>) {
-		let trie_id = Storage::<Test>::generate_trie_id(address);
-		Storage::<Test>::place_contract(&address, trie_id, code_hash).unwrap()
+		crate::test_utils::place_contract::<Test>(address, code_hash)
 	}
 	pub fn set_balance(who: &AccountIdOf<Test>, amount: u64) {
 		let imbalance = Balances::deposit_creating(who, amount);
@@ -128,7 +123,7 @@ impl frame_system::Config for Test {
 	type PalletInfo = ();
 	type AccountData = pallet_balances::AccountData<u64>;
 	type OnNewAccount = ();
-	type OnKilledAccount = ();
+	type OnKilledAccount = Contracts;
 	type SystemWeightInfo = ();
 }
 impl pallet_balances::Config for Test {
@@ -156,8 +151,91 @@ parameter_types! {
 	pub const RentByteFee: u64 = 4;
 	pub const RentDepositOffset: u64 = 10_000;
 	pub const SurchargeReward: u64 = 150;
-	pub const MaxDepth: u32 = 100;
-	pub const MaxValueSize: u32 = 16_384;
+	pub static MaxDepth: u32 = 100;
+	pub static MaxValueSize: u32 = 16_384;
+	pub static MaxBatchSize: u32 = 4;
+	pub static MaxInstantiationsPerCall: u32 = 2_000;
+	pub static ContractEventsEnabled: bool = true;
+	pub static KeyPartitioning: bool = false;
+	pub static DeferredBookkeeping: bool = false;
+	pub static ValueEncryption: bool = false;
+	pub static AuditReads: bool = false;
+	pub static AuditRetention: u64 = 3;
+	pub static ChargeRentOnWrite: bool = false;
+	pub static StorageDeltaEvents: bool = false;
+	pub static StorageBatchEvents: bool = false;
+}
+
+/// Only allows writes to keys that start with the first byte of the caller once
+/// `KeyPartitioning` is set.
+pub struct TestKeyPartitioner;
+
+impl KeyPartitioner<AccountId32> for TestKeyPartitioner {
+	fn may_write(_: &AccountId32, caller: &AccountId32, key: &StorageKey) -> bool {
+		!KeyPartitioning::get() || AsRef::<[u8]>::as_ref(caller)[0] == key[0]
+	}
+}
+
+/// Makes inserts expensive and deletes cheap so that the kinds of writes can be told apart
+/// by their weight. Deletes are weighed by the length of the removed value.
+pub struct TestWriteCostModel;
+
+impl WriteCostModel<Test> for TestWriteCostModel {
+	fn write_cost(_: &HostFnWeights<Test>, kind: WriteKind, old_len: u32, new_len: u32) -> Weight {
+		match kind {
+			WriteKind::Insert => 1_000 + Weight::from(new_len),
+			WriteKind::Overwrite => 100 + Weight::from(new_len),
+			WriteKind::Delete => 1 + Weight::from(old_len),
+		}
+	}
+}
+
+/// XORs every byte and appends a marker once `ValueEncryption` is set so that the
+/// ciphertext differs from the plaintext in content and length.
+pub struct TestCipher;
+
+impl Cipher for TestCipher {
+	fn encrypt(mut plaintext: Vec<u8>) -> Vec<u8> {
+		if ValueEncryption::get() {
+			plaintext.iter_mut().for_each(|b| *b ^= 0xff);
+			plaintext.push(0xaa);
+		}
+		plaintext
+	}
+
+	fn decrypt(mut ciphertext: Vec<u8>) -> Vec<u8> {
+		if ValueEncryption::get() {
+			assert_eq!(ciphertext.pop(), Some(0xaa));
+			ciphertext.iter_mut().for_each(|b| *b ^= 0xff);
+		}
+		ciphertext
+	}
+}
+
+thread_local! {
+	static MIRRORED: RefCell<Vec<(AccountId32, [u8; 32], Option<Vec<u8>>, u32)>> =
+		RefCell::new(Vec::new());
+}
+
+/// Records every mirrored write together with the pair count of the contract at the time
+/// of the call.
+pub struct TestMirror;
+
+impl TestMirror {
+	fn take() -> Vec<(AccountId32, [u8; 32], Option<Vec<u8>>, u32)> {
+		MIRRORED.with(|m| m.borrow_mut().drain(..).collect())
+	}
+}
+
+impl MirrorSink<AccountId32> for TestMirror {
+	fn mirror(contract: &AccountId32, hashed_key: &[u8; 32], value: Option<&[u8]>) {
+		let pairs = ContractInfoOf::<Test>::get(contract)
+			.and_then(|info| info.get_alive())
+			.map_or(0, |info| info.total_pair_count);
+		MIRRORED.with(|m| m.borrow_mut().push(
+			(contract.clone(), *hashed_key, value.map(|v| v.to_vec()), pairs)
+		));
+	}
 }
 
 parameter_types! {
@@ -184,8 +262,21 @@ impl Config for Test {
 	type SurchargeReward = SurchargeReward;
 	type MaxDepth = MaxDepth;
 	type MaxValueSize = MaxValueSize;
+	type MaxBatchSize = MaxBatchSize;
+	type MaxInstantiationsPerCall = MaxInstantiationsPerCall;
+	type ContractEventsEnabled = ContractEventsEnabled;
 	type WeightPrice = Self;
 	type WeightInfo = ();
+	type KeyPartitioner = TestKeyPartitioner;
+	type WriteCostModel = TestWriteCostModel;
+	type ValueCipher = TestCipher;
+	type WriteMirror = TestMirror;
+	type DeferredBookkeeping = DeferredBookkeeping;
+	type AuditReads = AuditReads;
+	type AuditRetention = AuditRetention;
+	type ChargeRentOnWrite = ChargeRentOnWrite;
+	type StorageDeltaEvents = StorageDeltaEvents;
+	type StorageBatchEvents = StorageBatchEvents;
 }
 
 type Balances = pallet_balances::Module<Test>;
@@ -221,19 +312,12 @@ impl ExtBuilder {
 	}
 	pub fn build(self) -> sp_io::TestExternalities {
 		self.set_associated_consts();
-		let mut t = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
-		pallet_balances::GenesisConfig::<Test> {
-			balances: vec![],
-		}.assimilate_storage(&mut t).unwrap();
-		GenesisConfig {
-			current_schedule: Schedule::<Test> {
+		crate::test_utils::ExtBuilder::<Test>::default()
+			.schedule(Schedule {
 				enable_println: true,
 				..Default::default()
-			},
-		}.assimilate_storage(&mut t).unwrap();
-		let mut ext = sp_io::TestExternalities::new(t);
-		ext.execute_with(|| System::set_block_number(1));
-		ext
+			})
+			.build()
 	}
 }
 
@@ -248,13 +332,13 @@ where
 	T: frame_system::Config,
 {
 	let fixture_path = ["fixtures/", fixture_name, ".wat"].concat();
-	let wasm_binary = wat::parse_file(fixture_path)?;
-	let code_hash = T::Hashing::hash(&wasm_binary);
-	Ok((wasm_binary, code_hash))
+	let source = std::fs::read_to_string(&fixture_path)
+		.unwrap_or_else(|_| panic!("fixture {} can't be read", fixture_path));
+	Ok(crate::test_utils::compile_wat::<T>(&source))
 }
 
-// Perform a call to a plain account.
-// The actual transfer fails because we can only call contracts.
+// Perform a call with input data to a plain account.
+// The call fails because only contracts can take input data.
 // Then we check that no gas was used because the base costs for calling are either charged
 // as part of the `call` extrinsic or by `seal_call`.
 #[test]
@@ -263,10 +347,10 @@ fn calling_plain_account_fails() {
 		let _ = Balances::deposit_creating(&ALICE, 100_000_000);
 
 		assert_eq!(
-			Contracts::call(Origin::signed(ALICE), BOB, 0, GAS_LIMIT, Vec::new()),
+			Contracts::call(Origin::signed(ALICE), BOB, 100, GAS_LIMIT, vec![1]),
 			Err(
 				DispatchErrorWithPostInfo {
-					error: Error::<Test>::NotCallable.into(),
+					error: Error::<Test>::ContractNotFound.into(),
 					post_info: PostDispatchInfo {
 						actual_weight: Some(0),
 						pays_fee: Default::default(),
@@ -274,20 +358,37 @@ fn calling_plain_account_fails() {
 				}
 			)
 		);
+		assert_eq!(Balances::total_balance(&BOB), 0);
+	});
+}
+
+// Perform a call without input data to a plain account. It is a plain transfer.
+#[test]
+fn calling_plain_account_without_input_transfers() {
+	ExtBuilder::default().build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 100_000_000);
+
+		assert_eq!(
+			Contracts::call(Origin::signed(ALICE), BOB, 100, GAS_LIMIT, Vec::new()),
+			Ok(PostDispatchInfo { actual_weight: Some(0), pays_fee: Default::default() }),
+		);
+		assert_eq!(Balances::total_balance(&BOB), 100);
+		assert_eq!(Balances::free_balance(&ALICE), 100_000_000 - 100);
 	});
 }
 
 #[test]
-fn account_removal_does_not_remove_storage() {
+fn account_removal_removes_storage_of_unreferenced_contracts() {
 	use self::test_utils::{set_storage, get_storage};
 
 	ExtBuilder::default().existential_deposit(100).build().execute_with(|| {
-		let trie_id1 = Storage::<Test>::generate_trie_id(&ALICE);
-		let trie_id2 = Storage::<Test>::generate_trie_id(&BOB);
+		let trie_id1 = Storage::<Test>::generate_trie_id(&ALICE).unwrap();
+		let trie_id2 = Storage::<Test>::generate_trie_id(&BOB).unwrap();
 		let key1 = &[1; 32];
 		let key2 = &[2; 32];
 
-		// Set up two accounts with free balance above the existential threshold.
+		// Set up two accounts with free balance above the existential threshold. Only the
+		// account of BOB holds the reference that is taken for alive contracts.
 		{
 			let alice_contract_info = ContractInfo::Alive(RawAliveContractInfo {
 				trie_id: trie_id1.clone(),
@@ -298,6 +399,9 @@ fn account_removal_does_not_remove_storage() {
 				code_hash: H256::repeat_byte(1),
 				rent_allowance: 40,
 				last_write: None,
+				frozen: false,
+				call_count: 0,
+				timelocked: false,
 			});
 			let _ = Balances::deposit_creating(&ALICE, 110);
 			ContractInfoOf::<Test>::insert(ALICE, &alice_contract_info);
@@ -313,9 +417,13 @@ fn account_removal_does_not_remove_storage() {
 				code_hash: H256::repeat_byte(2),
 				rent_allowance: 40,
 				last_write: None,
+				frozen: false,
+				call_count: 0,
+				timelocked: false,
 			});
 			let _ = Balances::deposit_creating(&BOB, 110);
 			ContractInfoOf::<Test>::insert(BOB, &bob_contract_info);
+			Storage::<Test>::hold_account(&BOB);
 			set_storage(&BOB, &key1, Some(b"3".to_vec()));
 			set_storage(&BOB, &key2, Some(b"4".to_vec()));
 		}
@@ -323,23 +431,20 @@ fn account_removal_does_not_remove_storage() {
 		// Transfer funds from ALICE account of such amount that after this transfer
 		// the balance of the ALICE account will be below the existential threshold.
 		//
-		// This does not remove the contract storage as we are not notified about a
-		// account removal. This cannot happen in reality because a contract can only
-		// remove itself by `seal_terminate`. There is no external event that can remove
-		// the account appart from that.
+		// The account is reaped because it holds no reference and we are notified about
+		// its removal. Its contract can't pay for a tombstone and is removed completely.
 		assert_ok!(Balances::transfer(Origin::signed(ALICE), BOB, 20));
+		assert!(!System::account_exists(&ALICE));
+		assert!(ContractInfoOf::<Test>::get(&ALICE).is_none());
+		assert_eq!(Storage::<Test>::read(&trie_id1, key1), None);
+		assert_eq!(Storage::<Test>::read(&trie_id1, key2), None);
 
-		// Verify that no entries are removed.
-		{
-			assert_eq!(
-				get_storage(&ALICE, key1),
-				Some(b"1".to_vec())
-			);
-			assert_eq!(
-				get_storage(&ALICE, key2),
-				Some(b"2".to_vec())
-			);
+		// A transfer that would reap the account of BOB fails because of the reference.
+		assert!(Balances::transfer(Origin::signed(BOB), DJANGO, 100).is_err());
+		assert_eq!(Balances::free_balance(&BOB), 130);
 
+		// Verify that no entries of BOB are removed.
+		{
 			assert_eq!(
 				get_storage(&BOB, key1),
 				Some(b"3".to_vec())
@@ -353,909 +458,3534 @@ fn account_removal_does_not_remove_storage() {
 }
 
 #[test]
-fn instantiate_and_call_and_deposit_event() {
-	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+fn generate_trie_id_skips_used_tries() {
+	use crate::{AccountCounter, storage::TRIE_ID_GENERATION_ATTEMPTS};
+	use frame_support::{StorageValue, storage::child};
+
+	// The trie id that `generate_trie_id` derives from the given counter value.
+	let trie_id_for = |seed: u64| -> Vec<u8> {
+		let buf: Vec<u8> = AsRef::<[u8]>::as_ref(&ALICE).iter()
+			.chain(&seed.to_le_bytes())
+			.cloned()
+			.collect();
+		BlakeTwo256::hash(&buf).as_ref().to_vec()
+	};
+	let is_empty = |trie_id: &Vec<u8>| {
+		let child_info = crate::child_trie_info(trie_id);
+		sp_io::default_child_storage::next_key(child_info.storage_key(), &[]).is_none()
+	};
 
-	ExtBuilder::default()
-		.existential_deposit(100)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			let subsistence = super::ConfigCache::<Test>::subsistence_threshold_uncached();
+	ExtBuilder::default().build().execute_with(|| {
+		// Seed a collision with the next trie id.
+		let counter = AccountCounter::get();
+		let used = trie_id_for(counter + 1);
+		child::put_raw(&crate::child_trie_info(&used), &[1; 32], b"occupied");
+
+		let trie_id = Storage::<Test>::generate_trie_id(&ALICE).unwrap();
+		assert_ne!(trie_id, used);
+		assert_eq!(trie_id, trie_id_for(counter + 2));
+		assert!(is_empty(&trie_id));
+		assert_eq!(AccountCounter::get(), counter + 2);
+
+		// Generation fails if all attempts hit a used trie.
+		let counter = AccountCounter::get();
+		for seed in 1..=TRIE_ID_GENERATION_ATTEMPTS as u64 {
+			child::put_raw(&crate::child_trie_info(&trie_id_for(counter + seed)), &[1; 32], b"x");
+		}
+		assert_eq!(
+			Storage::<Test>::generate_trie_id(&ALICE),
+			Err(Error::<Test>::TrieIdCollision.into()),
+		);
+	});
+}
 
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+/// Corrupt the pair counters of a contract and remove one of its pairs afterwards.
+fn remove_with_corrupted_pair_counts() {
+	use self::test_utils::{place_contract, set_storage};
 
-			// Check at the end to get hash on error easily
-			let creation = Contracts::instantiate(
-				Origin::signed(ALICE),
-				subsistence,
-				GAS_LIMIT,
-				code_hash.into(),
-				vec![],
-				vec![],
-			);
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		set_storage(&ALICE, &[1; 32], Some(vec![]));
+		set_storage(&ALICE, &[2; 32], Some(vec![1, 2, 3]));
+
+		ContractInfoOf::<Test>::mutate(&ALICE, |info| {
+			if let Some(ContractInfo::Alive(info)) = info {
+				info.total_pair_count = 0;
+				info.empty_pair_count = 0;
+			}
+		});
+		set_storage(&ALICE, &[1; 32], None);
 
-			pretty_assertions::assert_eq!(System::events(), vec![
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::system(frame_system::RawEvent::NewAccount(ALICE.clone())),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::balances(
-						pallet_balances::RawEvent::Endowed(ALICE, 1_000_000)
-					),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::contracts(RawEvent::CodeStored(code_hash.into())),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::system(frame_system::RawEvent::NewAccount(addr.clone())),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::balances(
-						pallet_balances::RawEvent::Endowed(addr.clone(), subsistence)
-					),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::balances(
-						pallet_balances::RawEvent::Transfer(ALICE, addr.clone(), subsistence)
-					),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::contracts(
-						RawEvent::ContractExecution(addr.clone(), vec![1, 2, 3, 4])
-					),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::contracts(RawEvent::Instantiated(ALICE, addr.clone())),
-					topics: vec![],
-				}
-			]);
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		assert_eq!(info.total_pair_count, 0);
+		assert_eq!(info.empty_pair_count, 0);
+		assert_eq!(info.storage_size, 3);
+	});
+}
 
-			assert_ok!(creation);
-			assert!(ContractInfoOf::<Test>::contains_key(&addr));
-		});
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "total_pair_count of a contract underflowed")]
+fn corrupted_pair_counts_are_detected() {
+	remove_with_corrupted_pair_counts();
 }
 
 #[test]
-fn deposit_event_max_value_limit() {
-	let (wasm, code_hash) = compile_module::<Test>("event_size").unwrap();
+#[cfg(not(debug_assertions))]
+fn corrupted_pair_counts_saturate() {
+	remove_with_corrupted_pair_counts();
+}
 
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				30_000,
-				GAS_LIMIT,
-				code_hash.into(),
-				vec![],
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+#[test]
+fn child_info_accessors_match_trie_id_accessors() {
+	use self::test_utils::place_contract;
 
-			// Check creation
-			let bob_contract = ContractInfoOf::<Test>::get(addr.clone())
-				.unwrap()
-				.get_alive()
-				.unwrap();
-			assert_eq!(bob_contract.rent_allowance, <BalanceOf<Test>>::max_value());
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		let child_info = info.child_trie_info();
 
-			// Call contract with allowed storage value.
-			assert_ok!(Contracts::call(
-				Origin::signed(ALICE),
-				addr.clone(),
-				0,
-				GAS_LIMIT * 2, // we are copying a huge buffer,
-				<Test as Config>::MaxValueSize::get().encode(),
-			));
+		assert_eq!(
+			Storage::<Test>::write_child(&ALICE, &child_info, &[1; 32], Some(vec![1, 2, 3])),
+			Ok(WriteDelta { size_delta: 3, pair_delta: 1, empty_pair_delta: 0 }),
+		);
+		assert_eq!(Storage::<Test>::read(&info.trie_id, &[1; 32]), Some(vec![1, 2, 3]));
 
-			// Call contract with too large a storage value.
-			assert_err_ignore_postinfo!(
-				Contracts::call(
-					Origin::signed(ALICE),
-					addr,
-					0,
-					GAS_LIMIT,
-					(<Test as Config>::MaxValueSize::get() + 1).encode(),
-				),
-				Error::<Test>::ValueTooLarge,
-			);
-		});
+		assert_eq!(
+			Storage::<Test>::write(&ALICE, &info.trie_id, &[1; 32], None),
+			Ok(WriteDelta { size_delta: -3, pair_delta: -1, empty_pair_delta: 0 }),
+		);
+		assert_eq!(Storage::<Test>::read_child(&child_info, &[1; 32]), None);
+		assert_eq!(
+			Storage::<Test>::write_child(&BOB, &child_info, &[1; 32], None),
+			Err(storage::WriteError::ContractAbsent),
+		);
+	});
 }
 
 #[test]
-fn run_out_of_gas() {
-	let (wasm, code_hash) = compile_module::<Test>("run_out_of_gas").unwrap();
+fn key_collisions_are_detected() {
+	// A hasher that maps every key to the same location.
+	let colliding_hash = |_: &StorageKey| [7; 32];
+	let mut detector = storage::KeyCollisionDetector::default();
+
+	assert!(!detector.observe(&[1; 32], colliding_hash(&[1; 32])));
+	// Writing the same key again is no collision.
+	assert!(!detector.observe(&[1; 32], colliding_hash(&[1; 32])));
+	// A different key under the same hash is.
+	assert!(detector.observe(&[2; 32], colliding_hash(&[2; 32])));
+	// Distinct hashes never collide.
+	assert!(!detector.observe(&[3; 32], [3; 32]));
+}
 
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+#[test]
+fn allowance_runway_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_eq!(Storage::<Test>::allowance_runway(&ALICE), Err(storage::ContractAbsentError));
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+
+		// The allowance of a new contract is unlimited.
+		assert_eq!(Storage::<Test>::allowance_runway(&ALICE), Ok(None));
+
+		// 100 bytes including the offset of which the balance covers 2 bytes.
+		// This amounts to a fee of 392 per block.
+		test_utils::set_balance(&ALICE, 20_000);
+		ContractInfoOf::<Test>::mutate(&ALICE, |info| {
+			let alive = info.as_mut().unwrap().as_alive_mut().unwrap();
+			alive.storage_size = 92;
+			alive.rent_allowance = 4_399;
+		});
+		assert_eq!(Storage::<Test>::allowance_runway(&ALICE), Ok(Some(11)));
 
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		// A contract that pays no rent is never limited by its allowance.
+		test_utils::set_balance(&ALICE, 1_000_000);
+		assert_eq!(Storage::<Test>::allowance_runway(&ALICE), Ok(None));
+	});
+}
 
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				100,
-				GAS_LIMIT,
-				code_hash.into(),
-				vec![],
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+#[test]
+fn estimate_termination_weight_works() {
+	use crate::weights::WeightInfo;
 
-			// Call the contract with a fixed gas limit. It must run out of gas because it just
-			// loops forever.
-			assert_err_ignore_postinfo!(
-				Contracts::call(
-					Origin::signed(ALICE),
-					addr, // newly created account
-					0,
-					67_500_000,
-					vec![],
-				),
-				Error::<Test>::OutOfGas,
-			);
-		});
+	ExtBuilder::default().build().execute_with(|| {
+		assert_eq!(
+			Storage::<Test>::estimate_termination_weight(&ALICE),
+			Err(storage::ContractAbsentError),
+		);
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+
+		// Removing a contract without storage costs nothing on top of the termination.
+		assert_eq!(Storage::<Test>::estimate_termination_weight(&ALICE), Ok(0));
+
+		for i in 0..10u8 {
+			test_utils::set_storage(&ALICE, &[i; 32], Some(vec![i]));
+		}
+		let weight = <Test as Config>::WeightInfo::destroy_contract(10)
+			.saturating_sub(<Test as Config>::WeightInfo::destroy_contract(0));
+		assert_ne!(weight, 0);
+		assert_eq!(Storage::<Test>::estimate_termination_weight(&ALICE), Ok(weight));
+	});
 }
 
-/// Input data for each call in set_rent code
-mod call {
-	use super::{AccountIdOf, Test};
-	pub fn set_storage_4_byte() -> Vec<u8> { 0u32.to_le_bytes().to_vec() }
-	pub fn remove_storage_4_byte() -> Vec<u8> { 1u32.to_le_bytes().to_vec() }
-	pub fn transfer(to: &AccountIdOf<Test>) -> Vec<u8> {
-		2u32.to_le_bytes().iter().chain(AsRef::<[u8]>::as_ref(to)).cloned().collect()
+#[test]
+fn read_decoded_works() {
+	#[derive(Encode, codec::Decode, PartialEq, Debug)]
+	struct Entry {
+		owner: AccountId32,
+		amounts: Vec<u64>,
 	}
-	pub fn null() -> Vec<u8> { 3u32.to_le_bytes().to_vec() }
+
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let entry = Entry { owner: BOB, amounts: vec![1, 2, 3] };
+		test_utils::set_storage(&ALICE, &[1; 32], Some(entry.encode()));
+
+		assert_eq!(Storage::<Test>::read_decoded::<Entry>(&trie_id, &[1; 32]).unwrap(), Some(entry));
+		assert_eq!(Storage::<Test>::read_decoded::<Entry>(&trie_id, &[2; 32]).unwrap(), None);
+
+		// Too few bytes and leftover bytes are both rejected.
+		test_utils::set_storage(&ALICE, &[3; 32], Some(vec![1, 2, 3]));
+		assert!(Storage::<Test>::read_decoded::<Entry>(&trie_id, &[3; 32]).is_err());
+		let trailing = 42u32.encode().into_iter().chain(vec![0]).collect();
+		test_utils::set_storage(&ALICE, &[4; 32], Some(trailing));
+		assert!(Storage::<Test>::read_decoded::<u32>(&trie_id, &[4; 32]).is_err());
+	});
 }
 
-/// Test correspondence of set_rent code and its hash.
-/// Also test that encoded extrinsic in code correspond to the correct transfer
 #[test]
-fn test_set_rent_code_and_hash() {
-	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+fn values_can_be_read_in_chunks() {
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let value: Vec<u8> = (0..=250).collect();
+		test_utils::set_storage(&ALICE, &[1; 32], Some(value.clone()));
+		let chunk = |index: u32, size: u32| {
+			Storage::<Test>::read_chunk(&trie_id, &[1; 32], index, size)
+		};
+
+		// Only the last chunk is shorter than the chunk size.
+		let mut chunks = Vec::new();
+		while let Some(next) = chunk(chunks.len() as u32, 100) {
+			chunks.push(next);
+		}
+		assert_eq!(chunks.iter().map(|chunk| chunk.len()).collect::<Vec<_>>(), vec![100, 100, 51]);
+		assert_eq!(chunks.concat(), value);
+
+		assert_eq!(chunk(0, 251), Some(value));
+		assert_eq!(chunk(3, 100), None);
+		assert_eq!(chunk(u32::max_value(), u32::max_value()), None);
+		assert_eq!(chunk(0, 0), None);
+		assert_eq!(Storage::<Test>::read_chunk(&trie_id, &[2; 32], 0, 100), None);
+	});
+}
 
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+#[test]
+fn keys_are_iterated_in_ascending_order() {
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		for key in [[7; 32], [1; 32], [9; 32], [3; 32]].iter() {
+			test_utils::set_storage(&ALICE, key, Some(vec![1]));
+		}
 
-			// If you ever need to update the wasm source this test will fail
-			// and will show you the actual hash.
-			assert_eq!(System::events(), vec![
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::system(frame_system::RawEvent::NewAccount(ALICE)),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::balances(pallet_balances::RawEvent::Endowed(
-						ALICE, 1_000_000
-					)),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::contracts(RawEvent::CodeStored(code_hash.into())),
-					topics: vec![],
-				},
-			]);
-		});
+		let keys: Vec<_> = Storage::<Test>::iter_keys(&trie_id).collect();
+		let hashed_keys: Vec<_> = [[1; 32], [3; 32], [7; 32], [9; 32]].iter()
+			.map(|key| storage::meta::hashed_key(key).to_vec())
+			.collect();
+		// The hashes of the keys don't follow the order of the keys themselves.
+		assert_ne!(keys, hashed_keys);
+		let mut sorted = hashed_keys;
+		sorted.sort();
+		assert_eq!(keys, sorted);
+	});
 }
 
 #[test]
-fn storage_size() {
-	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
-
-	// Storage size
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				30_000,
-				GAS_LIMIT,
-				code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
-			let bob_contract = ContractInfoOf::<Test>::get(&addr)
-				.unwrap()
-				.get_alive()
-				.unwrap();
-			assert_eq!(
-				bob_contract.storage_size,
-				4
-			);
-			assert_eq!(
-				bob_contract.total_pair_count,
-				1,
-			);
-			assert_eq!(
-				bob_contract.empty_pair_count,
-				0,
-			);
-
-			assert_ok!(Contracts::call(
-				Origin::signed(ALICE),
-				addr.clone(),
-				0,
-				GAS_LIMIT,
-				call::set_storage_4_byte()
-			));
-			let bob_contract = ContractInfoOf::<Test>::get(&addr)
-				.unwrap()
-				.get_alive()
-				.unwrap();
-			assert_eq!(
-				bob_contract.storage_size,
-				4 + 4
-			);
-			assert_eq!(
-				bob_contract.total_pair_count,
-				2,
-			);
-			assert_eq!(
-				bob_contract.empty_pair_count,
-				0,
-			);
+fn write_typed_returns_the_decoded_old_value() {
+	use crate::storage::WriteTypedError;
 
-			assert_ok!(Contracts::call(
-				Origin::signed(ALICE),
-				addr.clone(),
-				0,
-				GAS_LIMIT,
-				call::remove_storage_4_byte()
-			));
-			let bob_contract = ContractInfoOf::<Test>::get(&addr)
-				.unwrap()
-				.get_alive()
-				.unwrap();
-			assert_eq!(
-				bob_contract.storage_size,
-				4
-			);
-			assert_eq!(
-				bob_contract.total_pair_count,
-				1,
-			);
-			assert_eq!(
-				bob_contract.empty_pair_count,
-				0,
-			);
-		});
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let write = |value: Option<u64>| {
+			Storage::<Test>::write_typed::<u64, _>(&ALICE, &trie_id, &[1; 32], value)
+		};
+
+		assert_eq!(write(Some(1)), Ok(None));
+		assert_eq!(write(Some(2)), Ok(Some(1)));
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(2u64.encode()));
+		assert_eq!(write(None), Ok(Some(2)));
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), None);
+
+		// An old value of another type is rejected and left in place.
+		test_utils::set_storage(&ALICE, &[1; 32], Some(vec![1, 2, 3]));
+		assert_eq!(write(Some(3)), Err(WriteTypedError::UndecodableOldValue));
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(vec![1, 2, 3]));
+	});
 }
 
 #[test]
-fn empty_kv_pairs() {
-	let (wasm, code_hash) = compile_module::<Test>("set_empty_storage").unwrap();
+fn delete_if_equals_only_deletes_the_expected_value() {
+	use crate::storage::WriteError;
 
-	ExtBuilder::default()
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				30_000,
-				GAS_LIMIT,
-				code_hash.into(),
-				vec![],
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
-			let bob_contract = ContractInfoOf::<Test>::get(&addr)
-				.unwrap()
-				.get_alive()
-				.unwrap();
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		let delete = |expected: Vec<u8>| {
+			Storage::<Test>::delete_if_equals(&ALICE, &trie_id, &[1; 32], expected)
+		};
+		test_utils::set_storage(&ALICE, &[1; 32], Some(vec![1, 2, 3]));
+
+		// A mismatching value is left alone.
+		assert_eq!(delete(vec![1, 2]), Ok(false));
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(vec![1, 2, 3]));
+		assert_eq!(info().total_pair_count, 1);
+
+		// The matching value is removed together with its bookkeeping.
+		assert_eq!(delete(vec![1, 2, 3]), Ok(true));
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), None);
+		assert_eq!(info().total_pair_count, 0);
+		assert_eq!(info().storage_size, 0);
+
+		// An absent key is never deleted.
+		assert_eq!(delete(vec![1, 2, 3]), Ok(false));
+		assert_eq!(delete(vec![]), Ok(false));
 
-			assert_eq!(
-				bob_contract.storage_size,
-				0,
-			);
-			assert_eq!(
-				bob_contract.total_pair_count,
-				1,
-			);
-			assert_eq!(
-				bob_contract.empty_pair_count,
-				1,
-			);
-		});
+		assert_eq!(
+			Storage::<Test>::delete_if_equals(&BOB, &trie_id, &[1; 32], vec![]),
+			Err(WriteError::ContractAbsent),
+		);
+	});
 }
 
-fn initialize_block(number: u64) {
-	System::initialize(
-		&number,
-		&[0u8; 32].into(),
-		&[0u8; 32].into(),
-		&Default::default(),
-		Default::default(),
-	);
+#[test]
+fn idempotent_writes_are_applied_once() {
+	use storage::{IdempotentWrite, IDEMPOTENCY_HISTORY_LEN};
+
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let try_write = |value: u8, idempotency_key: u8| {
+			Storage::<Test>::write_idempotent(
+				&ALICE,
+				&trie_id,
+				&[1; 32],
+				Some(vec![value]),
+				[idempotency_key; 32],
+			)
+		};
+		let write = |value: u8, idempotency_key: u8| try_write(value, idempotency_key).unwrap();
+
+		assert_matches!(write(1, 0), IdempotentWrite::Applied(_));
+		assert_eq!(write(2, 0), IdempotentWrite::Duplicate);
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(vec![1]));
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		assert_eq!((info.storage_size, info.total_pair_count), (1, 1));
+
+		// The oldest key is forgotten once the history is full.
+		for idempotency_key in 1..=IDEMPOTENCY_HISTORY_LEN as u8 {
+			assert_matches!(write(3, idempotency_key), IdempotentWrite::Applied(_));
+		}
+		assert_eq!(crate::IdempotencyKeys::<Test>::get(&ALICE).len(), IDEMPOTENCY_HISTORY_LEN);
+		assert_eq!(write(4, IDEMPOTENCY_HISTORY_LEN as u8), IdempotentWrite::Duplicate);
+		assert_matches!(write(5, 0), IdempotentWrite::Applied(_));
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(vec![5]));
+
+		// A replayed key isn't reported as a duplicate for a contract that can't be written.
+		Storage::<Test>::set_frozen(&ALICE, true).unwrap();
+		assert_eq!(try_write(6, 0), Err(storage::WriteError::ContractFrozen));
+		Storage::<Test>::set_frozen(&ALICE, false).unwrap();
+
+		Storage::<Test>::destroy_contract(&ALICE, &trie_id);
+		assert!(!crate::IdempotencyKeys::<Test>::contains_key(&ALICE));
+		assert_eq!(try_write(6, 0), Err(storage::WriteError::ContractAbsent));
+	});
 }
 
 #[test]
-fn deduct_blocks() {
-	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
-
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				30_000,
-				GAS_LIMIT, code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+fn attributed_writes_record_last_writer() {
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let write = |writer: AccountId32, value: Option<Vec<u8>>| {
+			Storage::<Test>::write_attributed(writer, &ALICE, &trie_id, &[1; 32], value).unwrap();
+		};
+
+		// Writes are not attributed unless the contract opted in.
+		write(BOB, Some(vec![1]));
+		assert_eq!(Storage::<Test>::last_writer(&trie_id, &[1; 32]), None);
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(vec![1]));
+
+		Storage::<Test>::set_write_attribution(&ALICE, true).unwrap();
+		write(BOB, Some(vec![2]));
+		assert_eq!(Storage::<Test>::last_writer(&trie_id, &[1; 32]), Some((BOB, 1)));
+
+		System::set_block_number(2);
+		write(CHARLIE, None);
+		assert_eq!(Storage::<Test>::last_writer(&trie_id, &[1; 32]), Some((CHARLIE, 2)));
+		assert_eq!(Storage::<Test>::last_writer(&trie_id, &[2; 32]), None);
+
+		Storage::<Test>::set_write_attribution(&ALICE, false).unwrap();
+		assert_eq!(Storage::<Test>::last_writer(&trie_id, &[1; 32]), None);
+		assert_eq!(
+			Storage::<Test>::set_write_attribution(&BOB, true),
+			Err(storage::ContractAbsentError),
+		);
+	});
+}
 
-			// Check creation
-			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
-			assert_eq!(bob_contract.rent_allowance, 1_000);
+#[test]
+fn values_are_encrypted_at_rest() {
+	use frame_support::storage::child;
 
-			// Advance 4 blocks
-			initialize_block(5);
+	ExtBuilder::default().build().execute_with(|| {
+		ValueEncryption::set(true);
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
 
-			// Trigger rent through call
-			assert_ok!(
-				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
-			);
+		let delta = Storage::<Test>::write(&ALICE, &trie_id, &[1; 32], Some(vec![1, 2, 3])).unwrap();
+		assert_eq!(delta.size_delta, 4);
 
-			// Check result
-			let rent = (8 + 4 - 3) // storage size = size_offset + deploy_set_storage - deposit_offset
-				* 4 // rent byte price
-				* 4; // blocks to rent
-			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
-			assert_eq!(bob_contract.rent_allowance, 1_000 - rent);
-			assert_eq!(bob_contract.deduct_block, 5);
-			assert_eq!(Balances::free_balance(&addr), 30_000 - rent);
+		// The trie holds the ciphertext while reads return the plaintext.
+		let child_info = crate::child_trie_info(&trie_id);
+		assert_eq!(
+			child::get_raw(&child_info, &storage::meta::hashed_key(&[1; 32])),
+			Some(vec![0xfe, 0xfd, 0xfc, 0xaa]),
+		);
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(vec![1, 2, 3]));
+		assert_eq!(
+			Storage::<Test>::read_page(&trie_id, None, 10).0,
+			vec![(storage::meta::hashed_key(&[1; 32]).to_vec(), vec![1, 2, 3])],
+		);
 
-			// Advance 7 blocks more
-			initialize_block(12);
+		// The storage size accounts for the ciphertext.
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		assert_eq!(info.storage_size, 4);
+		Storage::<Test>::write(&ALICE, &trie_id, &[1; 32], None).unwrap();
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		assert_eq!(info.storage_size, 0);
+	});
+}
 
-			// Trigger rent through call
-			assert_ok!(
-				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
-			);
+#[test]
+fn rent_allowance_is_refilled_from_reserve() {
+	use self::test_utils::{place_contract, set_balance};
+	use crate::storage::RefillRentError;
 
-			// Check result
-			let rent_2 = (8 + 4 - 2) // storage size = size_offset + deploy_set_storage - deposit_offset
-				* 4 // rent byte price
-				* 7; // blocks to rent
-			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
-			assert_eq!(bob_contract.rent_allowance, 1_000 - rent - rent_2);
-			assert_eq!(bob_contract.deduct_block, 12);
-			assert_eq!(Balances::free_balance(&addr), 30_000 - rent - rent_2);
+	ExtBuilder::default().build().execute_with(|| {
+		let refill = Storage::<Test>::refill_rent_allowance_from_reserve;
+		place_contract(&ALICE, H256::repeat_byte(1));
+		set_balance(&ALICE, 1_000);
+		assert_ok!(Balances::reserve(&ALICE, 500));
+		Storage::<Test>::set_rent_allowance(&ALICE, 100).unwrap();
+
+		// The reserve decreases by exactly what is added to the allowance.
+		assert_eq!(refill(&ALICE, 200), Ok(()));
+		assert_eq!(Storage::<Test>::rent_allowance(&ALICE), Ok(300));
+		assert_eq!(Balances::reserved_balance(&ALICE), 300);
+		assert_eq!(Balances::free_balance(&ALICE), 700);
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 300);
+
+		// Asking for more than the reserve changes nothing.
+		assert_eq!(refill(&ALICE, 301), Err(RefillRentError::InsufficientReserve));
+		assert_eq!(Storage::<Test>::rent_allowance(&ALICE), Ok(300));
+		assert_eq!(Balances::reserved_balance(&ALICE), 300);
+
+		// The allowance saturates but the whole amount is unreserved.
+		Storage::<Test>::set_rent_allowance(&ALICE, u64::max_value() - 1).unwrap();
+		assert_eq!(refill(&ALICE, 300), Ok(()));
+		assert_eq!(Storage::<Test>::rent_allowance(&ALICE), Ok(u64::max_value()));
+		assert_eq!(Balances::reserved_balance(&ALICE), 0);
+
+		assert_eq!(refill(&BOB, 0), Err(RefillRentError::ContractAbsent));
+	});
+}
 
-			// Second call on same block should have no effect on rent
-			assert_ok!(
-				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
-			);
+#[test]
+fn total_rent_allowance_tracks_allowances() {
+	use self::test_utils::{place_contract, set_balance};
 
-			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
-			assert_eq!(bob_contract.rent_allowance, 1_000 - rent - rent_2);
-			assert_eq!(bob_contract.deduct_block, 12);
-			assert_eq!(Balances::free_balance(&addr), 30_000 - rent - rent_2);
-		});
+	ExtBuilder::default().build().execute_with(|| {
+		// Freshly placed contracts have an unlimited allowance which isn't counted.
+		place_contract(&ALICE, H256::repeat_byte(1));
+		place_contract(&BOB, H256::repeat_byte(2));
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 0);
+
+		Storage::<Test>::set_rent_allowance(&ALICE, 100).unwrap();
+		Storage::<Test>::set_rent_allowance(&BOB, 50).unwrap();
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 150);
+
+		set_balance(&ALICE, 1_000_000);
+		Storage::<Test>::top_up_rent(&ALICE, 20).unwrap();
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 170);
+
+		// Going back to an unlimited allowance removes the contract from the sum.
+		Storage::<Test>::set_rent_allowance(&BOB, u64::max_value()).unwrap();
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 120);
+		Storage::<Test>::set_rent_allowance(&BOB, 30).unwrap();
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 150);
+
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		Storage::<Test>::destroy_contract(&ALICE, &trie_id);
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 30);
+	});
 }
 
 #[test]
-fn call_contract_removals() {
-	removals(|addr| {
-		// Call on already-removed account might fail, and this is fine.
-		let _ = Contracts::call(Origin::signed(ALICE), addr, 0, GAS_LIMIT, call::null());
-		true
+fn write_cost_model_tells_writes_apart() {
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let write = |value: Option<Vec<u8>>| {
+			Storage::<Test>::write_weighted(&ALICE, &trie_id, &[1; 32], value).unwrap().1
+		};
+
+		assert_eq!(write(Some(vec![1; 10])), 1_010);
+		assert_eq!(write(Some(vec![1; 20])), 120);
+		assert_eq!(write(None), 21);
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), None);
+
+		// The old length is the one that is stored which is only the pointer of a
+		// deduplicated value.
+		assert_ok!(Storage::<Test>::write_dedup(&ALICE, &trie_id, &[1; 32], vec![1; 100]));
+		assert_eq!(write(None), 33);
+
+		// Failed writes are not weighed.
+		Storage::<Test>::set_frozen(&ALICE, true).unwrap();
+		assert_eq!(
+			Storage::<Test>::write_weighted(&ALICE, &trie_id, &[1; 32], Some(vec![1])),
+			Err(storage::WriteError::ContractFrozen),
+		);
 	});
 }
 
 #[test]
-fn inherent_claim_surcharge_contract_removals() {
-	removals(|addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok());
+fn default_write_cost_model_is_flat() {
+	let weights = Schedule::<Test>::default().host_fn_weights;
+	let cost = |kind, old_len, new_len| {
+		<() as WriteCostModel<Test>>::write_cost(&weights, kind, old_len, new_len)
+	};
+	let set_storage = weights.set_storage + 10 * weights.set_storage_per_byte;
+
+	assert_eq!(cost(WriteKind::Insert, 0, 10), set_storage);
+	assert_eq!(cost(WriteKind::Overwrite, 20, 10), set_storage);
+	assert_eq!(cost(WriteKind::Delete, 10, 0), weights.clear_storage);
 }
 
 #[test]
-fn signed_claim_surcharge_contract_removals() {
-	removals(|addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok());
+fn write_return_root_works() {
+	use frame_support::storage::child;
+	use sp_io::hashing::blake2_256;
+
+	let (trie_id, root) = ExtBuilder::default().build().execute_with(|| {
+		assert_eq!(
+			Storage::<Test>::write_return_root(&ALICE, &vec![1], &[1; 32], None),
+			Err(storage::WriteError::ContractAbsent),
+		);
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+
+		let (prev, first_root) =
+			Storage::<Test>::write_return_root(&ALICE, &trie_id, &[1; 32], Some(vec![1])).unwrap();
+		assert_eq!(prev, None);
+		let (prev, root) =
+			Storage::<Test>::write_return_root(&ALICE, &trie_id, &[2; 32], Some(vec![2])).unwrap();
+		assert_eq!(prev, None);
+		assert_ne!(root, first_root);
+
+		// Overwriting returns the previous value and changes the root again.
+		let (prev, root) =
+			Storage::<Test>::write_return_root(&ALICE, &trie_id, &[2; 32], Some(vec![3])).unwrap();
+		assert_eq!(prev, Some(vec![2]));
+		assert_ne!(root, first_root);
+		(trie_id, root)
+	});
+
+	// Build the same child trie from scratch in a fresh state.
+	ExtBuilder::default().build().execute_with(|| {
+		let child_info = crate::child_trie_info(&trie_id);
+		child::put_raw(&child_info, &blake2_256(&[1; 32]), &[1]);
+		child::put_raw(&child_info, &blake2_256(&[2; 32]), &[3]);
+		assert_eq!(child::root(&child_info), root);
+	});
 }
 
 #[test]
-fn claim_surcharge_malus() {
-	// Test surcharge malus for inherent
-	claim_surcharge(4, |addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok(), true);
-	claim_surcharge(3, |addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok(), true);
-	claim_surcharge(2, |addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok(), true);
-	claim_surcharge(1, |addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok(), false);
+fn storage_layout_is_stable() {
+	use frame_support::storage::child;
+	use hex_literal::hex;
+	use storage::meta;
+
+	// Downstream verifiers depend on these exact bytes. Changing them is a breaking change.
+	assert_eq!(meta::KEY_LEN, 32);
+	assert_eq!(meta::HASHED_KEY_LEN, 32);
+	assert_eq!(meta::CHILD_STORAGE_KEY_PREFIX, b":child_storage:default:");
+	assert_eq!(
+		meta::hashed_key(&[0; 32]),
+		hex!("89eb0d6a8a691dae2cd15ed0369931ce0a949ecafa5c3f93f8121833646e15c3"),
+	);
+	assert_eq!(
+		meta::hashed_key(&[1; 32]),
+		hex!("f40ceaf86e5776923332b8d8fd3bef849cadb19c6996bc272af1f648d9566a4c"),
+	);
+	assert_eq!(
+		meta::child_trie_info(&[1, 2, 3]).prefixed_storage_key().into_inner(),
+		b":child_storage:default:\x01\x02\x03".to_vec(),
+	);
 
-	// Test surcharge malus for signed
-	claim_surcharge(4, |addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok(), true);
-	claim_surcharge(3, |addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok(), false);
-	claim_surcharge(2, |addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok(), false);
-	claim_surcharge(1, |addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok(), false);
+	let (child_info, hashed_key) = meta::contract_child_storage_key(&[1, 2, 3], &[1; 32]);
+	assert_eq!(child_info, meta::child_trie_info(&[1, 2, 3]));
+	assert_eq!(hashed_key, meta::hashed_key(&[1; 32]));
+
+	// The pallet itself stores values where `meta` says they are.
+	ExtBuilder::default().build().execute_with(|| {
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		Storage::<Test>::write(&ALICE, &trie_id, &[1; 32], Some(vec![42])).unwrap();
+
+		let (child_info, hashed_key) = meta::contract_child_storage_key(&trie_id, &[1; 32]);
+		assert_eq!(child::get_raw(&child_info, &hashed_key), Some(vec![42]));
+	});
 }
 
-/// Claim surcharge with the given trigger_call at the given blocks.
-/// If `removes` is true then assert that the contract is a tombstone.
-fn claim_surcharge(blocks: u64, trigger_call: impl Fn(AccountIdOf<Test>) -> bool, removes: bool) {
-	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+#[test]
+fn frozen_contracts_reject_writes() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_eq!(
+			Storage::<Test>::set_frozen(&ALICE, true),
+			Err(storage::ContractAbsentError),
+		);
+		test_utils::place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		assert!(!Storage::<Test>::is_frozen(&ALICE));
+		Storage::<Test>::write(&ALICE, &trie_id, &[1; 32], Some(vec![1])).unwrap();
+
+		System::reset_events();
+		assert_ok!(Contracts::set_frozen(Origin::root(), ALICE, true));
+		assert!(Storage::<Test>::is_frozen(&ALICE));
+		assert_eq!(
+			System::events().pop().unwrap().event,
+			MetaEvent::contracts(RawEvent::FrozenStateChanged(ALICE, true)),
+		);
 
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				100,
-				GAS_LIMIT, code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		// Every kind of write fails while reads keep working.
+		assert_eq!(
+			Storage::<Test>::write(&ALICE, &trie_id, &[1; 32], Some(vec![2])),
+			Err(storage::WriteError::ContractFrozen),
+		);
+		assert_eq!(
+			Storage::<Test>::write(&ALICE, &trie_id, &[1; 32], None),
+			Err(storage::WriteError::ContractFrozen),
+		);
+		assert_eq!(
+			Storage::<Test>::increment_u64(&ALICE, &trie_id, &[2; 32], 1),
+			Err(storage::WriteError::ContractFrozen),
+		);
+		assert_eq!(
+			Storage::<Test>::patch(&ALICE, &trie_id, &[1; 32], 0, &[3]),
+			Err(storage::PatchError::Write(storage::WriteError::ContractFrozen)),
+		);
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(vec![1]));
+		assert_eq!(Storage::<Test>::read(&trie_id, &[2; 32]), None);
+
+		// Unfreezing allows writes again.
+		assert_ok!(Contracts::set_frozen(Origin::root(), ALICE, false));
+		assert!(!Storage::<Test>::is_frozen(&ALICE));
+		Storage::<Test>::write(&ALICE, &trie_id, &[1; 32], Some(vec![2])).unwrap();
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), Some(vec![2]));
+
+		// Only root is allowed to freeze contracts.
+		assert_err!(
+			Contracts::set_frozen(Origin::signed(BOB), ALICE, true),
+			sp_runtime::DispatchError::BadOrigin,
+		);
+	});
+}
 
-			// Advance blocks
-			initialize_block(blocks);
+#[test]
+fn read_with_fallback_works() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
 
-			// Trigger rent through call
-			assert!(trigger_call(addr.clone()));
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		place_contract(&BOB, H256::repeat_byte(1));
+		let trie_id = |addr: &AccountId32| {
+			ContractInfoOf::<Test>::get(addr).unwrap().get_alive().unwrap().trie_id
+		};
+		let (instance, template) = (trie_id(&ALICE), trie_id(&BOB));
+		let read = |key: &StorageKey| Storage::<Test>::read_with_fallback(&instance, &template, key);
+
+		// A key that is only set on the template is visible through the fallback.
+		set_storage(&BOB, &[1; 32], Some(vec![1]));
+		assert_eq!(read(&[1; 32]), Some(vec![1]));
+
+		// A key that is set on the instance shadows the template.
+		set_storage(&BOB, &[2; 32], Some(vec![2]));
+		set_storage(&ALICE, &[2; 32], Some(vec![3]));
+		assert_eq!(read(&[2; 32]), Some(vec![3]));
+		assert_eq!(get_storage(&BOB, &[2; 32]), Some(vec![2]));
+
+		// Keys that are set on neither are absent.
+		assert_eq!(read(&[3; 32]), None);
+	});
+}
 
-			if removes {
-				assert!(ContractInfoOf::<Test>::get(&addr).unwrap().get_tombstone().is_some());
-			} else {
-				assert!(ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().is_some());
-			}
+#[test]
+fn storage_size_is_accounted_beyond_u32() {
+	use self::test_utils::{place_contract, set_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		ContractInfoOf::<Test>::mutate(&ALICE, |info| {
+			info.as_mut().unwrap().as_alive_mut().unwrap().storage_size = u32::max_value().into();
 		});
-}
 
-/// Test for all kind of removals for the given trigger:
-/// * if balance is reached and balance > subsistence threshold
-/// * if allowance is exceeded
-/// * if balance is reached and balance < subsistence threshold
-///	    * this case cannot be triggered by a contract: we check whether a tombstone is left
-fn removals(trigger_call: impl Fn(AccountIdOf<Test>) -> bool) {
-	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+		set_storage(&ALICE, &[1; 32], Some(vec![1, 2, 3, 4]));
+		assert_eq!(info().storage_size, u64::from(u32::max_value()) + 4);
 
-	// Balance reached and superior to subsistence threshold
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				100,
-				GAS_LIMIT, code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		set_storage(&ALICE, &[1; 32], None);
+		assert_eq!(info().storage_size, u64::from(u32::max_value()));
+	});
+}
 
-			let subsistence_threshold = 50 /*existential_deposit*/ + 16 /*tombstone_deposit*/;
+#[test]
+fn storage_size_is_migrated_to_u64() {
+	use frame_support::StorageValue;
 
-			// Trigger rent must have no effect
-			assert!(trigger_call(addr.clone()));
-			assert_eq!(ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().rent_allowance, 1_000);
-			assert_eq!(Balances::free_balance(&addr), 100);
+	ExtBuilder::default().build().execute_with(|| {
+		let code_hash = H256::repeat_byte(1);
+		let old_info = (
+			0u8, // `ContractInfo::Alive`
+			vec![1u8, 2, 3], // trie_id
+			12u32, // storage_size
+			1u32, // empty_pair_count
+			2u32, // total_pair_count
+			code_hash,
+			100u64, // rent_allowance
+			5u64, // deduct_block
+			Some(6u64), // last_write
+		);
+		frame_support::storage::unhashed::put(
+			&ContractInfoOf::<Test>::hashed_key_for(&ALICE),
+			&old_info,
+		);
+		crate::StorageVersion::kill();
 
-			// Advance blocks
-			initialize_block(10);
+		crate::migration::migrate::<Test>();
 
-			// Trigger rent through call
-			assert!(trigger_call(addr.clone()));
-			assert!(ContractInfoOf::<Test>::get(&addr).unwrap().get_tombstone().is_some());
-			assert_eq!(Balances::free_balance(&addr), subsistence_threshold);
+		assert_eq!(crate::StorageVersion::get(), Some(crate::Releases::V9));
+		assert_eq!(
+			ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap(),
+			RawAliveContractInfo {
+				trie_id: vec![1, 2, 3],
+				storage_size: 12,
+				empty_pair_count: 1,
+				total_pair_count: 2,
+				code_hash,
+				rent_allowance: 100,
+				deduct_block: 5,
+				last_write: Some(6),
+				frozen: false,
+				call_count: 0,
+				timelocked: false,
+			},
+		);
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 100);
 
-			// Advance blocks
-			initialize_block(20);
+		// Running the migration again doesn't touch the storage.
+		crate::migration::migrate::<Test>();
+		assert_eq!(
+			ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().storage_size,
+			12,
+		);
+	});
+}
 
-			// Trigger rent must have no effect
-			assert!(trigger_call(addr.clone()));
-			assert!(ContractInfoOf::<Test>::get(&addr).unwrap().get_tombstone().is_some());
-			assert_eq!(Balances::free_balance(&addr), subsistence_threshold);
-		});
+#[test]
+fn tombstones_are_migrated_to_record_code_hash() {
+	use frame_support::StorageValue;
 
-	// Allowance exceeded
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				1_000,
-				GAS_LIMIT,
-				code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(100u32).encode(), // rent allowance
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+	ExtBuilder::default().build().execute_with(|| {
+		let hash = H256::repeat_byte(1);
+		frame_support::storage::unhashed::put(
+			&ContractInfoOf::<Test>::hashed_key_for(&ALICE),
+			&(1u8 /* `ContractInfo::Tombstone` */, hash),
+		);
+		crate::StorageVersion::put(crate::Releases::V1);
 
-			// Trigger rent must have no effect
-			assert!(trigger_call(addr.clone()));
-			assert_eq!(
-				ContractInfoOf::<Test>::get(&addr)
-					.unwrap()
-					.get_alive()
-					.unwrap()
-					.rent_allowance,
-				100
-			);
-			assert_eq!(Balances::free_balance(&addr), 1_000);
+		crate::migration::migrate::<Test>();
 
-			// Advance blocks
-			initialize_block(10);
+		assert_eq!(crate::StorageVersion::get(), Some(crate::Releases::V9));
+		let tombstone = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_tombstone().unwrap();
+		assert_eq!(tombstone.hash, hash);
+		assert_eq!(tombstone.code_hash, None);
+		assert_eq!(tombstone.storage_size, None);
+		assert_eq!(tombstone.evicted_at, None);
+		assert_eq!(
+			Contracts::tombstone_info(ALICE),
+			Some(pallet_contracts_primitives::TombstoneInfo { code_hash: None, storage_size: None }),
+		);
+	});
+}
 
-			// Trigger rent through call
-			assert!(trigger_call(addr.clone()));
-			assert!(ContractInfoOf::<Test>::get(&addr)
-				.unwrap()
-				.get_tombstone()
-				.is_some());
-			// Balance should be initial balance - initial rent_allowance
-			assert_eq!(Balances::free_balance(&addr), 900);
+#[test]
+fn tombstones_are_migrated_to_record_eviction_block() {
+	use frame_support::StorageValue;
 
-			// Advance blocks
-			initialize_block(20);
+	ExtBuilder::default().build().execute_with(|| {
+		let (hash, code_hash) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		frame_support::storage::unhashed::put(
+			&ContractInfoOf::<Test>::hashed_key_for(&ALICE),
+			&(1u8 /* `ContractInfo::Tombstone` */, hash, Some(code_hash), Some(7u64)),
+		);
+		crate::StorageVersion::put(crate::Releases::V5);
 
-			// Trigger rent must have no effect
-			assert!(trigger_call(addr.clone()));
-			assert!(ContractInfoOf::<Test>::get(&addr)
-				.unwrap()
-				.get_tombstone()
-				.is_some());
-			assert_eq!(Balances::free_balance(&addr), 900);
-		});
+		crate::migration::migrate::<Test>();
 
-	// Balance reached and inferior to subsistence threshold
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			let subsistence_threshold =
-				Balances::minimum_balance() + <Test as Config>::TombstoneDeposit::get();
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				50 + subsistence_threshold,
-				GAS_LIMIT,
-				code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		assert_eq!(crate::StorageVersion::get(), Some(crate::Releases::V9));
+		let tombstone = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_tombstone().unwrap();
+		assert_eq!(tombstone.hash, hash);
+		assert_eq!(tombstone.code_hash, Some(code_hash));
+		assert_eq!(tombstone.storage_size, Some(7));
+		assert_eq!(tombstone.evicted_at, None);
+	});
+}
 
-			// Trigger rent must have no effect
-			assert!(trigger_call(addr.clone()));
-			assert_eq!(
-				ContractInfoOf::<Test>::get(&addr)
-					.unwrap()
-					.get_alive()
-					.unwrap()
-					.rent_allowance,
-				1_000
-			);
-			assert_eq!(
-				Balances::free_balance(&addr),
-				50 + subsistence_threshold,
-			);
+#[test]
+fn tombstone_index_is_built_by_migration() {
+	use frame_support::StorageValue;
 
-			// Transfer funds
-			assert_ok!(Contracts::call(
-				Origin::signed(ALICE),
-				addr.clone(),
-				0,
-				GAS_LIMIT,
-				call::transfer(&BOB),
-			));
-			assert_eq!(
-				ContractInfoOf::<Test>::get(&addr)
-					.unwrap()
-					.get_alive()
-					.unwrap()
-					.rent_allowance,
-				1_000
+	ExtBuilder::default().build().execute_with(|| {
+		let tombstone = (
+			1u8, // `ContractInfo::Tombstone`
+			H256::repeat_byte(1), // hash
+			None::<H256>, // code_hash
+			None::<u64>, // storage_size
+			Some(3u64), // evicted_at
+		);
+		for who in [ALICE, BOB].iter() {
+			frame_support::storage::unhashed::put(
+				&ContractInfoOf::<Test>::hashed_key_for(who),
+				&tombstone,
 			);
-			assert_eq!(Balances::free_balance(&addr), subsistence_threshold);
+		}
+		test_utils::place_contract(&CHARLIE, H256::repeat_byte(2));
+		crate::StorageVersion::put(crate::Releases::V7);
+		assert!(Contracts::check_tombstone_index().is_err());
+
+		crate::migration::migrate::<Test>();
+
+		assert_eq!(crate::StorageVersion::get(), Some(crate::Releases::V9));
+		assert_ok!(Contracts::check_tombstone_index());
+
+		// The tombstones are listed one page after the other and the alive contract is left out.
+		let first = Contracts::list_tombstones(None, 1);
+		assert_eq!(first.len(), 1);
+		let second = Contracts::list_tombstones(Some(first[0].address.clone()), 1);
+		assert_eq!(second.len(), 1);
+		assert_eq!(Contracts::list_tombstones(Some(second[0].address.clone()), 1), vec![]);
+		let mut listed = vec![first[0].address.clone(), second[0].address.clone()];
+		listed.sort();
+		assert_eq!(listed, vec![ALICE, BOB]);
+		assert!(first.iter().chain(&second).all(|tombstone| tombstone.evicted_at == Some(3)));
+	});
+}
 
-			// Advance blocks
-			initialize_block(10);
+#[test]
+fn alive_contracts_are_migrated_to_count_calls() {
+	use frame_support::StorageValue;
 
-			// Trigger rent through call
-			assert!(trigger_call(addr.clone()));
-			assert_matches!(ContractInfoOf::<Test>::get(&addr), Some(ContractInfo::Tombstone(_)));
-			assert_eq!(Balances::free_balance(&addr), subsistence_threshold);
+	ExtBuilder::default().build().execute_with(|| {
+		let code_hash = H256::repeat_byte(1);
+		let old_info = (
+			0u8, // `ContractInfo::Alive`
+			vec![1u8, 2, 3], // trie_id
+			12u64, // storage_size
+			1u32, // empty_pair_count
+			2u32, // total_pair_count
+			code_hash,
+			100u64, // rent_allowance
+			5u64, // deduct_block
+			Some(6u64), // last_write
+			true, // frozen
+		);
+		frame_support::storage::unhashed::put(
+			&ContractInfoOf::<Test>::hashed_key_for(&ALICE),
+			&old_info,
+		);
+		crate::StorageVersion::put(crate::Releases::V6);
 
-			// Advance blocks
-			initialize_block(20);
+		crate::migration::migrate::<Test>();
 
-			// Trigger rent must have no effect
-			assert!(trigger_call(addr.clone()));
-			assert_matches!(ContractInfoOf::<Test>::get(&addr), Some(ContractInfo::Tombstone(_)));
-			assert_eq!(Balances::free_balance(&addr), subsistence_threshold);
-		});
+		assert_eq!(crate::StorageVersion::get(), Some(crate::Releases::V9));
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		assert!(info.frozen);
+		assert_eq!(info.last_write, Some(6));
+		assert_eq!(info.call_count, 0);
+		// The allowance was already accounted by an earlier migration.
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 0);
+	});
 }
 
 #[test]
-fn call_removed_contract() {
-	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+fn alive_contracts_are_migrated_to_record_timelocks() {
+	use frame_support::StorageValue;
 
-	// Balance reached and superior to subsistence threshold
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				100,
-				GAS_LIMIT, code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
-
-			// Calling contract should succeed.
-			assert_ok!(
-				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
-			);
+	ExtBuilder::default().build().execute_with(|| {
+		let code_hash = H256::repeat_byte(1);
+		let old_info = (
+			0u8, // `ContractInfo::Alive`
+			vec![1u8, 2, 3], // trie_id
+			12u64, // storage_size
+			1u32, // empty_pair_count
+			2u32, // total_pair_count
+			code_hash,
+			100u64, // rent_allowance
+			5u64, // deduct_block
+			Some(6u64), // last_write
+			false, // frozen
+			7u64, // call_count
+		);
+		frame_support::storage::unhashed::put(
+			&ContractInfoOf::<Test>::hashed_key_for(&ALICE),
+			&old_info,
+		);
+		crate::StorageVersion::put(crate::Releases::V8);
 
-			// Advance blocks
-			initialize_block(10);
+		crate::migration::migrate::<Test>();
 
-			// Calling contract should remove contract and fail.
-			assert_err_ignore_postinfo!(
-				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null()),
-				Error::<Test>::NotCallable
-			);
-			// Calling a contract that is about to evict shall emit an event.
-			assert_eq!(System::events(), vec![
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::contracts(RawEvent::Evicted(addr.clone(), true)),
-					topics: vec![],
-				},
-			]);
+		assert_eq!(crate::StorageVersion::get(), Some(crate::Releases::V9));
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		assert_eq!(info.call_count, 7);
+		assert!(!info.timelocked);
+		// Neither the allowance nor the tombstone index are built again.
+		assert_eq!(Storage::<Test>::total_rent_allowance(), 0);
+	});
+}
 
-			// Subsequent contract calls should also fail.
-			assert_err_ignore_postinfo!(
-				Contracts::call(Origin::signed(ALICE), addr, 0, GAS_LIMIT, call::null()),
-				Error::<Test>::NotCallable
-			);
-		})
+/// The pallet must build for a runtime blob, i.e. without `std` and without the benchmarks.
+///
+/// Ignored by default because it builds the dependency tree again for the wasm target which
+/// needs to be installed. Run it with `cargo test -p pallet-contracts -- --ignored`.
+#[test]
+#[ignore]
+fn builds_without_std() {
+	let status = std::process::Command::new(env!("CARGO"))
+		.args(&["check", "--no-default-features", "--target", "wasm32-unknown-unknown"])
+		.arg("--manifest-path")
+		.arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+		// A separate target directory keeps this from waiting on the lock of the test build.
+		.arg("--target-dir")
+		.arg(std::env::temp_dir().join("pallet-contracts-no-std"))
+		.status()
+		.expect("cargo can be spawned");
+	assert!(status.success());
 }
 
 #[test]
-fn default_rent_allowance_on_instantiate() {
-	let (wasm, code_hash) = compile_module::<Test>("check_default_rent_allowance").unwrap();
+fn integrity_test_accepts_test_config() {
+	use frame_support::traits::IntegrityTest;
 
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				30_000,
-				GAS_LIMIT,
-				code_hash.into(),
-				vec![],
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+	Contracts::integrity_test();
+}
 
-			// Check creation
-			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
-			assert_eq!(bob_contract.rent_allowance, <BalanceOf<Test>>::max_value());
+#[test]
+#[should_panic(expected = "`MaxDepth` must be at least 1")]
+fn integrity_test_rejects_zero_depth() {
+	use frame_support::traits::IntegrityTest;
 
-			// Advance blocks
-			initialize_block(5);
+	MaxDepth::set(0);
+	Contracts::integrity_test();
+}
 
-			// Trigger rent through call
-			assert_ok!(
-				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
-			);
+#[test]
+#[should_panic(expected = "`MaxBatchSize` must be at least 1")]
+fn integrity_test_rejects_zero_batch_size() {
+	use frame_support::traits::IntegrityTest;
 
-			// Check contract is still alive
-			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive();
-			assert!(bob_contract.is_some())
-		});
+	MaxBatchSize::set(0);
+	Contracts::integrity_test();
 }
 
 #[test]
-fn restorations_dirty_storage_and_different_storage() {
-	restoration(true, true);
+#[should_panic(expected = "`MaxValueSize` must be smaller than the memory")]
+fn integrity_test_rejects_values_exceeding_memory() {
+	use frame_support::traits::IntegrityTest;
+
+	MaxValueSize::set(crate::Limits::default().memory_pages * 64 * 1024);
+	Contracts::integrity_test();
 }
 
 #[test]
-fn restorations_dirty_storage() {
-	restoration(false, true);
+fn increment_u64_works() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let counter = &[1; 32];
+		let saturated = &[2; 32];
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+
+		// An absent counter starts at zero and is accounted as a new pair.
+		assert_eq!(Storage::<Test>::increment_u64(&ALICE, &trie_id, counter, 5), Ok(5));
+		assert_eq!(get_storage(&ALICE, counter), Some(5u64.to_le_bytes().to_vec()));
+		assert_eq!(info().total_pair_count, 1);
+		assert_eq!(info().storage_size, 8);
+
+		// An existing counter is incremented in place.
+		assert_eq!(Storage::<Test>::increment_u64(&ALICE, &trie_id, counter, 7), Ok(12));
+		assert_eq!(get_storage(&ALICE, counter), Some(12u64.to_le_bytes().to_vec()));
+		assert_eq!(info().total_pair_count, 1);
+		assert_eq!(info().storage_size, 8);
+
+		// An empty value is treated as zero and is no longer counted as empty afterwards.
+		set_storage(&ALICE, saturated, Some(vec![]));
+		assert_eq!(info().empty_pair_count, 1);
+		assert_eq!(
+			Storage::<Test>::increment_u64(&ALICE, &trie_id, saturated, u64::max_value() - 1),
+			Ok(u64::max_value() - 1),
+		);
+		assert_eq!(info().empty_pair_count, 0);
+		assert_eq!(info().total_pair_count, 2);
+
+		// The addition saturates.
+		assert_eq!(
+			Storage::<Test>::increment_u64(&ALICE, &trie_id, saturated, 10),
+			Ok(u64::max_value()),
+		);
+		assert_eq!(get_storage(&ALICE, saturated), Some(u64::max_value().to_le_bytes().to_vec()));
+
+		// A non existing contract can't be incremented.
+		assert_eq!(
+			Storage::<Test>::increment_u64(&BOB, &trie_id, counter, 1),
+			Err(storage::WriteError::ContractAbsent),
+		);
+	});
 }
 
 #[test]
-fn restoration_different_storage() {
-	restoration(true, false);
+fn read_across_reads_from_the_right_tries() {
+	use self::test_utils::{place_contract, set_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		let trie_id = |addr: &AccountIdOf<Test>| {
+			ContractInfoOf::<Test>::get(addr).unwrap().get_alive().unwrap().trie_id
+		};
+		let name = [1; 32];
+		for (addr, value) in [(&ALICE, b"alice"), (&BOB, b"bobby"), (&CHARLIE, b"chuck")].iter() {
+			place_contract(addr, H256::repeat_byte(1));
+			set_storage(addr, &name, Some(value.to_vec()));
+		}
+		set_storage(&BOB, &[2; 32], Some(vec![2]));
+
+		let entries = vec![
+			(trie_id(&CHARLIE), name),
+			(trie_id(&ALICE), name),
+			(trie_id(&ALICE), [2; 32]),
+			(trie_id(&BOB), [2; 32]),
+			(trie_id(&BOB), name),
+		];
+		assert_eq!(
+			Storage::<Test>::read_across(&entries),
+			vec![
+				Some(b"chuck".to_vec()),
+				Some(b"alice".to_vec()),
+				None,
+				Some(vec![2]),
+				Some(b"bobby".to_vec()),
+			],
+		);
+		assert_eq!(Storage::<Test>::read_across(&[]), Vec::<Option<Vec<u8>>>::new());
+	});
 }
 
 #[test]
-fn restoration_success() {
-	restoration(false, false);
+fn write_versioned_rejects_downgrades() {
+	use self::test_utils::{place_contract, get_storage};
+	use crate::storage::{VersionedWriteError, WriteError};
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let key = &[1; 32];
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		let write = |value: Option<Vec<u8>>| {
+			Storage::<Test>::write_versioned(&ALICE, &trie_id, key, value).map(|_| ())
+		};
+
+		// Upgrades are allowed.
+		assert_eq!(write(Some(vec![1, 10])), Ok(()));
+		assert_eq!(write(Some(vec![2, 20, 20])), Ok(()));
+		assert_eq!(get_storage(&ALICE, key), Some(vec![2, 20, 20]));
+
+		// The same version is allowed.
+		assert_eq!(write(Some(vec![2, 21])), Ok(()));
+		assert_eq!(get_storage(&ALICE, key), Some(vec![2, 21]));
+		let written = info();
+
+		// Downgrades are rejected without touching the state.
+		for value in [vec![1, 30], vec![0], vec![]].iter() {
+			assert_eq!(write(Some(value.clone())), Err(VersionedWriteError::VersionDowngrade));
+		}
+		assert_eq!(get_storage(&ALICE, key), Some(vec![2, 21]));
+		assert_eq!(info(), written);
+
+		// Removing the value is always allowed and resets the version.
+		assert_eq!(write(None), Ok(()));
+		assert_eq!(get_storage(&ALICE, key), None);
+		assert_eq!(write(Some(vec![0])), Ok(()));
+
+		assert_eq!(
+			Storage::<Test>::write_versioned(&BOB, &trie_id, key, Some(vec![3])).map(|_| ()),
+			Err(VersionedWriteError::Write(WriteError::ContractAbsent)),
+		);
+	});
 }
 
-fn restoration(test_different_storage: bool, test_restore_to_with_dirty_storage: bool) {
-	let (set_rent_wasm, set_rent_code_hash) = compile_module::<Test>("set_rent").unwrap();
-	let (restoration_wasm, restoration_code_hash) = compile_module::<Test>("restoration").unwrap();
+#[test]
+fn write_monotonic_u64_works() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+	use crate::storage::{MonotonicWriteError, WriteError};
 
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), restoration_wasm));
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), set_rent_wasm));
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let nonce = &[1; 32];
+		let empty = &[2; 32];
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
 
-			// If you ever need to update the wasm source this test will fail
-			// and will show you the actual hash.
-			assert_eq!(System::events(), vec![
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::system(frame_system::RawEvent::NewAccount(ALICE)),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::balances(pallet_balances::RawEvent::Endowed(ALICE, 1_000_000)),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::contracts(RawEvent::CodeStored(restoration_code_hash.into())),
-					topics: vec![],
-				},
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::contracts(RawEvent::CodeStored(set_rent_code_hash.into())),
-					topics: vec![],
-				},
-			]);
+		// An absent value is treated as zero.
+		assert_eq!(
+			Storage::<Test>::write_monotonic_u64(&ALICE, &trie_id, nonce, 0),
+			Err(MonotonicWriteError::NonMonotonic),
+		);
+		assert_eq!(get_storage(&ALICE, nonce), None);
+		assert_eq!(Storage::<Test>::write_monotonic_u64(&ALICE, &trie_id, nonce, 3), Ok(()));
+		assert_eq!(get_storage(&ALICE, nonce), Some(3u64.to_le_bytes().to_vec()));
+		assert_eq!(Storage::<Test>::write_monotonic_u64(&ALICE, &trie_id, nonce, 10), Ok(()));
+		assert_eq!(get_storage(&ALICE, nonce), Some(10u64.to_le_bytes().to_vec()));
+		let written = info();
+
+		// Equal and lower values are rejected without touching the state.
+		for value in [10, 9, 0].iter() {
+			assert_eq!(
+				Storage::<Test>::write_monotonic_u64(&ALICE, &trie_id, nonce, *value),
+				Err(MonotonicWriteError::NonMonotonic),
+			);
+		}
+		assert_eq!(get_storage(&ALICE, nonce), Some(10u64.to_le_bytes().to_vec()));
+		assert_eq!(info(), written);
 
-			// Create an account with address `BOB` with code `CODE_SET_RENT`.
-			// The input parameter sets the rent allowance to 0.
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				30_000,
-				GAS_LIMIT,
-				set_rent_code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
-				vec![],
-			));
-			let addr_bob = Contracts::contract_address(&ALICE, &set_rent_code_hash, &[]);
+		// An empty value is treated as zero as well.
+		set_storage(&ALICE, empty, Some(vec![]));
+		assert_eq!(Storage::<Test>::write_monotonic_u64(&ALICE, &trie_id, empty, 1), Ok(()));
+		assert_eq!(get_storage(&ALICE, empty), Some(1u64.to_le_bytes().to_vec()));
 
-			// Check if `BOB` was created successfully and that the rent allowance is
-			// set to 0.
-			let bob_contract = ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_alive().unwrap();
-			assert_eq!(bob_contract.rent_allowance, 0);
+		assert_eq!(
+			Storage::<Test>::write_monotonic_u64(&BOB, &trie_id, nonce, 11),
+			Err(MonotonicWriteError::Write(WriteError::ContractAbsent)),
+		);
+	});
+}
 
-			if test_different_storage {
-				assert_ok!(Contracts::call(
-					Origin::signed(ALICE),
-					addr_bob.clone(), 0, GAS_LIMIT,
-					call::set_storage_4_byte())
-				);
-			}
+#[test]
+fn next_sequence_hands_out_increasing_ids() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+	use crate::storage::{SequenceError, WriteError};
 
-			// Advance 4 blocks, to the 5th.
-			initialize_block(5);
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let seq = &[1; 32];
+		let other = &[2; 32];
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+
+		// An absent sequence starts at one and every id is greater than the previous one.
+		let ids: Vec<u64> = (0..10)
+			.map(|_| Storage::<Test>::next_sequence(&ALICE, &trie_id, seq).unwrap())
+			.collect();
+		assert_eq!(ids, (1..=10).collect::<Vec<u64>>());
+		assert_eq!(get_storage(&ALICE, seq), Some(10u64.to_le_bytes().to_vec()));
+		assert_eq!(info().total_pair_count, 1);
+		assert_eq!(info().storage_size, 8);
+
+		// Sequences under different keys are independent.
+		assert_eq!(Storage::<Test>::next_sequence(&ALICE, &trie_id, other), Ok(1));
+		assert_eq!(Storage::<Test>::next_sequence(&ALICE, &trie_id, seq), Ok(11));
+
+		// An exhausted sequence doesn't hand out the last id again.
+		set_storage(&ALICE, seq, Some(u64::max_value().to_le_bytes().to_vec()));
+		let exhausted = info();
+		assert_eq!(
+			Storage::<Test>::next_sequence(&ALICE, &trie_id, seq),
+			Err(SequenceError::Exhausted),
+		);
+		assert_eq!(info(), exhausted);
 
-			// Call `BOB`, which makes it pay rent. Since the rent allowance is set to 0
-			// we expect that it will get removed leaving tombstone.
-			assert_err_ignore_postinfo!(
-				Contracts::call(
-					Origin::signed(ALICE), addr_bob.clone(), 0, GAS_LIMIT, call::null()
-				),
-				Error::<Test>::NotCallable
-			);
-			assert!(ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_tombstone().is_some());
-			assert_eq!(System::events(), vec![
-				EventRecord {
-					phase: Phase::Initialization,
-					event: MetaEvent::contracts(
-						RawEvent::Evicted(addr_bob.clone(), true)
-					),
-					topics: vec![],
-				},
-			]);
+		Storage::<Test>::set_frozen(&ALICE, true).unwrap();
+		assert_eq!(
+			Storage::<Test>::next_sequence(&ALICE, &trie_id, other),
+			Err(SequenceError::Write(WriteError::ContractFrozen)),
+		);
+		assert_eq!(
+			Storage::<Test>::next_sequence(&BOB, &trie_id, other),
+			Err(SequenceError::Write(WriteError::ContractAbsent)),
+		);
+	});
+}
 
-			// Create another account with the address `DJANGO` with `CODE_RESTORATION`.
-			//
-			// Note that we can't use `ALICE` for creating `DJANGO` so we create yet another
-			// account `CHARLIE` and create `DJANGO` with it.
-			let _ = Balances::deposit_creating(&CHARLIE, 1_000_000);
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(CHARLIE),
-				30_000,
-				GAS_LIMIT,
-				restoration_code_hash.into(),
-				<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
-				vec![],
-			));
-			let addr_django = Contracts::contract_address(&CHARLIE, &restoration_code_hash, &[]);
+#[test]
+fn shared_storage_is_shared_by_code_hash() {
+	use self::test_utils::{place_contract, get_storage};
+	use crate::storage::{SharedStorageError, SharedStorageInfo};
+	use frame_support::StorageDoubleMap;
 
-			// Before performing a call to `DJANGO` save its original trie id.
-			let django_trie_id = ContractInfoOf::<Test>::get(&addr_django).unwrap()
-				.get_alive().unwrap().trie_id;
+	ExtBuilder::default().build().execute_with(|| {
+		let (shared, other) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		place_contract(&ALICE, shared);
+		place_contract(&BOB, shared);
+		place_contract(&CHARLIE, other);
+		let key = &[1; 32];
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		let alice_info = info();
+
+		// A write of one instance is seen by the other one.
+		assert_ok!(Storage::<Test>::write_shared(&ALICE, &shared, key, Some(vec![1, 2, 3])));
+		assert_eq!(Storage::<Test>::read_shared(&BOB, &shared, key), Ok(Some(vec![1, 2, 3])));
+		assert_ok!(Storage::<Test>::write_shared(&BOB, &shared, key, Some(vec![4])));
+		assert_eq!(Storage::<Test>::read_shared(&ALICE, &shared, key), Ok(Some(vec![4])));
+		assert_eq!(
+			crate::SharedStorage::<Test>::get(&shared),
+			SharedStorageInfo { storage_size: 1, pair_count: 1 },
+		);
 
-			// The trie is regarded as 'dirty' when it was written to in the current block.
-			if !test_restore_to_with_dirty_storage {
-				// Advance 1 block, to the 6th.
-				initialize_block(6);
-			}
+		// The storage of the contracts themselves isn't touched. Only the contract that wrote
+		// the pair last pays for it.
+		assert_eq!(get_storage(&ALICE, key), None);
+		assert_eq!(get_storage(&BOB, key), None);
+		assert_eq!(info(), alice_info);
+		let bob_info = ContractInfoOf::<Test>::get(&BOB).unwrap().get_alive().unwrap();
+		assert_eq!((bob_info.storage_size, bob_info.total_pair_count), (1, 1));
 
-			// Perform a call to `DJANGO`. This should either perform restoration successfully or
-			// fail depending on the test parameters.
-			let perform_the_restoration = || {
-				Contracts::call(
-					Origin::signed(ALICE),
-					addr_django.clone(),
-					0,
-					GAS_LIMIT,
-					set_rent_code_hash
-						.as_ref()
-						.iter()
-						.chain(AsRef::<[u8]>::as_ref(&addr_bob))
-						.cloned()
-						.collect(),
-				)
-			};
+		// A contract running another code can't access the shared storage and its own shared
+		// storage is separate.
+		assert_eq!(
+			Storage::<Test>::read_shared(&CHARLIE, &shared, key),
+			Err(SharedStorageError::CodeHashMismatch),
+		);
+		assert_eq!(
+			Storage::<Test>::write_shared(&CHARLIE, &shared, key, None),
+			Err(SharedStorageError::CodeHashMismatch),
+		);
+		assert_eq!(Storage::<Test>::read_shared(&CHARLIE, &other, key), Ok(None));
+		assert_eq!(
+			Storage::<Test>::read_shared(&DJANGO, &shared, key),
+			Err(SharedStorageError::ContractAbsent),
+		);
 
-			if test_different_storage || test_restore_to_with_dirty_storage {
-				// Parametrization of the test imply restoration failure. Check that `DJANGO` aka
-				// restoration contract is still in place and also that `BOB` doesn't exist.
+		Storage::<Test>::set_frozen(&BOB, true).unwrap();
+		assert_eq!(
+			Storage::<Test>::write_shared(&BOB, &shared, key, None),
+			Err(SharedStorageError::ContractFrozen),
+		);
+		assert_ok!(Storage::<Test>::write_shared(&ALICE, &shared, key, None));
+		assert_eq!(Storage::<Test>::read_shared(&BOB, &shared, key), Ok(None));
+		assert_eq!(crate::SharedStorage::<Test>::get(&shared), SharedStorageInfo::default());
+		assert_eq!(
+			Storage::<Test>::write_shared(
+				&ALICE,
+				&shared,
+				key,
+				Some(vec![0; MaxValueSize::get() as usize + 1]),
+			),
+			Err(SharedStorageError::ValueTooLarge),
+		);
+
+		// The pairs a contract pays for are removed together with the contract.
+		assert_ok!(Storage::<Test>::write_shared(&ALICE, &shared, key, Some(vec![5])));
+		assert_eq!((info().storage_size, info().total_pair_count), (1, 1));
+		Storage::<Test>::destroy_contract(&ALICE, &alice_info.trie_id);
+		assert_eq!(Storage::<Test>::read_shared(&BOB, &shared, key), Ok(None));
+		assert!(!crate::SharedStorage::<Test>::contains_key(&shared));
+		let hashed_key = storage::meta::hashed_key(key);
+		assert!(!crate::SharedPairOwners::<Test>::contains_key(&shared, hashed_key));
+	});
+}
+
+#[test]
+fn dedup_writes_share_blobs() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		place_contract(&BOB, H256::repeat_byte(1));
+		let alice_trie = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let bob_trie = ContractInfoOf::<Test>::get(&BOB).unwrap().get_alive().unwrap().trie_id;
+		let info = |account| ContractInfoOf::<Test>::get(account).unwrap().get_alive().unwrap();
+		let value = vec![7u8; 100];
+		let hash = sp_io::hashing::blake2_256(&value);
+
+		// Both keys point to the same blob and are only charged for the pointer.
+		assert_ok!(Storage::<Test>::write_dedup(&ALICE, &alice_trie, &[1; 32], value.clone()));
+		assert_ok!(Storage::<Test>::write_dedup(&BOB, &bob_trie, &[2; 32], value.clone()));
+		assert_eq!(Contracts::shared_blob(hash), Some((2, value.clone())));
+		assert_eq!(Contracts::shared_blob_bytes(), 100);
+		assert_eq!(get_storage(&ALICE, &[1; 32]), Some(value.clone()));
+		assert_eq!(get_storage(&BOB, &[2; 32]), Some(value.clone()));
+		// The pointer is the content hash.
+		assert_eq!((info(&ALICE).storage_size, info(&ALICE).total_pair_count), (32, 1));
+
+		// Rewriting the same value keeps the number of references.
+		assert_ok!(Storage::<Test>::write_dedup(&ALICE, &alice_trie, &[1; 32], value.clone()));
+		assert_eq!(Contracts::shared_blob(hash), Some((2, value.clone())));
+
+		// Deleting a key releases its reference.
+		set_storage(&ALICE, &[1; 32], None);
+		assert_eq!(get_storage(&ALICE, &[1; 32]), None);
+		assert_eq!(Contracts::shared_blob(hash), Some((1, value.clone())));
+		assert_eq!(info(&ALICE).storage_size, 0);
+
+		// Overwriting the last pointer with a plain value removes the blob.
+		set_storage(&BOB, &[2; 32], Some(vec![1; 32]));
+		assert_eq!(get_storage(&BOB, &[2; 32]), Some(vec![1; 32]));
+		assert_eq!(Contracts::shared_blob(hash), None);
+		assert_eq!(Contracts::shared_blob_bytes(), 0);
+
+		// Plain values are stored as written even if they look like a pointer.
+		let tagged: Vec<u8> = [0xff, 0].iter().chain(&hash).cloned().collect();
+		for value in [hash.to_vec(), tagged, vec![0xff, 1, 2], vec![0xff]].iter() {
+			set_storage(&ALICE, &[4; 32], Some(value.clone()));
+			assert_eq!(get_storage(&ALICE, &[4; 32]), Some(value.clone()));
+			assert_eq!(info(&ALICE).storage_size, value.len() as u64);
+		}
+		set_storage(&ALICE, &[4; 32], None);
+
+		// Dedup writes are mirrored, remove locks and are bounded like other writes.
+		assert_ok!(Storage::<Test>::write_timelock(&ALICE, &alice_trie, &[5; 32], vec![5], 10));
+		TestMirror::take();
+		assert_ok!(Storage::<Test>::write_dedup(&ALICE, &alice_trie, &[5; 32], value.clone()));
+		assert_eq!(get_storage(&ALICE, &[5; 32]), Some(value.clone()));
+		assert_eq!(
+			TestMirror::take(),
+			vec![(ALICE, storage::meta::hashed_key(&[5; 32]), Some(value.clone()), 1)],
+		);
+		let too_large = vec![0; MaxValueSize::get() as usize + 1];
+		assert_eq!(
+			Storage::<Test>::write_dedup(&ALICE, &alice_trie, &[6; 32], too_large),
+			Err(storage::WriteError::ValueTooLarge),
+		);
+		set_storage(&ALICE, &[5; 32], None);
+
+		// Destroying a contract releases its blobs as well.
+		assert_ok!(Storage::<Test>::write_dedup(&BOB, &bob_trie, &[3; 32], value.clone()));
+		Storage::<Test>::destroy_contract(&BOB, &bob_trie);
+		assert_eq!(Contracts::shared_blob(hash), None);
+
+		assert_eq!(
+			Storage::<Test>::write_dedup(&BOB, &alice_trie, &[1; 32], value),
+			Err(storage::WriteError::ContractAbsent),
+		);
+	});
+}
+
+#[test]
+fn audited_reads_are_recorded_and_pruned() {
+	use frame_support::traits::OnInitialize;
+	use self::test_utils::{place_contract, set_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		set_storage(&ALICE, &[1; 32], Some(vec![42]));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let child_info = crate::child_trie_info(&trie_id);
+		let read = |reader: &AccountIdOf<Test>, key: &StorageKey| {
+			Storage::<Test>::read_declared(None, reader, &child_info, false, key).unwrap()
+		};
+
+		// Reads leave no trace while auditing is disabled.
+		AuditReads::set(false);
+		assert_eq!(read(&ALICE, &[1; 32]), Some(vec![42]));
+		assert_eq!(Contracts::read_audit(1), vec![]);
+		assert_eq!(Contracts::on_initialize(5), 0);
+
+		// Every read is recorded including those of absent keys.
+		AuditReads::set(true);
+		assert_eq!(read(&ALICE, &[1; 32]), Some(vec![42]));
+		assert_eq!(read(&BOB, &[2; 32]), None);
+		assert_eq!(
+			Contracts::read_audit(1),
+			vec![
+				(trie_id.clone(), storage::meta::hashed_key(&[1; 32]), ALICE),
+				(trie_id.clone(), storage::meta::hashed_key(&[2; 32]), BOB),
+			],
+		);
+
+		// The entries of a block are removed once `AuditRetention` blocks have passed.
+		System::set_block_number(2);
+		read(&ALICE, &[1; 32]);
+		Contracts::on_initialize(1 + AuditRetention::get());
+		assert_eq!(Contracts::read_audit(1), vec![]);
+		assert_eq!(Contracts::read_audit(2).len(), 1);
+		assert_eq!(Contracts::read_audit_blocks(), Some((2, 2)));
+
+		// Recorded blocks are still pruned after auditing is disabled.
+		AuditReads::set(false);
+		Contracts::on_initialize(2 + AuditRetention::get());
+		assert_eq!(Contracts::read_audit(2), vec![]);
+		assert_eq!(Contracts::read_audit_blocks(), None);
+
+		// Lowering the retention prunes every block that has expired since.
+		AuditReads::set(true);
+		for block in 10..13 {
+			System::set_block_number(block);
+			read(&ALICE, &[1; 32]);
+		}
+		AuditRetention::set(1);
+		Contracts::on_initialize(13);
+		for block in 10..13 {
+			assert_eq!(Contracts::read_audit(block), vec![]);
+		}
+		assert_eq!(Contracts::read_audit_blocks(), None);
+	});
+}
+
+#[test]
+fn timelocked_values_are_hidden_until_unlocked() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+	use frame_support::StorageDoubleMap;
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		let trie_id = info().trie_id;
+		let (locked, open) = (&[1; 32], &[2; 32]);
+		set_storage(&ALICE, open, Some(vec![2]));
+
+		assert_ok!(Storage::<Test>::write_timelock(&ALICE, &trie_id, locked, vec![1, 1], 3));
+		// The value is paid for although it can't be read yet.
+		assert_eq!((info().storage_size, info().total_pair_count), (3, 2));
+
+		for block in 1..3 {
+			System::set_block_number(block);
+			assert_eq!(get_storage(&ALICE, locked), None);
+			assert_eq!(Storage::<Test>::read_page(&trie_id, None, 10).0.len(), 1);
+		}
+
+		System::set_block_number(3);
+		assert_eq!(get_storage(&ALICE, locked), Some(vec![1, 1]));
+		assert_eq!(get_storage(&ALICE, open), Some(vec![2]));
+		assert_eq!(Storage::<Test>::read_page(&trie_id, None, 10).0.len(), 2);
+
+		// A later write removes the lock and so does a removal.
+		assert_ok!(Storage::<Test>::write_timelock(&ALICE, &trie_id, locked, vec![3], 5));
+		assert_eq!(get_storage(&ALICE, locked), None);
+		set_storage(&ALICE, locked, Some(vec![4]));
+		assert_eq!(get_storage(&ALICE, locked), Some(vec![4]));
+		assert_ok!(Storage::<Test>::write_timelock(&ALICE, &trie_id, locked, vec![3], 5));
+		set_storage(&ALICE, locked, None);
+		set_storage(&ALICE, locked, Some(vec![5]));
+		assert_eq!(get_storage(&ALICE, locked), Some(vec![5]));
+		assert!(info().timelocked);
+
+		// The locks are removed together with the contract.
+		Storage::<Test>::destroy_contract(&ALICE, &trie_id);
+		let hashed_key = storage::meta::hashed_key(locked);
+		assert!(!crate::Timelocks::<Test>::contains_key(&trie_id, hashed_key));
+	});
+}
+
+#[test]
+fn ordered_writes_support_range_queries() {
+	use self::test_utils::place_contract;
+
+	ExtBuilder::default().build().execute_with(|| {
+		let trie_id = |addr: &AccountIdOf<Test>| {
+			ContractInfoOf::<Test>::get(addr).unwrap().get_alive().unwrap().trie_id
+		};
+		place_contract(&ALICE, H256::repeat_byte(1));
+		place_contract(&BOB, H256::repeat_byte(1));
+		let (alice, bob) = (trie_id(&ALICE), trie_id(&BOB));
+
+		for &key in &[50, 3, u128::max_value(), 1_000, 7, 0, 256] {
+			assert_ok!(Storage::<Test>::write_ordered(&ALICE, &alice, key, Some(key.encode())));
+		}
+		assert_ok!(Storage::<Test>::write_ordered(&BOB, &bob, 5, Some(vec![5])));
+		let keys = |lo, hi| Storage::<Test>::range(&alice, lo, hi)
+			.into_iter()
+			.map(|(key, value)| {
+				assert_eq!(value, key.encode());
+				key
+			})
+			.collect::<Vec<_>>();
+
+		assert_eq!(keys(0, u128::max_value()), vec![0, 3, 7, 50, 256, 1_000, u128::max_value()]);
+		assert_eq!(keys(5, 256), vec![7, 50, 256]);
+		assert_eq!(keys(8, 49), Vec::<u128>::new());
+		assert_eq!(keys(1_000, 3), Vec::<u128>::new());
+		assert_eq!(Storage::<Test>::range(&bob, 0, 10), vec![(5, vec![5])]);
+
+		// The values are ordinary storage of the contract.
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		assert_eq!(info.total_pair_count, 7);
+		assert_ok!(Storage::<Test>::write_ordered(&ALICE, &alice, 7, None));
+		assert_eq!(keys(5, 256), vec![50, 256]);
+		assert_eq!(Storage::<Test>::read(&alice, &storage::ordered_key(50)), Some(50u128.encode()));
+	});
+}
+
+#[test]
+fn compound_keys_are_derived_from_their_components() {
+	use self::test_utils::place_contract;
+	use crate::storage::{CompoundKey, meta};
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let key = |token_id: u64| CompoundKey::new().push(&BOB).push(&token_id).push(&b"owner");
+
+		assert_ok!(Storage::<Test>::write_compound(&ALICE, &trie_id, &key(1), Some(vec![1])));
+		assert_ok!(Storage::<Test>::write_compound(&ALICE, &trie_id, &key(2), Some(vec![2])));
+		assert_ne!(key(1).storage_key(), key(2).storage_key());
+		assert_eq!(Storage::<Test>::read_compound(&trie_id, &key(1)), Some(vec![1]));
+		assert_eq!(Storage::<Test>::read_compound(&trie_id, &key(2)), Some(vec![2]));
+
+		// Off-chain code derives the same location from the encoded tuple of components.
+		let storage_key = sp_io::hashing::blake2_256(&(BOB, 1u64, b"owner").encode());
+		assert_eq!(key(1).storage_key(), storage_key);
+		assert_eq!(Storage::<Test>::read(&trie_id, &storage_key), Some(vec![1]));
+		let (child_info, hashed_key) = meta::contract_child_storage_key(&trie_id, &storage_key);
+		assert!(frame_support::storage::child::exists(&child_info, &hashed_key));
+
+		assert_ok!(Storage::<Test>::write_compound(&ALICE, &trie_id, &key(1), None));
+		assert_eq!(Storage::<Test>::read_compound(&trie_id, &key(1)), None);
+		assert_eq!(Storage::<Test>::read_compound(&trie_id, &key(2)), Some(vec![2]));
+	});
+}
+
+#[test]
+fn successful_writes_are_mirrored() {
+	use self::test_utils::{place_contract, set_storage};
+	use crate::storage::meta::hashed_key;
+
+	ExtBuilder::default().build().execute_with(|| {
+		ValueEncryption::set(true);
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let (a, b) = ([1; 32], [2; 32]);
+		TestMirror::take();
+
+		// The mirror sees the plain values after the bookkeeping was updated.
+		set_storage(&ALICE, &a, Some(vec![1, 2]));
+		set_storage(&ALICE, &b, Some(vec![3]));
+		set_storage(&ALICE, &a, None);
+		assert_eq!(TestMirror::take(), vec![
+			(ALICE, hashed_key(&a), Some(vec![1, 2]), 1),
+			(ALICE, hashed_key(&b), Some(vec![3]), 2),
+			(ALICE, hashed_key(&a), None, 1),
+		]);
+
+		// Failed writes aren't mirrored.
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		Storage::<Test>::set_frozen(&ALICE, true).unwrap();
+		assert_eq!(
+			Storage::<Test>::write(&ALICE, &trie_id, &a, Some(vec![1])),
+			Err(storage::WriteError::ContractFrozen),
+		);
+		assert_eq!(
+			Storage::<Test>::write(&BOB, &trie_id, &a, Some(vec![1])),
+			Err(storage::WriteError::ContractAbsent),
+		);
+		assert_eq!(TestMirror::take(), vec![]);
+	});
+}
+
+#[test]
+fn raw_writes_skip_bookkeeping_and_spare_contract_tries() {
+	use self::test_utils::place_contract;
+	use crate::storage::OwnedTrie;
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		let before = info();
+		let scratch = b"scratch".to_vec();
+		let key = [1; 32];
+
+		assert_eq!(Storage::<Test>::write_raw(&scratch, &key, Some(vec![1, 2, 3])), Ok(()));
+		assert_eq!(Storage::<Test>::read(&scratch, &key), Some(vec![1, 2, 3]));
+		assert_eq!(Storage::<Test>::write_raw(&scratch, &key, None), Ok(()));
+		assert_eq!(Storage::<Test>::read(&scratch, &key), None);
+		assert_eq!(info(), before);
+
+		// The trie of a contract is refused and stays untouched.
+		assert_eq!(
+			Storage::<Test>::write_raw(&before.trie_id, &key, Some(vec![1])),
+			Err(OwnedTrie),
+		);
+		assert_eq!(Storage::<Test>::read(&before.trie_id, &key), None);
+		assert_eq!(info(), before);
+
+		// Once the contract is gone its trie is no longer guarded.
+		Storage::<Test>::destroy_contract(&ALICE, &before.trie_id);
+		assert!(!crate::TrieOwner::<Test>::contains_key(&before.trie_id));
+	});
+}
+
+#[test]
+fn trie_depth_estimate_detects_clustered_keys() {
+	use self::test_utils::{place_contract, set_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		let trie_id = |addr: &AccountIdOf<Test>| {
+			ContractInfoOf::<Test>::get(addr).unwrap().get_alive().unwrap().trie_id
+		};
+		place_contract(&ALICE, H256::repeat_byte(1));
+		place_contract(&BOB, H256::repeat_byte(1));
+		assert_eq!(Storage::<Test>::trie_depth_estimate(&trie_id(&ALICE)), 0);
+
+		// The keys of contracts are hashed which distributes them evenly.
+		for i in 0..32 {
+			set_storage(&ALICE, &[i; 32], Some(vec![1]));
+		}
+
+		// Every key branches off one nibble later than the previous one. Contracts can't
+		// create such a trie but it is the worst case that the estimate needs to detect.
+		let child_trie_info = crate::child_trie_info(&trie_id(&BOB));
+		for nibble in 0..32 {
+			let mut key = [0u8; 32];
+			key[nibble / 2] = if nibble % 2 == 0 { 0x10 } else { 0x01 };
+			frame_support::storage::child::put_raw(&child_trie_info, &key, &[1]);
+		}
+
+		let distributed = Storage::<Test>::trie_depth_estimate(&trie_id(&ALICE));
+		let clustered = Storage::<Test>::trie_depth_estimate(&trie_id(&BOB));
+		assert!(
+			clustered > 2 * distributed,
+			"clustered: {}, distributed: {}", clustered, distributed,
+		);
+	});
+}
+
+#[test]
+fn place_contract_with_storage_books_the_initial_storage() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		let code_hash = H256::repeat_byte(1);
+		let initial = vec![
+			([1; 32], vec![1, 2, 3]),
+			([2; 32], vec![]),
+			([3; 32], vec![4; 10]),
+			([1; 32], vec![5]),
+		];
+		let trie_id = Storage::<Test>::generate_trie_id(&ALICE).unwrap();
+		assert_ok!(Storage::<Test>::place_contract_with_storage(
+			&ALICE,
+			trie_id,
+			code_hash,
+			initial.clone(),
+		));
+		let info = |addr| ContractInfoOf::<Test>::get(addr).unwrap().get_alive().unwrap();
+		let alice = info(&ALICE);
+		assert_eq!(alice.storage_size, 1 + 0 + 10);
+		assert_eq!(alice.total_pair_count, 3);
+		assert_eq!(alice.empty_pair_count, 1);
+		assert_eq!(alice.last_write, Some(1));
+		assert_eq!(get_storage(&ALICE, &[1; 32]), Some(vec![5]));
+		assert_eq!(get_storage(&ALICE, &[2; 32]), Some(vec![]));
+		assert_eq!(get_storage(&ALICE, &[3; 32]), Some(vec![4; 10]));
+
+		// The bookkeeping is the same as if the pairs were written one by one.
+		place_contract(&BOB, code_hash);
+		for (key, value) in initial {
+			set_storage(&BOB, &key, Some(value));
+		}
+		let bob = info(&BOB);
+		assert_eq!(
+			(bob.storage_size, bob.total_pair_count, bob.empty_pair_count, bob.last_write),
+			(alice.storage_size, alice.total_pair_count, alice.empty_pair_count, alice.last_write),
+		);
+
+		let trie_id = Storage::<Test>::generate_trie_id(&ALICE).unwrap();
+		assert_eq!(
+			Storage::<Test>::place_contract_with_storage(&ALICE, trie_id, code_hash, vec![]),
+			Err("Alive contract or tombstone already exists"),
+		);
+		assert_eq!(info(&ALICE), alice);
+	});
+}
+
+#[test]
+fn place_contract_with_storage_encrypts_the_initial_storage() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+	use frame_support::storage::child;
+
+	ExtBuilder::default().build().execute_with(|| {
+		ValueEncryption::set(true);
+		let code_hash = H256::repeat_byte(1);
+		let trie_id = Storage::<Test>::generate_trie_id(&ALICE).unwrap();
+		assert_ok!(Storage::<Test>::place_contract_with_storage(
+			&ALICE,
+			trie_id.clone(),
+			code_hash,
+			vec![([1; 32], vec![1, 2, 3])],
+		));
+
+		// The initial storage is stored and booked like a regular write.
+		assert_eq!(
+			child::get_raw(&crate::child_trie_info(&trie_id), &storage::meta::hashed_key(&[1; 32])),
+			Some(vec![0xfe, 0xfd, 0xfc, 0xaa]),
+		);
+		assert_eq!(get_storage(&ALICE, &[1; 32]), Some(vec![1, 2, 3]));
+		place_contract(&BOB, code_hash);
+		set_storage(&BOB, &[1; 32], Some(vec![1, 2, 3]));
+		let info = |addr| ContractInfoOf::<Test>::get(addr).unwrap().get_alive().unwrap();
+		assert_eq!(info(&ALICE).storage_size, 4);
+		assert_eq!(info(&ALICE).storage_size, info(&BOB).storage_size);
+	});
+}
+
+#[test]
+fn value_size_histogram_counts_values_into_buckets() {
+	use self::test_utils::{place_contract, set_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		let buckets = [4, 16, 256];
+		assert_eq!(Storage::<Test>::value_size_histogram(&buckets), vec![0, 0, 0, 0]);
+
+		place_contract(&ALICE, H256::repeat_byte(1));
+		place_contract(&BOB, H256::repeat_byte(1));
+		for (i, len) in [1, 4, 5, 100, 257, 1_000].iter().enumerate() {
+			set_storage(&ALICE, &[i as u8; 32], Some(vec![1; *len]));
+		}
+		set_storage(&BOB, &[1; 32], Some(vec![1; 16]));
+
+		// The bounds are inclusive and the last entry counts the values above all bounds.
+		assert_eq!(Storage::<Test>::value_size_histogram(&buckets), vec![2, 2, 1, 2]);
+		assert_eq!(Storage::<Test>::value_size_histogram(&[]), vec![7]);
+	});
+}
+
+#[test]
+fn savepoints_roll_back_later_writes() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+	use crate::storage::{Savepoints, UnknownSavepoint};
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		let (a, b, c) = (&[1; 32], &[2; 32], &[3; 32]);
+
+		let mut savepoints = Savepoints::default();
+		set_storage(&ALICE, a, Some(vec![1, 2, 3]));
+		let outer = savepoints.savepoint();
+		set_storage(&ALICE, b, Some(vec![4, 5]));
+		let inner = savepoints.savepoint();
+		set_storage(&ALICE, c, Some(vec![]));
+		set_storage(&ALICE, a, None);
+
+		// Rolling back to the outer savepoint discards the later inner one as well.
+		assert_eq!(savepoints.rollback_to(outer), Ok(()));
+		assert_eq!(savepoints.rollback_to(inner), Err(UnknownSavepoint));
+		savepoints.commit();
+
+		assert_eq!(get_storage(&ALICE, a), Some(vec![1, 2, 3]));
+		assert_eq!(get_storage(&ALICE, b), None);
+		assert_eq!(get_storage(&ALICE, c), None);
+		assert_eq!(
+			(info().storage_size, info().total_pair_count, info().empty_pair_count),
+			(3, 1, 0),
+		);
+
+		// Writes before an inner savepoint survive a rollback to it.
+		let mut savepoints = Savepoints::default();
+		savepoints.savepoint();
+		set_storage(&ALICE, b, Some(vec![4, 5]));
+		let inner = savepoints.savepoint();
+		set_storage(&ALICE, c, Some(vec![6]));
+		assert_eq!(savepoints.rollback_to(inner), Ok(()));
+		drop(savepoints);
+
+		assert_eq!(get_storage(&ALICE, b), Some(vec![4, 5]));
+		assert_eq!(get_storage(&ALICE, c), None);
+		assert_eq!((info().storage_size, info().total_pair_count), (5, 2));
+	});
+}
+
+#[test]
+fn patch_works() {
+	use self::test_utils::{place_contract, set_storage, get_storage};
+	use crate::storage::{PatchError, WriteError};
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		let trie_id = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap().trie_id;
+		let key = &[1; 32];
+		let absent = &[2; 32];
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+
+		// Patching inside of the value keeps its size.
+		set_storage(&ALICE, key, Some(vec![1, 2, 3, 4]));
+		assert_eq!(Storage::<Test>::patch(&ALICE, &trie_id, key, 1, &[9, 9]), Ok(()));
+		assert_eq!(get_storage(&ALICE, key), Some(vec![1, 9, 9, 4]));
+		assert_eq!(info().storage_size, 4);
+
+		// Patching past the end extends the value with zeros.
+		assert_eq!(Storage::<Test>::patch(&ALICE, &trie_id, key, 6, &[7]), Ok(()));
+		assert_eq!(get_storage(&ALICE, key), Some(vec![1, 9, 9, 4, 0, 0, 7]));
+		assert_eq!(info().storage_size, 7);
+		assert_eq!(info().total_pair_count, 1);
+
+		// An absent value is treated as empty.
+		assert_eq!(Storage::<Test>::patch(&ALICE, &trie_id, absent, 2, &[5]), Ok(()));
+		assert_eq!(get_storage(&ALICE, absent), Some(vec![0, 0, 5]));
+		assert_eq!(info().storage_size, 10);
+		assert_eq!(info().total_pair_count, 2);
+
+		// The end of the patch must not overflow.
+		assert_eq!(
+			Storage::<Test>::patch(&ALICE, &trie_id, key, u32::max_value(), &[1]),
+			Err(PatchError::Overflow),
+		);
+		assert_eq!(get_storage(&ALICE, key), Some(vec![1, 9, 9, 4, 0, 0, 7]));
+
+		assert_eq!(
+			Storage::<Test>::patch(&BOB, &trie_id, key, 0, &[1]),
+			Err(PatchError::Write(WriteError::ContractAbsent)),
+		);
+	});
+}
+
+#[test]
+fn contract_info_detailed_works() {
+	use self::test_utils::{place_contract, set_balance, set_storage};
+	use pallet_contracts_primitives::RentProjection;
+
+	ExtBuilder::default().build().execute_with(|| {
+		place_contract(&ALICE, H256::repeat_byte(1));
+		set_balance(&ALICE, 1_000);
+		set_storage(&ALICE, &[1; 32], Some(vec![1, 2, 3, 4]));
+		set_storage(&ALICE, &[2; 32], Some(vec![]));
+
+		initialize_block(5);
+		let detailed = Storage::<Test>::contract_info_detailed(&ALICE).unwrap();
+		let info = ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+
+		// The derived values are computed without charging any rent.
+		assert_eq!(detailed.info, info);
+		assert_eq!(info.deduct_block, 1);
+
+		assert_eq!(detailed.blocks_since_last_write, Some(4));
+		assert_eq!(detailed.physical_size, 4);
+		// storage size + `StorageSizeOffset` + one byte for the empty pair
+		assert_eq!(detailed.logical_size, 4 + 8 + 1);
+
+		// The whole free balance is below `RentDepositOffset` and therefore no storage
+		// is covered by the deposit.
+		let fee_per_block = 13 * RentByteFee::get();
+		let budget = 1_000 - ConfigCache::<Test>::subsistence_threshold_uncached();
+		assert_eq!(
+			detailed.projected_eviction,
+			RentProjection::EvictionAt(info.deduct_block + budget / fee_per_block),
+		);
+
+		// Contracts that never wrote to their storage report no last write.
+		place_contract(&BOB, H256::repeat_byte(2));
+		assert_eq!(
+			Storage::<Test>::contract_info_detailed(&BOB).unwrap().blocks_since_last_write,
+			None,
+		);
+
+		assert_eq!(
+			Storage::<Test>::contract_info_detailed(&CHARLIE),
+			Err(crate::storage::ContractAbsentError),
+		);
+	});
+}
+
+#[test]
+fn contract_snapshot_can_be_reassembled() {
+	use self::test_utils::set_storage;
+	use pallet_contracts_primitives::{ContractAccessError, SnapshotHeader};
+	use sp_io::hashing::blake2_256;
+
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+		assert_ok!(
+			Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			),
+		);
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		Balances::make_free_balance_be(&addr, subsistence + 42);
+
+		let mut expected: Vec<(Vec<u8>, Vec<u8>)> = (0..100u8)
+			.map(|i| ([i; 32], vec![i; i as usize]))
+			.inspect(|(key, value)| set_storage(&addr, key, Some(value.clone())))
+			.map(|(key, value)| (blake2_256(&key).to_vec(), value))
+			.collect();
+		expected.sort();
+
+		// The first chunk contains the header.
+		let first = Contracts::contract_snapshot(addr.clone(), None, 7).unwrap();
+		let info = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+		assert_eq!(first.header, Some(SnapshotHeader {
+			code_hash,
+			pristine_code: Some(wasm),
+			free_balance: subsistence + 42,
+			rent_allowance: info.rent_allowance,
+			storage_size: info.storage_size,
+			total_pair_count: 100,
+			deduct_block: info.deduct_block,
+			last_write: Some(1),
+			call_count: 0,
+		}));
+		assert_eq!(first.pairs.len(), 7);
+
+		// Page through the remaining pairs.
+		let mut pairs = first.pairs;
+		let mut next_key = first.next_key;
+		let mut chunks = 1;
+		while let Some(start_key) = next_key {
+			let chunk = Contracts::contract_snapshot(addr.clone(), Some(start_key), 7).unwrap();
+			assert_eq!(chunk.header, None);
+			assert!(chunk.pairs.len() <= 7);
+			pairs.extend(chunk.pairs);
+			next_key = chunk.next_key;
+			chunks += 1;
+		}
+		assert_eq!(chunks, 15);
+		assert_eq!(pairs, expected);
+
+		assert_eq!(
+			Contracts::contract_snapshot(BOB, None, 7),
+			Err(ContractAccessError::DoesntExist),
+		);
+	});
+}
+
+#[test]
+fn uploading_code_twice_is_cheap() {
+	use crate::weights::WeightInfo;
+
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExtBuilder::default().build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		let code_len = wasm.len() as u32;
+
+		let first = Contracts::put_code(Origin::signed(ALICE), wasm.clone()).unwrap();
+		assert_eq!(first.actual_weight, None);
+
+		// The second upload is charged for hashing the code only and doesn't report it again.
+		let second = Contracts::put_code(Origin::signed(BOB), wasm).unwrap();
+		let existing_weight = <Test as Config>::WeightInfo::put_code_existing(code_len / 1024);
+		assert_eq!(second.actual_weight, Some(existing_weight));
+		assert!(existing_weight < <Test as Config>::WeightInfo::put_code(code_len / 1024));
+
+		let stored: Vec<_> = crate::test_utils::contract_events::<Test>()
+			.into_iter()
+			.filter_map(|event| match event {
+				RawEvent::CodeStored(hash, uploader) => Some((hash, uploader)),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(stored, vec![(code_hash.into(), ALICE)]);
+	});
+}
+
+#[test]
+fn instantiate_and_call_and_deposit_event() {
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(100)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			let subsistence = super::ConfigCache::<Test>::subsistence_threshold_uncached();
+
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+			// Check at the end to get hash on error easily
+			let creation = Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			);
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			pretty_assertions::assert_eq!(System::events(), vec![
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::system(frame_system::RawEvent::NewAccount(ALICE.clone())),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::balances(
+						pallet_balances::RawEvent::Endowed(ALICE, 1_000_000)
+					),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::contracts(RawEvent::CodeStored(code_hash.into(), ALICE)),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::system(frame_system::RawEvent::NewAccount(addr.clone())),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::balances(
+						pallet_balances::RawEvent::Endowed(addr.clone(), subsistence)
+					),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::balances(
+						pallet_balances::RawEvent::Transfer(ALICE, addr.clone(), subsistence)
+					),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::balances(
+						pallet_balances::RawEvent::Reserved(addr.clone(), 16)
+					),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::contracts(
+						RawEvent::ContractExecution(addr.clone(), vec![1, 2, 3, 4])
+					),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::contracts(RawEvent::Instantiated(ALICE, addr.clone())),
+					topics: vec![],
+				}
+			]);
+
+			assert_ok!(creation);
+			assert!(ContractInfoOf::<Test>::contains_key(&addr));
+		});
+}
+
+#[test]
+fn test_utils_instantiate_and_call() {
+	use crate::test_utils;
+
+	let (wasm, _) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExistentialDeposit::set(1);
+	test_utils::ExtBuilder::<Test>::default()
+		.balance(ALICE, 1_000_000)
+		.build()
+		.execute_with(|| {
+			// The start function emits an event on deployment and on every call.
+			let (addr, result) = test_utils::instantiate_and_call::<Test>(
+				ALICE, wasm, 100_000, vec![], vec![],
+			).unwrap();
+			assert!(result.exec_result.unwrap().is_success());
+			assert_eq!(
+				test_utils::contract_emitted::<Test>(&addr),
+				vec![vec![1, 2, 3, 4], vec![1, 2, 3, 4]],
+			);
+			assert_eq!(Balances::free_balance(&addr), 100_000 - TombstoneDeposit::get());
+			assert_eq!(Balances::reserved_balance(&addr), TombstoneDeposit::get());
+		});
+}
+
+#[test]
+fn call_results_carry_schedule_version() {
+	use crate::test_utils;
+
+	let (wasm, _) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExistentialDeposit::set(1);
+	test_utils::ExtBuilder::<Test>::default()
+		.balance(ALICE, 1_000_000)
+		.build()
+		.execute_with(|| {
+			let (addr, result) = test_utils::instantiate_and_call::<Test>(
+				ALICE, wasm, 100_000, vec![], vec![],
+			).unwrap();
+			let version = <Schedule<Test>>::default().version;
+			assert_eq!(result.schedule_version, version);
+
+			let schedule = Schedule { version: version + 1, .. Default::default() };
+			assert_ok!(Contracts::update_schedule(Origin::root(), schedule));
+			let result = Contracts::bare_call(ALICE, addr, 0, GAS_LIMIT, vec![]);
+			assert!(result.exec_result.unwrap().is_success());
+			assert_eq!(result.schedule_version, version + 1);
+		});
+}
+
+#[test]
+fn code_rejected_by_a_new_schedule_is_unusable() {
+	use crate::test_utils;
+
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExistentialDeposit::set(1);
+	test_utils::ExtBuilder::<Test>::default()
+		.balance(ALICE, 1_000_000)
+		.build()
+		.execute_with(|| {
+			let addr = test_utils::instantiate::<Test>(ALICE, wasm, 100_000, vec![]).unwrap();
+			let rejections = || test_utils::contract_events::<Test>()
+				.into_iter()
+				.filter(|event| event == &RawEvent::CodeRejected(code_hash))
+				.count();
+
+			// The code uses one page of memory which the new schedule doesn't allow.
+			let version = <Schedule<Test>>::default().version;
+			let mut schedule = Schedule { version: version + 1, .. Default::default() };
+			schedule.limits.memory_pages = 0;
+			assert_ok!(Contracts::update_schedule(Origin::root(), schedule));
+
+			for _ in 0..2 {
+				let result = Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, vec![]);
+				let error = result.exec_result.unwrap_err().error;
+				assert_eq!(error, Error::<Test>::CodeRejected.into());
+				// The rejection is only reported the first time.
+				assert_eq!(rejections(), 1);
+			}
+
+			// A later schedule gets to try again.
+			let schedule = Schedule { version: version + 2, .. Default::default() };
+			assert_ok!(Contracts::update_schedule(Origin::root(), schedule));
+			let result = Contracts::bare_call(ALICE, addr, 0, GAS_LIMIT, vec![]);
+			assert!(result.exec_result.unwrap().is_success());
+			assert_eq!(rejections(), 1);
+		});
+}
+
+#[test]
+fn contract_events_can_be_disabled() {
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+	let instantiate = |events_enabled: bool| {
+		ExtBuilder::default().existential_deposit(100).build().execute_with(|| {
+			ContractEventsEnabled::set(events_enabled);
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			let subsistence = super::ConfigCache::<Test>::subsistence_threshold_uncached();
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+			System::reset_events();
+
+			let result = Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			).unwrap();
+			(result.actual_weight, crate::test_utils::contract_events::<Test>())
+		})
+	};
+
+	let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+	let (enabled_weight, enabled_events) = instantiate(true);
+	assert_eq!(enabled_events, vec![
+		RawEvent::ContractExecution(addr.clone(), vec![1, 2, 3, 4]),
+		RawEvent::Instantiated(ALICE, addr.clone()),
+	]);
+
+	// The event of the contract is charged for but not deposited while the events of
+	// the pallet are still deposited.
+	let (disabled_weight, disabled_events) = instantiate(false);
+	assert_eq!(disabled_events, vec![RawEvent::Instantiated(ALICE, addr)]);
+	assert_eq!(disabled_weight, enabled_weight);
+}
+
+#[test]
+fn deposit_event_max_value_limit() {
+	let (wasm, code_hash) = compile_module::<Test>("event_size").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				30_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Check creation
+			let bob_contract = ContractInfoOf::<Test>::get(addr.clone())
+				.unwrap()
+				.get_alive()
+				.unwrap();
+			assert_eq!(bob_contract.rent_allowance, <BalanceOf<Test>>::max_value());
+
+			// Call contract with allowed storage value.
+			assert_ok!(Contracts::call(
+				Origin::signed(ALICE),
+				addr.clone(),
+				0,
+				GAS_LIMIT * 2, // we are copying a huge buffer,
+				<Test as Config>::MaxValueSize::get().encode(),
+			));
+
+			// Call contract with too large a storage value.
+			assert_err_ignore_postinfo!(
+				Contracts::call(
+					Origin::signed(ALICE),
+					addr,
+					0,
+					GAS_LIMIT,
+					(<Test as Config>::MaxValueSize::get() + 1).encode(),
+				),
+				Error::<Test>::ValueTooLarge,
+			);
+		});
+}
+
+#[test]
+fn run_out_of_gas() {
+	let (wasm, code_hash) = compile_module::<Test>("run_out_of_gas").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Call the contract with a fixed gas limit. It must run out of gas because it just
+			// loops forever.
+			assert_err_ignore_postinfo!(
+				Contracts::call(
+					Origin::signed(ALICE),
+					addr, // newly created account
+					0,
+					67_500_000,
+					vec![],
+				),
+				Error::<Test>::OutOfGas,
+			);
+		});
+}
+
+/// Input data for each call in set_rent code
+mod call {
+	use super::{AccountIdOf, Test};
+	pub fn set_storage_4_byte() -> Vec<u8> { 0u32.to_le_bytes().to_vec() }
+	pub fn remove_storage_4_byte() -> Vec<u8> { 1u32.to_le_bytes().to_vec() }
+	pub fn transfer(to: &AccountIdOf<Test>) -> Vec<u8> {
+		2u32.to_le_bytes().iter().chain(AsRef::<[u8]>::as_ref(to)).cloned().collect()
+	}
+	pub fn null() -> Vec<u8> { 3u32.to_le_bytes().to_vec() }
+}
+
+/// Test correspondence of set_rent code and its hash.
+/// Also test that encoded extrinsic in code correspond to the correct transfer
+#[test]
+fn test_set_rent_code_and_hash() {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+			// If you ever need to update the wasm source this test will fail
+			// and will show you the actual hash.
+			assert_eq!(System::events(), vec![
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::system(frame_system::RawEvent::NewAccount(ALICE)),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::balances(pallet_balances::RawEvent::Endowed(
+						ALICE, 1_000_000
+					)),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::contracts(RawEvent::CodeStored(code_hash.into(), ALICE)),
+					topics: vec![],
+				},
+			]);
+		});
+}
+
+#[test]
+fn storage_size() {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+
+	// Storage size
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				30_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+			let bob_contract = ContractInfoOf::<Test>::get(&addr)
+				.unwrap()
+				.get_alive()
+				.unwrap();
+			assert_eq!(
+				bob_contract.storage_size,
+				4
+			);
+			assert_eq!(
+				bob_contract.total_pair_count,
+				1,
+			);
+			assert_eq!(
+				bob_contract.empty_pair_count,
+				0,
+			);
+
+			assert_ok!(Contracts::call(
+				Origin::signed(ALICE),
+				addr.clone(),
+				0,
+				GAS_LIMIT,
+				call::set_storage_4_byte()
+			));
+			let bob_contract = ContractInfoOf::<Test>::get(&addr)
+				.unwrap()
+				.get_alive()
+				.unwrap();
+			assert_eq!(
+				bob_contract.storage_size,
+				4 + 4
+			);
+			assert_eq!(
+				bob_contract.total_pair_count,
+				2,
+			);
+			assert_eq!(
+				bob_contract.empty_pair_count,
+				0,
+			);
+
+			assert_ok!(Contracts::call(
+				Origin::signed(ALICE),
+				addr.clone(),
+				0,
+				GAS_LIMIT,
+				call::remove_storage_4_byte()
+			));
+			let bob_contract = ContractInfoOf::<Test>::get(&addr)
+				.unwrap()
+				.get_alive()
+				.unwrap();
+			assert_eq!(
+				bob_contract.storage_size,
+				4
+			);
+			assert_eq!(
+				bob_contract.total_pair_count,
+				1,
+			);
+			assert_eq!(
+				bob_contract.empty_pair_count,
+				0,
+			);
+		});
+}
+
+#[test]
+fn empty_kv_pairs() {
+	let (wasm, code_hash) = compile_module::<Test>("set_empty_storage").unwrap();
+
+	ExtBuilder::default()
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				30_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+			let bob_contract = ContractInfoOf::<Test>::get(&addr)
+				.unwrap()
+				.get_alive()
+				.unwrap();
+
+			assert_eq!(
+				bob_contract.storage_size,
+				0,
+			);
+			assert_eq!(
+				bob_contract.total_pair_count,
+				1,
+			);
+			assert_eq!(
+				bob_contract.empty_pair_count,
+				1,
+			);
+		});
+}
+
+fn initialize_block(number: u64) {
+	System::initialize(
+		&number,
+		&[0u8; 32].into(),
+		&[0u8; 32].into(),
+		&Default::default(),
+		Default::default(),
+	);
+}
+
+#[test]
+fn deduct_blocks() {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				30_000,
+				GAS_LIMIT, code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Check creation
+			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+			assert_eq!(bob_contract.rent_allowance, 1_000);
+
+			// Advance 4 blocks
+			initialize_block(5);
+
+			// Trigger rent through call
+			assert_ok!(
+				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
+			);
+
+			// Check result
+			let rent = (8 + 4 - 3) // storage size = size_offset + deploy_set_storage - deposit_offset
+				* 4 // rent byte price
+				* 4; // blocks to rent
+			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+			assert_eq!(bob_contract.rent_allowance, 1_000 - rent);
+			assert_eq!(bob_contract.deduct_block, 5);
+			assert_eq!(Balances::free_balance(&addr), 30_000 - TombstoneDeposit::get() - rent);
+
+			// Advance 7 blocks more
+			initialize_block(12);
+
+			// Trigger rent through call
+			assert_ok!(
+				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
+			);
+
+			// Check result
+			let rent_2 = (8 + 4 - 2) // storage size = size_offset + deploy_set_storage - deposit_offset
+				* 4 // rent byte price
+				* 7; // blocks to rent
+			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+			assert_eq!(bob_contract.rent_allowance, 1_000 - rent - rent_2);
+			assert_eq!(bob_contract.deduct_block, 12);
+			assert_eq!(
+				Balances::free_balance(&addr),
+				30_000 - TombstoneDeposit::get() - rent - rent_2,
+			);
+
+			// Second call on same block should have no effect on rent
+			assert_ok!(
+				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
+			);
+
+			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+			assert_eq!(bob_contract.rent_allowance, 1_000 - rent - rent_2);
+			assert_eq!(bob_contract.deduct_block, 12);
+			assert_eq!(
+				Balances::free_balance(&addr),
+				30_000 - TombstoneDeposit::get() - rent - rent_2,
+			);
+		});
+}
+
+#[test]
+fn rent_is_charged_on_write() {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			let instantiate = |rent_allowance: u64, salt: Vec<u8>| {
+				assert_ok!(Contracts::instantiate(
+					Origin::signed(ALICE),
+					30_000,
+					GAS_LIMIT, code_hash.into(),
+					<Test as pallet_balances::Config>::Balance::from(rent_allowance).encode(),
+					salt.clone(),
+				));
+				Contracts::contract_address(&ALICE, &code_hash, &salt)
+			};
+			let funded = instantiate(1_000, vec![0]);
+			let exhausted = instantiate(10, vec![1]);
+			let info = |addr: &AccountIdOf<Test>| {
+				ContractInfoOf::<Test>::get(addr).unwrap().get_alive().unwrap()
+			};
+			let write = |addr: &AccountIdOf<Test>, key: u8, len: usize| {
+				Storage::<Test>::write(addr, &info(addr).trie_id, &[key; 32], Some(vec![key; len]))
+			};
+			ChargeRentOnWrite::set(true);
+
+			// Advance 4 blocks
+			initialize_block(5);
+
+			// The rent is charged for the storage size before the write.
+			assert_ok!(write(&funded, 2, 4));
+			let rent = (8 + 4 - 3) // storage size = size_offset + set_storage - deposit_offset
+				* 4 // rent byte price
+				* 4; // blocks to rent
+			let funded_info = info(&funded);
+			assert_eq!(funded_info.rent_allowance, 1_000 - rent);
+			assert_eq!(funded_info.deduct_block, 5);
+			assert_eq!(funded_info.storage_size, 8);
+			assert_eq!(Balances::free_balance(&funded), 30_000 - TombstoneDeposit::get() - rent);
+			assert_eq!(Storage::<Test>::total_rent_allowance(), 1_000 - rent + 10);
+
+			// Writing again in the same block doesn't charge again.
+			assert_ok!(write(&funded, 3, 1));
+			assert_eq!(info(&funded).rent_allowance, 1_000 - rent);
+
+			// An allowance that doesn't cover the rent blocks the write but keeps the contract.
+			let exhausted_info = info(&exhausted);
+			assert_eq!(write(&exhausted, 2, 4), Err(storage::WriteError::RentNotCovered));
+			assert_eq!(info(&exhausted), exhausted_info);
+			assert_eq!(Storage::<Test>::read(&exhausted_info.trie_id, &[2; 32]), None);
+			assert_eq!(Balances::free_balance(&exhausted), 30_000 - TombstoneDeposit::get());
+
+			// Without the charge the write goes through and rent is left for the next collection.
+			ChargeRentOnWrite::set(false);
+			assert_ok!(write(&exhausted, 2, 4));
+			assert_eq!(info(&exhausted).deduct_block, 1);
+		});
+}
+
+#[test]
+fn rent_is_charged_once_per_block() {
+	use self::test_utils::{place_contract, set_balance, set_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		place_contract(&ALICE, H256::repeat_byte(1));
+		set_balance(&ALICE, 100_000);
+		set_storage(&ALICE, &[1; 32], Some(vec![1, 2, 3, 4]));
+
+		// storage size + `StorageSizeOffset` - bytes covered by the deposit
+		let fee_per_block = (4 + 8 - 10) * RentByteFee::get();
+		initialize_block(5);
+		Rent::<Test>::collect(&ALICE);
+		let paid = 100_000 - 4 * fee_per_block;
+		assert_eq!(info().deduct_block, 5);
+		assert_eq!(Balances::free_balance(&ALICE), paid);
+
+		// Two calls in the same block.
+		Rent::<Test>::collect(&ALICE);
+		assert_eq!(info().deduct_block, 5);
+		assert_eq!(Balances::free_balance(&ALICE), paid);
+
+		// A call, a write and another call in the same block.
+		set_storage(&ALICE, &[2; 32], Some(vec![1; 100]));
+		assert_eq!(info().last_write, Some(5));
+		Rent::<Test>::collect(&ALICE);
+		assert_eq!(Balances::free_balance(&ALICE), paid);
+
+		// The same with the rent charged on write.
+		ChargeRentOnWrite::set(true);
+		set_storage(&ALICE, &[3; 32], Some(vec![1; 10]));
+		Rent::<Test>::collect(&ALICE);
+		ChargeRentOnWrite::set(false);
+		assert_eq!(info().deduct_block, 5);
+		assert_eq!(Balances::free_balance(&ALICE), paid);
+		assert_ok!(Contracts::check_deduct_blocks());
+
+		// The grown storage is charged from the next block on.
+		initialize_block(6);
+		Rent::<Test>::collect(&ALICE);
+		let fee_per_block = (4 + 100 + 10 + 8 - paid / 10_000) * RentByteFee::get();
+		assert_eq!(info().deduct_block, 6);
+		assert_eq!(Balances::free_balance(&ALICE), paid - fee_per_block);
+		assert_ok!(Contracts::check_deduct_blocks());
+	});
+}
+
+#[test]
+fn rent_free_blocks_are_not_charged_when_storage_grows() {
+	use self::test_utils::{place_contract, set_balance, set_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		place_contract(&ALICE, H256::repeat_byte(1));
+		set_balance(&ALICE, 200_000);
+
+		// The deposit covers the `StorageSizeOffset` which makes the rent free.
+		initialize_block(5);
+		Rent::<Test>::collect(&ALICE);
+		assert_eq!(info().deduct_block, 5);
+		assert_eq!(Balances::free_balance(&ALICE), 200_000);
+
+		// Once the storage outgrows the deposit the free blocks stay free.
+		set_storage(&ALICE, &[1; 32], Some(vec![1; 100]));
+		Rent::<Test>::collect(&ALICE);
+		assert_eq!(Balances::free_balance(&ALICE), 200_000);
+
+		initialize_block(6);
+		Rent::<Test>::collect(&ALICE);
+		let fee_per_block = (100 + 8 - 20) * RentByteFee::get();
+		assert_eq!(Balances::free_balance(&ALICE), 200_000 - fee_per_block);
+		assert_ok!(Contracts::check_deduct_blocks());
+	});
+}
+
+#[test]
+fn rent_is_not_charged_right_after_restoration() {
+	use self::test_utils::{place_contract, set_balance, set_storage};
+
+	ExtBuilder::default().build().execute_with(|| {
+		let code_hash = H256::repeat_byte(1);
+		place_contract(&BOB, code_hash);
+		set_balance(&BOB, 100_000);
+		set_storage(&BOB, &[1; 32], Some(vec![1, 2, 3, 4]));
+		let bob = ContractInfoOf::<Test>::get(&BOB).unwrap().get_alive().unwrap();
+		let root = frame_support::storage::child::root(&bob.child_trie_info());
+		let tombstone = crate::TombstoneContractInfo::<Test>::new(&root, code_hash, 4, 1);
+		ContractInfoOf::<Test>::insert(&ALICE, ContractInfo::Tombstone(tombstone));
+
+		initialize_block(5);
+		assert_ok!(Rent::<Test>::restore_to(BOB, ALICE, code_hash, 100, vec![]));
+		let info = || ContractInfoOf::<Test>::get(&ALICE).unwrap().get_alive().unwrap();
+		assert_eq!(info().deduct_block, 5);
+		// The restored contract reserves a tombstone deposit of its own.
+		let free = 100_000 - TombstoneDeposit::get();
+		assert_eq!(Balances::free_balance(&ALICE), free);
+		assert_eq!(Balances::reserved_balance(&ALICE), TombstoneDeposit::get());
+
+		// A call immediately after the restoration doesn't pay for any block.
+		Rent::<Test>::collect(&ALICE);
+		assert_eq!(info().rent_allowance, 100);
+		assert_eq!(Balances::free_balance(&ALICE), free);
+		assert_ok!(Contracts::check_deduct_blocks());
+
+		initialize_block(6);
+		Rent::<Test>::collect(&ALICE);
+		let fee_per_block = (4 + 8 - 10) * RentByteFee::get();
+		assert_eq!(info().rent_allowance, 100 - fee_per_block);
+		assert_eq!(Balances::free_balance(&ALICE), free - fee_per_block);
+	});
+}
+
+#[test]
+fn call_contract_removals() {
+	removals(|addr| {
+		// Call on already-removed account might fail, and this is fine.
+		let _ = Contracts::call(Origin::signed(ALICE), addr, 0, GAS_LIMIT, call::null());
+		true
+	});
+}
+
+#[test]
+fn inherent_claim_surcharge_contract_removals() {
+	removals(|addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok());
+}
+
+#[test]
+fn signed_claim_surcharge_contract_removals() {
+	removals(|addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok());
+}
+
+#[test]
+fn claim_surcharge_malus() {
+	// Test surcharge malus for inherent
+	claim_surcharge(4, |addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok(), true);
+	claim_surcharge(3, |addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok(), true);
+	claim_surcharge(2, |addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok(), true);
+	claim_surcharge(1, |addr| Contracts::claim_surcharge(Origin::none(), addr, Some(ALICE)).is_ok(), false);
+
+	// Test surcharge malus for signed
+	claim_surcharge(4, |addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok(), true);
+	claim_surcharge(3, |addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok(), false);
+	claim_surcharge(2, |addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok(), false);
+	claim_surcharge(1, |addr| Contracts::claim_surcharge(Origin::signed(ALICE), addr, None).is_ok(), false);
+}
+
+/// Claim surcharge with the given trigger_call at the given blocks.
+/// If `removes` is true then assert that the contract is a tombstone.
+fn claim_surcharge(blocks: u64, trigger_call: impl Fn(AccountIdOf<Test>) -> bool, removes: bool) {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100,
+				GAS_LIMIT, code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Advance blocks
+			initialize_block(blocks);
+
+			// Trigger rent through call
+			assert!(trigger_call(addr.clone()));
+
+			if removes {
+				assert!(ContractInfoOf::<Test>::get(&addr).unwrap().get_tombstone().is_some());
+			} else {
+				assert!(ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().is_some());
+			}
+		});
+}
+
+/// Test for all kind of removals for the given trigger:
+/// * if balance is reached and balance > subsistence threshold
+/// * if allowance is exceeded
+/// * if balance is reached and balance < subsistence threshold
+///	    * this case cannot be triggered by a contract: we check whether a tombstone is left
+fn removals(trigger_call: impl Fn(AccountIdOf<Test>) -> bool) {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+
+	// Balance reached and superior to subsistence threshold
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100,
+				GAS_LIMIT, code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			let subsistence_threshold = 50 /*existential_deposit*/ + 16 /*tombstone_deposit*/;
+
+			// Trigger rent must have no effect
+			assert!(trigger_call(addr.clone()));
+			assert_eq!(ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().rent_allowance, 1_000);
+			assert_eq!(Balances::free_balance(&addr), 100 - 16);
+			assert_eq!(Storage::<Test>::total_rent_allowance(), 1_000);
+
+			// Advance blocks
+			initialize_block(10);
+
+			// Trigger rent through call
+			assert!(trigger_call(addr.clone()));
+			assert!(ContractInfoOf::<Test>::get(&addr).unwrap().get_tombstone().is_some());
+			// The reserved tombstone deposit paid for the tombstone.
+			assert_eq!(Balances::free_balance(&addr), subsistence_threshold - 16);
+			assert_eq!(Balances::reserved_balance(&addr), 0);
+			assert_eq!(Storage::<Test>::total_rent_allowance(), 0);
+
+			// Advance blocks
+			initialize_block(20);
+
+			// Trigger rent must have no effect
+			assert!(trigger_call(addr.clone()));
+			assert!(ContractInfoOf::<Test>::get(&addr).unwrap().get_tombstone().is_some());
+			assert_eq!(Balances::free_balance(&addr), subsistence_threshold - 16);
+		});
+
+	// Allowance exceeded
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				1_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(100u32).encode(), // rent allowance
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Trigger rent must have no effect
+			assert!(trigger_call(addr.clone()));
+			assert_eq!(
+				ContractInfoOf::<Test>::get(&addr)
+					.unwrap()
+					.get_alive()
+					.unwrap()
+					.rent_allowance,
+				100
+			);
+			assert_eq!(Balances::free_balance(&addr), 1_000 - 16);
+
+			// Advance blocks
+			initialize_block(10);
+
+			// Trigger rent through call
+			assert!(trigger_call(addr.clone()));
+			assert!(ContractInfoOf::<Test>::get(&addr)
+				.unwrap()
+				.get_tombstone()
+				.is_some());
+			// Balance should be initial balance - initial rent_allowance - tombstone deposit
+			assert_eq!(Balances::free_balance(&addr), 900 - 16);
+
+			// Advance blocks
+			initialize_block(20);
+
+			// Trigger rent must have no effect
+			assert!(trigger_call(addr.clone()));
+			assert!(ContractInfoOf::<Test>::get(&addr)
+				.unwrap()
+				.get_tombstone()
+				.is_some());
+			assert_eq!(Balances::free_balance(&addr), 900 - 16);
+		});
+
+	// Balance reached and inferior to subsistence threshold
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			let subsistence_threshold =
+				Balances::minimum_balance() + <Test as Config>::TombstoneDeposit::get();
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				50 + subsistence_threshold,
+				GAS_LIMIT,
+				code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Trigger rent must have no effect
+			assert!(trigger_call(addr.clone()));
+			assert_eq!(
+				ContractInfoOf::<Test>::get(&addr)
+					.unwrap()
+					.get_alive()
+					.unwrap()
+					.rent_allowance,
+				1_000
+			);
+			assert_eq!(
+				Balances::free_balance(&addr),
+				50 + subsistence_threshold - 16,
+			);
+
+			// Transfer funds
+			assert_ok!(Contracts::call(
+				Origin::signed(ALICE),
+				addr.clone(),
+				0,
+				GAS_LIMIT,
+				call::transfer(&BOB),
+			));
+			assert_eq!(
+				ContractInfoOf::<Test>::get(&addr)
+					.unwrap()
+					.get_alive()
+					.unwrap()
+					.rent_allowance,
+				1_000
+			);
+			assert_eq!(Balances::free_balance(&addr), subsistence_threshold - 16);
+			assert_eq!(Balances::total_balance(&addr), subsistence_threshold);
+
+			// Advance blocks
+			initialize_block(10);
+
+			// Trigger rent through call
+			assert!(trigger_call(addr.clone()));
+			assert_matches!(ContractInfoOf::<Test>::get(&addr), Some(ContractInfo::Tombstone(_)));
+			assert_eq!(Balances::total_balance(&addr), subsistence_threshold - 16);
+
+			// Advance blocks
+			initialize_block(20);
+
+			// Trigger rent must have no effect
+			assert!(trigger_call(addr.clone()));
+			assert_matches!(ContractInfoOf::<Test>::get(&addr), Some(ContractInfo::Tombstone(_)));
+			assert_eq!(Balances::total_balance(&addr), subsistence_threshold - 16);
+		});
+}
+
+#[test]
+fn call_removed_contract() {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+
+	// Balance reached and superior to subsistence threshold
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100,
+				GAS_LIMIT, code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Calling contract should succeed.
+			assert_ok!(
+				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
+			);
+
+			// Advance blocks
+			initialize_block(10);
+
+			// Calling contract should remove contract and fail.
+			assert_err_ignore_postinfo!(
+				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null()),
+				Error::<Test>::NotCallable
+			);
+			// Calling a contract that is about to evict shall emit an event.
+			assert_eq!(System::events(), vec![
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::contracts(RawEvent::Evicted(addr.clone(), true)),
+					topics: vec![],
+				},
+			]);
+
+			// Subsequent contract calls should also fail.
+			assert_err_ignore_postinfo!(
+				Contracts::call(Origin::signed(ALICE), addr, 0, GAS_LIMIT, call::null()),
+				Error::<Test>::NotCallable
+			);
+		})
+}
+
+#[test]
+fn drained_contract_is_evicted_with_tombstone() {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				30_000,
+				GAS_LIMIT, code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(), // rent allowance
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+			assert_eq!(Balances::reserved_balance(&addr), TombstoneDeposit::get());
+			assert_eq!(crate::TombstoneDeposits::<Test>::get(&addr), TombstoneDeposit::get());
+
+			// The free balance is drained below the subsistence threshold. Only the reserved
+			// deposit is left to pay for the tombstone.
+			Balances::make_free_balance_be(&addr, 40);
+			assert!(
+				Balances::total_balance(&addr) <
+					ConfigCache::<Test>::subsistence_threshold_uncached()
+			);
+			// What the contract reserved and its idempotency keys don't survive the eviction.
+			assert_ok!(Balances::reserve(&addr, 10));
+			let trie_id = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().trie_id;
+			let write = Storage::<Test>::write_idempotent;
+			assert_matches!(
+				write(&addr, &trie_id, &[1; 32], Some(vec![1]), [1; 32]),
+				Ok(storage::IdempotentWrite::Applied(_))
+			);
+
+			initialize_block(5);
+			assert_err_ignore_postinfo!(
+				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null()),
+				Error::<Test>::NotCallable
+			);
+			assert_matches!(ContractInfoOf::<Test>::get(&addr), Some(ContractInfo::Tombstone(_)));
+			assert!(System::events().iter().any(|record| {
+				record.event == MetaEvent::contracts(RawEvent::Evicted(addr.clone(), true))
+			}));
+			assert!(!crate::TombstoneDeposits::<Test>::contains_key(&addr));
+			assert_eq!(Balances::reserved_balance(&addr), 0);
+			assert!(!crate::IdempotencyKeys::<Test>::contains_key(&addr));
+			assert!(!crate::TrieOwner::<Test>::contains_key(&trie_id));
+			assert_ok!(Contracts::check_tombstone_index());
+		});
+}
+
+#[test]
+fn default_rent_allowance_on_instantiate() {
+	let (wasm, code_hash) = compile_module::<Test>("check_default_rent_allowance").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				30_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Check creation
+			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+			assert_eq!(bob_contract.rent_allowance, <BalanceOf<Test>>::max_value());
+
+			// Advance blocks
+			initialize_block(5);
+
+			// Trigger rent through call
+			assert_ok!(
+				Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, call::null())
+			);
+
+			// Check contract is still alive
+			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive();
+			assert!(bob_contract.is_some())
+		});
+}
+
+#[test]
+fn top_up_rent_allowance_works() {
+	let (wasm, code_hash) = compile_module::<Test>("top_up_rent").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence + 100,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+			Storage::<Test>::set_rent_allowance(&addr, 10).unwrap();
+			let top_up = |amount: u64| Contracts::bare_call(
+				ALICE,
+				addr.clone(),
+				0,
+				GAS_LIMIT,
+				amount.encode(),
+			).exec_result.unwrap();
+
+			// The free balance covers the amount on top of the subsistence threshold.
+			assert_return_code!(top_up(100), RuntimeReturnCode::Success);
+			assert_eq!(Storage::<Test>::rent_allowance(&addr), Ok(110));
+
+			// Any amount that isn't covered is rejected and leaves the allowance untouched.
+			assert_return_code!(top_up(101), RuntimeReturnCode::BelowSubsistenceThreshold);
+			assert_eq!(Storage::<Test>::rent_allowance(&addr), Ok(110));
+			assert_eq!(
+				Storage::<Test>::top_up_rent(&addr, 101),
+				Err(crate::storage::TopUpRentError::BelowSubsistenceThreshold),
+			);
+
+			// The allowance saturates.
+			Storage::<Test>::set_rent_allowance(&addr, u64::max_value() - 1).unwrap();
+			assert_eq!(Storage::<Test>::top_up_rent(&addr, 100), Ok(u64::max_value()));
+
+			assert_eq!(
+				Storage::<Test>::top_up_rent(&BOB, 1),
+				Err(crate::storage::TopUpRentError::ContractAbsent),
+			);
+		});
+}
+
+#[test]
+fn contracts_can_reserve_balance() {
+	let (wasm, code_hash) = compile_module::<Test>("reserve").unwrap();
+
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			subsistence + 100,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		let call = |op: u8, amount: u64| {
+			let input = op.encode().into_iter().chain(amount.encode()).collect();
+			Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, input).exec_result.unwrap()
+		};
+
+		// The tombstone deposit is reserved at instantiation and covers its part of the
+		// subsistence threshold.
+		let deposit = TombstoneDeposit::get();
+		assert_eq!(Balances::reserved_balance(&addr), deposit);
+
+		// The free balance covers the amount on top of the subsistence threshold.
+		assert_return_code!(call(0, 100), RuntimeReturnCode::Success);
+		assert_eq!(Balances::reserved_balance(&addr), deposit + 100);
+		assert_eq!(Balances::free_balance(&addr), subsistence - deposit);
+		assert_return_code!(call(0, 1), RuntimeReturnCode::BelowSubsistenceThreshold);
+		assert_eq!(Balances::reserved_balance(&addr), deposit + 100);
+
+		// Only what is reserved can be unreserved. The tombstone deposit isn't part of it.
+		assert_return_code!(call(1, 101), RuntimeReturnCode::InsufficientReserve);
+		assert_return_code!(call(1, 40), RuntimeReturnCode::Success);
+		assert_eq!(Balances::reserved_balance(&addr), deposit + 60);
+		assert_eq!(Balances::free_balance(&addr), subsistence - deposit + 40);
+		assert_return_code!(call(1, 61), RuntimeReturnCode::InsufficientReserve);
+		assert_eq!(Balances::reserved_balance(&addr), deposit + 60);
+
+		// Terminating releases the reserved balance to the beneficiary.
+		let beneficiary_balance = Balances::free_balance(&ALICE);
+		assert!(call(2, 0).is_success());
+		assert!(ContractInfoOf::<Test>::get(&addr).is_none());
+		assert_eq!(Balances::total_balance(&addr), 0);
+		assert_eq!(Balances::free_balance(&ALICE), beneficiary_balance + subsistence + 100);
+	});
+}
+
+#[test]
+fn tombstone_info_allows_restoration() {
+	let (set_rent_wasm, set_rent_code_hash) = compile_module::<Test>("set_rent").unwrap();
+	let (restoration_wasm, restoration_code_hash) = compile_module::<Test>("restoration").unwrap();
+
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		let _ = Balances::deposit_creating(&CHARLIE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), restoration_wasm));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), set_rent_wasm));
+
+		// A contract with a rent allowance of 0 is evicted when it is called later.
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			set_rent_code_hash.into(),
+			<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
+			vec![],
+		));
+		let addr_bob = Contracts::contract_address(&ALICE, &set_rent_code_hash, &[]);
+		let storage_size = ContractInfoOf::<Test>::get(&addr_bob).unwrap()
+			.get_alive().unwrap().storage_size;
+		assert_eq!(Contracts::tombstone_info(addr_bob.clone()), None);
+
+		initialize_block(5);
+		assert_err_ignore_postinfo!(
+			Contracts::call(Origin::signed(ALICE), addr_bob.clone(), 0, GAS_LIMIT, call::null()),
+			Error::<Test>::NotCallable
+		);
+		let info = Contracts::tombstone_info(addr_bob.clone()).unwrap();
+		assert_eq!(info.code_hash, Some(set_rent_code_hash));
+		assert_eq!(info.storage_size, Some(storage_size));
+		assert_eq!(
+			Contracts::list_tombstones(None, 10),
+			vec![pallet_contracts_primitives::TombstoneListing {
+				address: addr_bob.clone(),
+				evicted_at: Some(5),
+				code_hash: Some(set_rent_code_hash),
+				deposit: Balances::total_balance(&addr_bob),
+			}],
+		);
+		assert_ok!(Contracts::check_tombstone_index());
+
+		// Restore the contract using only what is recorded in its tombstone.
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(CHARLIE),
+			30_000,
+			GAS_LIMIT,
+			restoration_code_hash.into(),
+			<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
+			vec![],
+		));
+		let addr_django = Contracts::contract_address(&CHARLIE, &restoration_code_hash, &[]);
+		initialize_block(6);
+		assert_ok!(Contracts::call(
+			Origin::signed(ALICE),
+			addr_django.clone(),
+			0,
+			GAS_LIMIT,
+			info.code_hash
+				.unwrap()
+				.as_ref()
+				.iter()
+				.chain(AsRef::<[u8]>::as_ref(&addr_bob))
+				.cloned()
+				.collect(),
+		));
+		let bob = ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_alive().unwrap();
+		assert_eq!(bob.code_hash, set_rent_code_hash);
+		assert_eq!(Contracts::tombstone_info(addr_bob), None);
+		assert_eq!(Contracts::list_tombstones(None, 10), vec![]);
+		assert_ok!(Contracts::check_tombstone_index());
+	});
+}
+
+#[test]
+fn restoration_moves_the_reserved_balance_of_the_origin() {
+	let (set_rent_wasm, set_rent_code_hash) = compile_module::<Test>("set_rent").unwrap();
+	let (restoration_wasm, restoration_code_hash) = compile_module::<Test>("restoration").unwrap();
+
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		let _ = Balances::deposit_creating(&CHARLIE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), restoration_wasm));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), set_rent_wasm));
+
+		// A contract with a rent allowance of 0 is evicted when it is called later.
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			set_rent_code_hash.into(),
+			<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
+			vec![],
+		));
+		let addr_bob = Contracts::contract_address(&ALICE, &set_rent_code_hash, &[]);
+		initialize_block(5);
+		assert_err_ignore_postinfo!(
+			Contracts::call(Origin::signed(ALICE), addr_bob.clone(), 0, GAS_LIMIT, call::null()),
+			Error::<Test>::NotCallable
+		);
+
+		// The restoring contract reserved some of its balance like `seal_reserve` does.
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(CHARLIE),
+			30_000,
+			GAS_LIMIT,
+			restoration_code_hash.into(),
+			<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
+			vec![],
+		));
+		let addr_django = Contracts::contract_address(&CHARLIE, &restoration_code_hash, &[]);
+		assert_ok!(Balances::reserve(&addr_django, 100));
+		assert_eq!(Storage::<Test>::free_reserve(&addr_django), 100);
+
+		initialize_block(6);
+		assert_ok!(Contracts::call(
+			Origin::signed(ALICE),
+			addr_django.clone(),
+			0,
+			GAS_LIMIT,
+			set_rent_code_hash
+				.as_ref()
+				.iter()
+				.chain(AsRef::<[u8]>::as_ref(&addr_bob))
+				.cloned()
+				.collect(),
+		));
+
+		// Nothing is left behind on the removed origin.
+		assert!(ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_alive().is_some());
+		assert_eq!(Balances::total_balance(&addr_django), 0);
+		assert_eq!(Storage::<Test>::free_reserve(&addr_bob), 0);
+	});
+}
+
+#[test]
+fn restoration_waits_one_block_after_eviction() {
+	let (set_rent_wasm, set_rent_code_hash) = compile_module::<Test>("set_rent").unwrap();
+	let (restoration_wasm, restoration_code_hash) = compile_module::<Test>("restoration").unwrap();
+
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		let _ = Balances::deposit_creating(&CHARLIE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), restoration_wasm));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), set_rent_wasm));
+
+		// A contract with a rent allowance of 0 is evicted when it is called later.
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			set_rent_code_hash.into(),
+			<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
+			vec![],
+		));
+		let addr_bob = Contracts::contract_address(&ALICE, &set_rent_code_hash, &[]);
+
+		// The restoring contract must not write its storage in the block of the restoration.
+		initialize_block(4);
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(CHARLIE),
+			30_000,
+			GAS_LIMIT,
+			restoration_code_hash.into(),
+			<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
+			vec![],
+		));
+		let addr_django = Contracts::contract_address(&CHARLIE, &restoration_code_hash, &[]);
+		let restore = || Contracts::call(
+			Origin::signed(ALICE),
+			addr_django.clone(),
+			0,
+			GAS_LIMIT,
+			set_rent_code_hash
+				.as_ref()
+				.iter()
+				.chain(AsRef::<[u8]>::as_ref(&addr_bob))
+				.cloned()
+				.collect(),
+		);
+
+		initialize_block(5);
+		assert_err_ignore_postinfo!(
+			Contracts::call(Origin::signed(ALICE), addr_bob.clone(), 0, GAS_LIMIT, call::null()),
+			Error::<Test>::NotCallable
+		);
+		let tombstone = ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_tombstone().unwrap();
+		assert_eq!(tombstone.evicted_at, Some(5));
+
+		// Restoring in the block of the eviction is rejected and leaves both contracts alone.
+		assert_err_ignore_postinfo!(restore(), Error::<Test>::TombstoneTooRecent);
+		assert!(ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_tombstone().is_some());
+		assert!(ContractInfoOf::<Test>::get(&addr_django).unwrap().get_alive().is_some());
+
+		initialize_block(6);
+		assert_ok!(restore());
+		assert!(ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_alive().is_some());
+		assert!(ContractInfoOf::<Test>::get(&addr_django).is_none());
+	});
+}
+
+#[test]
+fn restorations_dirty_storage_and_different_storage() {
+	restoration(true, true);
+}
+
+#[test]
+fn restorations_dirty_storage() {
+	restoration(false, true);
+}
+
+#[test]
+fn restoration_different_storage() {
+	restoration(true, false);
+}
+
+#[test]
+fn restoration_success() {
+	restoration(false, false);
+}
+
+fn restoration(test_different_storage: bool, test_restore_to_with_dirty_storage: bool) {
+	let (set_rent_wasm, set_rent_code_hash) = compile_module::<Test>("set_rent").unwrap();
+	let (restoration_wasm, restoration_code_hash) = compile_module::<Test>("restoration").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), restoration_wasm));
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), set_rent_wasm));
+
+			// If you ever need to update the wasm source this test will fail
+			// and will show you the actual hash.
+			assert_eq!(System::events(), vec![
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::system(frame_system::RawEvent::NewAccount(ALICE)),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::balances(pallet_balances::RawEvent::Endowed(ALICE, 1_000_000)),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::contracts(RawEvent::CodeStored(restoration_code_hash.into(), ALICE)),
+					topics: vec![],
+				},
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::contracts(RawEvent::CodeStored(set_rent_code_hash.into(), ALICE)),
+					topics: vec![],
+				},
+			]);
+
+			// Create an account with address `BOB` with code `CODE_SET_RENT`.
+			// The input parameter sets the rent allowance to 0.
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				30_000,
+				GAS_LIMIT,
+				set_rent_code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
+				vec![],
+			));
+			let addr_bob = Contracts::contract_address(&ALICE, &set_rent_code_hash, &[]);
+
+			// Check if `BOB` was created successfully and that the rent allowance is
+			// set to 0.
+			let bob_contract = ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_alive().unwrap();
+			assert_eq!(bob_contract.rent_allowance, 0);
+
+			if test_different_storage {
+				assert_ok!(Contracts::call(
+					Origin::signed(ALICE),
+					addr_bob.clone(), 0, GAS_LIMIT,
+					call::set_storage_4_byte())
+				);
+			}
+
+			// Advance 4 blocks, to the 5th.
+			initialize_block(5);
+
+			// Call `BOB`, which makes it pay rent. Since the rent allowance is set to 0
+			// we expect that it will get removed leaving tombstone.
+			assert_err_ignore_postinfo!(
+				Contracts::call(
+					Origin::signed(ALICE), addr_bob.clone(), 0, GAS_LIMIT, call::null()
+				),
+				Error::<Test>::NotCallable
+			);
+			assert!(ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_tombstone().is_some());
+			assert_eq!(System::events(), vec![
+				EventRecord {
+					phase: Phase::Initialization,
+					event: MetaEvent::contracts(
+						RawEvent::Evicted(addr_bob.clone(), true)
+					),
+					topics: vec![],
+				},
+			]);
+
+			// Create another account with the address `DJANGO` with `CODE_RESTORATION`.
+			//
+			// Note that we can't use `ALICE` for creating `DJANGO` so we create yet another
+			// account `CHARLIE` and create `DJANGO` with it.
+			let _ = Balances::deposit_creating(&CHARLIE, 1_000_000);
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(CHARLIE),
+				30_000,
+				GAS_LIMIT,
+				restoration_code_hash.into(),
+				<Test as pallet_balances::Config>::Balance::from(0u32).encode(),
+				vec![],
+			));
+			let addr_django = Contracts::contract_address(&CHARLIE, &restoration_code_hash, &[]);
+
+			// Before performing a call to `DJANGO` save its original trie id.
+			let django_trie_id = ContractInfoOf::<Test>::get(&addr_django).unwrap()
+				.get_alive().unwrap().trie_id;
+
+			// The trie is regarded as 'dirty' when it was written to in the current block.
+			if !test_restore_to_with_dirty_storage {
+				// Advance 1 block, to the 6th.
+				initialize_block(6);
+			}
+
+			// Perform a call to `DJANGO`. This should either perform restoration successfully or
+			// fail depending on the test parameters.
+			let perform_the_restoration = || {
+				Contracts::call(
+					Origin::signed(ALICE),
+					addr_django.clone(),
+					0,
+					GAS_LIMIT,
+					set_rent_code_hash
+						.as_ref()
+						.iter()
+						.chain(AsRef::<[u8]>::as_ref(&addr_bob))
+						.cloned()
+						.collect(),
+				)
+			};
+
+			if test_different_storage || test_restore_to_with_dirty_storage {
+				// Parametrization of the test imply restoration failure. Check that `DJANGO` aka
+				// restoration contract is still in place and also that `BOB` doesn't exist.
 				let result = perform_the_restoration();
 				assert!(ContractInfoOf::<Test>::get(&addr_bob).unwrap().get_tombstone().is_some());
 				let django_contract = ContractInfoOf::<Test>::get(&addr_django).unwrap()
@@ -1307,6 +4037,13 @@ fn restoration(test_different_storage: bool, test_restore_to_with_dirty_storage:
 								),
 								topics: vec![],
 							},
+							EventRecord {
+								phase: Phase::Initialization,
+								event: MetaEvent::balances(
+									pallet_balances::RawEvent::Reserved(addr_django.clone(), 16)
+								),
+								topics: vec![],
+							},
 							EventRecord {
 								phase: Phase::Initialization,
 								event: MetaEvent::contracts(RawEvent::Instantiated(CHARLIE, addr_django.clone())),
@@ -1319,394 +4056,1551 @@ fn restoration(test_different_storage: bool, test_restore_to_with_dirty_storage:
 			} else {
 				assert_ok!(perform_the_restoration());
 
-				// Here we expect that the restoration is succeeded. Check that the restoration
-				// contract `DJANGO` ceased to exist and that `BOB` returned back.
-				println!("{:?}", ContractInfoOf::<Test>::get(&addr_bob));
-				let bob_contract = ContractInfoOf::<Test>::get(&addr_bob).unwrap()
-					.get_alive().unwrap();
-				assert_eq!(bob_contract.rent_allowance, 50);
-				assert_eq!(bob_contract.storage_size, 4);
-				assert_eq!(bob_contract.trie_id, django_trie_id);
-				assert_eq!(bob_contract.deduct_block, System::block_number());
-				assert!(ContractInfoOf::<Test>::get(&addr_django).is_none());
-				assert_eq!(System::events(), vec![
-					EventRecord {
-						phase: Phase::Initialization,
-						event: MetaEvent::system(system::RawEvent::KilledAccount(addr_django.clone())),
-						topics: vec![],
-					},
-					EventRecord {
-						phase: Phase::Initialization,
-						event: MetaEvent::contracts(
-							RawEvent::Restored(addr_django, addr_bob, bob_contract.code_hash, 50)
-						),
-						topics: vec![],
-					},
-				]);
-			}
+				// Here we expect that the restoration is succeeded. Check that the restoration
+				// contract `DJANGO` ceased to exist and that `BOB` returned back.
+				println!("{:?}", ContractInfoOf::<Test>::get(&addr_bob));
+				let bob_contract = ContractInfoOf::<Test>::get(&addr_bob).unwrap()
+					.get_alive().unwrap();
+				assert_eq!(bob_contract.rent_allowance, 50);
+				assert_eq!(bob_contract.storage_size, 4);
+				assert_eq!(bob_contract.trie_id, django_trie_id);
+				assert_eq!(bob_contract.deduct_block, System::block_number());
+				assert!(ContractInfoOf::<Test>::get(&addr_django).is_none());
+				assert_eq!(System::events(), vec![
+					EventRecord {
+						phase: Phase::Initialization,
+						event: MetaEvent::balances(
+							pallet_balances::RawEvent::Unreserved(addr_django.clone(), 16)
+						),
+						topics: vec![],
+					},
+					EventRecord {
+						phase: Phase::Initialization,
+						event: MetaEvent::system(system::RawEvent::KilledAccount(addr_django.clone())),
+						topics: vec![],
+					},
+					EventRecord {
+						phase: Phase::Initialization,
+						event: MetaEvent::balances(
+							pallet_balances::RawEvent::Reserved(addr_bob.clone(), 16)
+						),
+						topics: vec![],
+					},
+					EventRecord {
+						phase: Phase::Initialization,
+						event: MetaEvent::contracts(
+							RawEvent::Restored(addr_django, addr_bob, bob_contract.code_hash, 50)
+						),
+						topics: vec![],
+					},
+				]);
+			}
+		});
+}
+
+#[test]
+fn storage_max_value_limit() {
+	let (wasm, code_hash) = compile_module::<Test>("storage_size").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				30_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Check creation
+			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+			assert_eq!(bob_contract.rent_allowance, <BalanceOf<Test>>::max_value());
+
+			// Call contract with allowed storage value.
+			assert_ok!(Contracts::call(
+				Origin::signed(ALICE),
+				addr.clone(),
+				0,
+				GAS_LIMIT * 2, // we are copying a huge buffer
+				<Test as Config>::MaxValueSize::get().encode(),
+			));
+
+			// Call contract with too large a storage value.
+			assert_err_ignore_postinfo!(
+				Contracts::call(
+					Origin::signed(ALICE),
+					addr,
+					0,
+					GAS_LIMIT,
+					(<Test as Config>::MaxValueSize::get() + 1).encode(),
+				),
+				Error::<Test>::ValueTooLarge,
+			);
+		});
+}
+
+#[test]
+fn deploy_and_call_other_contract() {
+	let (callee_wasm, callee_code_hash) = compile_module::<Test>("return_with_data").unwrap();
+	let (caller_wasm, caller_code_hash) = compile_module::<Test>("caller_contract").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_wasm));
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_wasm));
+
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100_000,
+				GAS_LIMIT,
+				caller_code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &caller_code_hash, &[]);
+
+			// Call BOB contract, which attempts to instantiate and call the callee contract and
+			// makes various assertions on the results from those calls.
+			assert_ok!(Contracts::call(
+				Origin::signed(ALICE),
+				addr,
+				0,
+				GAS_LIMIT,
+				callee_code_hash.as_ref().to_vec(),
+			));
+		});
+}
+
+#[test]
+fn cannot_self_destruct_through_draning() {
+	let (wasm, code_hash) = compile_module::<Test>("drain").unwrap();
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+			// Instantiate the BOB contract.
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Check that the BOB contract has been instantiated.
+			assert_matches!(
+				ContractInfoOf::<Test>::get(&addr),
+				Some(ContractInfo::Alive(_))
+			);
+
+			// Call BOB which makes it send all funds to the zero address
+			// The contract code asserts that the correct error value is returned.
+			assert_ok!(
+				Contracts::call(
+					Origin::signed(ALICE),
+					addr,
+					0,
+					GAS_LIMIT,
+					vec![],
+				)
+			);
+		});
+}
+
+#[test]
+fn cannot_self_destruct_while_live() {
+	let (wasm, code_hash) = compile_module::<Test>("self_destruct").unwrap();
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+			// Instantiate the BOB contract.
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Check that the BOB contract has been instantiated.
+			assert_matches!(
+				ContractInfoOf::<Test>::get(&addr),
+				Some(ContractInfo::Alive(_))
+			);
+
+			// Call BOB with input data, forcing it make a recursive call to itself to
+			// self-destruct, resulting in a trap.
+			assert_err_ignore_postinfo!(
+				Contracts::call(
+					Origin::signed(ALICE),
+					addr.clone(),
+					0,
+					GAS_LIMIT,
+					vec![0],
+				),
+				Error::<Test>::ContractTrapped,
+			);
+
+			// Check that BOB is still alive.
+			assert_matches!(
+				ContractInfoOf::<Test>::get(&addr),
+				Some(ContractInfo::Alive(_))
+			);
+		});
+}
+
+#[test]
+fn self_destruct_works() {
+	let (wasm, code_hash) = compile_module::<Test>("self_destruct").unwrap();
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+			// Instantiate the BOB contract.
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+			// Check that the BOB contract has been instantiated.
+			assert_matches!(
+				ContractInfoOf::<Test>::get(&addr),
+				Some(ContractInfo::Alive(_))
+			);
+
+			// Call BOB without input data which triggers termination.
+			assert_matches!(
+				Contracts::call(
+					Origin::signed(ALICE),
+					addr.clone(),
+					0,
+					GAS_LIMIT,
+					vec![],
+				),
+				Ok(_)
+			);
+
+			// Check that account is gone
+			assert!(ContractInfoOf::<Test>::get(&addr).is_none());
+
+			// check that the beneficiary (django) got remaining balance
+			assert_eq!(Balances::free_balance(DJANGO), 100_000);
+		});
+}
+
+#[test]
+fn terminate_is_charged_per_stored_key() {
+	use self::test_utils::set_storage;
+	let (wasm, code_hash) = compile_module::<Test>("self_destruct").unwrap();
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+			let instantiate = |salt: Vec<u8>| {
+				assert_ok!(Contracts::instantiate(
+					Origin::signed(ALICE),
+					100_000,
+					GAS_LIMIT,
+					code_hash.into(),
+					vec![],
+					salt.clone(),
+				));
+				Contracts::contract_address(&ALICE, &code_hash, &salt)
+			};
+			let empty = instantiate(vec![0]);
+			let full = instantiate(vec![1]);
+			for i in 0..100u8 {
+				set_storage(&full, &[i; 32], Some(vec![i]));
+			}
+
+			// The dry run reports the gas that is charged for the actual pair count.
+			let terminate = |addr: AccountIdOf<Test>| {
+				let result = Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, vec![]);
+				assert!(result.exec_result.unwrap().is_success());
+				assert!(ContractInfoOf::<Test>::get(&addr).is_none());
+				result.gas_consumed
+			};
+			let empty_gas = terminate(empty);
+			let full_gas = terminate(full);
+
+			let per_key = Contracts::current_schedule().host_fn_weights.terminate_per_key;
+			assert!(per_key > 0);
+			assert!(
+				full_gas >= empty_gas + 100 * per_key,
+				"full: {}, empty: {}, per key: {}", full_gas, empty_gas, per_key,
+			);
+		});
+}
+
+// This tests that one contract cannot prevent another from self-destructing by sending it
+// additional funds after it has been drained.
+#[test]
+fn destroy_contract_and_transfer_funds() {
+	let (callee_wasm, callee_code_hash) = compile_module::<Test>("self_destruct").unwrap();
+	let (caller_wasm, caller_code_hash) = compile_module::<Test>("destroy_and_transfer").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			// Create
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_wasm));
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_wasm));
+
+			// This deploys the BOB contract, which in turn deploys the CHARLIE contract during
+			// construction.
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				200_000,
+				GAS_LIMIT,
+				caller_code_hash.into(),
+				callee_code_hash.as_ref().to_vec(),
+				vec![],
+			));
+			let addr_bob = Contracts::contract_address(&ALICE, &caller_code_hash, &[]);
+			let addr_charlie = Contracts::contract_address(
+				&addr_bob, &callee_code_hash, &[0x47, 0x11]
+			);
+
+			// Check that the CHARLIE contract has been instantiated.
+			assert_matches!(
+				ContractInfoOf::<Test>::get(&addr_charlie),
+				Some(ContractInfo::Alive(_))
+			);
+
+			// Call BOB, which calls CHARLIE, forcing CHARLIE to self-destruct.
+			assert_ok!(Contracts::call(
+				Origin::signed(ALICE),
+				addr_bob,
+				0,
+				GAS_LIMIT,
+				addr_charlie.encode(),
+			));
+
+			// Check that CHARLIE has moved on to the great beyond (ie. died).
+			assert!(ContractInfoOf::<Test>::get(&addr_charlie).is_none());
+		});
+}
+
+#[test]
+fn cannot_self_destruct_in_constructor() {
+	let (wasm, code_hash) = compile_module::<Test>("self_destructing_constructor").unwrap();
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+			// Fail to instantiate the BOB because the contructor calls seal_terminate.
+			assert_err_ignore_postinfo!(
+				Contracts::instantiate(
+					Origin::signed(ALICE),
+					100_000,
+					GAS_LIMIT,
+					code_hash.into(),
+					vec![],
+					vec![],
+				),
+				Error::<Test>::NewContractNotFunded,
+			);
+
+			// The failed instantiation is rolled back completely. No half created
+			// contract is left behind.
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+			assert!(ContractInfoOf::<Test>::get(&addr).is_none());
+			assert_eq!(Balances::total_balance(&addr), 0);
+			assert_eq!(Balances::free_balance(&ALICE), 1_000_000);
+		});
+}
+
+#[test]
+fn crypto_hashes() {
+	let (wasm, code_hash) = compile_module::<Test>("crypto_hashes").unwrap();
+
+	ExtBuilder::default()
+		.existential_deposit(50)
+		.build()
+		.execute_with(|| {
+			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+			// Instantiate the CRYPTO_HASHES contract.
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				100_000,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+			// Perform the call.
+			let input = b"_DEAD_BEEF";
+			use sp_io::hashing::*;
+			// Wraps a hash function into a more dynamic form usable for testing.
+			macro_rules! dyn_hash_fn {
+				($name:ident) => {
+					Box::new(|input| $name(input).as_ref().to_vec().into_boxed_slice())
+				};
+			}
+			// All hash functions and their associated output byte lengths.
+			let test_cases: &[(Box<dyn Fn(&[u8]) -> Box<[u8]>>, usize)] = &[
+				(dyn_hash_fn!(sha2_256), 32),
+				(dyn_hash_fn!(keccak_256), 32),
+				(dyn_hash_fn!(blake2_256), 32),
+				(dyn_hash_fn!(blake2_128), 16),
+			];
+			// Test the given hash functions for the input: "_DEAD_BEEF"
+			for (n, (hash_fn, expected_size)) in test_cases.iter().enumerate() {
+				// We offset data in the contract tables by 1.
+				let mut params = vec![(n + 1) as u8];
+				params.extend_from_slice(input);
+				let result = <Module<Test>>::bare_call(
+					ALICE,
+					addr.clone(),
+					0,
+					GAS_LIMIT,
+					params,
+				).exec_result.unwrap();
+				assert!(result.is_success());
+				let expected = hash_fn(input.as_ref());
+				assert_eq!(&result.data[..*expected_size], &*expected);
+			}
+		})
+}
+
+#[test]
+fn transfer_return_code() {
+	let (wasm, code_hash) = compile_module::<Test>("transfer_return_code").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+
+		assert_ok!(
+			Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			),
+		);
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+		// Contract has only the minimal balance so any transfer will return BelowSubsistence.
+		let result = Contracts::bare_call(
+			ALICE,
+			addr.clone(),
+			0,
+			GAS_LIMIT,
+			vec![],
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::BelowSubsistenceThreshold);
+
+		// Contract has enough total balance in order to not go below the subsistence
+		// threshold when transfering 100 balance but this balance is reserved so
+		// the transfer still fails but with another return code.
+		Balances::make_free_balance_be(&addr, subsistence + 100);
+		Balances::reserve(&addr, subsistence + 100).unwrap();
+		let result = Contracts::bare_call(
+			ALICE,
+			addr,
+			0,
+			GAS_LIMIT,
+			vec![],
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::TransferFailed);
+	});
+}
+
+#[test]
+fn call_return_code() {
+	let (caller_code, caller_hash) = compile_module::<Test>("call_return_code").unwrap();
+	let (callee_code, callee_hash) = compile_module::<Test>("ok_trap_revert").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		let _ = Balances::deposit_creating(&CHARLIE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_code));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_code));
+
+		assert_ok!(
+			Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence,
+				GAS_LIMIT,
+				caller_hash.into(),
+				vec![0],
+				vec![],
+			),
+		);
+		let addr_bob = Contracts::contract_address(&ALICE, &caller_hash, &[]);
+
+		// Contract calls into Django which is no valid contract. Neither call transfers
+		// the value regardless of the input data.
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob.clone(),
+			0,
+			GAS_LIMIT,
+			AsRef::<[u8]>::as_ref(&DJANGO).to_vec(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::NotCallable);
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob.clone(),
+			0,
+			GAS_LIMIT,
+			AsRef::<[u8]>::as_ref(&DJANGO).iter().chain(&0u32.to_le_bytes()).cloned().collect(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::NotCallable);
+		assert_eq!(Balances::total_balance(&DJANGO), 0);
+
+		assert_ok!(
+			Contracts::instantiate(
+				Origin::signed(CHARLIE),
+				subsistence,
+				GAS_LIMIT,
+				callee_hash.into(),
+				vec![0],
+				vec![],
+			),
+		);
+		let addr_django = Contracts::contract_address(&CHARLIE, &callee_hash, &[]);
+
+		// Contract has only the minimal balance so any transfer will return BelowSubsistence.
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob.clone(),
+			0,
+			GAS_LIMIT,
+			AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&0u32.to_le_bytes()).cloned().collect(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::BelowSubsistenceThreshold);
+
+		// Contract has enough total balance in order to not go below the subsistence
+		// threshold when transfering 100 balance but this balance is reserved so
+		// the transfer still fails but with another return code.
+		Balances::make_free_balance_be(&addr_bob, subsistence + 100);
+		Balances::reserve(&addr_bob, subsistence + 100).unwrap();
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob.clone(),
+			0,
+			GAS_LIMIT,
+			AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&0u32.to_le_bytes()).cloned().collect(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::TransferFailed);
+
+		// Contract has enough balance but callee reverts because "1" is passed.
+		Balances::make_free_balance_be(&addr_bob, subsistence + 1000);
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob.clone(),
+			0,
+			GAS_LIMIT,
+			AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&1u32.to_le_bytes()).cloned().collect(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::CalleeReverted);
+
+		// Contract has enough balance but callee traps because "2" is passed.
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob,
+			0,
+			GAS_LIMIT,
+			AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&2u32.to_le_bytes()).cloned().collect(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::CalleeTrapped);
+
+	});
+}
+
+#[test]
+fn storage_writes_are_summarized_per_call() {
+	let (wasm, code_hash) = compile_module::<Test>("storage_batch").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		StorageBatchEvents::set(true);
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+		// A reverted call leaves neither writes nor a summary behind.
+		System::reset_events();
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![1]));
+		assert_eq!(System::events(), vec![]);
+		assert_eq!(
+			ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().total_pair_count,
+			0,
+		);
+
+		// Four writes result in exactly one event with the totals of all of them.
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![0]));
+		assert_eq!(System::events(), vec![
+			EventRecord {
+				phase: Phase::Initialization,
+				event: MetaEvent::contracts(RawEvent::ContractStorageBatch(addr.clone(), 3, 2, 4)),
+				topics: vec![],
+			},
+		]);
+		let info = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+		assert_eq!(info.storage_size, 3);
+		assert_eq!(info.total_pair_count, 2);
+
+		// The weight of the event is charged and neither happens once it is disabled.
+		let call = || Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, vec![0]);
+		let enabled = call().gas_consumed;
+		StorageBatchEvents::set(false);
+		System::reset_events();
+		let disabled = call().gas_consumed;
+		assert_eq!(System::events(), vec![]);
+		assert!(disabled < enabled);
+	});
+}
+
+/// Instantiates two `storage_changes` contracts and returns the `StorageChanged` events that
+/// calling the first one with `input` deposits together with the gas it consumed.
+fn storage_changed_events(input: impl FnOnce(&AccountIdOf<Test>) -> Vec<u8>)
+	-> (AccountIdOf<Test>, Vec<RawEvent<u64, AccountIdOf<Test>, H256>>, Gas)
+{
+	let (wasm, code_hash) = compile_module::<Test>("storage_changes").unwrap();
+	let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+	let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+	assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+	let instantiate = |salt: u8| {
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			subsistence,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![salt],
+		));
+		Contracts::contract_address(&ALICE, &code_hash, &[salt])
+	};
+	let (first, second) = (instantiate(1), instantiate(2));
+
+	System::reset_events();
+	let result = Contracts::bare_call(ALICE, first.clone(), 0, GAS_LIMIT, input(&second));
+	assert_ok!(result.exec_result);
+	let events = System::events()
+		.into_iter()
+		.filter_map(|record| match record.event {
+			MetaEvent::contracts(event @ RawEvent::StorageChanged(..)) => Some(event),
+			_ => None,
+		})
+		.collect();
+	(first, events, result.gas_consumed)
+}
+
+#[test]
+fn storage_changed_is_deposited_for_top_level_calls() {
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		StorageDeltaEvents::set(true);
+		// Two items of 4 and 2 bytes are added and the first of them is removed again.
+		let (first, events, _) = storage_changed_events(|_| vec![0]);
+		assert_eq!(events, vec![RawEvent::StorageChanged(first, 6, 4, 2, 1)]);
+	});
+
+	// Nothing is reported for a reverted call.
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		StorageDeltaEvents::set(true);
+		let (_, events, _) = storage_changed_events(|_| vec![1]);
+		assert_eq!(events, vec![]);
+	});
+}
+
+#[test]
+fn storage_changed_includes_committed_nested_calls() {
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		StorageDeltaEvents::set(true);
+		// The callee is called twice but its first call reverts. Only the writes of its
+		// second call are added to the ones of the top-level call.
+		let (first, events, _) = storage_changed_events(|second| {
+			Some(0u8).into_iter().chain(second.encode()).collect()
 		});
+		assert_eq!(events, vec![RawEvent::StorageChanged(first, 12, 8, 4, 2)]);
+	});
 }
 
 #[test]
-fn storage_max_value_limit() {
-	let (wasm, code_hash) = compile_module::<Test>("storage_size").unwrap();
+fn storage_changed_is_charged_only_when_enabled() {
+	let run = |enabled: bool| ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		StorageDeltaEvents::set(enabled);
+		let (_, events, gas_consumed) = storage_changed_events(|_| vec![0]);
+		assert_eq!(events.len(), enabled as usize);
+		gas_consumed
+	});
 
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+	let disabled = run(false);
+	let enabled = run(true);
+	assert!(enabled > disabled);
+}
+
+#[test]
+fn deferred_bookkeeping_matches_immediate_bookkeeping() {
+	let (wasm, code_hash) = compile_module::<Test>("storage_batch").unwrap();
+	let run = |deferred: bool| ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		DeferredBookkeeping::set(deferred);
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+		// The reverted call must not leave any bookkeeping changes behind.
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![1]));
+		let info = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+		assert_eq!(info.storage_size, 0);
+		assert_eq!(info.total_pair_count, 0);
+
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![0]));
+		let info = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+		let values: Vec<_> = (1..=3u8)
+			.map(|first| {
+				let mut key = [0; 32];
+				key[0] = first;
+				Storage::<Test>::read(&info.trie_id, &key)
+			})
+			.collect();
+		(info, values)
+	});
+
+	let (immediate_info, immediate_values) = run(false);
+	let (deferred_info, deferred_values) = run(true);
+	assert_eq!(immediate_info.storage_size, 3);
+	assert_eq!(immediate_info.total_pair_count, 2);
+	assert_eq!(immediate_values, vec![Some(vec![1]), Some(vec![1, 2]), None]);
+	assert_eq!(deferred_info, immediate_info);
+	assert_eq!(deferred_values, immediate_values);
+}
+
+#[test]
+fn deferred_writes_are_mirrored() {
+	let (wasm, code_hash) = compile_module::<Test>("storage_batch").unwrap();
+	let run = |deferred: bool| ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		DeferredBookkeeping::set(deferred);
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		TestMirror::take();
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![0]));
+		// Only the bookkeeping at the time of the write differs between the modes.
+		TestMirror::take()
+			.into_iter()
+			.map(|(contract, hashed_key, value, _)| (contract, hashed_key, value))
+			.collect::<Vec<_>>()
+	});
+
+	let immediate = run(false);
+	assert_eq!(immediate.len(), 4);
+	assert_eq!(run(true), immediate);
+}
+
+#[test]
+fn timelocks_are_only_looked_up_for_contracts_that_use_them() {
+	let (wasm, code_hash) = compile_module::<Test>("storage_batch").unwrap();
+	let run = |deferred: bool| ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		DeferredBookkeeping::set(deferred);
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		let call = || {
+			let result = Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, vec![0]);
+			assert!(result.exec_result.unwrap().is_success());
+			result.gas_consumed
+		};
+		let info = || ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
+		let plain = call();
+		assert!(!info().timelocked);
+
+		// The first key of the fixture is locked and overwritten by the next call.
+		let mut key = [0; 32];
+		key[0] = 1;
+		let hashed_key = storage::meta::hashed_key(&key);
+		assert_ok!(Storage::<Test>::write_timelock(&addr, &info().trie_id, &key, vec![7], 10));
+		assert!(info().timelocked);
+		let locked = call();
+		assert!(!crate::Timelocks::<Test>::contains_key(&info().trie_id, hashed_key));
+
+		// Each of the four storage accesses of the fixture looks up the lock of its key.
+		let per_access = Contracts::current_schedule().host_fn_weights.get_storage;
+		assert_eq!(locked, plain + 4 * per_access);
+	});
+
+	run(false);
+	run(true);
+}
+
+#[test]
+fn call_setup_matches_across_bookkeeping_modes() {
+	let (wasm, code_hash) = compile_module::<Test>("set_rent").unwrap();
+	let run = |deferred: bool| ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		DeferredBookkeeping::set(deferred);
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm.clone()));
+		let instantiate = |endowment: u64, salt: Vec<u8>| {
 			assert_ok!(Contracts::instantiate(
 				Origin::signed(ALICE),
-				30_000,
+				endowment,
 				GAS_LIMIT,
 				code_hash.into(),
-				vec![],
-				vec![],
+				<Test as pallet_balances::Config>::Balance::from(1_000u32).encode(),
+				salt.clone(),
 			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+			Contracts::contract_address(&ALICE, &code_hash, &salt)
+		};
+		let healthy = instantiate(30_000, vec![0]);
+		let doomed = instantiate(100, vec![1]);
+		let state = |addr: &AccountIdOf<Test>| {
+			(System::events(), ContractInfoOf::<Test>::get(addr).encode())
+		};
+		let mut states = Vec::new();
+
+		// Rent was already paid in this block.
+		assert_ok!(Contracts::call(
+			Origin::signed(ALICE), healthy.clone(), 0, GAS_LIMIT, call::set_storage_4_byte(),
+		));
+		states.push(state(&healthy));
+
+		// Rent is deducted before the contract executes.
+		initialize_block(5);
+		assert_ok!(Contracts::call(
+			Origin::signed(ALICE), healthy.clone(), 0, GAS_LIMIT, call::remove_storage_4_byte(),
+		));
+		let info = ContractInfoOf::<Test>::get(&healthy).unwrap().get_alive().unwrap();
+		assert_eq!(info.deduct_block, 5);
+		assert_eq!(info.call_count, 2);
+		states.push(state(&healthy));
+
+		// The contract is evicted on access and never executes.
+		initialize_block(10);
+		assert_err_ignore_postinfo!(
+			Contracts::call(Origin::signed(ALICE), doomed.clone(), 0, GAS_LIMIT, call::null()),
+			Error::<Test>::NotCallable,
+		);
+		assert!(ContractInfoOf::<Test>::get(&doomed).unwrap().get_tombstone().is_some());
+		states.push(state(&doomed));
+		states
+	});
 
-			// Check creation
-			let bob_contract = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap();
-			assert_eq!(bob_contract.rent_allowance, <BalanceOf<Test>>::max_value());
+	assert_eq!(run(true), run(false));
+}
+
+#[test]
+fn block_weight_left_is_reported() {
+	let (wasm, code_hash) = compile_module::<Test>("block_weight_left").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		let max_block = <Test as frame_system::Config>::BlockWeights::get().max_block;
+		let mut key = [0; 32];
+		key[0] = 1;
+
+		System::register_extra_weight_unchecked(max_block / 4, DispatchClass::Normal);
+		System::register_extra_weight_unchecked(max_block / 4, DispatchClass::Operational);
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![]));
+		assert_eq!(
+			test_utils::get_storage(&addr, &key),
+			Some((max_block - 2 * (max_block / 4)).encode()),
+		);
+
+		// The weight left saturates at zero once the block is overweight.
+		System::register_extra_weight_unchecked(max_block, DispatchClass::Mandatory);
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![]));
+		assert_eq!(test_utils::get_storage(&addr, &key), Some((0 as Weight).encode()));
+	});
+}
+
+#[test]
+fn later_versions_are_not_in_the_first_module() {
+	let (wasm, _) = compile_module::<Test>("input_chunk_from_seal0").unwrap();
+	ExtBuilder::default().build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_err_ignore_postinfo!(
+			Contracts::put_code(Origin::signed(ALICE), wasm),
+			"module imports a non-existent function",
+		);
+	});
+}
+
+#[test]
+fn input_can_be_read_in_chunks() {
+	let (wasm, code_hash) = compile_module::<Test>("input_chunks").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+		// 100 KiB don't fit into the 4 KiB buffer the contract reads the input with.
+		let input: Vec<u8> = (0..100 * 1024).map(|i| (i % 251) as u8).collect();
+		let result = Contracts::bare_call(
+			ALICE,
+			addr.clone(),
+			0,
+			GAS_LIMIT,
+			input.clone(),
+		).exec_result.unwrap();
+		assert!(result.is_success());
+		assert_eq!(result.data, input);
+	});
+}
+
+#[test]
+fn call_batch_is_atomic() {
+	let (wasm, code_hash) = compile_module::<Test>("storage_batch").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		let pair_count = || {
+			ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().total_pair_count
+		};
+		System::reset_events();
+
+		// The second call reverts which rolls back the writes of the first call, too.
+		assert_err_ignore_postinfo!(
+			Contracts::call_batch(
+				Origin::signed(ALICE),
+				vec![(addr.clone(), 0, vec![0]), (addr.clone(), 0, vec![1])],
+				GAS_LIMIT,
+			),
+			Error::<Test>::BatchCallReverted,
+		);
+		assert_eq!(pair_count(), 0);
+		assert_eq!(System::events(), vec![]);
 
-			// Call contract with allowed storage value.
-			assert_ok!(Contracts::call(
+		// The same is true for a call that fails.
+		assert!(Contracts::call_batch(
+			Origin::signed(ALICE),
+			vec![(addr.clone(), 0, vec![0]), (addr.clone(), 10_000_000, vec![0])],
+			GAS_LIMIT,
+		).is_err());
+		assert_eq!(pair_count(), 0);
+		assert_eq!(System::events(), vec![]);
+
+		assert_err_ignore_postinfo!(
+			Contracts::call_batch(
 				Origin::signed(ALICE),
-				addr.clone(),
-				0,
-				GAS_LIMIT * 2, // we are copying a huge buffer
-				<Test as Config>::MaxValueSize::get().encode(),
-			));
+				vec![(addr.clone(), 0, vec![0]); 5],
+				GAS_LIMIT,
+			),
+			Error::<Test>::BatchTooLarge,
+		);
 
-			// Call contract with too large a storage value.
-			assert_err_ignore_postinfo!(
-				Contracts::call(
-					Origin::signed(ALICE),
-					addr,
-					0,
-					GAS_LIMIT,
-					(<Test as Config>::MaxValueSize::get() + 1).encode(),
-				),
-				Error::<Test>::ValueTooLarge,
-			);
-		});
+		// A successful batch returns the outputs of all of its calls.
+		let outputs = Contracts::bare_call_batch(
+			ALICE,
+			vec![(addr.clone(), 0, vec![0]); 2],
+			GAS_LIMIT,
+		).exec_result.unwrap();
+		assert_eq!(outputs.len(), 2);
+		assert!(outputs.iter().all(|output| output.is_success()));
+		assert_eq!(pair_count(), 2);
+	});
 }
 
 #[test]
-fn deploy_and_call_other_contract() {
-	let (callee_wasm, callee_code_hash) = compile_module::<Test>("return_with_data").unwrap();
-	let (caller_wasm, caller_code_hash) = compile_module::<Test>("caller_contract").unwrap();
+fn call_v2_returns_encoded_error() {
+	use codec::Decode;
+	use sp_runtime::DispatchError;
 
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_wasm));
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_wasm));
+	let (caller_code, caller_hash) = compile_module::<Test>("call_v2_return_error").unwrap();
+	let (callee_code, callee_hash) = compile_module::<Test>("ok_trap_revert").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		let _ = Balances::deposit_creating(&CHARLIE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_code));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_code));
 
-			assert_ok!(Contracts::instantiate(
+		assert_ok!(
+			Contracts::instantiate(
 				Origin::signed(ALICE),
-				100_000,
+				subsistence,
 				GAS_LIMIT,
-				caller_code_hash.into(),
+				caller_hash.into(),
+				vec![0],
 				vec![],
+			),
+		);
+		let addr_bob = Contracts::contract_address(&ALICE, &caller_hash, &[]);
+
+		assert_ok!(
+			Contracts::instantiate(
+				Origin::signed(CHARLIE),
+				subsistence,
+				GAS_LIMIT,
+				callee_hash.into(),
+				vec![0],
 				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &caller_code_hash, &[]);
+			),
+		);
+		let addr_django = Contracts::contract_address(&CHARLIE, &callee_hash, &[]);
 
-			// Call BOB contract, which attempts to instantiate and call the callee contract and
-			// makes various assertions on the results from those calls.
-			assert_ok!(Contracts::call(
-				Origin::signed(ALICE),
-				addr,
+		let call = |forward: u32| {
+			let result = Contracts::bare_call(
+				ALICE,
+				addr_bob.clone(),
 				0,
 				GAS_LIMIT,
-				callee_code_hash.as_ref().to_vec(),
-			));
-		});
+				AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&forward.to_le_bytes()).cloned().collect(),
+			).exec_result.unwrap();
+			let (code, output) = result.data.split_at(4);
+			(u32::from_le_bytes([code[0], code[1], code[2], code[3]]), output.to_vec())
+		};
+		let error = |err: Error<Test>| DispatchError::from(err).stripped();
+
+		// The transfer would bring the caller below the subsistence threshold.
+		let (code, output) = call(0);
+		assert_eq!(code, RuntimeReturnCode::BelowSubsistenceThreshold as u32);
+		assert_eq!(
+			DispatchError::decode(&mut &output[..]).unwrap(),
+			error(Error::<Test>::BelowSubsistenceThreshold),
+		);
+
+		// The balance is reserved so the transfer in the nested call fails.
+		Balances::make_free_balance_be(&addr_bob, subsistence + 100);
+		Balances::reserve(&addr_bob, subsistence + 100).unwrap();
+		let (code, output) = call(0);
+		assert_eq!(code, RuntimeReturnCode::TransferFailed as u32);
+		assert_eq!(
+			DispatchError::decode(&mut &output[..]).unwrap(),
+			error(Error::<Test>::TransferFailed),
+		);
+
+		// A reverting callee returns its (empty) output buffer as before.
+		Balances::make_free_balance_be(&addr_bob, subsistence + 1000);
+		let (code, output) = call(1);
+		assert_eq!(code, RuntimeReturnCode::CalleeReverted as u32);
+		assert!(output.is_empty());
+
+		// A trapping callee reports the reason of the trap.
+		let (code, output) = call(2);
+		assert_eq!(code, RuntimeReturnCode::CalleeTrapped as u32);
+		assert_eq!(
+			DispatchError::decode(&mut &output[..]).unwrap(),
+			error(Error::<Test>::ContractTrapped),
+		);
+	});
 }
 
 #[test]
-fn cannot_self_destruct_through_draning() {
-	let (wasm, code_hash) = compile_module::<Test>("drain").unwrap();
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+fn nested_instantiations_emit_events_with_parent_as_deployer() {
+	let (factory_code, factory_hash) = compile_module::<Test>("factory").unwrap();
+	let (child_code, child_hash) = compile_module::<Test>("ok_trap_revert").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 100 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), factory_code));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), child_code));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			10 * subsistence,
+			GAS_LIMIT,
+			factory_hash.into(),
+			vec![],
+			vec![],
+		));
+		let factory = Contracts::contract_address(&ALICE, &factory_hash, &[]);
 
-			// Instantiate the BOB contract.
-			assert_ok!(Contracts::instantiate(
-				Origin::signed(ALICE),
-				100_000,
-				GAS_LIMIT,
-				code_hash.into(),
-				vec![],
-				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		System::reset_events();
+		assert_ok!(Contracts::call(
+			Origin::signed(ALICE),
+			factory.clone(),
+			0,
+			GAS_LIMIT,
+			child_hash.as_ref().iter().cloned().chain(subsistence.encode()).collect(),
+		));
+
+		// Only the two successful instantiations are reported. Code can't be stored by
+		// a contract which is why there is no `CodeStored` event.
+		crate::test_utils::assert_contract_events::<Test>(vec![
+			RawEvent::Instantiated(
+				factory.clone(),
+				Contracts::contract_address(&factory, &child_hash, &[1]),
+			),
+			RawEvent::Instantiated(
+				factory.clone(),
+				Contracts::contract_address(&factory, &child_hash, &[2]),
+			),
+		]);
+		assert!(
+			ContractInfoOf::<Test>::get(Contracts::contract_address(&factory, &child_hash, &[3]))
+				.is_none()
+		);
+	});
+}
 
-			// Check that the BOB contract has been instantiated.
-			assert_matches!(
-				ContractInfoOf::<Test>::get(&addr),
-				Some(ContractInfo::Alive(_))
-			);
+#[test]
+fn instantiations_per_call_are_limited() {
+	let (factory_code, factory_hash) = compile_module::<Test>("instantiate_until_limit").unwrap();
+	let (child_code, child_hash) = compile_module::<Test>("ok_trap_revert").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		MaxInstantiationsPerCall::set(3);
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 100 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), factory_code));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), child_code));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			10 * subsistence,
+			GAS_LIMIT,
+			factory_hash.into(),
+			vec![],
+			vec![],
+		));
+		let factory = Contracts::contract_address(&ALICE, &factory_hash, &[]);
 
-			// Call BOB which makes it send all funds to the zero address
-			// The contract code asserts that the correct error value is returned.
-			assert_ok!(
-				Contracts::call(
-					Origin::signed(ALICE),
-					addr,
-					0,
-					GAS_LIMIT,
-					vec![],
-				)
-			);
-		});
+		// The factory stops at the limit and finishes successfully.
+		let result = Contracts::bare_call(
+			ALICE,
+			factory.clone(),
+			0,
+			GAS_LIMIT,
+			child_hash.as_ref().iter().cloned().chain(subsistence.encode()).collect(),
+		).exec_result.unwrap();
+		assert!(result.is_success());
+		assert_eq!(
+			result.data,
+			(3u32, RuntimeReturnCode::TooManyInstantiations as u32).encode(),
+		);
+		for salt in 0..3u32 {
+			let child = Contracts::contract_address(&factory, &child_hash, &salt.encode());
+			assert!(ContractInfoOf::<Test>::get(&child).unwrap().get_alive().is_some());
+		}
+		let child = Contracts::contract_address(&factory, &child_hash, &3u32.encode());
+		assert!(ContractInfoOf::<Test>::get(&child).is_none());
+	});
 }
 
 #[test]
-fn cannot_self_destruct_while_live() {
-	let (wasm, code_hash) = compile_module::<Test>("self_destruct").unwrap();
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+fn derived_addresses_match_instantiated_addresses() {
+	let (caller_code, caller_hash) = compile_module::<Test>("derive_address").unwrap();
+	let (callee_code, callee_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 1_000 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_code));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_code));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			100 * subsistence,
+			GAS_LIMIT,
+			caller_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &caller_hash, &[]);
 
-			// Instantiate the BOB contract.
-			assert_ok!(Contracts::instantiate(
+		// The contract traps if the instantiated address differs from the derived one.
+		for salt in [vec![], vec![1], vec![1, 2, 3], vec![0xff; 88]].iter() {
+			let input: Vec<u8> = subsistence.encode().into_iter()
+				.chain(callee_hash.as_ref().iter().cloned())
+				.chain(salt.iter().cloned())
+				.collect();
+			let result = Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, input)
+				.exec_result
+				.unwrap();
+			let derived = Contracts::contract_address(&addr, &callee_hash, salt);
+			assert_eq!(result.data, derived.encode());
+			assert!(ContractInfoOf::<Test>::get(&derived).unwrap().get_alive().is_some());
+		}
+	});
+}
+
+#[test]
+fn instantiate_return_code() {
+	let (caller_code, caller_hash) = compile_module::<Test>("instantiate_return_code").unwrap();
+	let (callee_code, callee_hash) = compile_module::<Test>("ok_trap_revert").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		let _ = Balances::deposit_creating(&CHARLIE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_code));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_code));
+		let callee_hash = callee_hash.as_ref().to_vec();
+
+		assert_ok!(
+			Contracts::instantiate(
 				Origin::signed(ALICE),
-				100_000,
+				subsistence,
 				GAS_LIMIT,
-				code_hash.into(),
+				caller_hash.into(),
 				vec![],
 				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+			),
+		);
+		let addr = Contracts::contract_address(&ALICE, &caller_hash, &[]);
 
-			// Check that the BOB contract has been instantiated.
-			assert_matches!(
-				ContractInfoOf::<Test>::get(&addr),
-				Some(ContractInfo::Alive(_))
-			);
+		// Contract has only the minimal balance so any transfer will return BelowSubsistence.
+		let result = Contracts::bare_call(
+			ALICE,
+			addr.clone(),
+			0,
+			GAS_LIMIT,
+			vec![0; 33],
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::BelowSubsistenceThreshold);
 
-			// Call BOB with input data, forcing it make a recursive call to itself to
-			// self-destruct, resulting in a trap.
-			assert_err_ignore_postinfo!(
-				Contracts::call(
-					Origin::signed(ALICE),
-					addr.clone(),
-					0,
-					GAS_LIMIT,
-					vec![0],
-				),
-				Error::<Test>::ContractTrapped,
-			);
+		// Contract has enough total balance in order to not go below the subsistence
+		// threshold when transfering 100 balance but this balance is reserved so
+		// the transfer still fails but with another return code.
+		Balances::make_free_balance_be(&addr, subsistence + 100);
+		Balances::reserve(&addr, subsistence + 100).unwrap();
+		let result = Contracts::bare_call(
+			ALICE,
+			addr.clone(),
+			0,
+			GAS_LIMIT,
+			vec![0; 33],
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::TransferFailed);
+
+		// Contract has enough balance but the passed code hash is invalid
+		Balances::make_free_balance_be(&addr, subsistence + 1000);
+		let result = Contracts::bare_call(
+			ALICE,
+			addr.clone(),
+			0,
+			GAS_LIMIT,
+			vec![0; 33],
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::CodeNotFound);
+
+		// Contract has enough balance but callee reverts because "1" is passed.
+		let result = Contracts::bare_call(
+			ALICE,
+			addr.clone(),
+			0,
+			GAS_LIMIT,
+			callee_hash.iter().chain(&1u32.to_le_bytes()).cloned().collect(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::CalleeReverted);
 
-			// Check that BOB is still alive.
-			assert_matches!(
-				ContractInfoOf::<Test>::get(&addr),
-				Some(ContractInfo::Alive(_))
-			);
-		});
+		// Contract has enough balance but callee traps because "2" is passed.
+		let result = Contracts::bare_call(
+			ALICE,
+			addr,
+			0,
+			GAS_LIMIT,
+			callee_hash.iter().chain(&2u32.to_le_bytes()).cloned().collect(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::CalleeTrapped);
+
+	});
 }
 
 #[test]
-fn self_destruct_works() {
-	let (wasm, code_hash) = compile_module::<Test>("self_destruct").unwrap();
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
-
-			// Instantiate the BOB contract.
-			assert_ok!(Contracts::instantiate(
+fn paused_contract_cannot_be_called() {
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(
+			Contracts::instantiate(
 				Origin::signed(ALICE),
-				100_000,
+				subsistence,
 				GAS_LIMIT,
 				code_hash.into(),
 				vec![],
 				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
-
-			// Check that the BOB contract has been instantiated.
-			assert_matches!(
-				ContractInfoOf::<Test>::get(&addr),
-				Some(ContractInfo::Alive(_))
-			);
+			),
+		);
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
 
-			// Call BOB without input data which triggers termination.
-			assert_matches!(
-				Contracts::call(
-					Origin::signed(ALICE),
-					addr.clone(),
-					0,
-					GAS_LIMIT,
-					vec![],
-				),
-				Ok(_)
-			);
+		// Only root is allowed to pause a contract.
+		assert_err!(
+			Contracts::pause_contract(Origin::signed(ALICE), addr.clone()),
+			sp_runtime::DispatchError::BadOrigin,
+		);
 
-			// Check that account is gone
-			assert!(ContractInfoOf::<Test>::get(&addr).is_none());
+		assert_ok!(Contracts::pause_contract(Origin::root(), addr.clone()));
+		assert_eq!(
+			System::events().pop().unwrap().event,
+			MetaEvent::contracts(RawEvent::ContractPaused(addr.clone())),
+		);
+		assert_err_ignore_postinfo!(
+			Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![]),
+			Error::<Test>::ContractPaused,
+		);
 
-			// check that the beneficiary (django) got remaining balance
-			assert_eq!(Balances::free_balance(DJANGO), 100_000);
-		});
+		assert_ok!(Contracts::unpause_contract(Origin::root(), addr.clone()));
+		assert_eq!(
+			System::events().pop().unwrap().event,
+			MetaEvent::contracts(RawEvent::ContractUnpaused(addr.clone())),
+		);
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr, 0, GAS_LIMIT, vec![]));
+	});
 }
 
-// This tests that one contract cannot prevent another from self-destructing by sending it
-// additional funds after it has been drained.
 #[test]
-fn destroy_contract_and_transfer_funds() {
-	let (callee_wasm, callee_code_hash) = compile_module::<Test>("self_destruct").unwrap();
-	let (caller_wasm, caller_code_hash) = compile_module::<Test>("destroy_and_transfer").unwrap();
-
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			// Create
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_wasm));
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_wasm));
-
-			// This deploys the BOB contract, which in turn deploys the CHARLIE contract during
-			// construction.
-			assert_ok!(Contracts::instantiate(
+fn paused_contract_is_not_callable_from_contract() {
+	let (caller_code, caller_hash) = compile_module::<Test>("call_return_code").unwrap();
+	let (callee_code, callee_hash) = compile_module::<Test>("ok_trap_revert").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		let _ = Balances::deposit_creating(&CHARLIE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_code));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_code));
+		assert_ok!(
+			Contracts::instantiate(
 				Origin::signed(ALICE),
-				200_000,
+				subsistence + 1000,
 				GAS_LIMIT,
-				caller_code_hash.into(),
-				callee_code_hash.as_ref().to_vec(),
+				caller_hash.into(),
+				vec![0],
 				vec![],
-			));
-			let addr_bob = Contracts::contract_address(&ALICE, &caller_code_hash, &[]);
-			let addr_charlie = Contracts::contract_address(
-				&addr_bob, &callee_code_hash, &[0x47, 0x11]
-			);
-
-			// Check that the CHARLIE contract has been instantiated.
-			assert_matches!(
-				ContractInfoOf::<Test>::get(&addr_charlie),
-				Some(ContractInfo::Alive(_))
-			);
-
-			// Call BOB, which calls CHARLIE, forcing CHARLIE to self-destruct.
-			assert_ok!(Contracts::call(
-				Origin::signed(ALICE),
-				addr_bob,
-				0,
+			),
+		);
+		let addr_bob = Contracts::contract_address(&ALICE, &caller_hash, &[]);
+		assert_ok!(
+			Contracts::instantiate(
+				Origin::signed(CHARLIE),
+				subsistence,
 				GAS_LIMIT,
-				addr_charlie.encode(),
-			));
+				callee_hash.into(),
+				vec![0],
+				vec![],
+			),
+		);
+		let addr_django = Contracts::contract_address(&CHARLIE, &callee_hash, &[]);
+		let input: Vec<u8> = AsRef::<[u8]>::as_ref(&addr_django).iter()
+			.chain(&0u32.to_le_bytes())
+			.cloned()
+			.collect();
 
-			// Check that CHARLIE has moved on to the great beyond (ie. died).
-			assert!(ContractInfoOf::<Test>::get(&addr_charlie).is_none());
-		});
-}
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob.clone(),
+			0,
+			GAS_LIMIT,
+			input.clone(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::Success);
 
-#[test]
-fn cannot_self_destruct_in_constructor() {
-	let (wasm, code_hash) = compile_module::<Test>("self_destructing_constructor").unwrap();
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		// The nested call into the paused contract is rejected.
+		assert_ok!(Contracts::pause_contract(Origin::root(), addr_django.clone()));
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob.clone(),
+			0,
+			GAS_LIMIT,
+			input.clone(),
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::NotCallable);
 
-			// Fail to instantiate the BOB because the contructor calls seal_terminate.
-			assert_err_ignore_postinfo!(
-				Contracts::instantiate(
-					Origin::signed(ALICE),
-					100_000,
-					GAS_LIMIT,
-					code_hash.into(),
-					vec![],
-					vec![],
-				),
-				Error::<Test>::NewContractNotFunded,
-			);
-		});
+		assert_ok!(Contracts::unpause_contract(Origin::root(), addr_django));
+		let result = Contracts::bare_call(
+			ALICE,
+			addr_bob,
+			0,
+			GAS_LIMIT,
+			input,
+		).exec_result.unwrap();
+		assert_return_code!(result, RuntimeReturnCode::Success);
+	});
 }
 
 #[test]
-fn crypto_hashes() {
-	let (wasm, code_hash) = compile_module::<Test>("crypto_hashes").unwrap();
-
-	ExtBuilder::default()
-		.existential_deposit(50)
-		.build()
-		.execute_with(|| {
-			let _ = Balances::deposit_creating(&ALICE, 1_000_000);
-			assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
-
-			// Instantiate the CRYPTO_HASHES contract.
-			assert_ok!(Contracts::instantiate(
+fn force_terminate_works() {
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(
+			Contracts::instantiate(
 				Origin::signed(ALICE),
-				100_000,
+				subsistence,
 				GAS_LIMIT,
 				code_hash.into(),
 				vec![],
 				vec![],
-			));
-			let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
-			// Perform the call.
-			let input = b"_DEAD_BEEF";
-			use sp_io::hashing::*;
-			// Wraps a hash function into a more dynamic form usable for testing.
-			macro_rules! dyn_hash_fn {
-				($name:ident) => {
-					Box::new(|input| $name(input).as_ref().to_vec().into_boxed_slice())
-				};
-			}
-			// All hash functions and their associated output byte lengths.
-			let test_cases: &[(Box<dyn Fn(&[u8]) -> Box<[u8]>>, usize)] = &[
-				(dyn_hash_fn!(sha2_256), 32),
-				(dyn_hash_fn!(keccak_256), 32),
-				(dyn_hash_fn!(blake2_256), 32),
-				(dyn_hash_fn!(blake2_128), 16),
-			];
-			// Test the given hash functions for the input: "_DEAD_BEEF"
-			for (n, (hash_fn, expected_size)) in test_cases.iter().enumerate() {
-				// We offset data in the contract tables by 1.
-				let mut params = vec![(n + 1) as u8];
-				params.extend_from_slice(input);
-				let result = <Module<Test>>::bare_call(
-					ALICE,
-					addr.clone(),
-					0,
-					GAS_LIMIT,
-					params,
-				).exec_result.unwrap();
-				assert!(result.is_success());
-				let expected = hash_fn(input.as_ref());
-				assert_eq!(&result.data[..*expected_size], &*expected);
-			}
-		})
+			),
+		);
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		let trie_id = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().trie_id;
+		test_utils::set_storage(&addr, &[1; 32], Some(vec![1]));
+		// The reserved tombstone deposit goes to the beneficiary as well.
+		let balance = Balances::total_balance(&addr);
+
+		// Only root is allowed to terminate a contract.
+		assert_err!(
+			Contracts::force_terminate(Origin::signed(ALICE), addr.clone(), DJANGO, 0),
+			sp_runtime::DispatchError::BadOrigin,
+		);
+
+		// The witness has to cover the storage items of the contract.
+		assert_err!(
+			Contracts::force_terminate(Origin::root(), addr.clone(), DJANGO, 0),
+			Error::<Test>::InvalidPairCountWitness,
+		);
+
+		// Paused contracts can be terminated.
+		assert_ok!(Contracts::pause_contract(Origin::root(), addr.clone()));
+		assert_ok!(Contracts::force_terminate(Origin::root(), addr.clone(), DJANGO, 1));
+		assert_eq!(
+			System::events().pop().unwrap().event,
+			MetaEvent::contracts(RawEvent::Terminated(addr.clone(), DJANGO)),
+		);
+		assert!(ContractInfoOf::<Test>::get(&addr).is_none());
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), None);
+		assert_eq!(Balances::total_balance(&addr), 0);
+		assert_eq!(Balances::free_balance(&DJANGO), balance);
+
+		// The contract is gone and can't be called anymore.
+		assert_err_ignore_postinfo!(
+			Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, vec![1]),
+			Error::<Test>::ContractNotFound,
+		);
+		assert_err!(
+			Contracts::force_terminate(Origin::root(), addr.clone(), DJANGO, 0),
+			Error::<Test>::NotCallable,
+		);
+
+		// Tombstones are left alone.
+		ContractInfoOf::<Test>::insert(
+			&BOB,
+			ContractInfo::Tombstone(crate::TombstoneContractInfo::<Test>::new(&[0], code_hash, 0, 1)),
+		);
+		assert_err!(
+			Contracts::force_terminate(Origin::root(), BOB, DJANGO, 0),
+			Error::<Test>::NotCallable,
+		);
+		assert_matches!(ContractInfoOf::<Test>::get(&BOB), Some(ContractInfo::Tombstone(_)));
+	});
 }
 
 #[test]
-fn transfer_return_code() {
-	let (wasm, code_hash) = compile_module::<Test>("transfer_return_code").unwrap();
+fn force_terminate_releases_reserved_balance() {
+	let (wasm, code_hash) = compile_module::<Test>("reserve").unwrap();
 	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
 		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
-		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
 		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			subsistence + 100,
+			GAS_LIMIT,
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		let input = 0u8.encode().into_iter().chain(100u64.encode()).collect();
+		let result = Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, input);
+		assert_return_code!(result.exec_result.unwrap(), RuntimeReturnCode::Success);
+		assert_eq!(Balances::reserved_balance(&addr), TombstoneDeposit::get() + 100);
+
+		// Both the tombstone deposit and the balance reserved by the contract go to the
+		// beneficiary.
+		assert_ok!(Contracts::force_terminate(Origin::root(), addr.clone(), DJANGO, 0));
+		assert_eq!(Balances::total_balance(&addr), 0);
+		assert_eq!(Balances::free_balance(&DJANGO), subsistence + 100);
+	});
+}
 
+#[test]
+fn reaped_contract_accounts_are_removed() {
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
 		assert_ok!(
 			Contracts::instantiate(
 				Origin::signed(ALICE),
@@ -1718,204 +5612,327 @@ fn transfer_return_code() {
 			),
 		);
 		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
-
-		// Contract has only the minimal balance so any transfer will return BelowSubsistence.
-		let result = Contracts::bare_call(
-			ALICE,
-			addr.clone(),
-			0,
-			GAS_LIMIT,
-			vec![],
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::BelowSubsistenceThreshold);
-
-		// Contract has enough total balance in order to not go below the subsistence
-		// threshold when transfering 100 balance but this balance is reserved so
-		// the transfer still fails but with another return code.
-		Balances::make_free_balance_be(&addr, subsistence + 100);
-		Balances::reserve(&addr, subsistence + 100).unwrap();
-		let result = Contracts::bare_call(
-			ALICE,
-			addr,
-			0,
-			GAS_LIMIT,
-			vec![],
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::TransferFailed);
+		let trie_id = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().trie_id;
+		test_utils::set_storage(&addr, &[1; 32], Some(vec![1]));
+
+		// The reference keeps transfers from reaping the account of an alive contract.
+		assert_eq!(System::refs(&addr), 1);
+		assert!(
+			<Balances as Currency<_>>::transfer(
+				&addr,
+				&DJANGO,
+				Balances::free_balance(&addr),
+				frame_support::traits::ExistenceRequirement::AllowDeath,
+			).is_err()
+		);
+		assert!(ContractInfoOf::<Test>::get(&addr).is_some());
+
+		// Slashing still reaps it in which case the contract is removed.
+		let _ = Balances::slash(&addr, Balances::total_balance(&addr));
+		assert!(!System::account_exists(&addr));
+		assert!(ContractInfoOf::<Test>::get(&addr).is_none());
+		assert_eq!(Storage::<Test>::read(&trie_id, &[1; 32]), None);
+		assert!(System::events().iter().any(|record| {
+			record.event == MetaEvent::contracts(RawEvent::Evicted(addr.clone(), false))
+		}));
 	});
 }
 
 #[test]
-fn call_return_code() {
-	let (caller_code, caller_hash) = compile_module::<Test>("call_return_code").unwrap();
-	let (callee_code, callee_hash) = compile_module::<Test>("ok_trap_revert").unwrap();
+fn terminated_contracts_release_their_account() {
+	let (wasm, code_hash) = compile_module::<Test>("return_from_start_fn").unwrap();
 	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
 		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
 		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
-		let _ = Balances::deposit_creating(&CHARLIE, 10 * subsistence);
-		assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_code));
-		assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_code));
-
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
 		assert_ok!(
 			Contracts::instantiate(
 				Origin::signed(ALICE),
 				subsistence,
 				GAS_LIMIT,
-				caller_hash.into(),
-				vec![0],
+				code_hash.into(),
+				vec![],
 				vec![],
 			),
 		);
-		let addr_bob = Contracts::contract_address(&ALICE, &caller_hash, &[]);
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		assert_ok!(Contracts::force_terminate(Origin::root(), addr.clone(), DJANGO, 0));
+		assert!(!System::account_exists(&addr));
+		assert!(!System::events().iter().any(|record| {
+			record.event == MetaEvent::contracts(RawEvent::Evicted(addr.clone(), false))
+		}));
+	});
+}
 
-		// Contract calls into Django which is no valid contract
-		let result = Contracts::bare_call(
-			ALICE,
-			addr_bob.clone(),
-			0,
+#[test]
+fn gas_profile_is_recorded() {
+	use pallet_contracts_primitives::GasCategory;
+
+	let (wasm, code_hash) = compile_module::<Test>("storage_size").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let _ = Balances::deposit_creating(&ALICE, 1_000_000);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		assert_ok!(Contracts::instantiate(
+			Origin::signed(ALICE),
+			30_000,
 			GAS_LIMIT,
-			AsRef::<[u8]>::as_ref(&DJANGO).to_vec(),
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::NotCallable);
+			code_hash.into(),
+			vec![],
+			vec![],
+		));
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+
+		// Nothing is recorded unless requested.
+		let result = Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, 16_000u32.encode());
+		assert_ok!(result.exec_result);
+		assert_eq!(result.profile, None);
+
+		let result =
+			Contracts::bare_call_profiled(ALICE, addr, 0, GAS_LIMIT, 16_000u32.encode());
+		assert_ok!(result.exec_result);
+		let profile = result.profile.unwrap();
+
+		// Every charge is attributed to exactly one category.
+		assert_eq!(profile.values().sum::<u64>(), result.gas_consumed);
+
+		// Writing and reading the large value dominates the costs of the call.
+		let storage = profile[&GasCategory::Storage];
+		assert!(profile.iter().all(|(category, gas)| {
+			*category == GasCategory::Storage || *gas < storage
+		}));
+		assert!(profile[&GasCategory::Instructions] > 0);
+	});
+}
+
+#[test]
+fn gas_breakdown_excludes_nested_frames() {
+	let (wasm, code_hash) = compile_module::<Test>("call_chain").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		let instantiate = |salt: u8| {
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![salt],
+			));
+			Contracts::contract_address(&ALICE, &code_hash, &[salt])
+		};
+		let (first, second, third) = (instantiate(1), instantiate(2), instantiate(3));
+		let input: Vec<u8> = second.encode().into_iter().chain(third.encode()).collect();
+
+		// Nothing is recorded unless requested.
+		let result = Contracts::bare_call(ALICE, first.clone(), 0, GAS_LIMIT, input.clone());
+		assert_ok!(result.exec_result);
+		assert_eq!(result.gas_breakdown, None);
+
+		let result = Contracts::bare_call_profiled(ALICE, first.clone(), 0, GAS_LIMIT, input);
+		assert_ok!(result.exec_result);
+		let breakdown = result.gas_breakdown.unwrap();
+
+		// The innermost frame returns first.
+		let accounts: Vec<_> = breakdown.iter().map(|frame| frame.account.clone()).collect();
+		assert_eq!(accounts, vec![third.encode(), second.encode(), first.encode()]);
+		assert!(breakdown.iter().all(|frame| {
+			frame.code_hash == code_hash.encode() && frame.gas_consumed > 0
+		}));
+
+		// No gas is attributed to more than one frame.
+		assert_eq!(
+			breakdown.iter().map(|frame| frame.gas_consumed).sum::<u64>(),
+			result.gas_consumed,
+		);
+	});
+}
 
+#[test]
+fn layout_can_be_registered() {
+	let (wasm, code_hash) = compile_module::<Test>("register_layout").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
 		assert_ok!(
 			Contracts::instantiate(
-				Origin::signed(CHARLIE),
+				Origin::signed(ALICE),
 				subsistence,
 				GAS_LIMIT,
-				callee_hash.into(),
-				vec![0],
+				code_hash.into(),
+				vec![],
 				vec![],
 			),
 		);
-		let addr_django = Contracts::contract_address(&CHARLIE, &callee_hash, &[]);
-
-		// Contract has only the minimal balance so any transfer will return BelowSubsistence.
-		let result = Contracts::bare_call(
-			ALICE,
-			addr_bob.clone(),
-			0,
-			GAS_LIMIT,
-			AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&0u32.to_le_bytes()).cloned().collect(),
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::BelowSubsistenceThreshold);
-
-		// Contract has enough total balance in order to not go below the subsistence
-		// threshold when transfering 100 balance but this balance is reserved so
-		// the transfer still fails but with another return code.
-		Balances::make_free_balance_be(&addr_bob, subsistence + 100);
-		Balances::reserve(&addr_bob, subsistence + 100).unwrap();
-		let result = Contracts::bare_call(
-			ALICE,
-			addr_bob.clone(),
-			0,
-			GAS_LIMIT,
-			AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&0u32.to_le_bytes()).cloned().collect(),
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::TransferFailed);
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		assert_eq!(Contracts::layout(&addr), None);
 
-		// Contract has enough balance but callee reverts because "1" is passed.
-		Balances::make_free_balance_be(&addr_bob, subsistence + 1000);
-		let result = Contracts::bare_call(
-			ALICE,
-			addr_bob.clone(),
-			0,
-			GAS_LIMIT,
-			AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&1u32.to_le_bytes()).cloned().collect(),
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::CalleeReverted);
+		// The contract registers its own layout.
+		let layout = (1u8, vec![(H256::repeat_byte(1), "u32".to_string())]).encode();
+		assert_ok!(Contracts::call(Origin::signed(ALICE), addr.clone(), 0, GAS_LIMIT, layout.clone()));
+		assert_eq!(Contracts::layout(&addr), Some(layout));
 
-		// Contract has enough balance but callee traps because "2" is passed.
-		let result = Contracts::bare_call(
-			ALICE,
-			addr_bob,
-			0,
-			GAS_LIMIT,
-			AsRef::<[u8]>::as_ref(&addr_django).iter().chain(&2u32.to_le_bytes()).cloned().collect(),
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::CalleeTrapped);
+		// Root can replace it but nobody else can.
+		assert_err!(
+			Contracts::set_layout(Origin::signed(ALICE), addr.clone(), vec![2]),
+			sp_runtime::DispatchError::BadOrigin,
+		);
+		assert_ok!(Contracts::set_layout(Origin::root(), addr.clone(), vec![2]));
+		assert_eq!(Contracts::layout(&addr), Some(vec![2]));
+		assert_err!(
+			Contracts::set_layout(Origin::root(), BOB, vec![2]),
+			Error::<Test>::NotCallable,
+		);
+		assert_err!(
+			Contracts::set_layout(Origin::root(), addr.clone(), vec![0; 16_385]),
+			Error::<Test>::ValueTooLarge,
+		);
 
+		// The layout doesn't touch the contract's storage and is removed with the contract.
+		let trie_id = ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().trie_id;
+		assert_eq!(storage::Storage::<Test>::read(&trie_id, &[0; 32]), None);
+		assert_ok!(Contracts::force_terminate(Origin::root(), addr.clone(), DJANGO, 0));
+		assert_eq!(Contracts::layout(&addr), None);
 	});
 }
 
 #[test]
-fn instantiate_return_code() {
-	let (caller_code, caller_hash) = compile_module::<Test>("instantiate_return_code").unwrap();
-	let (callee_code, callee_hash) = compile_module::<Test>("ok_trap_revert").unwrap();
+fn call_count_only_counts_successful_top_level_calls() {
+	use pallet_contracts_primitives::ReturnFlags;
+
+	let (wasm, code_hash) = compile_module::<Test>("call_count").unwrap();
 	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
 		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
 		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
-		let _ = Balances::deposit_creating(&CHARLIE, 10 * subsistence);
-		assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_code));
-		assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_code));
-		let callee_hash = callee_hash.as_ref().to_vec();
-
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
 		assert_ok!(
 			Contracts::instantiate(
 				Origin::signed(ALICE),
 				subsistence,
 				GAS_LIMIT,
-				caller_hash.into(),
+				code_hash.into(),
 				vec![],
 				vec![],
 			),
 		);
-		let addr = Contracts::contract_address(&ALICE, &caller_hash, &[]);
-
-		// Contract has only the minimal balance so any transfer will return BelowSubsistence.
-		let result = Contracts::bare_call(
-			ALICE,
-			addr.clone(),
-			0,
-			GAS_LIMIT,
-			vec![0; 33],
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::BelowSubsistenceThreshold);
-
-		// Contract has enough total balance in order to not go below the subsistence
-		// threshold when transfering 100 balance but this balance is reserved so
-		// the transfer still fails but with another return code.
-		Balances::make_free_balance_be(&addr, subsistence + 100);
-		Balances::reserve(&addr, subsistence + 100).unwrap();
-		let result = Contracts::bare_call(
-			ALICE,
-			addr.clone(),
-			0,
-			GAS_LIMIT,
-			vec![0; 33],
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::TransferFailed);
-
-		// Contract has enough balance but the passed code hash is invalid
-		Balances::make_free_balance_be(&addr, subsistence + 1000);
-		let result = Contracts::bare_call(
-			ALICE,
-			addr.clone(),
-			0,
-			GAS_LIMIT,
-			vec![0; 33],
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::CodeNotFound);
+		let addr = Contracts::contract_address(&ALICE, &code_hash, &[]);
+		let count = || ContractInfoOf::<Test>::get(&addr).unwrap().get_alive().unwrap().call_count;
+		let call = |mode: u8| Contracts::bare_call(ALICE, addr.clone(), 0, GAS_LIMIT, vec![mode])
+			.exec_result;
+
+		// The constructor is no call.
+		assert_eq!(count(), 0);
+
+		// The current call is already counted while the contract executes.
+		let result = call(0).unwrap();
+		assert!(result.is_success());
+		assert_eq!(result.data, 1u64.encode());
+		assert_eq!(count(), 1);
+
+		// A reverted call observes its own increment but it doesn't stick.
+		let result = call(1).unwrap();
+		assert!(result.flags.contains(ReturnFlags::REVERT));
+		assert_eq!(result.data, 2u64.encode());
+		assert_eq!(count(), 1);
+
+		// Neither does the one of a trapped call.
+		assert!(call(2).is_err());
+		assert_eq!(count(), 1);
+
+		let result = call(0).unwrap();
+		assert_eq!(result.data, 2u64.encode());
+		assert_eq!(count(), 2);
+		let snapshot = Contracts::contract_snapshot(addr.clone(), None, 1).unwrap();
+		assert_eq!(snapshot.header.unwrap().call_count, 2);
+	});
+}
 
-		// Contract has enough balance but callee reverts because "1" is passed.
-		let result = Contracts::bare_call(
-			ALICE,
-			addr.clone(),
-			0,
-			GAS_LIMIT,
-			callee_hash.iter().chain(&1u32.to_le_bytes()).cloned().collect(),
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::CalleeReverted);
+#[test]
+fn call_count_is_not_increased_again_by_nested_calls() {
+	let (caller_wasm, caller_hash) = compile_module::<Test>("call_count_nested").unwrap();
+	let (callee_wasm, callee_hash) = compile_module::<Test>("call_count").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), caller_wasm));
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), callee_wasm));
+		let instantiate = |code_hash: H256| {
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				vec![],
+			));
+			Contracts::contract_address(&ALICE, &code_hash, &[])
+		};
+		let caller = instantiate(caller_hash);
+		let callee = instantiate(callee_hash);
+		let count = |addr: &AccountIdOf<Test>| {
+			ContractInfoOf::<Test>::get(addr).unwrap().get_alive().unwrap().call_count
+		};
+
+		// The nested call flushes the bookkeeping of the caller which must not count its
+		// call a second time when the caller commits.
+		for expected in 1..3u64 {
+			let result = Contracts::bare_call(ALICE, caller.clone(), 0, GAS_LIMIT, callee.encode())
+				.exec_result
+				.unwrap();
+			assert_eq!(result.data, expected.encode());
+			assert_eq!(count(&caller), expected);
+		}
+		assert_eq!(count(&callee), 0);
+	});
+}
 
-		// Contract has enough balance but callee traps because "2" is passed.
-		let result = Contracts::bare_call(
-			ALICE,
-			addr,
-			0,
-			GAS_LIMIT,
-			callee_hash.iter().chain(&2u32.to_le_bytes()).cloned().collect(),
-		).exec_result.unwrap();
-		assert_return_code!(result, RuntimeReturnCode::CalleeTrapped);
+#[test]
+fn contract_events_are_filtered_by_address_and_topics() {
+	use pallet_contracts_primitives::ContractEvent;
 
+	let (wasm, code_hash) = compile_module::<Test>("emit_topics").unwrap();
+	ExtBuilder::default().existential_deposit(50).build().execute_with(|| {
+		let subsistence = ConfigCache::<Test>::subsistence_threshold_uncached();
+		let _ = Balances::deposit_creating(&ALICE, 10 * subsistence);
+		assert_ok!(Contracts::put_code(Origin::signed(ALICE), wasm));
+		let instantiate = |salt: Vec<u8>| {
+			assert_ok!(Contracts::instantiate(
+				Origin::signed(ALICE),
+				subsistence,
+				GAS_LIMIT,
+				code_hash.into(),
+				vec![],
+				salt.clone(),
+			));
+			Contracts::contract_address(&ALICE, &code_hash, &salt)
+		};
+		let addr = instantiate(vec![]);
+		let other = instantiate(vec![1]);
+		let emit = |dest: &AccountIdOf<Test>, topics: Vec<H256>| {
+			let result = Contracts::bare_call(ALICE, dest.clone(), 0, GAS_LIMIT, topics.encode());
+			assert!(result.exec_result.unwrap().is_success());
+		};
+		let (first, second) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		emit(&addr, vec![first, second]);
+		emit(&addr, vec![first]);
+		emit(&other, vec![first, second]);
+
+		let event = |topics: Vec<H256>| ContractEvent { topics, data: vec![0xca, 0xfe] };
+		assert_eq!(
+			Contracts::contract_events(&addr, &[]),
+			vec![event(vec![first, second]), event(vec![first])],
+		);
+		assert_eq!(
+			Contracts::contract_events(&addr, &[first]),
+			vec![event(vec![first, second]), event(vec![first])],
+		);
+		assert_eq!(
+			Contracts::contract_events(&addr, &[second, first]),
+			vec![event(vec![first, second])],
+		);
+		assert_eq!(Contracts::contract_events(&other, &[first]), vec![event(vec![first, second])]);
+		assert_eq!(Contracts::contract_events(&BOB, &[]), vec![]);
 	});
 }
@@ -25,11 +25,13 @@
 //! - When we update the schedule we want it to have strictly greater version than the current saved one:
 //! this guarantees that every instrumented contract code in cache cannot have the version equal to the current one.
 //! Thus, before executing a contract it should be reinstrument with new schedule.
+//! - Code that fails to be re-instrumented with a new schedule is marked as unusable and
+//! rejected without another attempt until the schedule version increases again.
 
 use crate::wasm::{prepare, runtime::Env, PrefabWasmModule};
-use crate::{CodeHash, CodeStorage, PristineCode, Schedule, Config};
+use crate::{CodeHash, CodeStorage, Error, Module, PristineCode, RawEvent, Schedule, Config};
 use sp_std::prelude::*;
-use sp_runtime::traits::Hash;
+use sp_runtime::{traits::Hash, DispatchError};
 use sp_core::crypto::UncheckedFrom;
 use frame_support::StorageMap;
 
@@ -73,21 +75,43 @@ pub fn save_raw<T: Config>(
 /// If the module was instrumented with a lower version of schedule than
 /// the current one given as an argument, then this function will perform
 /// re-instrumentation and update the cache in the storage.
+///
+/// Code that fails re-instrumentation is marked as unusable since the current schedule
+/// version and `CodeRejected` is deposited. Loading it fails with `Error::CodeRejected`
+/// until the schedule version is increased.
 pub fn load<T: Config>(
 	code_hash: &CodeHash<T>,
 	schedule: &Schedule<T>,
-) -> Result<PrefabWasmModule, &'static str> where T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]> {
-	let mut prefab_module =
-		<CodeStorage<T>>::get(code_hash).ok_or_else(|| "code is not found")?;
+) -> Result<PrefabWasmModule, DispatchError> where T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]> {
+	let mut prefab_module = <CodeStorage<T>>::get(code_hash).ok_or(Error::<T>::CodeNotFound)?;
 
 	if prefab_module.schedule_version < schedule.version {
+		// The code was already rejected by the current schedule. Trying again would
+		// yield the same result.
+		if prefab_module.unusable_since.map_or(false, |since| since >= schedule.version) {
+			return Err(Error::<T>::CodeRejected.into());
+		}
+
 		// The current schedule version is greater than the version of the one cached
 		// in the storage.
 		//
 		// We need to re-instrument the code with the latest schedule here.
-		let original_code =
-			<PristineCode<T>>::get(code_hash).ok_or_else(|| "pristine code is not found")?;
-		prefab_module = prepare::prepare_contract::<Env, T>(&original_code, schedule)?;
+		let original_code = <PristineCode<T>>::get(code_hash).ok_or(Error::<T>::CodeNotFound)?;
+		match prepare::prepare_contract::<Env, T>(&original_code, schedule) {
+			Ok(module) => prefab_module = module,
+			Err(reason) => {
+				log_error!(
+					"code {:?} was rejected by schedule version {}: {}",
+					code_hash,
+					schedule.version,
+					reason,
+				);
+				prefab_module.unusable_since = Some(schedule.version);
+				<CodeStorage<T>>::insert(&code_hash, &prefab_module);
+				<Module<T>>::deposit_event(RawEvent::CodeRejected(*code_hash));
+				return Err(Error::<T>::CodeRejected.into());
+			}
+		}
 		<CodeStorage<T>>::insert(&code_hash, &prefab_module);
 	}
 	Ok(prefab_module)
@@ -45,20 +45,24 @@ macro_rules! gen_signature {
 #[macro_export]
 macro_rules! gen_signature_dispatch {
 	(
+		$needle_module:ident,
 		$needle_name:ident,
 		$needle_sig:ident ;
+		$module:ident,
 		$name:ident
 		( $ctx:ident $( , $names:ident : $params:ty )* ) $( -> $returns:ty )* , $($rest:tt)* ) => {
-		if stringify!($name).as_bytes() == $needle_name {
+		if stringify!($module).as_bytes() == $needle_module
+			&& stringify!($name).as_bytes() == $needle_name
+		{
 			let signature = gen_signature!( ( $( $params ),* ) $( -> $returns )* );
 			if $needle_sig == &signature {
 				return true;
 			}
 		} else {
-			gen_signature_dispatch!($needle_name, $needle_sig ; $($rest)*);
+			gen_signature_dispatch!($needle_module, $needle_name, $needle_sig ; $($rest)*);
 		}
 	};
-	( $needle_name:ident, $needle_sig:ident ; ) => {
+	( $needle_module:ident, $needle_name:ident, $needle_sig:ident ; ) => {
 	};
 }
 
@@ -150,10 +154,11 @@ macro_rules! register_func {
 	( $reg_cb:ident, < E: $seal_ty:tt > ; ) => {};
 
 	( $reg_cb:ident, < E: $seal_ty:tt > ;
-		$name:ident ( $ctx:ident $( , $names:ident : $params:ty )* )
+		$module:ident $name:ident ( $ctx:ident $( , $names:ident : $params:ty )* )
 		$( -> $returns:ty )* => $body:tt $($rest:tt)*
 	) => {
 		$reg_cb(
+			stringify!($module).as_bytes(),
 			stringify!($name).as_bytes(),
 			{
 				define_func!(
@@ -168,6 +173,10 @@ macro_rules! register_func {
 
 /// Define a function set that can be imported by executing wasm code.
 ///
+/// Every function is prefixed with the module it is imported from, e.g. `[seal0]`. A module
+/// and name pair identifies a function, so a new version of a function is defined under the
+/// same name in the next module.
+///
 /// **NB**: Be advised that all functions defined by this macro
 /// will panic if called with unexpected arguments.
 ///
@@ -175,14 +184,21 @@ macro_rules! register_func {
 /// and reject the code if any imported function has a mismatched signature.
 macro_rules! define_env {
 	( $init_name:ident , < E: $seal_ty:tt > ,
-		$( $name:ident ( $ctx:ident $( , $names:ident : $params:ty )* )
+		$( [$module:ident] $name:ident ( $ctx:ident $( , $names:ident : $params:ty )* )
 			$( -> $returns:ty )* => $body:tt , )*
 	) => {
 		pub struct $init_name;
 
 		impl $crate::wasm::env_def::ImportSatisfyCheck for $init_name {
-			fn can_satisfy(name: &[u8], func_type: &parity_wasm::elements::FunctionType) -> bool {
-				gen_signature_dispatch!( name, func_type ; $( $name ( $ctx $(, $names : $params )* ) $( -> $returns )* , )* );
+			fn can_satisfy(
+				module: &[u8],
+				name: &[u8],
+				func_type: &parity_wasm::elements::FunctionType,
+			) -> bool {
+				gen_signature_dispatch!(
+					module, name, func_type ;
+					$( $module, $name ( $ctx $(, $names : $params )* ) $( -> $returns )* , )*
+				);
 
 				return false;
 			}
@@ -194,8 +210,8 @@ macro_rules! define_env {
 				sp_core::crypto::UncheckedFrom<<E::T as frame_system::Config>::Hash> +
 					AsRef<[u8]>
 		{
-			fn impls<F: FnMut(&[u8], $crate::wasm::env_def::HostFunc<E>)>(f: &mut F) {
-				register_func!(f, < E: $seal_ty > ; $( $name ( $ctx $( , $names : $params )* ) $( -> $returns)* => $body )* );
+			fn impls<F: FnMut(&[u8], &[u8], $crate::wasm::env_def::HostFunc<E>)>(f: &mut F) {
+				register_func!(f, < E: $seal_ty > ; $( $module $name ( $ctx $( , $names : $params )* ) $( -> $returns)* => $body )* );
 			}
 		}
 	};
@@ -317,7 +333,7 @@ mod tests {
 		use crate::wasm::env_def::ImportSatisfyCheck;
 
 		define_env!(Env, <E: Ext>,
-			seal_gas( _ctx, amount: u32 ) => {
+			[seal0] seal_gas( _ctx, amount: u32 ) => {
 				let amount = Gas::from(amount);
 				if !amount.is_zero() {
 					Ok(())
@@ -325,9 +341,23 @@ mod tests {
 					Err(sp_sandbox::HostError)
 				}
 			},
+
+			[seal1] seal_gas( _ctx, amount: u64 ) => {
+				if !amount.is_zero() {
+					Ok(())
+				} else {
+					Err(sp_sandbox::HostError)
+				}
+			},
 		);
 
-		assert!(Env::can_satisfy(b"seal_gas", &FunctionType::new(vec![ValueType::I32], None)));
-		assert!(!Env::can_satisfy(b"not_exists", &FunctionType::new(vec![], None)));
+		let v0 = FunctionType::new(vec![ValueType::I32], None);
+		let v1 = FunctionType::new(vec![ValueType::I64], None);
+		assert!(Env::can_satisfy(b"seal0", b"seal_gas", &v0));
+		assert!(Env::can_satisfy(b"seal1", b"seal_gas", &v1));
+		assert!(!Env::can_satisfy(b"seal0", b"seal_gas", &v1));
+		assert!(!Env::can_satisfy(b"seal1", b"seal_gas", &v0));
+		assert!(!Env::can_satisfy(b"seal2", b"seal_gas", &v1));
+		assert!(!Env::can_satisfy(b"seal0", b"not_exists", &FunctionType::new(vec![], None)));
 	}
 }
@@ -73,14 +73,15 @@ pub(crate) type HostFunc<E> =
 	) -> Result<sp_sandbox::ReturnValue, sp_sandbox::HostError>;
 
 pub(crate) trait FunctionImplProvider<E: Ext> {
-	fn impls<F: FnMut(&[u8], HostFunc<E>)>(f: &mut F);
+	/// Calls `f` with the module, the name and the implementation of every host function.
+	fn impls<F: FnMut(&[u8], &[u8], HostFunc<E>)>(f: &mut F);
 }
 
 /// This trait can be used to check whether the host environment can satisfy
 /// a requested function import.
 pub trait ImportSatisfyCheck {
 	/// Returns `true` if the host environment contains a function with
-	/// the specified name and its type matches to the given type, or `false`
-	/// otherwise.
-	fn can_satisfy(name: &[u8], func_type: &FunctionType) -> bool;
+	/// the specified name in the specified module and its type matches to the given type,
+	/// or `false` otherwise.
+	fn can_satisfy(module: &[u8], name: &[u8], func_type: &FunctionType) -> bool;
 }
@@ -24,6 +24,7 @@ use crate::gas::GasMeter;
 
 use sp_std::prelude::*;
 use sp_core::crypto::UncheckedFrom;
+use sp_runtime::DispatchError;
 use codec::{Encode, Decode};
 use sp_sandbox;
 
@@ -52,11 +53,12 @@ pub struct PrefabWasmModule {
 	initial: u32,
 	#[codec(compact)]
 	maximum: u32,
-	/// This field is reserved for future evolution of format.
+	/// The schedule version from which on the code failed to be re-instrumented.
 	///
-	/// Basically, for now this field will be serialized as `None`. In the future
-	/// we would be able to extend this structure with.
-	_reserved: Option<()>,
+	/// Code that is unusable isn't re-instrumented again until the schedule version
+	/// increases. This field used to be reserved and always `None` which keeps the
+	/// encoding of existing modules valid.
+	unusable_since: Option<u32>,
 	/// Code instrumented with the latest schedule.
 	code: Vec<u8>,
 }
@@ -84,14 +86,14 @@ where
 {
 	type Executable = WasmExecutable;
 
-	fn load_init(&self, code_hash: &CodeHash<T>) -> Result<WasmExecutable, &'static str> {
+	fn load_init(&self, code_hash: &CodeHash<T>) -> Result<WasmExecutable, DispatchError> {
 		let prefab_module = load_code::<T>(code_hash, self.schedule)?;
 		Ok(WasmExecutable {
 			entrypoint_name: "deploy",
 			prefab_module,
 		})
 	}
-	fn load_main(&self, code_hash: &CodeHash<T>) -> Result<WasmExecutable, &'static str> {
+	fn load_main(&self, code_hash: &CodeHash<T>) -> Result<WasmExecutable, DispatchError> {
 		let prefab_module = load_code::<T>(code_hash, self.schedule)?;
 		Ok(WasmExecutable {
 			entrypoint_name: "call",
@@ -138,8 +140,8 @@ where
 
 		let mut imports = sp_sandbox::EnvironmentDefinitionBuilder::new();
 		imports.add_memory(self::prepare::IMPORT_MODULE_MEMORY, "memory", memory.clone());
-		runtime::Env::impls(&mut |name, func_ptr| {
-			imports.add_host_func(self::prepare::IMPORT_MODULE_FN, name, func_ptr);
+		runtime::Env::impls(&mut |module, name, func_ptr| {
+			imports.add_host_func(module, name, func_ptr);
 		});
 
 		let mut runtime = Runtime::new(
@@ -213,7 +215,9 @@ mod tests {
 	#[derive(Default)]
 	pub struct MockExt {
 		storage: HashMap<StorageKey, Vec<u8>>,
+		layout: Option<Vec<u8>>,
 		rent_allowance: u64,
+		reserved: u64,
 		instantiates: Vec<InstantiateEntry>,
 		terminations: Vec<TerminationEntry>,
 		transfers: Vec<TransferEntry>,
@@ -225,11 +229,19 @@ mod tests {
 	impl Ext for MockExt {
 		type T = Test;
 
-		fn get_storage(&self, key: &StorageKey) -> Option<Vec<u8>> {
-			self.storage.get(key).cloned()
+		fn get_storage(&self, key: &StorageKey) -> Result<Option<Vec<u8>>, DispatchError> {
+			Ok(self.storage.get(key).cloned())
 		}
-		fn set_storage(&mut self, key: StorageKey, value: Option<Vec<u8>>) {
+		fn set_storage(
+			&mut self,
+			key: StorageKey,
+			value: Option<Vec<u8>>,
+		) -> Result<(), DispatchError> {
 			*self.storage.entry(key).or_insert(Vec::new()) = value.unwrap_or(Vec::new());
+			Ok(())
+		}
+		fn register_layout(&mut self, layout: Vec<u8>) {
+			self.layout = Some(layout);
 		}
 		fn instantiate(
 			&mut self,
@@ -246,11 +258,12 @@ mod tests {
 				gas_left: gas_meter.gas_left(),
 				salt: salt.to_vec(),
 			});
+			// The input data is echoed as output.
 			Ok((
 				Contracts::<Test>::contract_address(&ALICE, code_hash, salt),
 				ExecReturnValue {
 					flags: ReturnFlags::empty(),
-					data: Vec::new(),
+					data,
 				},
 			))
 		}
@@ -276,11 +289,11 @@ mod tests {
 			self.transfers.push(TransferEntry {
 				to: to.clone(),
 				value,
-				data: data,
+				data: data.clone(),
 			});
-			// Assume for now that it was just a plain transfer.
+			// Assume for now that it was just a plain transfer which echoes its input.
 			// TODO: Add tests for different call outcomes.
-			Ok(ExecReturnValue { flags: ReturnFlags::empty(), data: Vec::new() })
+			Ok(ExecReturnValue { flags: ReturnFlags::empty(), data })
 		}
 		fn terminate(
 			&mut self,
@@ -297,14 +310,19 @@ mod tests {
 			code_hash: H256,
 			rent_allowance: u64,
 			delta: Vec<StorageKey>,
-		) -> Result<(), DispatchError> {
+		) -> Result<u32, DispatchError> {
+			let restored = delta.iter().filter(|key| self.storage.contains_key(*key)).count();
 			self.restores.push(RestoreEntry {
 				dest,
 				code_hash,
 				rent_allowance,
 				delta,
 			});
-			Ok(())
+			Ok(restored as u32)
+		}
+		fn derive_address(&self, code_hash: &CodeHash<Test>, salt: &[u8]) -> AccountIdOf<Test> {
+			// The same deployer as used by `instantiate`.
+			Contracts::<Test>::contract_address(&ALICE, code_hash, salt)
 		}
 		fn caller(&self) -> &AccountIdOf<Self::T> {
 			&ALICE
@@ -343,12 +361,38 @@ mod tests {
 			self.rent_allowance = rent_allowance;
 		}
 
+		fn top_up_rent_allowance(&mut self, amount: u64) -> Result<(), DispatchError> {
+			self.rent_allowance = self.rent_allowance.saturating_add(amount);
+			Ok(())
+		}
+
+		fn reserve(&mut self, amount: u64) -> Result<(), DispatchError> {
+			self.reserved = self.reserved.saturating_add(amount);
+			Ok(())
+		}
+
+		fn unreserve(&mut self, amount: u64) -> Result<(), DispatchError> {
+			self.reserved = self.reserved.checked_sub(amount)
+				.ok_or(Error::<Test>::InsufficientReserve)?;
+			Ok(())
+		}
+
 		fn rent_allowance(&self) -> u64 {
 			self.rent_allowance
 		}
 
+		fn total_pair_count(&self) -> u32 {
+			self.storage.len() as u32
+		}
+
+		fn call_count(&self) -> u64 { 3 }
+
+		fn uses_timelocks(&self) -> bool { false }
+
 		fn block_number(&self) -> u64 { 121 }
 
+		fn block_weight_left(&self) -> Weight { 1_000_000 }
+
 		fn max_value_size(&self) -> u32 { 16_384 }
 
 		fn get_weight_price(&self, weight: Weight) -> BalanceOf<Self::T> {
@@ -359,12 +403,19 @@ mod tests {
 	impl Ext for &mut MockExt {
 		type T = <MockExt as Ext>::T;
 
-		fn get_storage(&self, key: &[u8; 32]) -> Option<Vec<u8>> {
+		fn get_storage(&self, key: &[u8; 32]) -> Result<Option<Vec<u8>>, DispatchError> {
 			(**self).get_storage(key)
 		}
-		fn set_storage(&mut self, key: [u8; 32], value: Option<Vec<u8>>) {
+		fn set_storage(
+			&mut self,
+			key: [u8; 32],
+			value: Option<Vec<u8>>,
+		) -> Result<(), DispatchError> {
 			(**self).set_storage(key, value)
 		}
+		fn register_layout(&mut self, layout: Vec<u8>) {
+			(**self).register_layout(layout)
+		}
 		fn instantiate(
 			&mut self,
 			code: &CodeHash<Test>,
@@ -403,7 +454,7 @@ mod tests {
 			code_hash: H256,
 			rent_allowance: u64,
 			delta: Vec<StorageKey>,
-		) -> Result<(), DispatchError> {
+		) -> Result<u32, DispatchError> {
 			(**self).restore_to(
 				dest,
 				code_hash,
@@ -411,6 +462,9 @@ mod tests {
 				delta,
 			)
 		}
+		fn derive_address(&self, code_hash: &CodeHash<Test>, salt: &[u8]) -> AccountIdOf<Test> {
+			(**self).derive_address(code_hash, salt)
+		}
 		fn caller(&self) -> &AccountIdOf<Self::T> {
 			(**self).caller()
 		}
@@ -441,12 +495,33 @@ mod tests {
 		fn set_rent_allowance(&mut self, rent_allowance: u64) {
 			(**self).set_rent_allowance(rent_allowance)
 		}
+		fn top_up_rent_allowance(&mut self, amount: u64) -> Result<(), DispatchError> {
+			(**self).top_up_rent_allowance(amount)
+		}
+		fn reserve(&mut self, amount: u64) -> Result<(), DispatchError> {
+			(**self).reserve(amount)
+		}
+		fn unreserve(&mut self, amount: u64) -> Result<(), DispatchError> {
+			(**self).unreserve(amount)
+		}
 		fn rent_allowance(&self) -> u64 {
 			(**self).rent_allowance()
 		}
+		fn total_pair_count(&self) -> u32 {
+			(**self).total_pair_count()
+		}
+		fn call_count(&self) -> u64 {
+			(**self).call_count()
+		}
+		fn uses_timelocks(&self) -> bool {
+			(**self).uses_timelocks()
+		}
 		fn block_number(&self) -> u64 {
 			(**self).block_number()
 		}
+		fn block_weight_left(&self) -> Weight {
+			(**self).block_weight_left()
+		}
 		fn max_value_size(&self) -> u32 {
 			(**self).max_value_size()
 		}
@@ -1138,6 +1213,63 @@ mod tests {
 		assert!(gas_left > gas_meter.gas_left(), "gas_left must be greater than final");
 	}
 
+	/// Calls `seal_restore_to` with the first `delta_count` of the keys `[1; 32]` and `[2; 32]`.
+	fn code_restore_to(delta_count: u32) -> String {
+		format!(r#"
+(module
+	(import "seal0" "seal_restore_to"
+		(func $seal_restore_to (param i32 i32 i32 i32 i32 i32 i32 i32))
+	)
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 32) dest
+	;; [32, 64) code hash
+	;; [64, 72) rent allowance
+	;; [72, 136) delta keys
+	(data (i32.const 72) "{key_1}{key_2}")
+
+	(func (export "call")
+		(call $seal_restore_to
+			(i32.const 0) (i32.const 32)
+			(i32.const 32) (i32.const 32)
+			(i32.const 64) (i32.const 8)
+			(i32.const 72) (i32.const {delta_count})
+		)
+	)
+	(func (export "deploy"))
+)
+"#,
+			key_1 = "\\01".repeat(32),
+			key_2 = "\\02".repeat(32),
+			delta_count = delta_count,
+		)
+	}
+
+	#[test]
+	fn restore_to_refunds_absent_delta_keys() {
+		use frame_support::traits::Get;
+
+		let gas_used = |delta_count: u32, stored: &[StorageKey]| {
+			let mut ext = MockExt::default();
+			for key in stored {
+				ext.storage.insert(*key, vec![1]);
+			}
+			let mut gas_meter = GasMeter::new(GAS_LIMIT);
+			let _ = execute(&code_restore_to(delta_count), vec![], &mut ext, &mut gas_meter);
+			assert_eq!(ext.restores.len(), 1);
+			GAS_LIMIT - gas_meter.gas_left()
+		};
+		let per_delta = crate::Schedule::<Test>::default().host_fn_weights.restore_to_per_delta;
+		let lookup = <Test as frame_system::Config>::DbWeight::get().reads(1);
+		let all_stored = gas_used(2, &[[1; 32], [2; 32]]);
+
+		// Every stored delta key is charged in full.
+		assert_eq!(all_stored - gas_used(1, &[[1; 32]]), per_delta);
+
+		// Declared delta keys that aren't stored only pay for the lookup.
+		assert_eq!(all_stored - gas_used(2, &[]), 2 * (per_delta - lookup));
+	}
+
 	const CODE_VALUE_TRANSFERRED: &str = r#"
 (module
 	(import "seal0" "seal_value_transferred" (func $seal_value_transferred (param i32 i32)))
@@ -1769,4 +1901,283 @@ mod tests {
 		);
 	}
 
+	const CODE_GET_STORAGE_ALIASED_LEN: &str = r#"
+(module
+	(import "seal0" "seal_get_storage" (func $seal_get_storage (param i32 i32 i32) (result i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 32) storage key
+	(data (i32.const 0)
+		"\11\11\11\11\11\11\11\11\11\11\11\11\11\11\11\11"
+		"\11\11\11\11\11\11\11\11\11\11\11\11\11\11\11\11"
+	)
+
+	;; [32, 64) output buffer whose length is stored inside of it at [36, 40)
+	(data (i32.const 36) "\20")
+
+	(func (export "call")
+		(drop
+			(call $seal_get_storage
+				(i32.const 0)	;; Pointer to the storage key
+				(i32.const 32)	;; Pointer to the output buffer
+				(i32.const 36)	;; Pointer to the size of the buffer
+			)
+		)
+		(call $seal_return (i32.const 0) (i32.const 32) (i32.const 32))
+	)
+	(func (export "deploy"))
+)
+"#;
+
+	#[test]
+	fn get_storage_writes_len_after_value() {
+		let mut mock_ext = MockExt::default();
+		mock_ext.storage.insert([0x11; 32], [0x22; 32].to_vec());
+
+		let output = execute(
+			CODE_GET_STORAGE_ALIASED_LEN,
+			vec![],
+			mock_ext,
+			&mut GasMeter::new(GAS_LIMIT),
+		).unwrap();
+
+		// The length overwrites the part of the value it aliases.
+		let mut expected = [0x22; 32].to_vec();
+		expected[4..8].copy_from_slice(&32u32.to_le_bytes());
+		assert_eq!(output, ExecReturnValue { flags: ReturnFlags::empty(), data: expected });
+	}
+
+	const CODE_CALL_ALIASED_LEN: &str = r#"
+(module
+	(import "seal0" "seal_call" (func $seal_call (param i32 i32 i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	(func (export "call")
+		(drop
+			(call $seal_call
+				(i32.const 4)	;; Pointer to "callee" address.
+				(i32.const 32)	;; Length of "callee" address.
+				(i64.const 0)	;; How much gas to devote for the execution. 0 = all.
+				(i32.const 36)	;; Pointer to the buffer with value to transfer
+				(i32.const 8)	;; Length of the buffer with value to transfer.
+				(i32.const 44)	;; Pointer to input data buffer address
+				(i32.const 4)	;; Length of input data buffer
+				(i32.const 44)	;; Pointer to the output buffer which is also the input buffer
+				(i32.const 44)	;; Pointer to the output buffer size which is also the input buffer
+			)
+		)
+		(call $seal_return (i32.const 0) (i32.const 44) (i32.const 4))
+	)
+	(func (export "deploy"))
+
+	;; Destination AccountId (ALICE)
+	(data (i32.const 4)
+		"\01\01\01\01\01\01\01\01\01\01\01\01\01\01\01\01"
+		"\01\01\01\01\01\01\01\01\01\01\01\01\01\01\01\01"
+	)
+
+	;; Amount of value to transfer.
+	(data (i32.const 36) "\06\00\00\00\00\00\00\00")
+
+	;; Input data which also serves as the size of the output buffer.
+	(data (i32.const 44) "\04\00\00\00")
+)
+"#;
+
+	#[test]
+	fn call_reads_input_before_writing_output() {
+		let mut mock_ext = MockExt::default();
+		let output = execute(
+			CODE_CALL_ALIASED_LEN,
+			vec![],
+			&mut mock_ext,
+			&mut GasMeter::new(GAS_LIMIT),
+		).unwrap();
+
+		// The callee received the input as it was before any output was written.
+		assert_eq!(mock_ext.transfers[0].data, vec![4, 0, 0, 0]);
+		// The echoed output is overwritten by its length.
+		assert_eq!(output.data, 4u32.to_le_bytes().to_vec());
+	}
+
+	const CODE_INSTANTIATE_ALIASED_LEN: &str = r#"
+(module
+	(import "seal0" "seal_instantiate" (func $seal_instantiate
+		(param i32 i32 i64 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+	))
+	(import "env" "memory" (memory 1 1))
+	(func (export "call")
+		(drop
+			(call $seal_instantiate
+				(i32.const 16)	;; Pointer to `code_hash`
+				(i32.const 32)	;; Length of `code_hash`
+				(i64.const 0)	;; How much gas to devote for the execution. 0 = all.
+				(i32.const 4)	;; Pointer to the buffer with value to transfer
+				(i32.const 8)	;; Length of the buffer with value to transfer
+				(i32.const 12)	;; Pointer to input data buffer address
+				(i32.const 4)	;; Length of input data buffer
+				(i32.const 100)	;; Pointer to the address output buffer
+				(i32.const 96)	;; Pointer to the address output buffer size
+				(i32.const 200)	;; Pointer to the output buffer
+				(i32.const 104)	;; Pointer to the output buffer size inside the address buffer
+				(i32.const 0)	;; salt_ptr
+				(i32.const 4)	;; salt_len
+			)
+		)
+	)
+	(func (export "deploy"))
+
+	;; Salt
+	(data (i32.const 0) "\42\43\44\45")
+	;; Amount of value to transfer.
+	(data (i32.const 4) "\03\00\00\00\00\00\00\00")
+	;; Input data which is echoed as output.
+	(data (i32.const 12) "\01\02\03\04")
+	;; Hash of code.
+	(data (i32.const 16)
+		"\11\11\11\11\11\11\11\11\11\11\11\11\11\11\11\11"
+		"\11\11\11\11\11\11\11\11\11\11\11\11\11\11\11\11"
+	)
+	;; Size of the address output buffer.
+	(data (i32.const 96) "\20")
+	;; [100, 132) address output buffer. The size of the output buffer at [104, 108) is zero.
+)
+"#;
+
+	#[test]
+	fn instantiate_reads_capacities_before_writing_outputs() {
+		let mut mock_ext = MockExt::default();
+		let result = execute(
+			CODE_INSTANTIATE_ALIASED_LEN,
+			vec![],
+			&mut mock_ext,
+			&mut GasMeter::new(GAS_LIMIT),
+		);
+
+		// The output buffer has a size of zero even though the address written before it
+		// overwrites its size.
+		assert_eq!(
+			result,
+			Err(ExecError {
+				error: Error::<Test>::OutputBufferTooSmall.into(),
+				origin: ErrorOrigin::Caller,
+			})
+		);
+	}
+
+	const CODE_INPUT_CHUNKS: &str = r#"
+(module
+	(import "seal0" "seal_input" (func $seal_input (param i32 i32)))
+	(import "seal1" "seal_input" (func $seal_input_chunk (param i32 i32 i32)))
+	(import "seal0" "seal_input_len" (func $seal_input_len (result i32)))
+	(import "env" "memory" (memory 1 1))
+
+	;; [0, 4) size of the output buffer
+	(data (i32.const 0) "\08")
+
+	;; [4, 12) output buffer
+
+	(func $assert (param i32)
+		(block $ok
+			(br_if $ok
+				(get_local 0)
+			)
+			(unreachable)
+		)
+	)
+
+	(func (export "call")
+		(call $assert (i32.eq (call $seal_input_len) (i32.const 4)))
+
+		;; Only the remaining two bytes are copied although there is space for eight.
+		(call $seal_input_chunk (i32.const 4) (i32.const 0) (i32.const 2))
+		(call $assert (i32.eq (i32.load (i32.const 0)) (i32.const 2)))
+		(call $assert (i32.eq (i32.load16_u (i32.const 4)) (i32.const 0x0403)))
+
+		;; Reading a chunk doesn't consume the input.
+		(i32.store (i32.const 0) (i32.const 8))
+		(call $seal_input (i32.const 4) (i32.const 0))
+		(call $assert (i32.eq (i32.load (i32.const 0)) (i32.const 4)))
+
+		;; But `[seal0] seal_input` does. This traps.
+		(drop (call $seal_input_len))
+	)
+	(func (export "deploy"))
+)
+"#;
+
+	#[test]
+	fn input_chunk_does_not_consume_input() {
+		assert_eq!(
+			execute(
+				CODE_INPUT_CHUNKS,
+				vec![1, 2, 3, 4],
+				MockExt::default(),
+				&mut GasMeter::new(GAS_LIMIT),
+			),
+			Err(ExecError {
+				error: Error::<Test>::ContractTrapped.into(),
+				origin: ErrorOrigin::Caller,
+			})
+		);
+	}
+
+	const CODE_INPUT_OFFSET_OUT_OF_BOUNDS: &str = r#"
+(module
+	(import "seal1" "seal_input" (func $seal_input (param i32 i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	(data (i32.const 0) "\08")
+
+	(func (export "call")
+		(call $seal_input (i32.const 4) (i32.const 0) (i32.const 5))
+	)
+	(func (export "deploy"))
+)
+"#;
+
+	#[test]
+	fn input_chunk_traps_on_offset_out_of_bounds() {
+		assert_eq!(
+			execute(
+				CODE_INPUT_OFFSET_OUT_OF_BOUNDS,
+				vec![1, 2, 3, 4],
+				MockExt::default(),
+				&mut GasMeter::new(GAS_LIMIT),
+			),
+			Err(ExecError {
+				error: Error::<Test>::InputOffsetOutOfBounds.into(),
+				origin: ErrorOrigin::Caller,
+			})
+		);
+	}
+
+	const CODE_REGISTER_LAYOUT: &str = r#"
+(module
+	(import "seal0" "seal_register_layout" (func $seal_register_layout (param i32 i32)))
+	(import "env" "memory" (memory 1 1))
+
+	(data (i32.const 0) "\01\02\03")
+
+	(func (export "call")
+		(call $seal_register_layout (i32.const 0) (i32.const 3))
+	)
+	(func (export "deploy"))
+)
+"#;
+
+	#[test]
+	fn register_layout() {
+		let mut mock_ext = MockExt::default();
+		let _ = execute(
+			CODE_REGISTER_LAYOUT,
+			vec![],
+			&mut mock_ext,
+			&mut GasMeter::new(GAS_LIMIT),
+		).unwrap();
+
+		assert_eq!(mock_ext.layout, Some(vec![1, 2, 3]));
+	}
 }
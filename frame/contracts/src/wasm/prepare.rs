@@ -26,10 +26,14 @@ use parity_wasm::elements::{self, Internal, External, MemoryType, Type, ValueTyp
 use pwasm_utils;
 use sp_std::prelude::*;
 
-/// Currently, all imported functions must be located inside this module. We might support
-/// additional modules for versioning later.
+/// The module of the original versions of all host functions. It is also the module of the
+/// `gas` function that is injected by the gas instrumentation.
 pub const IMPORT_MODULE_FN: &str = "seal0";
 
+/// Imported functions must be located inside a module that starts with this prefix. It is
+/// followed by the version of the function, e.g. `seal1` for its second version.
+pub const IMPORT_MODULE_FN_PREFIX: &str = "seal";
+
 /// Imported memory must be located inside this module. The reason for that is that current
 /// compiler toolchains might not support specifying other modules than "env" for memory imports.
 pub const IMPORT_MODULE_MEMORY: &str = "env";
@@ -324,7 +328,7 @@ impl<'a, T: Config> ContractModule<'a, T> {
 				&External::Table(_) => return Err("Cannot import tables"),
 				&External::Global(_) => return Err("Cannot import globals"),
 				&External::Function(ref type_idx) => {
-					if import.module() != IMPORT_MODULE_FN {
+					if !import.module().starts_with(IMPORT_MODULE_FN_PREFIX) {
 						return Err("Invalid module for imported function");
 					}
 					type_idx
@@ -355,7 +359,7 @@ impl<'a, T: Config> ContractModule<'a, T> {
 			}
 
 			if import_fn_banlist.iter().any(|f| import.field().as_bytes() == *f)
-				|| !C::can_satisfy(import.field().as_bytes(), func_ty)
+				|| !C::can_satisfy(import.module().as_bytes(), import.field().as_bytes(), func_ty)
 			{
 				return Err("module imports a non-existent function");
 			}
@@ -438,7 +442,7 @@ pub fn prepare_contract<C: ImportSatisfyCheck, T: Config>(
 		schedule_version: schedule.version,
 		initial: memory_limits.0,
 		maximum: memory_limits.1,
-		_reserved: None,
+		unusable_since: None,
 		code: contract_module.into_wasm_code()?,
 	})
 }
@@ -457,7 +461,7 @@ pub mod benchmarking {
 	use parity_wasm::elements::FunctionType;
 
 	impl ImportSatisfyCheck for () {
-		fn can_satisfy(_name: &[u8], _func_type: &FunctionType) -> bool {
+		fn can_satisfy(_module: &[u8], _name: &[u8], _func_type: &FunctionType) -> bool {
 			true
 		}
 	}
@@ -472,7 +476,7 @@ pub mod benchmarking {
 			schedule_version: schedule.version,
 			initial: memory_limits.0,
 			maximum: memory_limits.1,
-			_reserved: None,
+			unusable_since: None,
 			code: contract_module.into_wasm_code()?,
 		})
 	}
@@ -494,14 +498,17 @@ mod tests {
 	// Define test environment for tests. We need ImportSatisfyCheck
 	// implementation from it. So actual implementations doesn't matter.
 	define_env!(TestEnv, <E: Ext>,
-		panic(_ctx) => { unreachable!(); },
+		[seal0] panic(_ctx) => { unreachable!(); },
 
 		// gas is an implementation defined function and a contract can't import it.
-		gas(_ctx, _amount: u32) => { unreachable!(); },
+		[seal0] gas(_ctx, _amount: u32) => { unreachable!(); },
+
+		[seal0] nop(_ctx, _unused: u64) => { unreachable!(); },
 
-		nop(_ctx, _unused: u64) => { unreachable!(); },
+		// a later version of `nop` with a different signature.
+		[seal1] nop(_ctx, _unused: i32) => { unreachable!(); },
 
-		seal_println(_ctx, _ptr: u32, _len: u32) => { unreachable!(); },
+		[seal0] seal_println(_ctx, _ptr: u32, _len: u32) => { unreachable!(); },
 	);
 
 	macro_rules! prepare_test {
@@ -893,6 +900,45 @@ mod tests {
 			Err("module imports a non-existent function")
 		);
 
+		prepare_test!(versions_from_both_modules,
+			r#"
+			(module
+				(import "seal0" "nop" (func (param i64)))
+				(import "seal1" "nop" (func (param i32)))
+
+				(func (export "call"))
+				(func (export "deploy"))
+			)
+			"#,
+			Ok(_)
+		);
+
+		// the signature of the later version isn't available in "seal0"
+		prepare_test!(later_version_from_first_module,
+			r#"
+			(module
+				(import "seal0" "nop" (func (param i32)))
+
+				(func (export "call"))
+				(func (export "deploy"))
+			)
+			"#,
+			Err("module imports a non-existent function")
+		);
+
+		// functions only exist in the modules of their versions
+		prepare_test!(function_not_in_module_of_version,
+			r#"
+			(module
+				(import "seal1" "panic" (func))
+
+				(func (export "call"))
+				(func (export "deploy"))
+			)
+			"#,
+			Err("module imports a non-existent function")
+		);
+
 		prepare_test!(unknown_func_name,
 			r#"
 			(module
@@ -25,7 +25,7 @@ use crate::{
 use sp_sandbox;
 use parity_wasm::elements::ValueType;
 use frame_system;
-use frame_support::dispatch::DispatchError;
+use frame_support::{dispatch::DispatchError, traits::Get};
 use sp_std::prelude::*;
 use codec::{Decode, Encode};
 use sp_runtime::traits::SaturatedConversion;
@@ -36,7 +36,9 @@ use sp_io::hashing::{
 	blake2_128,
 	sha2_256,
 };
-use pallet_contracts_primitives::{ExecResult, ExecReturnValue, ReturnFlags, ExecError};
+use pallet_contracts_primitives::{
+	ExecResult, ExecReturnValue, ReturnFlags, ExecError, GasCategory,
+};
 
 /// Every error that can be returned to a contract when it calls any of the host functions.
 #[repr(u32)]
@@ -65,6 +67,12 @@ pub enum ReturnCode {
 	/// The contract that was called is either no contract at all (a plain account)
 	/// or is a tombstone.
 	NotCallable = 8,
+	/// The call already instantiated the maximum number of contracts.
+	TooManyInstantiations = 9,
+	/// The reserved balance of the contract doesn't cover the amount to unreserve.
+	InsufficientReserve = 10,
+	/// The code of the callee failed to be re-instrumented with the current schedule.
+	CodeRejected = 11,
 }
 
 impl ConvertibleToWasm for ReturnCode {
@@ -98,6 +106,16 @@ struct ReturnData {
 	data: Vec<u8>,
 }
 
+/// An output buffer in sandbox memory whose capacity was already read.
+struct OutputBuffer {
+	/// Where the output is written to.
+	ptr: u32,
+	/// Where the length of the output is written to.
+	len_ptr: u32,
+	/// The size of the buffer at `ptr` as read from `len_ptr`.
+	capacity: u32,
+}
+
 /// Enumerates all possible reasons why a trap was generated.
 ///
 /// This is either used to supply the caller with more information about why an error
@@ -127,6 +145,8 @@ pub enum RuntimeToken {
 	Caller,
 	/// Weight of calling `seal_address`.
 	Address,
+	/// Weight of calling `seal_derive_address` with the given salt size.
+	DeriveAddress(u32),
 	/// Weight of calling `seal_gas_left`.
 	GasLeft,
 	/// Weight of calling `seal_balance`.
@@ -141,6 +161,10 @@ pub enum RuntimeToken {
 	RentAllowance,
 	/// Weight of calling `seal_block_number`.
 	BlockNumber,
+	/// Weight of calling `seal_block_weight_left`.
+	BlockWeightLeft,
+	/// Weight of calling `seal_call_count`.
+	CallCount,
 	/// Weight of calling `seal_now`.
 	Now,
 	/// Weight of calling `seal_weight_to_fee`.
@@ -149,10 +173,16 @@ pub enum RuntimeToken {
 	InputBase,
 	/// Weight of copying the input data for the given size.
 	InputCopyOut(u32),
+	/// Weight of calling `[seal1] seal_input` without the weight of copying the input.
+	InputChunkBase,
+	/// Weight of copying a chunk of the input data of the given size.
+	InputChunkCopyOut(u32),
+	/// Weight of calling `seal_input_len`.
+	InputLen,
 	/// Weight of calling `seal_return` for the given output size.
 	Return(u32),
-	/// Weight of calling `seal_terminate`.
-	Terminate,
+	/// Weight of calling `seal_terminate` on a contract that stores the given number of pairs.
+	Terminate(u32),
 	/// Weight of calling `seal_restore_to` per number of supplied delta entries.
 	RestoreTo(u32),
 	/// Weight of calling `seal_random`. It includes the weight for copying the subject.
@@ -161,6 +191,12 @@ pub enum RuntimeToken {
 	DepositEvent{num_topic: u32, len: u32},
 	/// Weight of calling `seal_set_rent_allowance`.
 	SetRentAllowance,
+	/// Weight of calling `seal_top_up_rent_allowance`.
+	TopUpRentAllowance,
+	/// Weight of calling `seal_reserve`.
+	Reserve,
+	/// Weight of calling `seal_unreserve`.
+	Unreserve,
 	/// Weight of calling `seal_set_storage` for the given storage item size.
 	SetStorage(u32),
 	/// Weight of calling `seal_clear_storage`.
@@ -169,6 +205,12 @@ pub enum RuntimeToken {
 	GetStorageBase,
 	/// Weight of an item received via `seal_get_storage` for the given size.
 	GetStorageCopyOut(u32),
+	/// Weight of accessing the lock of a key of a contract that uses timelocks.
+	///
+	/// This is an additional storage access which is why it costs as much as a read.
+	Timelock,
+	/// Weight of calling `seal_register_layout` for the given layout size.
+	RegisterLayout(u32),
 	/// Weight of calling `seal_transfer`.
 	Transfer,
 	/// Weight of calling `seal_call` for the given input size.
@@ -205,6 +247,8 @@ where
 			MeteringBlock(amount) => s.gas.saturating_add(amount.into()),
 			Caller => s.caller,
 			Address => s.address,
+			DeriveAddress(salt_len) => s.derive_address
+				.saturating_add(s.instantiate_per_salt_byte.saturating_mul(salt_len.into())),
 			GasLeft => s.gas_left,
 			Balance => s.balance,
 			ValueTransferred => s.value_transferred,
@@ -212,13 +256,19 @@ where
 			TombstoneDeposit => s.tombstone_deposit,
 			RentAllowance => s.rent_allowance,
 			BlockNumber => s.block_number,
+			BlockWeightLeft => s.block_weight_left,
+			CallCount => s.call_count,
 			Now => s.now,
 			WeightToFee => s.weight_to_fee,
 			InputBase => s.input,
 			InputCopyOut(len) => s.input_per_byte.saturating_mul(len.into()),
+			InputChunkBase => s.input_chunk,
+			InputChunkCopyOut(len) => s.input_chunk_per_byte.saturating_mul(len.into()),
+			InputLen => s.input_len,
 			Return(len) => s.r#return
 				.saturating_add(s.return_per_byte.saturating_mul(len.into())),
-			Terminate => s.terminate,
+			Terminate(pairs) => s.terminate
+				.saturating_add(s.terminate_per_key.saturating_mul(pairs.into())),
 			RestoreTo(delta) => s.restore_to
 				.saturating_add(s.restore_to_per_delta.saturating_mul(delta.into())),
 			Random => s.random,
@@ -226,11 +276,17 @@ where
 				.saturating_add(s.deposit_event_per_topic.saturating_mul(num_topic.into()))
 				.saturating_add(s.deposit_event_per_byte.saturating_mul(len.into())),
 			SetRentAllowance => s.set_rent_allowance,
+			TopUpRentAllowance => s.top_up_rent_allowance,
+			Reserve => s.reserve,
+			Unreserve => s.unreserve,
 			SetStorage(len) => s.set_storage
 				.saturating_add(s.set_storage_per_byte.saturating_mul(len.into())),
 			ClearStorage => s.clear_storage,
 			GetStorageBase => s.get_storage,
 			GetStorageCopyOut(len) => s.get_storage_per_byte.saturating_mul(len.into()),
+			Timelock => s.get_storage,
+			RegisterLayout(len) => s.register_layout
+				.saturating_add(s.register_layout_per_byte.saturating_mul(len.into())),
 			Transfer => s.transfer,
 			CallBase(len) => s.call
 				.saturating_add(s.call_per_input_byte.saturating_mul(len.into())),
@@ -251,6 +307,20 @@ where
 				.saturating_add(s.hash_blake2_128_per_byte.saturating_mul(len.into())),
 		}
 	}
+
+	fn category(&self) -> GasCategory {
+		use self::RuntimeToken::*;
+		match *self {
+			MeteringBlock(_) => GasCategory::Instructions,
+			SetStorage(_) | ClearStorage | GetStorageBase | GetStorageCopyOut(_) | Timelock =>
+				GasCategory::Storage,
+			Transfer | CallBase(_) | CallSurchargeTransfer | CallCopyOut(_) |
+			InstantiateBase{..} | InstantiateCopyOut(_) => GasCategory::Calls,
+			HashSha256(_) | HashKeccak256(_) | HashBlake256(_) | HashBlake128(_) =>
+				GasCategory::Hashing,
+			_ => GasCategory::Other,
+		}
+	}
 }
 
 /// This is only appropriate when writing out data of constant size that does not depend on user
@@ -453,6 +523,9 @@ where
 	///
 	/// In addition to the error conditions of `write_sandbox_memory` this functions returns
 	/// `Err` if the size of the buffer located at `out_ptr` is too small to fit `buf`.
+	///
+	/// Host functions that write more than one output must use [`Self::read_output_buffer`]
+	/// and [`Self::write_output_buffer`] instead. See the latter for the aliasing rules.
 	fn write_sandbox_output(
 		&mut self,
 		out_ptr: u32,
@@ -462,14 +535,48 @@ where
 		create_token: impl FnOnce(u32) -> Option<RuntimeToken>,
 	) -> Result<(), sp_sandbox::HostError>
 	{
+		let output = self.read_output_buffer(out_ptr, out_len_ptr, allow_skip)?;
+		self.write_output_buffer(output, buf, create_token)
+	}
+
+	/// Read the capacity of the output buffer at `out_ptr` from `out_len_ptr`.
+	///
+	/// Returns `None` if `allow_skip` is set and `out_ptr` is the sentinel value of
+	/// `u32::max_value()`. See [`Self::write_sandbox_output`].
+	fn read_output_buffer(
+		&mut self,
+		out_ptr: u32,
+		out_len_ptr: u32,
+		allow_skip: bool,
+	) -> Result<Option<OutputBuffer>, sp_sandbox::HostError> {
 		if allow_skip && out_ptr == u32::max_value() {
-			return Ok(());
+			return Ok(None);
 		}
+		let capacity = self.read_sandbox_memory_as(out_len_ptr, 4)?;
+		Ok(Some(OutputBuffer { ptr: out_ptr, len_ptr: out_len_ptr, capacity }))
+	}
 
+	/// Write `buf` and its length to an output buffer obtained by [`Self::read_output_buffer`].
+	///
+	/// The contract controls all pointers and can therefore let inputs and outputs alias. In
+	/// order to make the outcome independent of the implementation of a host function all of
+	/// its inputs, including the capacities of all of its output buffers, are read before any
+	/// output is written. Outputs are written in the order they are declared in. The buffer is
+	/// written before its length which means that the length wins if `len_ptr` points into
+	/// the buffer.
+	fn write_output_buffer(
+		&mut self,
+		output: Option<OutputBuffer>,
+		buf: &[u8],
+		create_token: impl FnOnce(u32) -> Option<RuntimeToken>,
+	) -> Result<(), sp_sandbox::HostError> {
+		let output = match output {
+			Some(output) => output,
+			None => return Ok(()),
+		};
 		let buf_len = buf.len() as u32;
-		let len: u32 = self.read_sandbox_memory_as(out_len_ptr, 4)?;
 
-		if len < buf_len {
+		if output.capacity < buf_len {
 			Err(self.store_err(Error::<E::T>::OutputBufferTooSmall))?
 		}
 
@@ -477,14 +584,113 @@ where
 			self.charge_gas(token)?;
 		}
 
-		self.memory.set(out_ptr, buf).and_then(|_| {
-			self.memory.set(out_len_ptr, &buf_len.encode())
+		self.memory.set(output.ptr, buf).and_then(|_| {
+			self.memory.set(output.len_ptr, &buf_len.encode())
 		})
 		.map_err(|_| self.store_err(Error::<E::T>::OutOfBounds))?;
 
 		Ok(())
 	}
 
+	/// Copy the input of the current frame starting at `offset` to the output buffer
+	/// without consuming it.
+	///
+	/// As much of the remaining input as fits into the buffer is copied and the amount of bytes
+	/// copied is written to `out_len_ptr`. Returns `Err` if the input was already consumed by
+	/// `seal_input` or if `offset` lies beyond the end of the input.
+	fn input_chunk(
+		&mut self,
+		out_ptr: u32,
+		out_len_ptr: u32,
+		offset: u32,
+	) -> Result<(), sp_sandbox::HostError> {
+		// The input is put back after the copy so that it stays available to the frame.
+		let input = self.input_data.take().ok_or(sp_sandbox::HostError)?;
+		let result = self.read_output_buffer(out_ptr, out_len_ptr, false).and_then(|output| {
+			let offset = offset as usize;
+			if offset > input.len() {
+				Err(self.store_err(Error::<E::T>::InputOffsetOutOfBounds))?
+			}
+			let capacity = output.as_ref().map_or(0, |output| output.capacity as usize);
+			let end = input.len().min(offset.saturating_add(capacity));
+			self.write_output_buffer(output, &input[offset..end], |len| {
+				Some(RuntimeToken::InputChunkCopyOut(len))
+			})
+		});
+		self.input_data = Some(input);
+		result
+	}
+
+	/// Shared implementation of all versions of `seal_call`.
+	///
+	/// If `output_error` is set and the call fails with a non fatal error the SCALE encoded
+	/// `DispatchError` is written to the output buffer. The buffer is left untouched when the
+	/// encoded error doesn't fit into it.
+	fn call(
+		&mut self,
+		callee_ptr: u32,
+		callee_len: u32,
+		gas: u64,
+		value_ptr: u32,
+		value_len: u32,
+		input_data_ptr: u32,
+		input_data_len: u32,
+		output_ptr: u32,
+		output_len_ptr: u32,
+		output_error: bool,
+	) -> Result<ReturnCode, sp_sandbox::HostError> {
+		self.charge_gas(RuntimeToken::CallBase(input_data_len))?;
+		let callee: <E::T as frame_system::Config>::AccountId =
+			self.read_sandbox_memory_as(callee_ptr, callee_len)?;
+		let value: BalanceOf<E::T> = self.read_sandbox_memory_as(value_ptr, value_len)?;
+		let input_data = self.read_sandbox_memory(input_data_ptr, input_data_len)?;
+
+		if value > 0u32.into() {
+			self.charge_gas(RuntimeToken::CallSurchargeTransfer)?;
+		}
+
+		let nested_gas_limit = if gas == 0 {
+			self.gas_meter.gas_left()
+		} else {
+			gas.saturated_into()
+		};
+		let ext = &mut self.ext;
+		let call_outcome = self.gas_meter.with_nested(nested_gas_limit, |nested_meter| {
+			match nested_meter {
+				Some(nested_meter) => {
+					ext.call(
+						&callee,
+						value,
+						nested_meter,
+						input_data,
+					)
+				}
+				// there is not enough gas to allocate for the nested call.
+				None => Err(Error::<E::T>::OutOfGas.into()),
+			}
+		});
+
+		match &call_outcome {
+			Ok(output) => {
+				self.write_sandbox_output(output_ptr, output_len_ptr, &output.data, true, |len| {
+					Some(RuntimeToken::CallCopyOut(len))
+				})?;
+			}
+			Err(err) if output_error => {
+				let output = self.read_output_buffer(output_ptr, output_len_ptr, true)?;
+				let encoded = err.error.encode();
+				// An error that doesn't fit leaves the output buffer untouched.
+				if output.as_ref().map_or(false, |o| encoded.len() as u32 <= o.capacity) {
+					self.write_output_buffer(output, &encoded, |len| {
+						Some(RuntimeToken::CallCopyOut(len))
+					})?;
+				}
+			}
+			Err(_) => (),
+		}
+		self.map_exec_result(call_outcome)
+	}
+
 	/// Computes the given hash function on the supplied input.
 	///
 	/// Reads from the sandboxed input buffer into an intermediate buffer.
@@ -568,13 +774,20 @@ where
 		let not_funded = Error::<E::T>::NewContractNotFunded.into();
 		let no_code = Error::<E::T>::CodeNotFound.into();
 		let invalid_contract = Error::<E::T>::NotCallable.into();
+		let paused_contract = Error::<E::T>::ContractPaused.into();
+		let too_many_instantiations = Error::<E::T>::TooManyInstantiations.into();
+		let insufficient_reserve = Error::<E::T>::InsufficientReserve.into();
+		let code_rejected = Error::<E::T>::CodeRejected.into();
 
 		match from {
 			x if x == below_sub => Ok(BelowSubsistenceThreshold),
 			x if x == transfer_failed => Ok(TransferFailed),
 			x if x == not_funded => Ok(NewContractNotFunded),
 			x if x == no_code => Ok(CodeNotFound),
-			x if x == invalid_contract => Ok(NotCallable),
+			x if x == invalid_contract || x == paused_contract => Ok(NotCallable),
+			x if x == too_many_instantiations => Ok(TooManyInstantiations),
+			x if x == insufficient_reserve => Ok(InsufficientReserve),
+			x if x == code_rejected => Ok(CodeRejected),
 			err => Err(err)
 		}
 	}
@@ -607,6 +820,10 @@ where
 // Any input that leads to a out of bound error (reading or writing) or failing to decode
 // data passed to the supervisor will lead to a trap. This is not documented explicitly
 // for every function.
+//
+// Functions are never changed once they are available to contracts. A new version of a
+// function is added under the same name to the module that follows the module of its
+// latest version, e.g. `seal1` for the second version of a function from `seal0`.
 define_env!(Env, <E: Ext>,
 
 	// Account for used gas. Traps if gas used is greater than gas limit.
@@ -615,7 +832,7 @@ define_env!(Env, <E: Ext>,
 	// This call is supposed to be called only by instrumentation injected code.
 	//
 	// - amount: How much gas is used.
-	gas(ctx, amount: u32) => {
+	[seal0] gas(ctx, amount: u32) => {
 		ctx.charge_gas(RuntimeToken::MeteringBlock(amount))?;
 		Ok(())
 	},
@@ -635,7 +852,8 @@ define_env!(Env, <E: Ext>,
 	//
 	// - If value length exceeds the configured maximum value length of a storage entry.
 	// - Upon trying to set an empty storage entry (value length is 0).
-	seal_set_storage(ctx, key_ptr: u32, value_ptr: u32, value_len: u32) => {
+	// - If the call has an access list that doesn't declare the key.
+	[seal0] seal_set_storage(ctx, key_ptr: u32, value_ptr: u32, value_len: u32) => {
 		ctx.charge_gas(RuntimeToken::SetStorage(value_len))?;
 		if value_len > ctx.ext.max_value_size() {
 			Err(ctx.store_err(Error::<E::T>::ValueTooLarge))?;
@@ -643,7 +861,10 @@ define_env!(Env, <E: Ext>,
 		let mut key: StorageKey = [0; 32];
 		ctx.read_sandbox_memory_into_buf(key_ptr, &mut key)?;
 		let value = Some(ctx.read_sandbox_memory(value_ptr, value_len)?);
-		ctx.ext.set_storage(key, value);
+		if ctx.ext.uses_timelocks() {
+			ctx.charge_gas(RuntimeToken::Timelock)?;
+		}
+		ctx.ext.set_storage(key, value).map_err(|e| ctx.store_err(e))?;
 		Ok(())
 	},
 
@@ -652,11 +873,18 @@ define_env!(Env, <E: Ext>,
 	// # Parameters
 	//
 	// - `key_ptr`: pointer into the linear memory where the location to clear the value is placed.
-	seal_clear_storage(ctx, key_ptr: u32) => {
+	//
+	// # Traps
+	//
+	// - If the call has an access list that doesn't declare the key.
+	[seal0] seal_clear_storage(ctx, key_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::ClearStorage)?;
 		let mut key: StorageKey = [0; 32];
 		ctx.read_sandbox_memory_into_buf(key_ptr, &mut key)?;
-		ctx.ext.set_storage(key, None);
+		if ctx.ext.uses_timelocks() {
+			ctx.charge_gas(RuntimeToken::Timelock)?;
+		}
+		ctx.ext.set_storage(key, None).map_err(|e| ctx.store_err(e))?;
 		Ok(())
 	},
 
@@ -672,11 +900,18 @@ define_env!(Env, <E: Ext>,
 	// # Errors
 	//
 	// `ReturnCode::KeyNotFound`
-	seal_get_storage(ctx, key_ptr: u32, out_ptr: u32, out_len_ptr: u32) -> ReturnCode => {
+	//
+	// # Traps
+	//
+	// - If the call has an access list that doesn't declare the key.
+	[seal0] seal_get_storage(ctx, key_ptr: u32, out_ptr: u32, out_len_ptr: u32) -> ReturnCode => {
 		ctx.charge_gas(RuntimeToken::GetStorageBase)?;
 		let mut key: StorageKey = [0; 32];
 		ctx.read_sandbox_memory_into_buf(key_ptr, &mut key)?;
-		if let Some(value) = ctx.ext.get_storage(&key) {
+		if ctx.ext.uses_timelocks() {
+			ctx.charge_gas(RuntimeToken::Timelock)?;
+		}
+		if let Some(value) = ctx.ext.get_storage(&key).map_err(|e| ctx.store_err(e))? {
 			ctx.write_sandbox_output(out_ptr, out_len_ptr, &value, false, |len| {
 				Some(RuntimeToken::GetStorageCopyOut(len))
 			})?;
@@ -686,6 +921,30 @@ define_env!(Env, <E: Ext>,
 		}
 	},
 
+	// Register a description of the storage layout of the calling contract.
+	//
+	// The layout is opaque to the runtime and replaces any previously registered layout.
+	// It is meant for tooling that wants to render the storage of the contract and doesn't
+	// affect the contract's storage in any way.
+	//
+	// # Parameters
+	//
+	// - `layout_ptr`: pointer into the linear memory where the layout is placed.
+	// - `layout_len`: length of the layout in bytes.
+	//
+	// # Traps
+	//
+	// - If the layout is larger than the maximum value size.
+	[seal0] seal_register_layout(ctx, layout_ptr: u32, layout_len: u32) => {
+		ctx.charge_gas(RuntimeToken::RegisterLayout(layout_len))?;
+		if layout_len > ctx.ext.max_value_size() {
+			Err(ctx.store_err(Error::<E::T>::ValueTooLarge))?;
+		}
+		let layout = ctx.read_sandbox_memory(layout_ptr, layout_len)?;
+		ctx.ext.register_layout(layout);
+		Ok(())
+	},
+
 	// Transfer some value to another account.
 	//
 	// # Parameters
@@ -701,7 +960,7 @@ define_env!(Env, <E: Ext>,
 	//
 	// `ReturnCode::BelowSubsistenceThreshold`
 	// `ReturnCode::TransferFailed`
-	seal_transfer(
+	[seal0] seal_transfer(
 		ctx,
 		account_ptr: u32,
 		account_len: u32,
@@ -748,8 +1007,11 @@ define_env!(Env, <E: Ext>,
 	// `ReturnCode::CalleeTrapped`
 	// `ReturnCode::BelowSubsistenceThreshold`
 	// `ReturnCode::TransferFailed`
-	// `ReturnCode::NotCallable`
-	seal_call(
+	// `ReturnCode::NotCallable`: The callee is a plain account or a tombstone. This is
+	//   returned regardless of the input data and no value is transferred. Use
+	//   `seal_transfer` in order to send value to a plain account.
+	// `ReturnCode::CodeRejected`
+	[seal0] seal_call(
 		ctx,
 		callee_ptr: u32,
 		callee_len: u32,
@@ -761,43 +1023,64 @@ define_env!(Env, <E: Ext>,
 		output_ptr: u32,
 		output_len_ptr: u32
 	) -> ReturnCode => {
-		ctx.charge_gas(RuntimeToken::CallBase(input_data_len))?;
-		let callee: <<E as Ext>::T as frame_system::Config>::AccountId =
-			ctx.read_sandbox_memory_as(callee_ptr, callee_len)?;
-		let value: BalanceOf<<E as Ext>::T> = ctx.read_sandbox_memory_as(value_ptr, value_len)?;
-		let input_data = ctx.read_sandbox_memory(input_data_ptr, input_data_len)?;
-
-		if value > 0u32.into() {
-			ctx.charge_gas(RuntimeToken::CallSurchargeTransfer)?;
-		}
-
-		let nested_gas_limit = if gas == 0 {
-			ctx.gas_meter.gas_left()
-		} else {
-			gas.saturated_into()
-		};
-		let ext = &mut ctx.ext;
-		let call_outcome = ctx.gas_meter.with_nested(nested_gas_limit, |nested_meter| {
-			match nested_meter {
-				Some(nested_meter) => {
-					ext.call(
-						&callee,
-						value,
-						nested_meter,
-						input_data,
-					)
-				}
-				// there is not enough gas to allocate for the nested call.
-				None => Err(Error::<<E as Ext>::T>::OutOfGas.into()),
-			}
-		});
+		ctx.call(
+			callee_ptr,
+			callee_len,
+			gas,
+			value_ptr,
+			value_len,
+			input_data_ptr,
+			input_data_len,
+			output_ptr,
+			output_len_ptr,
+			false,
+		)
+	},
 
-		if let Ok(output) = &call_outcome {
-			ctx.write_sandbox_output(output_ptr, output_len_ptr, &output.data, true, |len| {
-				Some(RuntimeToken::CallCopyOut(len))
-			})?;
-		}
-		ctx.map_exec_result(call_outcome)
+	// Make a call to another contract and receive the reason of a failure.
+	//
+	// Works exactly like `[seal0] seal_call` with the following difference: If the call fails
+	// with a non fatal error the SCALE encoded `DispatchError` describing that failure is
+	// copied to `output_ptr` and its length to `output_len_ptr`. This allows the caller to
+	// decode the module and error index of the failure. If the encoded error doesn't fit into
+	// the supplied output buffer it is left untouched. Nothing is copied when the sentinel
+	// value of `u32::max_value()` is supplied to `output_ptr`.
+	//
+	// # Parameters
+	//
+	// See `seal_call`.
+	//
+	// # Errors
+	//
+	// `ReturnCode::CalleeReverted`: Output buffer is returned.
+	// `ReturnCode::CalleeTrapped`: Encoded error is returned.
+	// `ReturnCode::BelowSubsistenceThreshold`: Encoded error is returned.
+	// `ReturnCode::TransferFailed`: Encoded error is returned.
+	// `ReturnCode::NotCallable`: Encoded error is returned.
+	[seal1] seal_call(
+		ctx,
+		callee_ptr: u32,
+		callee_len: u32,
+		gas: u64,
+		value_ptr: u32,
+		value_len: u32,
+		input_data_ptr: u32,
+		input_data_len: u32,
+		output_ptr: u32,
+		output_len_ptr: u32
+	) -> ReturnCode => {
+		ctx.call(
+			callee_ptr,
+			callee_len,
+			gas,
+			value_ptr,
+			value_len,
+			input_data_ptr,
+			input_data_len,
+			output_ptr,
+			output_len_ptr,
+			true,
+		)
 	},
 
 	// Instantiate a contract with the specified code hash.
@@ -845,7 +1128,9 @@ define_env!(Env, <E: Ext>,
 	// `ReturnCode::TransferFailed`
 	// `ReturnCode::NewContractNotFunded`
 	// `ReturnCode::CodeNotFound`
-	seal_instantiate(
+	// `ReturnCode::CodeRejected`
+	// `ReturnCode::TooManyInstantiations`
+	[seal0] seal_instantiate(
 		ctx,
 		code_hash_ptr: u32,
 		code_hash_len: u32,
@@ -890,12 +1175,16 @@ define_env!(Env, <E: Ext>,
 			}
 		});
 		if let Ok((address, output)) = &instantiate_outcome {
-			if !output.flags.contains(ReturnFlags::REVERT) {
-				ctx.write_sandbox_output(
-					address_ptr, address_len_ptr, &address.encode(), true, already_charged,
-				)?;
-			}
-			ctx.write_sandbox_output(output_ptr, output_len_ptr, &output.data, true, |len| {
+			// Both capacities are read before anything is written so that the buffers
+			// can't influence each other.
+			let address_output = if !output.flags.contains(ReturnFlags::REVERT) {
+				ctx.read_output_buffer(address_ptr, address_len_ptr, true)?
+			} else {
+				None
+			};
+			let data_output = ctx.read_output_buffer(output_ptr, output_len_ptr, true)?;
+			ctx.write_output_buffer(address_output, &address.encode(), already_charged)?;
+			ctx.write_output_buffer(data_output, &output.data, |len| {
 				Some(RuntimeToken::InstantiateCopyOut(len))
 			})?;
 		}
@@ -916,12 +1205,13 @@ define_env!(Env, <E: Ext>,
 	// # Traps
 	//
 	// - The contract is live i.e is already on the call stack.
-	seal_terminate(
+	[seal0] seal_terminate(
 		ctx,
 		beneficiary_ptr: u32,
 		beneficiary_len: u32
 	) => {
-		ctx.charge_gas(RuntimeToken::Terminate)?;
+		let pairs = ctx.ext.total_pair_count();
+		ctx.charge_gas(RuntimeToken::Terminate(pairs))?;
 		let beneficiary: <<E as Ext>::T as frame_system::Config>::AccountId =
 			ctx.read_sandbox_memory_as(beneficiary_ptr, beneficiary_len)?;
 
@@ -931,7 +1221,7 @@ define_env!(Env, <E: Ext>,
 		Err(sp_sandbox::HostError)
 	},
 
-	seal_input(ctx, buf_ptr: u32, buf_len_ptr: u32) => {
+	[seal0] seal_input(ctx, buf_ptr: u32, buf_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::InputBase)?;
 		if let Some(input) = ctx.input_data.take() {
 			ctx.write_sandbox_output(buf_ptr, buf_len_ptr, &input, false, |len| {
@@ -942,6 +1232,38 @@ define_env!(Env, <E: Ext>,
 		}
 	},
 
+	// Stores a chunk of the input passed by the caller into the supplied buffer.
+	//
+	// The chunk starts at `offset` within the input and is as large as the available
+	// space at `out_ptr` allows. `out_len_ptr` must point to a u32 value that describes
+	// the available space at `out_ptr`. This call overwrites it with the size of the chunk
+	// which is smaller than the available space once the end of the input is reached.
+	//
+	// Unlike `[seal0] seal_input` this function doesn't consume the input: It can be called any
+	// number of times in order to read an input that doesn't fit into the contract memory
+	// at once. Use `seal_input_len` to learn the size of the input.
+	//
+	// # Traps
+	//
+	// - The input was already consumed by `[seal0] seal_input`.
+	// - `offset` is larger than the size of the input.
+	[seal1] seal_input(ctx, out_ptr: u32, out_len_ptr: u32, offset: u32) => {
+		ctx.charge_gas(RuntimeToken::InputChunkBase)?;
+		ctx.input_chunk(out_ptr, out_len_ptr, offset)
+	},
+
+	// Returns the size of the input passed by the caller.
+	//
+	// This doesn't consume the input. See `[seal1] seal_input`.
+	//
+	// # Traps
+	//
+	// - The input was already consumed by `[seal0] seal_input`.
+	[seal0] seal_input_len(ctx) -> u32 => {
+		ctx.charge_gas(RuntimeToken::InputLen)?;
+		ctx.input_data.as_ref().map(|input| input.len() as u32).ok_or(sp_sandbox::HostError)
+	},
+
 	// Cease contract execution and save a data buffer as a result of the execution.
 	//
 	// This function never retuns as it stops execution of the caller.
@@ -959,7 +1281,7 @@ define_env!(Env, <E: Ext>,
 	// --- msb ---
 	//
 	// Using a reserved bit triggers a trap.
-	seal_return(ctx, flags: u32, data_ptr: u32, data_len: u32) => {
+	[seal0] seal_return(ctx, flags: u32, data_ptr: u32, data_len: u32) => {
 		ctx.charge_gas(RuntimeToken::Return(data_len))?;
 		ctx.trap_reason = Some(TrapReason::Return(ReturnData {
 			flags,
@@ -982,7 +1304,7 @@ define_env!(Env, <E: Ext>,
 	// If this is a top-level call (i.e. initiated by an extrinsic) the origin address of the
 	// extrinsic will be returned. Otherwise, if this call is initiated by another contract then the
 	// address of the contract will be returned. The value is encoded as T::AccountId.
-	seal_caller(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_caller(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::Caller)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.caller().encode(), false, already_charged
@@ -995,13 +1317,43 @@ define_env!(Env, <E: Ext>,
 	// `out_len_ptr` must point to a u32 value that describes the available space at
 	// `out_ptr`. This call overwrites it with the size of the value. If the available
 	// space at `out_ptr` is less than the size of the value a trap is triggered.
-	seal_address(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_address(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::Address)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.address().encode(), false, already_charged
 		)
 	},
 
+	// Stores the address that `seal_instantiate` would give a contract instantiated by the
+	// current contract from the supplied code hash and salt into the supplied buffer.
+	//
+	// The code doesn't need to exist. The derivation is the one used by `seal_instantiate`.
+	//
+	// - code_hash_ptr: a pointer to the buffer that contains the code hash.
+	//   Should be decodable as a `T::Hash`. Traps otherwise.
+	// - code_hash_len: length of the code hash buffer.
+	// - salt_ptr: Pointer to raw bytes used for address deriviation. See `fn contract_address`.
+	// - salt_len: length in bytes of the supplied salt.
+	// - out_ptr: a pointer where the address is copied to.
+	// - out_len_ptr: in-out pointer to where the length of the buffer is read from
+	//   and the actual length is written to.
+	[seal0] seal_derive_address(
+		ctx,
+		code_hash_ptr: u32,
+		code_hash_len: u32,
+		salt_ptr: u32,
+		salt_len: u32,
+		out_ptr: u32,
+		out_len_ptr: u32
+	) => {
+		ctx.charge_gas(RuntimeToken::DeriveAddress(salt_len))?;
+		let code_hash: CodeHash<<E as Ext>::T> =
+			ctx.read_sandbox_memory_as(code_hash_ptr, code_hash_len)?;
+		let salt = ctx.read_sandbox_memory(salt_ptr, salt_len)?;
+		let address = ctx.ext.derive_address(&code_hash, &salt);
+		ctx.write_sandbox_output(out_ptr, out_len_ptr, &address.encode(), false, already_charged)
+	},
+
 	// Stores the price for the specified amount of gas into the supplied buffer.
 	//
 	// The value is stored to linear memory at the address pointed to by `out_ptr`.
@@ -1015,7 +1367,7 @@ define_env!(Env, <E: Ext>,
 	//
 	// It is recommended to avoid specifying very small values for `gas` as the prices for a single
 	// gas can be smaller than one.
-	seal_weight_to_fee(ctx, gas: u64, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_weight_to_fee(ctx, gas: u64, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::WeightToFee)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.get_weight_price(gas).encode(), false, already_charged
@@ -1030,7 +1382,7 @@ define_env!(Env, <E: Ext>,
 	// space at `out_ptr` is less than the size of the value a trap is triggered.
 	//
 	// The data is encoded as Gas.
-	seal_gas_left(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_gas_left(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::GasLeft)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.gas_meter.gas_left().encode(), false, already_charged
@@ -1045,7 +1397,7 @@ define_env!(Env, <E: Ext>,
 	// space at `out_ptr` is less than the size of the value a trap is triggered.
 	//
 	// The data is encoded as T::Balance.
-	seal_balance(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_balance(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::Balance)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.balance().encode(), false, already_charged
@@ -1060,7 +1412,7 @@ define_env!(Env, <E: Ext>,
 	// space at `out_ptr` is less than the size of the value a trap is triggered.
 	//
 	// The data is encoded as T::Balance.
-	seal_value_transferred(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_value_transferred(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::ValueTransferred)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.value_transferred().encode(), false, already_charged
@@ -1075,7 +1427,7 @@ define_env!(Env, <E: Ext>,
 	// space at `out_ptr` is less than the size of the value a trap is triggered.
 	//
 	// The data is encoded as T::Hash.
-	seal_random(ctx, subject_ptr: u32, subject_len: u32, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_random(ctx, subject_ptr: u32, subject_len: u32, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::Random)?;
 		if subject_len > ctx.schedule.limits.subject_len {
 			return Err(sp_sandbox::HostError);
@@ -1092,7 +1444,7 @@ define_env!(Env, <E: Ext>,
 	// `out_len_ptr` must point to a u32 value that describes the available space at
 	// `out_ptr`. This call overwrites it with the size of the value. If the available
 	// space at `out_ptr` is less than the size of the value a trap is triggered.
-	seal_now(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_now(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::Now)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.now().encode(), false, already_charged
@@ -1102,7 +1454,7 @@ define_env!(Env, <E: Ext>,
 	// Stores the minimum balance (a.k.a. existential deposit) into the supplied buffer.
 	//
 	// The data is encoded as T::Balance.
-	seal_minimum_balance(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_minimum_balance(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::MinimumBalance)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.minimum_balance().encode(), false, already_charged
@@ -1124,7 +1476,7 @@ define_env!(Env, <E: Ext>,
 	// a contract to leave a tombstone the balance of the contract must not go
 	// below the sum of existential deposit and the tombstone deposit. The sum
 	// is commonly referred as subsistence threshold in code.
-	seal_tombstone_deposit(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_tombstone_deposit(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::TombstoneDeposit)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.tombstone_deposit().encode(), false, already_charged
@@ -1156,8 +1508,10 @@ define_env!(Env, <E: Ext>,
 	// # Traps
 	//
 	// - Tombstone hashes do not match
+	// - The tombstone was created in the current block. Restorations are possible from
+	//   the block after the eviction on.
 	// - Calling cantract is live i.e is already on the call stack.
-	seal_restore_to(
+	[seal0] seal_restore_to(
 		ctx,
 		dest_ptr: u32,
 		dest_len: u32,
@@ -1195,12 +1549,19 @@ define_env!(Env, <E: Ext>,
 			delta
 		};
 
-		if let Ok(()) = ctx.ext.restore_to(
+		if let Ok(restored) = ctx.ext.restore_to(
 			dest,
 			code_hash,
 			rent_allowance,
 			delta,
 		).map_err(|e| ctx.store_err(e)) {
+			// The weight per delta key assumes that the key is stored. Keys that aren't only
+			// cost a lookup.
+			let absent_key_refund = ctx.schedule.host_fn_weights.restore_to_per_delta
+				.saturating_sub(<E::T as frame_system::Config>::DbWeight::get().reads(1));
+			ctx.gas_meter.refund(
+				absent_key_refund.saturating_mul(delta_count.saturating_sub(restored).into()),
+			);
 			ctx.trap_reason = Some(TrapReason::Restoration);
 		}
 		Err(sp_sandbox::HostError)
@@ -1214,7 +1575,7 @@ define_env!(Env, <E: Ext>,
 	// - topics_len - the length of the topics buffer. Pass 0 if you want to pass an empty vector.
 	// - data_ptr - a pointer to a raw data buffer which will saved along the event.
 	// - data_len - the length of the data buffer.
-	seal_deposit_event(ctx, topics_ptr: u32, topics_len: u32, data_ptr: u32, data_len: u32) => {
+	[seal0] seal_deposit_event(ctx, topics_ptr: u32, topics_len: u32, data_ptr: u32, data_len: u32) => {
 		let num_topic = topics_len
 			.checked_div(sp_std::mem::size_of::<TopicOf<E::T>>() as u32)
 			.ok_or_else(|| ctx.store_err("Zero sized topics are not allowed"))?;
@@ -1253,7 +1614,7 @@ define_env!(Env, <E: Ext>,
 	// - value_ptr: a pointer to the buffer with value, how much to allow for rent
 	//   Should be decodable as a `T::Balance`. Traps otherwise.
 	// - value_len: length of the value buffer.
-	seal_set_rent_allowance(ctx, value_ptr: u32, value_len: u32) => {
+	[seal0] seal_set_rent_allowance(ctx, value_ptr: u32, value_len: u32) => {
 		ctx.charge_gas(RuntimeToken::SetRentAllowance)?;
 		let value: BalanceOf<<E as Ext>::T> =
 			ctx.read_sandbox_memory_as(value_ptr, value_len)?;
@@ -1262,6 +1623,67 @@ define_env!(Env, <E: Ext>,
 		Ok(())
 	},
 
+	// Increase the rent allowance of the contract by the supplied value.
+	//
+	// The free balance of the contract must cover the value on top of the subsistence
+	// threshold. In contrast to `seal_set_rent_allowance` this guarantees that the added
+	// allowance is backed by balance at the time of the call. The allowance saturates
+	// at the maximum balance value.
+	//
+	// - value_ptr: a pointer to the buffer with value, how much to add to the rent allowance.
+	//   Should be decodable as a `T::Balance`. Traps otherwise.
+	// - value_len: length of the value buffer.
+	//
+	// # Errors
+	//
+	// `ReturnCode::BelowSubsistenceThreshold`
+	[seal0] seal_top_up_rent_allowance(ctx, value_ptr: u32, value_len: u32) -> ReturnCode => {
+		ctx.charge_gas(RuntimeToken::TopUpRentAllowance)?;
+		let value: BalanceOf<<E as Ext>::T> =
+			ctx.read_sandbox_memory_as(value_ptr, value_len)?;
+		let result = ctx.ext.top_up_rent_allowance(value);
+		ctx.map_dispatch_result(result)
+	},
+
+	// Move the supplied value from the free to the reserved balance of the contract.
+	//
+	// The free balance of the contract must cover the value on top of the subsistence
+	// threshold. Reserved balance can't be transferred and isn't returned by `seal_balance`.
+	// It is released when the contract terminates.
+	//
+	// - value_ptr: a pointer to the buffer with value, how much to reserve.
+	//   Should be decodable as a `T::Balance`. Traps otherwise.
+	// - value_len: length of the value buffer.
+	//
+	// # Errors
+	//
+	// `ReturnCode::BelowSubsistenceThreshold`
+	// `ReturnCode::TransferFailed`
+	[seal0] seal_reserve(ctx, value_ptr: u32, value_len: u32) -> ReturnCode => {
+		ctx.charge_gas(RuntimeToken::Reserve)?;
+		let value: BalanceOf<<E as Ext>::T> =
+			ctx.read_sandbox_memory_as(value_ptr, value_len)?;
+		let result = ctx.ext.reserve(value);
+		ctx.map_dispatch_result(result)
+	},
+
+	// Move the supplied value from the reserved to the free balance of the contract.
+	//
+	// - value_ptr: a pointer to the buffer with value, how much to unreserve.
+	//   Should be decodable as a `T::Balance`. Traps otherwise.
+	// - value_len: length of the value buffer.
+	//
+	// # Errors
+	//
+	// `ReturnCode::InsufficientReserve`
+	[seal0] seal_unreserve(ctx, value_ptr: u32, value_len: u32) -> ReturnCode => {
+		ctx.charge_gas(RuntimeToken::Unreserve)?;
+		let value: BalanceOf<<E as Ext>::T> =
+			ctx.read_sandbox_memory_as(value_ptr, value_len)?;
+		let result = ctx.ext.unreserve(value);
+		ctx.map_dispatch_result(result)
+	},
+
 	// Stores the rent allowance into the supplied buffer.
 	//
 	// The value is stored to linear memory at the address pointed to by `out_ptr`.
@@ -1270,7 +1692,7 @@ define_env!(Env, <E: Ext>,
 	// space at `out_ptr` is less than the size of the value a trap is triggered.
 	//
 	// The data is encoded as T::Balance.
-	seal_rent_allowance(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_rent_allowance(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::RentAllowance)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.rent_allowance().encode(), false, already_charged
@@ -1280,7 +1702,7 @@ define_env!(Env, <E: Ext>,
 	// Prints utf8 encoded string from the data buffer.
 	// Only available on `--dev` chains.
 	// This function may be removed at any time, superseded by a more general contract debugging feature.
-	seal_println(ctx, str_ptr: u32, str_len: u32) => {
+	[seal0] seal_println(ctx, str_ptr: u32, str_len: u32) => {
 		let data = ctx.read_sandbox_memory(str_ptr, str_len)?;
 		if let Ok(utf8) = core::str::from_utf8(&data) {
 			sp_runtime::print(utf8);
@@ -1294,13 +1716,54 @@ define_env!(Env, <E: Ext>,
 	// `out_len_ptr` must point to a u32 value that describes the available space at
 	// `out_ptr`. This call overwrites it with the size of the value. If the available
 	// space at `out_ptr` is less than the size of the value a trap is triggered.
-	seal_block_number(ctx, out_ptr: u32, out_len_ptr: u32) => {
+	[seal0] seal_block_number(ctx, out_ptr: u32, out_len_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::BlockNumber)?;
 		ctx.write_sandbox_output(
 			out_ptr, out_len_ptr, &ctx.ext.block_number().encode(), false, already_charged
 		)
 	},
 
+	// Stores the weight that is left in the current block into the supplied buffer.
+	//
+	// The value is stored to linear memory at the address pointed to by `out_ptr`.
+	// `out_len_ptr` must point to a u32 value that describes the available space at
+	// `out_ptr`. This call overwrites it with the size of the value. If the available
+	// space at `out_ptr` is less than the size of the value a trap is triggered.
+	//
+	// The value is the SCALE encoded `Weight` of the maximum block weight minus the weight
+	// consumed by the block so far.
+	//
+	// # Note
+	//
+	// The value is only advisory and is meant to let contracts size batches of optional work.
+	// It is not a guarantee that this amount of weight can be used: The weight of the current
+	// extrinsic is only accounted after it finished and the contract is still limited by the
+	// gas limit it was called with.
+	[seal0] seal_block_weight_left(ctx, out_ptr: u32, out_len_ptr: u32) => {
+		ctx.charge_gas(RuntimeToken::BlockWeightLeft)?;
+		ctx.write_sandbox_output(
+			out_ptr, out_len_ptr, &ctx.ext.block_weight_left().encode(), false, already_charged
+		)
+	},
+
+	// Stores the number of successful top-level calls made to the current contract into the
+	// supplied buffer.
+	//
+	// The value is stored to linear memory at the address pointed to by `out_ptr`.
+	// `out_len_ptr` must point to a u32 value that describes the available space at
+	// `out_ptr`. This call overwrites it with the size of the value. If the available
+	// space at `out_ptr` is less than the size of the value a trap is triggered.
+	//
+	// The value is a SCALE encoded `u64`. A top-level call is counted before the contract
+	// is executed so that the value includes the current call. Calls made by other contracts
+	// are not counted and neither are calls that reverted or trapped.
+	[seal0] seal_call_count(ctx, out_ptr: u32, out_len_ptr: u32) => {
+		ctx.charge_gas(RuntimeToken::CallCount)?;
+		ctx.write_sandbox_output(
+			out_ptr, out_len_ptr, &ctx.ext.call_count().encode(), false, already_charged
+		)
+	},
+
 	// Computes the SHA2 256-bit hash on the given input buffer.
 	//
 	// Returns the result directly into the given output buffer.
@@ -1321,7 +1784,7 @@ define_env!(Env, <E: Ext>,
 	// - `output_ptr`: the pointer into the linear memory where the output
 	//                 data is placed. The function will write the result
 	//                 directly into this buffer.
-	seal_hash_sha2_256(ctx, input_ptr: u32, input_len: u32, output_ptr: u32) => {
+	[seal0] seal_hash_sha2_256(ctx, input_ptr: u32, input_len: u32, output_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::HashSha256(input_len))?;
 		ctx.compute_hash_on_intermediate_buffer(sha2_256, input_ptr, input_len, output_ptr)
 	},
@@ -1346,7 +1809,7 @@ define_env!(Env, <E: Ext>,
 	// - `output_ptr`: the pointer into the linear memory where the output
 	//                 data is placed. The function will write the result
 	//                 directly into this buffer.
-	seal_hash_keccak_256(ctx, input_ptr: u32, input_len: u32, output_ptr: u32) => {
+	[seal0] seal_hash_keccak_256(ctx, input_ptr: u32, input_len: u32, output_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::HashKeccak256(input_len))?;
 		ctx.compute_hash_on_intermediate_buffer(keccak_256, input_ptr, input_len, output_ptr)
 	},
@@ -1371,7 +1834,7 @@ define_env!(Env, <E: Ext>,
 	// - `output_ptr`: the pointer into the linear memory where the output
 	//                 data is placed. The function will write the result
 	//                 directly into this buffer.
-	seal_hash_blake2_256(ctx, input_ptr: u32, input_len: u32, output_ptr: u32) => {
+	[seal0] seal_hash_blake2_256(ctx, input_ptr: u32, input_len: u32, output_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::HashBlake256(input_len))?;
 		ctx.compute_hash_on_intermediate_buffer(blake2_256, input_ptr, input_len, output_ptr)
 	},
@@ -1396,7 +1859,7 @@ define_env!(Env, <E: Ext>,
 	// - `output_ptr`: the pointer into the linear memory where the output
 	//                 data is placed. The function will write the result
 	//                 directly into this buffer.
-	seal_hash_blake2_128(ctx, input_ptr: u32, input_len: u32, output_ptr: u32) => {
+	[seal0] seal_hash_blake2_128(ctx, input_ptr: u32, input_len: u32, output_ptr: u32) => {
 		ctx.charge_gas(RuntimeToken::HashBlake128(input_len))?;
 		ctx.compute_hash_on_intermediate_buffer(blake2_128, input_ptr, input_len, output_ptr)
 	},
@@ -45,11 +45,20 @@ use sp_std::marker::PhantomData;
 pub trait WeightInfo {
 	fn update_schedule() -> Weight;
 	fn put_code(n: u32, ) -> Weight;
+	fn put_code_existing(n: u32, ) -> Weight;
 	fn instantiate(n: u32, s: u32, ) -> Weight;
 	fn call() -> Weight;
+	fn call_batch(n: u32, ) -> Weight;
 	fn claim_surcharge() -> Weight;
+	fn destroy_contract(k: u32, ) -> Weight;
+	fn pause_contract() -> Weight;
+	fn unpause_contract() -> Weight;
+	fn set_frozen() -> Weight;
+	fn set_layout(n: u32, ) -> Weight;
+	fn force_terminate() -> Weight;
 	fn seal_caller(r: u32, ) -> Weight;
 	fn seal_address(r: u32, ) -> Weight;
+	fn seal_derive_address(r: u32, ) -> Weight;
 	fn seal_gas_left(r: u32, ) -> Weight;
 	fn seal_balance(r: u32, ) -> Weight;
 	fn seal_value_transferred(r: u32, ) -> Weight;
@@ -57,25 +66,36 @@ pub trait WeightInfo {
 	fn seal_tombstone_deposit(r: u32, ) -> Weight;
 	fn seal_rent_allowance(r: u32, ) -> Weight;
 	fn seal_block_number(r: u32, ) -> Weight;
+	fn seal_block_weight_left(r: u32, ) -> Weight;
+	fn seal_call_count(r: u32, ) -> Weight;
 	fn seal_now(r: u32, ) -> Weight;
 	fn seal_weight_to_fee(r: u32, ) -> Weight;
 	fn seal_gas(r: u32, ) -> Weight;
 	fn seal_input(r: u32, ) -> Weight;
 	fn seal_input_per_kb(n: u32, ) -> Weight;
+	fn seal_input_chunk(r: u32, ) -> Weight;
+	fn seal_input_chunk_per_kb(n: u32, ) -> Weight;
+	fn seal_input_len(r: u32, ) -> Weight;
 	fn seal_return(r: u32, ) -> Weight;
 	fn seal_return_per_kb(n: u32, ) -> Weight;
 	fn seal_terminate(r: u32, ) -> Weight;
+	fn seal_terminate_per_key(k: u32, ) -> Weight;
 	fn seal_restore_to(r: u32, ) -> Weight;
 	fn seal_restore_to_per_delta(d: u32, ) -> Weight;
 	fn seal_random(r: u32, ) -> Weight;
 	fn seal_deposit_event(r: u32, ) -> Weight;
 	fn seal_deposit_event_per_topic_and_kb(t: u32, n: u32, ) -> Weight;
 	fn seal_set_rent_allowance(r: u32, ) -> Weight;
+	fn seal_top_up_rent_allowance(r: u32, ) -> Weight;
+	fn seal_reserve(r: u32, ) -> Weight;
+	fn seal_unreserve(r: u32, ) -> Weight;
 	fn seal_set_storage(r: u32, ) -> Weight;
 	fn seal_set_storage_per_kb(n: u32, ) -> Weight;
 	fn seal_clear_storage(r: u32, ) -> Weight;
 	fn seal_get_storage(r: u32, ) -> Weight;
 	fn seal_get_storage_per_kb(n: u32, ) -> Weight;
+	fn seal_register_layout(r: u32, ) -> Weight;
+	fn seal_register_layout_per_kb(n: u32, ) -> Weight;
 	fn seal_transfer(r: u32, ) -> Weight;
 	fn seal_call(r: u32, ) -> Weight;
 	fn seal_call_per_transfer_input_output_kb(t: u32, i: u32, o: u32, ) -> Weight;
@@ -142,6 +162,127 @@ pub trait WeightInfo {
 	fn instr_i64rotr(r: u32, ) -> Weight;
 }
 
+/// The names of the functions of `WeightInfo` in the order of their declaration.
+pub const WEIGHT_FUNCTIONS: &[&str] = &[
+	"update_schedule",
+	"put_code",
+	"put_code_existing",
+	"instantiate",
+	"call",
+	"call_batch",
+	"claim_surcharge",
+	"destroy_contract",
+	"pause_contract",
+	"unpause_contract",
+	"set_frozen",
+	"set_layout",
+	"force_terminate",
+	"seal_caller",
+	"seal_address",
+	"seal_derive_address",
+	"seal_gas_left",
+	"seal_balance",
+	"seal_value_transferred",
+	"seal_minimum_balance",
+	"seal_tombstone_deposit",
+	"seal_rent_allowance",
+	"seal_block_number",
+	"seal_block_weight_left",
+	"seal_call_count",
+	"seal_now",
+	"seal_weight_to_fee",
+	"seal_gas",
+	"seal_input",
+	"seal_input_per_kb",
+	"seal_input_chunk",
+	"seal_input_chunk_per_kb",
+	"seal_input_len",
+	"seal_return",
+	"seal_return_per_kb",
+	"seal_terminate",
+	"seal_terminate_per_key",
+	"seal_restore_to",
+	"seal_restore_to_per_delta",
+	"seal_random",
+	"seal_deposit_event",
+	"seal_deposit_event_per_topic_and_kb",
+	"seal_set_rent_allowance",
+	"seal_top_up_rent_allowance",
+	"seal_reserve",
+	"seal_unreserve",
+	"seal_set_storage",
+	"seal_set_storage_per_kb",
+	"seal_clear_storage",
+	"seal_get_storage",
+	"seal_get_storage_per_kb",
+	"seal_register_layout",
+	"seal_register_layout_per_kb",
+	"seal_transfer",
+	"seal_call",
+	"seal_call_per_transfer_input_output_kb",
+	"seal_instantiate",
+	"seal_instantiate_per_input_output_salt_kb",
+	"seal_hash_sha2_256",
+	"seal_hash_sha2_256_per_kb",
+	"seal_hash_keccak_256",
+	"seal_hash_keccak_256_per_kb",
+	"seal_hash_blake2_256",
+	"seal_hash_blake2_256_per_kb",
+	"seal_hash_blake2_128",
+	"seal_hash_blake2_128_per_kb",
+	"instr_i64const",
+	"instr_i64load",
+	"instr_i64store",
+	"instr_select",
+	"instr_if",
+	"instr_br",
+	"instr_br_if",
+	"instr_br_table",
+	"instr_br_table_per_entry",
+	"instr_call",
+	"instr_call_indirect",
+	"instr_call_indirect_per_param",
+	"instr_local_get",
+	"instr_local_set",
+	"instr_local_tee",
+	"instr_global_get",
+	"instr_global_set",
+	"instr_memory_current",
+	"instr_memory_grow",
+	"instr_i64clz",
+	"instr_i64ctz",
+	"instr_i64popcnt",
+	"instr_i64eqz",
+	"instr_i64extendsi32",
+	"instr_i64extendui32",
+	"instr_i32wrapi64",
+	"instr_i64eq",
+	"instr_i64ne",
+	"instr_i64lts",
+	"instr_i64ltu",
+	"instr_i64gts",
+	"instr_i64gtu",
+	"instr_i64les",
+	"instr_i64leu",
+	"instr_i64ges",
+	"instr_i64geu",
+	"instr_i64add",
+	"instr_i64sub",
+	"instr_i64mul",
+	"instr_i64divs",
+	"instr_i64divu",
+	"instr_i64rems",
+	"instr_i64remu",
+	"instr_i64and",
+	"instr_i64or",
+	"instr_i64xor",
+	"instr_i64shl",
+	"instr_i64shrs",
+	"instr_i64shru",
+	"instr_i64rotl",
+	"instr_i64rotr",
+];
+
 /// Weights for pallet_contracts using the Substrate node and recommended hardware.
 pub struct SubstrateWeight<T>(PhantomData<T>);
 impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
@@ -156,6 +297,11 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads(1 as Weight))
 			.saturating_add(T::DbWeight::get().writes(2 as Weight))
 	}
+	fn put_code_existing(n: u32, ) -> Weight {
+		(14_906_000 as Weight)
+			.saturating_add((2_418_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+	}
 	fn instantiate(n: u32, s: u32, ) -> Weight {
 		(195_276_000 as Weight)
 			.saturating_add((35_000 as Weight).saturating_mul(n as Weight))
@@ -168,11 +314,48 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads(5 as Weight))
 			.saturating_add(T::DbWeight::get().writes(2 as Weight))
 	}
+	fn call_batch(n: u32, ) -> Weight {
+		(86_093_000 as Weight)
+			.saturating_add((204_588_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().reads((4 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(T::DbWeight::get().writes((2 as Weight).saturating_mul(n as Weight)))
+	}
 	fn claim_surcharge() -> Weight {
 		(489_633_000 as Weight)
 			.saturating_add(T::DbWeight::get().reads(3 as Weight))
 			.saturating_add(T::DbWeight::get().writes(2 as Weight))
 	}
+	fn destroy_contract(k: u32, ) -> Weight {
+		(18_605_000 as Weight)
+			.saturating_add((1_243_000 as Weight).saturating_mul(k as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(k as Weight)))
+	}
+	fn pause_contract() -> Weight {
+		(21_337_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	fn unpause_contract() -> Weight {
+		(21_097_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	fn set_frozen() -> Weight {
+		(24_516_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	fn set_layout(n: u32, ) -> Weight {
+		(26_813_000 as Weight)
+			.saturating_add((6_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	fn force_terminate() -> Weight {
+		(98_372_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
 	fn seal_caller(r: u32, ) -> Weight {
 		(136_550_000 as Weight)
 			.saturating_add((373_182_000 as Weight).saturating_mul(r as Weight))
@@ -183,6 +366,11 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add((373_392_000 as Weight).saturating_mul(r as Weight))
 			.saturating_add(T::DbWeight::get().reads(4 as Weight))
 	}
+	fn seal_derive_address(r: u32, ) -> Weight {
+		(137_804_000 as Weight)
+			.saturating_add((612_541_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+	}
 	fn seal_gas_left(r: u32, ) -> Weight {
 		(111_577_000 as Weight)
 			.saturating_add((373_536_000 as Weight).saturating_mul(r as Weight))
@@ -218,6 +406,16 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add((369_156_000 as Weight).saturating_mul(r as Weight))
 			.saturating_add(T::DbWeight::get().reads(4 as Weight))
 	}
+	fn seal_block_weight_left(r: u32, ) -> Weight {
+		(135_118_000 as Weight)
+			.saturating_add((392_504_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+	}
+	fn seal_call_count(r: u32, ) -> Weight {
+		(143_862_000 as Weight)
+			.saturating_add((848_317_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+	}
 	fn seal_now(r: u32, ) -> Weight {
 		(139_700_000 as Weight)
 			.saturating_add((368_961_000 as Weight).saturating_mul(r as Weight))
@@ -243,6 +441,21 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add((274_000 as Weight).saturating_mul(n as Weight))
 			.saturating_add(T::DbWeight::get().reads(4 as Weight))
 	}
+	fn seal_input_chunk(r: u32, ) -> Weight {
+		(134_812_000 as Weight)
+			.saturating_add((412_367_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+	}
+	fn seal_input_chunk_per_kb(n: u32, ) -> Weight {
+		(145_023_000 as Weight)
+			.saturating_add((279_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+	}
+	fn seal_input_len(r: u32, ) -> Weight {
+		(131_966_000 as Weight)
+			.saturating_add((341_205_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+	}
 	fn seal_return(r: u32, ) -> Weight {
 		(126_257_000 as Weight)
 			.saturating_add((5_455_000 as Weight).saturating_mul(r as Weight))
@@ -260,6 +473,13 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads((2 as Weight).saturating_mul(r as Weight)))
 			.saturating_add(T::DbWeight::get().writes((3 as Weight).saturating_mul(r as Weight)))
 	}
+	fn seal_terminate_per_key(k: u32, ) -> Weight {
+		(489_211_000 as Weight)
+			.saturating_add((124_570_000 as Weight).saturating_mul(k as Weight))
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes((100 as Weight).saturating_mul(k as Weight)))
+	}
 	fn seal_restore_to(r: u32, ) -> Weight {
 		(233_645_000 as Weight)
 			.saturating_add((135_355_000 as Weight).saturating_mul(r as Weight))
@@ -299,6 +519,24 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads(4 as Weight))
 			.saturating_add(T::DbWeight::get().writes(1 as Weight))
 	}
+	fn seal_top_up_rent_allowance(r: u32, ) -> Weight {
+		(159_584_000 as Weight)
+			.saturating_add((1_063_218_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	fn seal_reserve(r: u32, ) -> Weight {
+		(148_712_000 as Weight)
+			.saturating_add((1_912_806_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	fn seal_unreserve(r: u32, ) -> Weight {
+		(151_033_000 as Weight)
+			.saturating_add((1_874_520_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
 	fn seal_set_storage(r: u32, ) -> Weight {
 		(0 as Weight)
 			.saturating_add((14_938_793_000 as Weight).saturating_mul(r as Weight))
@@ -332,6 +570,19 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add((148_986_000 as Weight).saturating_mul(n as Weight))
 			.saturating_add(T::DbWeight::get().reads(5 as Weight))
 	}
+	fn seal_register_layout(r: u32, ) -> Weight {
+		(132_173_000 as Weight)
+			.saturating_add((1_824_716_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes((100 as Weight).saturating_mul(r as Weight)))
+	}
+	fn seal_register_layout_per_kb(n: u32, ) -> Weight {
+		(1_937_204_000 as Weight)
+			.saturating_add((63_415_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(101 as Weight))
+	}
 	fn seal_transfer(r: u32, ) -> Weight {
 		(92_276_000 as Weight)
 			.saturating_add((6_216_852_000 as Weight).saturating_mul(r as Weight))
@@ -630,6 +881,11 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
 			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
 	}
+	fn put_code_existing(n: u32, ) -> Weight {
+		(14_906_000 as Weight)
+			.saturating_add((2_418_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+	}
 	fn instantiate(n: u32, s: u32, ) -> Weight {
 		(195_276_000 as Weight)
 			.saturating_add((35_000 as Weight).saturating_mul(n as Weight))
@@ -642,11 +898,48 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
 			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
 	}
+	fn call_batch(n: u32, ) -> Weight {
+		(86_093_000 as Weight)
+			.saturating_add((204_588_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((4 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(RocksDbWeight::get().writes((2 as Weight).saturating_mul(n as Weight)))
+	}
 	fn claim_surcharge() -> Weight {
 		(489_633_000 as Weight)
 			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
 			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
 	}
+	fn destroy_contract(k: u32, ) -> Weight {
+		(18_605_000 as Weight)
+			.saturating_add((1_243_000 as Weight).saturating_mul(k as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((1 as Weight).saturating_mul(k as Weight)))
+	}
+	fn pause_contract() -> Weight {
+		(21_337_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn unpause_contract() -> Weight {
+		(21_097_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn set_frozen() -> Weight {
+		(24_516_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn set_layout(n: u32, ) -> Weight {
+		(26_813_000 as Weight)
+			.saturating_add((6_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn force_terminate() -> Weight {
+		(98_372_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
 	fn seal_caller(r: u32, ) -> Weight {
 		(136_550_000 as Weight)
 			.saturating_add((373_182_000 as Weight).saturating_mul(r as Weight))
@@ -657,6 +950,11 @@ impl WeightInfo for () {
 			.saturating_add((373_392_000 as Weight).saturating_mul(r as Weight))
 			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
 	}
+	fn seal_derive_address(r: u32, ) -> Weight {
+		(137_804_000 as Weight)
+			.saturating_add((612_541_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+	}
 	fn seal_gas_left(r: u32, ) -> Weight {
 		(111_577_000 as Weight)
 			.saturating_add((373_536_000 as Weight).saturating_mul(r as Weight))
@@ -692,6 +990,16 @@ impl WeightInfo for () {
 			.saturating_add((369_156_000 as Weight).saturating_mul(r as Weight))
 			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
 	}
+	fn seal_block_weight_left(r: u32, ) -> Weight {
+		(135_118_000 as Weight)
+			.saturating_add((392_504_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
+	}
+	fn seal_call_count(r: u32, ) -> Weight {
+		(143_862_000 as Weight)
+			.saturating_add((848_317_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+	}
 	fn seal_now(r: u32, ) -> Weight {
 		(139_700_000 as Weight)
 			.saturating_add((368_961_000 as Weight).saturating_mul(r as Weight))
@@ -717,6 +1025,21 @@ impl WeightInfo for () {
 			.saturating_add((274_000 as Weight).saturating_mul(n as Weight))
 			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
 	}
+	fn seal_input_chunk(r: u32, ) -> Weight {
+		(134_812_000 as Weight)
+			.saturating_add((412_367_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+	}
+	fn seal_input_chunk_per_kb(n: u32, ) -> Weight {
+		(145_023_000 as Weight)
+			.saturating_add((279_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+	}
+	fn seal_input_len(r: u32, ) -> Weight {
+		(131_966_000 as Weight)
+			.saturating_add((341_205_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+	}
 	fn seal_return(r: u32, ) -> Weight {
 		(126_257_000 as Weight)
 			.saturating_add((5_455_000 as Weight).saturating_mul(r as Weight))
@@ -734,6 +1057,13 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads((2 as Weight).saturating_mul(r as Weight)))
 			.saturating_add(RocksDbWeight::get().writes((3 as Weight).saturating_mul(r as Weight)))
 	}
+	fn seal_terminate_per_key(k: u32, ) -> Weight {
+		(489_211_000 as Weight)
+			.saturating_add((124_570_000 as Weight).saturating_mul(k as Weight))
+			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((100 as Weight).saturating_mul(k as Weight)))
+	}
 	fn seal_restore_to(r: u32, ) -> Weight {
 		(233_645_000 as Weight)
 			.saturating_add((135_355_000 as Weight).saturating_mul(r as Weight))
@@ -773,6 +1103,24 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
 			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
 	}
+	fn seal_top_up_rent_allowance(r: u32, ) -> Weight {
+		(159_584_000 as Weight)
+			.saturating_add((1_063_218_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn seal_reserve(r: u32, ) -> Weight {
+		(148_712_000 as Weight)
+			.saturating_add((1_912_806_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn seal_unreserve(r: u32, ) -> Weight {
+		(151_033_000 as Weight)
+			.saturating_add((1_874_520_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
 	fn seal_set_storage(r: u32, ) -> Weight {
 		(0 as Weight)
 			.saturating_add((14_938_793_000 as Weight).saturating_mul(r as Weight))
@@ -806,6 +1154,19 @@ impl WeightInfo for () {
 			.saturating_add((148_986_000 as Weight).saturating_mul(n as Weight))
 			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
 	}
+	fn seal_register_layout(r: u32, ) -> Weight {
+		(132_173_000 as Weight)
+			.saturating_add((1_824_716_000 as Weight).saturating_mul(r as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((100 as Weight).saturating_mul(r as Weight)))
+	}
+	fn seal_register_layout_per_kb(n: u32, ) -> Weight {
+		(1_937_204_000 as Weight)
+			.saturating_add((63_415_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(101 as Weight))
+	}
 	fn seal_transfer(r: u32, ) -> Weight {
 		(92_276_000 as Weight)
 			.saturating_add((6_216_852_000 as Weight).saturating_mul(r as Weight))